@@ -0,0 +1,251 @@
+use std::io;
+
+// reads wav and aiff files into the samples of each channel, for loading into the loop
+// the common uncompressed formats, integer or float, but nothing compressed
+
+pub struct DecodedAudio {
+    // one vec per channel, all the same length
+    pub channels: Vec<Vec<f32>>,
+    pub sample_rate: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Encoding {
+    Int,
+    Float,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Format {
+    num_channels: usize,
+    sample_rate: f32,
+    bits: usize,
+    encoding: Encoding,
+    big_endian: bool,
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+pub fn decode(bytes: &[u8]) -> io::Result<DecodedAudio> {
+    match (bytes.get(0..4), bytes.get(8..12)) {
+        (Some(b"RIFF"), Some(b"WAVE")) => decode_wav(bytes),
+        (Some(b"FORM"), Some(b"AIFF")) => decode_aiff(bytes, false),
+        (Some(b"FORM"), Some(b"AIFC")) => decode_aiff(bytes, true),
+        _ => Err(invalid("not a wav or aiff file")),
+    }
+}
+
+fn decode_wav(bytes: &[u8]) -> io::Result<DecodedAudio> {
+    let mut format = None;
+    let mut data = None;
+    for (id, chunk) in chunks(bytes, false) {
+        match id {
+            b"fmt " => format = Some(wav_format(chunk)?),
+            b"data" => data = Some(chunk),
+            _ => (),
+        }
+    }
+    let format = format.ok_or_else(|| invalid("wav file has no fmt chunk"))?;
+    decode_samples(data.unwrap_or(&[]), format)
+}
+
+fn wav_format(chunk: &[u8]) -> io::Result<Format> {
+    if chunk.len() < 16 {
+        return Err(invalid("wav fmt chunk is too short"));
+    }
+    let mut tag = read_u16(&chunk[0..], false);
+    // extensible files keep the real format at the start of the sub format guid
+    if tag == 0xfffe && chunk.len() >= 26 {
+        tag = read_u16(&chunk[24..], false);
+    }
+    let bits = read_u16(&chunk[14..], false) as usize;
+    let encoding = match (tag, bits) {
+        (1, 16 | 24 | 32) => Encoding::Int,
+        (3, 32 | 64) => Encoding::Float,
+        _ => return Err(invalid("unsupported wav sample format")),
+    };
+    Ok(Format {
+        num_channels: read_u16(&chunk[2..], false) as usize,
+        sample_rate: read_u32(&chunk[4..], false) as f32,
+        bits,
+        encoding,
+        big_endian: false,
+    })
+}
+
+fn decode_aiff(bytes: &[u8], compressed: bool) -> io::Result<DecodedAudio> {
+    let mut format = None;
+    let mut data = None;
+    for (id, chunk) in chunks(bytes, true) {
+        match id {
+            b"COMM" => format = Some(aiff_format(chunk, compressed)?),
+            // the samples start after an offset and block size
+            b"SSND" if chunk.len() >= 8 => {
+                let offset = read_u32(chunk, true) as usize;
+                data = chunk.get(8 + offset..);
+            }
+            _ => (),
+        }
+    }
+    let format = format.ok_or_else(|| invalid("aiff file has no COMM chunk"))?;
+    decode_samples(data.unwrap_or(&[]), format)
+}
+
+fn aiff_format(chunk: &[u8], compressed: bool) -> io::Result<Format> {
+    if chunk.len() < 18 || (compressed && chunk.len() < 22) {
+        return Err(invalid("aiff COMM chunk is too short"));
+    }
+    let bits = read_u16(&chunk[6..], true) as usize;
+    let (encoding, bits, big_endian) = match compressed.then(|| &chunk[18..22]) {
+        None | Some(b"NONE") | Some(b"twos") => (Encoding::Int, bits, true),
+        Some(b"sowt") => (Encoding::Int, bits, false),
+        Some(b"fl32") | Some(b"FL32") => (Encoding::Float, 32, true),
+        Some(b"fl64") | Some(b"FL64") => (Encoding::Float, 64, true),
+        _ => return Err(invalid("compressed aiff files aren't supported")),
+    };
+    if encoding == Encoding::Int && !(1..=32).contains(&bits) {
+        return Err(invalid("unsupported aiff sample size"));
+    }
+    Ok(Format {
+        num_channels: read_u16(&chunk[0..], true) as usize,
+        sample_rate: read_extended(&chunk[8..18]),
+        bits,
+        encoding,
+        big_endian,
+    })
+}
+
+// the id and contents of each chunk after the 12 byte header
+// a chunk cut short by the end of the file is read as far as it goes
+fn chunks(bytes: &[u8], big_endian: bool) -> impl Iterator<Item = (&[u8], &[u8])> {
+    let mut at: usize = 12;
+    std::iter::from_fn(move || {
+        let header = bytes.get(at..at.checked_add(8)?)?;
+        let size = read_u32(&header[4..], big_endian) as usize;
+        let start = at + 8;
+        let end = start.saturating_add(size).min(bytes.len());
+        // chunks are padded to an even length
+        at = start.saturating_add(size).saturating_add(size & 1);
+        Some((&header[..4], &bytes[start..end]))
+    })
+}
+
+fn decode_samples(data: &[u8], format: Format) -> io::Result<DecodedAudio> {
+    if format.num_channels == 0 || format.sample_rate <= 0.0 {
+        return Err(invalid("file has no channels or sample rate"));
+    }
+    // samples that aren't a whole number of bytes are padded out to one
+    let sample_size = format.bits.div_ceil(8);
+    let frame_size = sample_size * format.num_channels;
+    let num_frames = data.len() / frame_size;
+    let mut channels = vec![Vec::with_capacity(num_frames); format.num_channels];
+    for frame in data.chunks_exact(frame_size) {
+        for (channel, sample) in channels.iter_mut().zip(frame.chunks_exact(sample_size)) {
+            channel.push(read_sample(sample, format));
+        }
+    }
+    Ok(DecodedAudio {
+        channels,
+        sample_rate: format.sample_rate,
+    })
+}
+
+fn read_sample(bytes: &[u8], format: Format) -> f32 {
+    let mut little_endian = [0; 8];
+    little_endian[..bytes.len()].copy_from_slice(bytes);
+    if format.big_endian {
+        little_endian[..bytes.len()].reverse();
+    }
+    match (format.encoding, bytes.len()) {
+        (Encoding::Float, 4) => f32::from_le_bytes(little_endian[..4].try_into().unwrap()),
+        (Encoding::Float, _) => f64::from_le_bytes(little_endian) as f32,
+        (Encoding::Int, size) => {
+            // moved up to the top of an i64 so the sign comes with it, whatever the size
+            let value = i64::from_le_bytes(little_endian) << (64 - 8 * size);
+            (value as f64 / 2f64.powi(63)) as f32
+        }
+    }
+}
+
+fn read_u16(bytes: &[u8], big_endian: bool) -> u16 {
+    let bytes = [bytes[0], bytes[1]];
+    if big_endian {
+        u16::from_be_bytes(bytes)
+    } else {
+        u16::from_le_bytes(bytes)
+    }
+}
+
+fn read_u32(bytes: &[u8], big_endian: bool) -> u32 {
+    let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+    if big_endian {
+        u32::from_be_bytes(bytes)
+    } else {
+        u32::from_le_bytes(bytes)
+    }
+}
+
+// aiff keeps its sample rate as an 80 bit float, a 15 bit exponent and 64 bit mantissa
+fn read_extended(bytes: &[u8]) -> f32 {
+    let exponent = (read_u16(bytes, true) & 0x7fff) as i32;
+    let mantissa = u64::from_be_bytes(bytes[2..10].try_into().unwrap());
+    (mantissa as f64 * 2f64.powi(exponent - 16383 - 63)) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wav::write_wav;
+
+    #[test]
+    fn test_decode_wav() {
+        let mut bytes = Vec::new();
+        write_wav(&mut bytes, &[0.5, -0.5, 0.25, -0.25], 2, 48000).unwrap();
+        let decoded = decode(&bytes).unwrap();
+        assert_eq!(decoded.sample_rate, 48000.0);
+        assert_eq!(decoded.channels, vec![vec![0.5, 0.25], vec![-0.5, -0.25]]);
+
+        // 16 bit mono, with a chunk to skip before the samples
+        let mut bytes = b"RIFF\0\0\0\0WAVEfmt \x10\0\0\0\x01\0\x01\0".to_vec();
+        bytes.extend_from_slice(&44100u32.to_le_bytes());
+        bytes.extend_from_slice(&88200u32.to_le_bytes());
+        bytes.extend_from_slice(b"\x02\0\x10\0LIST\x03\0\0\0abc\0data\x06\0\0\0");
+        for sample in [16384i16, -32768, 0] {
+            bytes.extend_from_slice(&sample.to_le_bytes());
+        }
+        let decoded = decode(&bytes).unwrap();
+        assert_eq!(decoded.sample_rate, 44100.0);
+        assert_eq!(decoded.channels, vec![vec![0.5, -1.0, 0.0]]);
+    }
+
+    #[test]
+    fn test_decode_aiff() {
+        // 24 bit stereo at 44100, big endian
+        let mut bytes = b"FORM\0\0\0\0AIFFCOMM\0\0\0\x12\0\x02\0\0\0\x01\0\x18".to_vec();
+        bytes.extend_from_slice(&[0x40, 0x0e, 0xac, 0x44, 0, 0, 0, 0, 0, 0]);
+        bytes.extend_from_slice(b"SSND\0\0\0\x0e\0\0\0\0\0\0\0\0");
+        bytes.extend_from_slice(&[0x40, 0, 0, 0xc0, 0, 0]);
+        let decoded = decode(&bytes).unwrap();
+        assert_eq!(decoded.sample_rate, 44100.0);
+        assert_eq!(decoded.channels, vec![vec![0.5], vec![-0.5]]);
+
+        // aiff-c floats
+        let mut bytes = b"FORM\0\0\0\0AIFCCOMM\0\0\0\x16\0\x01\0\0\0\x01\0\x20".to_vec();
+        bytes.extend_from_slice(&[0x40, 0x0e, 0xbb, 0x80, 0, 0, 0, 0, 0, 0]);
+        bytes.extend_from_slice(b"fl32SSND\0\0\0\x0c\0\0\0\0\0\0\0\0");
+        bytes.extend_from_slice(&0.75f32.to_be_bytes());
+        let decoded = decode(&bytes).unwrap();
+        assert_eq!(decoded.sample_rate, 48000.0);
+        assert_eq!(decoded.channels, vec![vec![0.75]]);
+    }
+
+    #[test]
+    fn test_decode_rejects() {
+        assert!(decode(b"OggS").is_err());
+        let compressed =
+            b"FORM\0\0\0\0AIFCCOMM\0\0\0\x16\0\x01\0\0\0\x01\0\x10\x40\x0e\xac\x44\0\0\0\0\0\0ima4";
+        assert!(decode(compressed).is_err());
+    }
+}
@@ -0,0 +1,107 @@
+use crate::stereo_pair::{AudioSampleOps, StereoPair};
+
+// the most channels a host buffer can have for us to move frames in and out of it
+pub const MAX_CHANNELS: usize = 8;
+
+// a sample for every channel at one point in time, that can be read from and written to
+// the host's channels whatever the channel count, so the process loop doesn't depend on it
+pub trait AudioFrame: AudioSampleOps {
+    const NUM_CHANNELS: usize;
+
+    // when the host is mono it is spread across the frame
+    fn from_channels(channels: &[f32]) -> Self;
+    // when the host is mono the frame is mixed down
+    fn write_channels(&self, channels: &mut [f32]);
+
+    // -1 is left, 1 is right, as a balance so the centre is unity and nothing is boosted
+    // only a stereo frame has a left and right, anything else is left where it is
+    fn pan(&self, _pan: f32) -> Self {
+        *self
+    }
+}
+
+// a mono frame mixes the host channels down, and is copied back to all of them
+impl AudioFrame for f32 {
+    const NUM_CHANNELS: usize = 1;
+
+    fn from_channels(channels: &[f32]) -> f32 {
+        channels.iter().sum::<f32>() / channels.len() as f32
+    }
+
+    fn write_channels(&self, channels: &mut [f32]) {
+        channels.fill(*self);
+    }
+}
+
+impl AudioFrame for StereoPair<f32> {
+    const NUM_CHANNELS: usize = 2;
+
+    fn pan(&self, pan: f32) -> StereoPair<f32> {
+        StereoPair::new(
+            self.left * (1.0 - pan).min(1.0),
+            self.right * (1.0 + pan).min(1.0),
+        )
+    }
+
+    fn from_channels(channels: &[f32]) -> StereoPair<f32> {
+        match channels {
+            [mono] => StereoPair::new(*mono, *mono),
+            [left, right, ..] => StereoPair::new(*left, *right),
+            [] => StereoPair::default(),
+        }
+    }
+
+    fn write_channels(&self, channels: &mut [f32]) {
+        match channels {
+            [mono] => *mono = (self.left + self.right) * 0.5,
+            [left, right, rest @ ..] => {
+                *left = self.left;
+                *right = self.right;
+                rest.fill(0.0);
+            }
+            [] => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stereo_frame_mono_channels() {
+        let frame = StereoPair::<f32>::from_channels(&[0.5]);
+        assert_eq!(frame, StereoPair::new(0.5, 0.5));
+
+        let mut channels = [0.0];
+        StereoPair::new(1.0, 0.0).write_channels(&mut channels);
+        assert_eq!(channels, [0.5]);
+    }
+
+    #[test]
+    fn test_stereo_frame_stereo_channels() {
+        let frame = StereoPair::<f32>::from_channels(&[0.5, -0.5]);
+        assert_eq!(frame, StereoPair::new(0.5, -0.5));
+
+        let mut channels = [0.0, 0.0];
+        frame.write_channels(&mut channels);
+        assert_eq!(channels, [0.5, -0.5]);
+    }
+
+    #[test]
+    fn test_frame_pan() {
+        let frame = StereoPair::new(1.0, 1.0);
+        assert_eq!(frame.pan(0.0), frame);
+        assert_eq!(frame.pan(-0.5), StereoPair::new(1.0, 0.5));
+        assert_eq!(frame.pan(1.0), StereoPair::new(0.0, 1.0));
+        assert_eq!(0.5.pan(1.0), 0.5);
+    }
+
+    #[test]
+    fn test_mono_frame() {
+        assert_eq!(f32::from_channels(&[1.0, 0.0]), 0.5);
+        let mut channels = [0.0, 0.0];
+        0.25.write_channels(&mut channels);
+        assert_eq!(channels, [0.25, 0.25]);
+    }
+}
@@ -0,0 +1,86 @@
+use crate::lfo::{BeatLfo, LfoShape};
+use crate::process_context::ProcessContextLite;
+use crate::stereo_pair::StereoPair;
+use crate::wet_chain::WetProcess;
+use crate::MetaloopParams;
+
+// moves a stereo signal between the left and right with a tempo synced lfo
+// uses a balance control, so the centre is unity gain and nothing is ever boosted
+pub struct AutoPan {
+    lfo: BeatLfo,
+    // 0 is off, 1 pans hard left and right
+    depth: f32,
+}
+
+#[allow(dead_code)]
+impl AutoPan {
+    pub fn new() -> AutoPan {
+        AutoPan {
+            lfo: BeatLfo::new(LfoShape::Sine, 1.0),
+            depth: 0.0,
+        }
+    }
+
+    // how many beats one cycle of left and right takes
+    pub fn set_rate(&mut self, period_beats: f32) {
+        self.lfo.set_period(period_beats);
+    }
+
+    pub fn set_depth(&mut self, depth: f32) {
+        self.depth = depth.clamp(0.0, 1.0);
+    }
+
+    pub fn set_shape(&mut self, shape: LfoShape) {
+        self.lfo.set_shape(shape);
+    }
+
+    pub fn process(&self, input: StereoPair<f32>, beat_time: f64) -> StereoPair<f32> {
+        if self.depth == 0.0 {
+            return input;
+        }
+        // -1 is left, 1 is right
+        let pan = self.depth * self.lfo.value_at(beat_time);
+        StereoPair::new(
+            input.left * (1.0 - pan).min(1.0),
+            input.right * (1.0 + pan).min(1.0),
+        )
+    }
+}
+
+impl WetProcess for AutoPan {
+    fn update_params(&mut self, params: &MetaloopParams) {
+        self.set_rate(params.pan_rate.value().beats());
+        self.set_depth(params.pan_depth.value());
+        self.set_shape(params.pan_shape.value());
+    }
+
+    fn process_block(&mut self, context: &ProcessContextLite, block: &mut [StereoPair<f32>]) {
+        for (i, frame) in block.iter_mut().enumerate() {
+            *frame = self.process(*frame, context.beat_time_at(i));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auto_pan() {
+        let mut pan = AutoPan::new();
+        pan.set_shape(LfoShape::Square);
+        pan.set_rate(2.0);
+        let input = StereoPair::new(1.0, 1.0);
+
+        // no depth is no change
+        assert_eq!(pan.process(input, 0.5), input);
+
+        pan.set_depth(0.5);
+        // first half of the cycle is to the right
+        assert_eq!(pan.process(input, 0.5), StereoPair::new(0.5, 1.0));
+        assert_eq!(pan.process(input, 1.5), StereoPair::new(1.0, 0.5));
+
+        pan.set_depth(1.0);
+        assert_eq!(pan.process(input, 2.5), StereoPair::new(0.0, 1.0));
+    }
+}
@@ -0,0 +1,112 @@
+use crate::loop_export::LoopExport;
+use crate::loop_import::LoopImport;
+use crate::phase_check;
+use nih_plug::nih_log;
+
+// slow work the audio thread hands off rather than doing itself, e.g. anything that logs,
+// allocates or touches the disk
+// the host runs these on its background thread
+// anything bigger than fits in a task, like a loop to export or a file to import, is shared
+// with the executor
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BackgroundTask {
+    LooperReset {
+        surround: bool,
+    },
+    LatencyMeasured(usize),
+    LatencyTimedOut,
+    PhaseCheck {
+        num_channels: usize,
+        sample_rate: f32,
+    },
+    // the audio thread has captured a loop that was asked to be exported
+    ExportLoop,
+    // a file was asked to be imported, to decode for the audio thread to loop
+    ImportLoop,
+}
+
+// the executor the plugin gives the host
+pub fn run(task: BackgroundTask, export: &LoopExport, import: &LoopImport) {
+    if let Some(message) = message(task, export, import) {
+        nih_log!("{}", message);
+    }
+}
+
+// does the work, and returns what is worth telling
+fn message(task: BackgroundTask, export: &LoopExport, import: &LoopImport) -> Option<String> {
+    match task {
+        BackgroundTask::LooperReset { surround } => Some(format!(
+            "{}looper state was inconsistent, resetting",
+            if surround { "surround " } else { "" }
+        )),
+        BackgroundTask::LatencyMeasured(latency) => {
+            Some(format!("measured a capture latency of {} samples", latency))
+        }
+        BackgroundTask::LatencyTimedOut => {
+            Some("latency click didn't come back, is the output looped back?".to_string())
+        }
+        BackgroundTask::PhaseCheck {
+            num_channels,
+            sample_rate,
+        } => {
+            let cancellation = phase_check::check(sample_rate, num_channels);
+            (!cancellation.passes()).then(|| {
+                format!(
+                    "loop only cancels the dry by {:.1} dB{}",
+                    cancellation.depth_db(),
+                    if cancellation.is_inverted() {
+                        ", and is inverted"
+                    } else {
+                        ""
+                    }
+                )
+            })
+        }
+        BackgroundTask::ExportLoop => Some(match export.write() {
+            Ok(path) => format!("exported the loop to {}", path.display()),
+            Err(error) => format!("couldn't export the loop: {}", error),
+        }),
+        BackgroundTask::ImportLoop => Some(match import.decode() {
+            Ok((path, seconds)) => format!("imported {:.1}s of {}", seconds, path.display()),
+            Err(error) => format!("couldn't import the file: {}", error),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_background_task_messages() {
+        let export = LoopExport::default();
+        let import = LoopImport::default();
+        assert_eq!(
+            message(
+                BackgroundTask::LooperReset { surround: true },
+                &export,
+                &import
+            )
+            .unwrap(),
+            "surround looper state was inconsistent, resetting"
+        );
+        assert_eq!(
+            message(BackgroundTask::LatencyMeasured(12), &export, &import).unwrap(),
+            "measured a capture latency of 12 samples"
+        );
+        // a check that passes has nothing to say
+        let check = BackgroundTask::PhaseCheck {
+            num_channels: 2,
+            sample_rate: 48000.0,
+        };
+        assert_eq!(message(check, &export, &import), None);
+        assert_eq!(
+            message(BackgroundTask::ExportLoop, &export, &import).unwrap(),
+            "couldn't export the loop: no loop was captured"
+        );
+        assert_eq!(
+            message(BackgroundTask::ImportLoop, &export, &import).unwrap(),
+            "couldn't import the file: no file was asked for"
+        );
+    }
+}
@@ -0,0 +1,85 @@
+use crate::ramped_value::RampedValue;
+
+// below this the wheel is taken to be resting in the centre
+const CENTRE_DEAD_ZONE: f32 = 0.01;
+
+// maps the pitch bend wheel to a scrub around the current loop offset
+// bending up moves the loop later, toward the present, bending down moves it into the past
+// when the wheel is let go it springs back to the centre, and the scrub glides back
+// rather than jumping with it
+pub struct BendScrub {
+    // -1..1, slewed
+    bend: RampedValue,
+    range_beats: f32,
+    follow_samples: usize,
+    return_samples: usize,
+}
+
+#[allow(dead_code)]
+impl BendScrub {
+    pub fn new(follow_samples: usize, return_samples: usize) -> BendScrub {
+        BendScrub {
+            bend: RampedValue::new(0.0),
+            range_beats: 0.25,
+            follow_samples,
+            return_samples,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.bend = RampedValue::new(0.0);
+    }
+
+    // how far a full bend moves the offset
+    pub fn set_range(&mut self, range_beats: f32) {
+        self.range_beats = range_beats;
+    }
+
+    // how quickly the scrub follows the wheel
+    pub fn set_follow_samples(&mut self, follow_samples: usize) {
+        self.follow_samples = follow_samples;
+    }
+
+    // how long the scrub takes to glide back once the wheel is released
+    pub fn set_return_samples(&mut self, return_samples: usize) {
+        self.return_samples = return_samples;
+    }
+
+    // value is the normalized wheel position, 0.5 is the centre
+    pub fn handle_bend(&mut self, value: f32) {
+        let bend = value * 2.0 - 1.0;
+        if bend.abs() < CENTRE_DEAD_ZONE {
+            self.bend.ramp(0.0, self.return_samples);
+        } else {
+            self.bend.ramp(bend as f64, self.follow_samples);
+        }
+    }
+
+    // call once per block, returns how much to add to the loop offset in beats
+    pub fn process(&mut self, num_samples: usize) -> f32 {
+        -(self.bend.tick_many(num_samples) as f32) * self.range_beats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bend_scrub() {
+        let mut scrub = BendScrub::new(0, 4);
+        scrub.set_range(0.5);
+        assert_eq!(scrub.process(1), 0.0);
+
+        // full bend down is the full range into the past
+        scrub.handle_bend(0.0);
+        assert_eq!(scrub.process(1), 0.5);
+        scrub.handle_bend(0.75);
+        assert_eq!(scrub.process(1), -0.25);
+
+        // released, glides back over 5 samples
+        scrub.handle_bend(0.5);
+        assert!((scrub.process(2) + 0.15).abs() < 0.0001);
+        assert_eq!(scrub.process(10), 0.0);
+    }
+}
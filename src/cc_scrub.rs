@@ -0,0 +1,163 @@
+use crate::ramped_value::RampedValue;
+use crate::soft_takeover::SoftTakeover;
+
+// maps a midi cc onto the loop offset
+// the cc is slewed so that the steps of a 7 bit controller and fast hand movements
+// don't throw the loop around
+// once the cc has come up to the offset playing it takes over, until the offset parameter
+// itself is moved, so a controller left somewhere else doesn't jump the loop
+pub struct CcScrub {
+    learn_armed: bool,
+    learn_enabled: bool,
+    // normalized 0..1, None while the parameter is in control
+    slewed_value: Option<RampedValue>,
+    slew_samples: usize,
+    last_param_value: Option<f32>,
+    takeover: SoftTakeover,
+}
+
+#[allow(dead_code)]
+impl CcScrub {
+    pub fn new(slew_samples: usize) -> CcScrub {
+        CcScrub {
+            learn_armed: false,
+            learn_enabled: false,
+            slewed_value: None,
+            slew_samples,
+            last_param_value: None,
+            takeover: SoftTakeover::new(),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.slewed_value = None;
+        self.last_param_value = None;
+        self.takeover.reset();
+    }
+
+    pub fn set_slew_samples(&mut self, slew_samples: usize) {
+        self.slew_samples = slew_samples;
+    }
+
+    // turning learning on arms it, the next cc received becomes the scrub cc
+    pub fn set_learning(&mut self, enabled: bool) {
+        if enabled && !self.learn_enabled {
+            self.learn_armed = true;
+        }
+        if !enabled {
+            self.learn_armed = false;
+        }
+        self.learn_enabled = enabled;
+    }
+
+    pub fn is_learning(&self) -> bool {
+        self.learn_armed
+    }
+
+    // returns the cc to store if this message was learned
+    pub fn handle_cc(&mut self, scrub_cc: u8, cc: u8, value: f32) -> Option<u8> {
+        let mut learned = None;
+        if self.learn_armed {
+            self.learn_armed = false;
+            // a new controller has to pick the offset up afresh
+            self.takeover.reset();
+            learned = Some(cc);
+        } else if cc != scrub_cc {
+            return None;
+        }
+
+        // with nothing playing yet, there is nothing to jump from
+        // while slewing, the cc is taken to be where it last sent the offset
+        let current = match &self.slewed_value {
+            Some(slewed_value) => Some(slewed_value.target() as f32),
+            None => self.last_param_value,
+        };
+        if let Some(current) = current {
+            if self.takeover.process(value, current).is_none() {
+                return learned;
+            }
+        }
+
+        match &mut self.slewed_value {
+            Some(slewed_value) => slewed_value.ramp(value as f64, self.slew_samples),
+            // the first message jumps straight there, there is nothing to slew from
+            None => self.slewed_value = Some(RampedValue::new(value as f64)),
+        }
+        learned
+    }
+
+    // call once per block with the offset parameter, normalized
+    // returns the normalized offset the cc wants, or None if the parameter is in control
+    pub fn process(&mut self, param_value: f32, num_samples: usize) -> Option<f32> {
+        if let Some(last_param_value) = self.last_param_value {
+            if last_param_value != param_value {
+                self.slewed_value = None;
+                self.takeover.reset();
+            }
+        }
+        self.last_param_value = Some(param_value);
+
+        self.slewed_value
+            .as_mut()
+            .map(|slewed_value| slewed_value.tick_many(num_samples) as f32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cc_scrub_slews() {
+        let mut scrub = CcScrub::new(4);
+        assert_eq!(scrub.process(0.5, 2), None);
+        // only the scrub cc is used
+        scrub.handle_cc(1, 2, 0.0);
+        assert_eq!(scrub.process(0.5, 2), None);
+
+        scrub.handle_cc(1, 1, 0.5);
+        assert_eq!(scrub.process(0.5, 2), Some(0.5));
+        scrub.handle_cc(1, 1, 1.0);
+        assert!((scrub.process(0.5, 2).unwrap() - 0.7).abs() < 0.0001);
+        assert_eq!(scrub.process(0.5, 10), Some(1.0));
+
+        // moving the parameter takes back control
+        assert_eq!(scrub.process(0.6, 2), None);
+    }
+
+    #[test]
+    fn test_cc_scrub_learn() {
+        let mut scrub = CcScrub::new(0);
+        scrub.set_learning(true);
+        assert!(scrub.is_learning());
+        assert_eq!(scrub.handle_cc(1, 74, 0.25), Some(74));
+        assert!(!scrub.is_learning());
+        assert_eq!(scrub.process(0.5, 1), Some(0.25));
+
+        // only learns once per time learning is turned on
+        assert_eq!(scrub.handle_cc(74, 75, 0.5), None);
+        scrub.set_learning(true);
+        assert!(!scrub.is_learning());
+    }
+
+    #[test]
+    fn test_cc_scrub_soft_takeover() {
+        let mut scrub = CcScrub::new(0);
+        scrub.process(0.5, 1);
+        // a controller left far from the offset doesn't move it
+        scrub.handle_cc(1, 1, 0.0);
+        assert_eq!(scrub.process(0.5, 1), None);
+        scrub.handle_cc(1, 1, 0.2);
+        assert_eq!(scrub.process(0.5, 1), None);
+        // until it comes past it
+        scrub.handle_cc(1, 1, 0.6);
+        assert_eq!(scrub.process(0.5, 1), Some(0.6));
+        scrub.handle_cc(1, 1, 0.1);
+        assert_eq!(scrub.process(0.5, 1), Some(0.1));
+
+        // once the param takes back control, it has to be picked up again
+        assert_eq!(scrub.process(0.8, 1), None);
+        scrub.handle_cc(1, 1, 0.1);
+        assert_eq!(scrub.process(0.8, 1), None);
+    }
+}
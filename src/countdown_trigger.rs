@@ -1,4 +1,4 @@
-struct CountdownTrigger {
+pub struct CountdownTrigger {
     count: i32,
 }
 
@@ -12,6 +12,10 @@ impl CountdownTrigger {
         self.count = count;
     }
 
+    pub fn count(&self) -> i32 {
+        self.count
+    }
+
     pub fn tick(&mut self) -> Option<()> {
         if self.count == 0 {
             return Some(());
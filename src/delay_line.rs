@@ -1,8 +1,16 @@
 #![allow(dead_code)]
 // delay line
 
+use crate::resampler::{sinc, window};
 use crate::stereo_pair::AudioSampleOps;
 
+// zero crossings of the sinc either side of a band-limited read, fewer than the offline resampler
+// as it runs for every grain on every sample
+const BAND_LIMIT_HALF_WIDTH: usize = 4;
+// past this speed the filter stops widening, so the cost of a read is bounded
+// faster still aliases a little, but that's a long way up
+const MAX_BAND_LIMIT_SPEED: f32 = 4.0;
+
 pub struct DelayLine<T>
 where
     T: Copy,
@@ -62,6 +70,66 @@ where
         self.write_index = (self.write_index + 1) % self.buffer.len();
     }
 
+    // as ticking each sample in turn, copied in at most two runs around the end of the buffer
+    pub fn write_block(&mut self, input: &[T]) {
+        // anything older than the buffer holds would be overwritten anyway
+        let input = &input[input.len().saturating_sub(self.buffer.len())..];
+        let first = (self.buffer.len() - self.write_index).min(input.len());
+        self.buffer[self.write_index..self.write_index + first].copy_from_slice(&input[..first]);
+        self.buffer[..input.len() - first].copy_from_slice(&input[first..]);
+        self.write_index = (self.write_index + input.len()) % self.buffer.len();
+    }
+
+    // makes this read as source does gap samples further back, in one copy rather than by ticking
+    // the newest gap samples are left silent, for set to fill in as they come
+    pub fn freeze_from(&mut self, source: &DelayLine<T>, gap: usize) {
+        let len = self.buffer.len();
+        let count = len.saturating_sub(gap);
+        assert!(count <= source.len(), "source is too short to copy from");
+        // the oldest sample copied, and where it sits in the source
+        let start = (source.write_index + source.len() - count) % source.len();
+        let first = count.min(source.len() - start);
+        self.buffer[..first].copy_from_slice(&source.buffer[start..start + first]);
+        self.buffer[first..count].copy_from_slice(&source.buffer[..count - first]);
+        self.buffer[count..].fill(T::default());
+        self.write_index = 0;
+    }
+
+    // as reading each delay in turn from delay_samples on, newest first, in at most two copies
+    // anything further back than the buffer reaches is left silent
+    pub fn read_block(&self, delay_samples: usize, output: &mut [T]) {
+        let len = self.buffer.len();
+        let available = len.saturating_sub(delay_samples).min(output.len());
+        let (output, beyond) = output.split_at_mut(available);
+        beyond.fill(T::default());
+        if available == 0 {
+            return;
+        }
+        // the buffer runs backwards from the newest sample read, around the end
+        let newest = (self.write_index + len - delay_samples - 1) % len;
+        let first = available.min(newest + 1);
+        let (newer, older) = output.split_at_mut(first);
+        newer.copy_from_slice(&self.buffer[newest + 1 - first..=newest]);
+        newer.reverse();
+        older.copy_from_slice(&self.buffer[len - older.len()..]);
+        older.reverse();
+    }
+
+    // replaces everything the line holds, newest first as read_block leaves it
+    pub fn load_block(&mut self, input: &[T]) {
+        assert_eq!(input.len(), self.buffer.len(), "input must fill the buffer");
+        self.buffer.copy_from_slice(input);
+        self.buffer.reverse();
+        self.write_index = 0;
+    }
+
+    // overwrites the sample at delay without moving the line on
+    pub fn set(&mut self, delay_samples: usize, value: T) {
+        assert!(delay_samples < self.buffer.len());
+        let index = (self.write_index + self.buffer.len() - delay_samples - 1) % self.buffer.len();
+        self.buffer[index] = value;
+    }
+
     pub fn read(&self, delay_samples: usize) -> T {
         assert!(
             delay_samples < self.buffer.len(),
@@ -100,6 +168,32 @@ where
 
         lerp(v0, v1, frac)
     }
+
+    // as read_interpolated, but for reading through the buffer speed samples at a time
+    // above 1x the sinc is widened to filter out what would alias once played back faster
+    // taps that fall outside the buffer are left out, and the rest scaled up to make up for them
+    pub fn read_band_limited(&self, delay_samples: f32, speed: f32) -> T {
+        if speed <= 1.0 {
+            return self.read_interpolated(delay_samples);
+        }
+        let cutoff = 1.0 / speed.min(MAX_BAND_LIMIT_SPEED);
+        let half_width = BAND_LIMIT_HALF_WIDTH as f32 / cutoff;
+        let first = (delay_samples - half_width).ceil().max(0.0) as usize;
+        let last = ((delay_samples + half_width).floor() as usize).min(self.buffer.len() - 1);
+
+        let mut sum = T::default();
+        let mut total_gain = 0.0;
+        for delay in first..=last {
+            let distance = delay_samples - delay as f32;
+            let gain = sinc(cutoff * distance) * window(distance / half_width);
+            sum += self.read(delay) * gain;
+            total_gain += gain;
+        }
+        if total_gain == 0.0 {
+            return T::default();
+        }
+        sum * (1.0 / total_gain)
+    }
 }
 #[cfg(test)]
 mod tests {
@@ -129,6 +223,88 @@ mod tests {
         assert_eq!(delay_line.read_interpolated(0.6), 3.4);
     }
 
+    #[test]
+    fn test_delay_line_write_block() {
+        let mut ticked = DelayLine::new(5);
+        let mut written = DelayLine::new(5);
+        let input: Vec<f32> = (0..12).map(|x| x as f32).collect();
+        // around the end of the buffer, and longer than it
+        for block in [&input[..3], &input[3..7], &input[7..12]] {
+            for sample in block {
+                ticked.tick(*sample);
+            }
+            written.write_block(block);
+            for delay in 0..5 {
+                assert_eq!(written.read(delay), ticked.read(delay));
+            }
+        }
+        written.write_block(&input);
+        assert_eq!(written.read(0), 11.0);
+        assert_eq!(written.read(4), 7.0);
+    }
+
+    #[test]
+    fn test_delay_line_freeze_from() {
+        let mut source = DelayLine::new(7);
+        let mut frozen = DelayLine::new(5);
+        frozen.tick(-1.0);
+        // wherever the source has got to around its buffer
+        for ticks in [7, 9, 12] {
+            source.reset();
+            for x in 0..ticks {
+                source.tick(x as f32);
+            }
+            frozen.freeze_from(&source, 2);
+            assert_eq!(frozen.read(0), 0.0);
+            assert_eq!(frozen.read(1), 0.0);
+            for delay in 2..5 {
+                assert_eq!(frozen.read(delay), source.read(delay - 2));
+            }
+        }
+
+        frozen.set(1, 20.0);
+        frozen.set(0, 21.0);
+        assert_eq!(frozen.read(0), 21.0);
+        assert_eq!(frozen.read(1), 20.0);
+        assert_eq!(frozen.read(2), 11.0);
+    }
+
+    #[test]
+    fn test_delay_line_read_and_load_block() {
+        let mut delay_line = DelayLine::new(5);
+        // wherever it has got to around its buffer
+        for ticks in [3, 5, 7] {
+            delay_line.reset();
+            for x in 0..ticks {
+                delay_line.tick(x as f32 + 1.0);
+            }
+            for from in 0..6 {
+                let mut block = [-1.0; 4];
+                delay_line.read_block(from, &mut block);
+                let expected: Vec<f32> = (from..from + 4)
+                    .map(|delay| {
+                        if delay < 5 {
+                            delay_line.read(delay)
+                        } else {
+                            0.0
+                        }
+                    })
+                    .collect();
+                assert_eq!(block.to_vec(), expected, "{} ticks from {}", ticks, from);
+            }
+        }
+
+        let mut loaded = DelayLine::new(5);
+        loaded.tick(9.0);
+        loaded.load_block(&[5.0, 4.0, 3.0, 2.0, 1.0]);
+        for delay in 0..5 {
+            assert_eq!(loaded.read(delay), 5.0 - delay as f32);
+        }
+        loaded.tick(6.0);
+        assert_eq!(loaded.read(0), 6.0);
+        assert_eq!(loaded.read(4), 2.0);
+    }
+
     #[test]
     fn test_delay_line_type() {
         let mut bool_delay_line = DelayLine::new(4);
@@ -156,4 +332,33 @@ mod tests {
             StereoPair::new(2.0, 3.0)
         );
     }
+
+    #[test]
+    fn test_delay_line_band_limited() {
+        let mut delay_line = DelayLine::new(64);
+        fill_delay_ramp(&mut delay_line);
+        // at or below 1x it's the plain interpolated read
+        assert_eq!(delay_line.read_band_limited(10.6, 1.0), 52.4);
+
+        // a steady level comes through as it is, even at the ends of the buffer
+        let mut steady = DelayLine::new(64);
+        for _ in 0..64 {
+            steady.tick(0.5);
+        }
+        for delay in [0.0, 10.3, 62.5] {
+            let read = steady.read_band_limited(delay, 2.0);
+            assert!((read - 0.5).abs() < 1e-5, "{}", read);
+        }
+
+        // the highest frequency the buffer holds would alias at 2x, it's filtered out
+        let mut nyquist = DelayLine::new(64);
+        for i in 0..64 {
+            nyquist.tick(if i % 2 == 0 { 1.0 } else { -1.0 });
+        }
+        assert_eq!(nyquist.read_interpolated(32.0).abs(), 1.0);
+        for delay in [20.0, 32.0, 32.5] {
+            let read = nyquist.read_band_limited(delay, 2.0);
+            assert!(read.abs() < 0.05, "{}", read);
+        }
+    }
 }
@@ -0,0 +1,96 @@
+use crate::audio_frame::MAX_CHANNELS;
+use crate::ramped_value::RampedValue;
+
+// the level of the dry in each channel, ramped as the loop takes over from it and gives it back
+// when linked every channel follows the first, which is the only one ticked
+// unlinked, each channel can be ramped on its own, e.g. for loops starting at different times
+pub struct DryRamps {
+    ramps: [RampedValue; MAX_CHANNELS],
+    linked: bool,
+}
+
+#[allow(dead_code)]
+impl DryRamps {
+    pub fn new(value: f64) -> DryRamps {
+        DryRamps {
+            ramps: std::array::from_fn(|_| RampedValue::new(value)),
+            linked: true,
+        }
+    }
+
+    pub fn set(&mut self, value: f64) {
+        for ramp in self.ramps.iter_mut() {
+            ramp.set(value);
+        }
+    }
+
+    pub fn ramp(&mut self, target: f64, duration: usize) {
+        for ramp in self.ramps.iter_mut() {
+            ramp.ramp(target, duration);
+        }
+    }
+
+    // when linked this ramps every channel
+    pub fn ramp_channel(&mut self, channel: usize, target: f64, duration: usize) {
+        if self.linked {
+            self.ramp(target, duration);
+        } else if let Some(ramp) = self.ramps.get_mut(channel) {
+            ramp.ramp(target, duration);
+        }
+    }
+
+    // linking snaps every channel to where the first one is
+    pub fn set_linked(&mut self, linked: bool) {
+        if linked && !self.linked {
+            let first = self.ramps[0];
+            self.ramps.fill(first);
+        }
+        self.linked = linked;
+    }
+
+    pub fn is_linked(&self) -> bool {
+        self.linked
+    }
+
+    pub fn tick(&mut self) -> [f32; MAX_CHANNELS] {
+        if self.linked {
+            let level = self.ramps[0].tick() as f32;
+            return [level; MAX_CHANNELS];
+        }
+        let mut levels = [0.0; MAX_CHANNELS];
+        for (level, ramp) in levels.iter_mut().zip(self.ramps.iter_mut()) {
+            *level = ramp.tick() as f32;
+        }
+        levels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dry_ramps_linked() {
+        let mut ramps = DryRamps::new(1.0);
+        ramps.ramp_channel(1, 0.0, 1);
+        assert_eq!(ramps.tick(), [0.5; MAX_CHANNELS]);
+        assert_eq!(ramps.tick(), [0.0; MAX_CHANNELS]);
+    }
+
+    #[test]
+    fn test_dry_ramps_independent() {
+        let mut ramps = DryRamps::new(1.0);
+        ramps.set_linked(false);
+        ramps.ramp_channel(1, 0.0, 1);
+        assert_eq!(ramps.tick()[..2], [1.0, 0.5]);
+        ramps.ramp_channel(0, 0.0, 0);
+        assert_eq!(ramps.tick()[..2], [0.0, 0.0]);
+        // out of range channels are ignored
+        ramps.ramp_channel(MAX_CHANNELS, 1.0, 0);
+
+        ramps.ramp_channel(0, 1.0, 3);
+        ramps.tick();
+        ramps.set_linked(true);
+        assert_eq!(ramps.tick(), [0.5; MAX_CHANNELS]);
+    }
+}
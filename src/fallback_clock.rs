@@ -0,0 +1,55 @@
+// keeps a beat position going for hosts that don't report one
+// follows the host position whenever there is one, and carries on from it when there isn't
+pub struct FallbackClock {
+    beat_time: f64,
+}
+
+#[allow(dead_code)]
+impl FallbackClock {
+    pub fn new() -> FallbackClock {
+        FallbackClock { beat_time: 0.0 }
+    }
+
+    pub fn reset(&mut self) {
+        self.beat_time = 0.0;
+    }
+
+    // the beat time at the start of this block, and moves on to the start of the next one
+    pub fn next_block(
+        &mut self,
+        host_beat_time: Option<f64>,
+        tempo: f32,
+        sample_rate: f32,
+        num_samples: usize,
+    ) -> f64 {
+        let beat_time = host_beat_time.unwrap_or(self.beat_time);
+        self.beat_time = beat_time + num_samples as f64 * tempo as f64 / 60.0 / sample_rate as f64;
+        beat_time
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fallback_clock_free_runs() {
+        let mut clock = FallbackClock::new();
+        // two beats per second, so ten samples is two beats
+        assert_eq!(clock.next_block(None, 120.0, 10.0, 10), 0.0);
+        assert_eq!(clock.next_block(None, 120.0, 10.0, 5), 2.0);
+        assert_eq!(clock.next_block(None, 60.0, 10.0, 10), 3.0);
+        assert_eq!(clock.next_block(None, 60.0, 10.0, 10), 4.0);
+    }
+
+    #[test]
+    fn test_fallback_clock_follows_host() {
+        let mut clock = FallbackClock::new();
+        assert_eq!(clock.next_block(Some(8.0), 120.0, 10.0, 10), 8.0);
+        // carries on from the host position when it goes away
+        assert_eq!(clock.next_block(None, 120.0, 10.0, 10), 10.0);
+
+        clock.reset();
+        assert_eq!(clock.next_block(None, 120.0, 10.0, 10), 0.0);
+    }
+}
@@ -0,0 +1,126 @@
+use crate::audio_frame::AudioFrame;
+use std::array;
+use std::ops::{Add, AddAssign, Index, IndexMut, Mul, Sub};
+
+// a sample for each of N channels, for looping more than stereo
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frame<const N: usize>(pub [f32; N]);
+
+impl<const N: usize> Default for Frame<N> {
+    fn default() -> Frame<N> {
+        Frame([0.0; N])
+    }
+}
+
+impl<const N: usize> Add for Frame<N> {
+    type Output = Self;
+
+    fn add(self, other: Frame<N>) -> Frame<N> {
+        Frame(array::from_fn(|i| self.0[i] + other.0[i]))
+    }
+}
+
+impl<const N: usize> Sub for Frame<N> {
+    type Output = Self;
+
+    fn sub(self, other: Frame<N>) -> Frame<N> {
+        Frame(array::from_fn(|i| self.0[i] - other.0[i]))
+    }
+}
+
+impl<const N: usize> Mul for Frame<N> {
+    type Output = Self;
+
+    fn mul(self, other: Frame<N>) -> Frame<N> {
+        Frame(array::from_fn(|i| self.0[i] * other.0[i]))
+    }
+}
+
+impl<const N: usize> Mul<f32> for Frame<N> {
+    type Output = Self;
+
+    fn mul(self, other: f32) -> Frame<N> {
+        Frame(self.0.map(|x| x * other))
+    }
+}
+
+impl<const N: usize> AddAssign for Frame<N> {
+    fn add_assign(&mut self, other: Frame<N>) {
+        for (a, b) in self.0.iter_mut().zip(other.0.iter()) {
+            *a += *b;
+        }
+    }
+}
+
+impl<const N: usize> Index<usize> for Frame<N> {
+    type Output = f32;
+
+    fn index(&self, index: usize) -> &f32 {
+        &self.0[index]
+    }
+}
+
+impl<const N: usize> IndexMut<usize> for Frame<N> {
+    fn index_mut(&mut self, index: usize) -> &mut f32 {
+        &mut self.0[index]
+    }
+}
+
+// a mono host channel is spread to all channels and mixed back down,
+// otherwise channels map one to one, with missing ones silent
+impl<const N: usize> AudioFrame for Frame<N> {
+    const NUM_CHANNELS: usize = N;
+
+    fn from_channels(channels: &[f32]) -> Frame<N> {
+        match channels {
+            [mono] => Frame([*mono; N]),
+            _ => Frame(array::from_fn(|i| channels.get(i).copied().unwrap_or(0.0))),
+        }
+    }
+
+    fn write_channels(&self, channels: &mut [f32]) {
+        match channels {
+            [mono] => *mono = self.0.iter().sum::<f32>() / N as f32,
+            _ => {
+                for (i, channel) in channels.iter_mut().enumerate() {
+                    *channel = self.0.get(i).copied().unwrap_or(0.0);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_ops() {
+        let a = Frame([1.0, 2.0, 3.0]);
+        let b = Frame([0.5, 0.5, 0.5]);
+        assert_eq!(a + b, Frame([1.5, 2.5, 3.5]));
+        assert_eq!(a - b, Frame([0.5, 1.5, 2.5]));
+        assert_eq!(a * b, Frame([0.5, 1.0, 1.5]));
+        assert_eq!(a * 2.0, Frame([2.0, 4.0, 6.0]));
+        let mut c = a;
+        c += b;
+        assert_eq!(c[2], 3.5);
+    }
+
+    #[test]
+    fn test_frame_channels() {
+        // quad host into a six channel frame
+        let frame = Frame::<6>::from_channels(&[1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(frame, Frame([1.0, 2.0, 3.0, 4.0, 0.0, 0.0]));
+        let mut channels = [0.0; 4];
+        frame.write_channels(&mut channels);
+        assert_eq!(channels, [1.0, 2.0, 3.0, 4.0]);
+
+        // mono host
+        let frame = Frame::<4>::from_channels(&[0.5]);
+        assert_eq!(frame, Frame([0.5; 4]));
+        let mut channels = [0.0];
+        frame.write_channels(&mut channels);
+        assert_eq!(channels, [0.5]);
+    }
+}
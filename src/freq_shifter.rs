@@ -0,0 +1,164 @@
+use crate::stereo_pair::StereoPair;
+use crate::wet_effect::WetEffect;
+
+// two chains of allpasses with a 90 degree phase difference over most of the audio band
+// coefficients from Olli Niemitalo's hilbert transformer design
+const HILBERT_COEFFS_I: [f32; 4] = [0.6923878, 0.9360654, 0.9882295, 0.9987488];
+const HILBERT_COEFFS_Q: [f32; 4] = [0.4021921, 0.8561711, 0.972291, 0.9952885];
+
+// second order allpasses in z^-2, out = a^2 * (in + out[n-2]) - in[n-2]
+#[derive(Clone, Copy, Default)]
+struct AllpassChain {
+    coeffs: [f32; 4],
+    x1: [f32; 4],
+    x2: [f32; 4],
+    y1: [f32; 4],
+    y2: [f32; 4],
+}
+
+impl AllpassChain {
+    fn new(coeffs: [f32; 4]) -> AllpassChain {
+        AllpassChain {
+            coeffs: coeffs.map(|a| a * a),
+            ..Default::default()
+        }
+    }
+
+    fn reset(&mut self) {
+        *self = AllpassChain {
+            coeffs: self.coeffs,
+            ..Default::default()
+        };
+    }
+
+    fn process(&mut self, input: f32) -> f32 {
+        let mut x = input;
+        for i in 0..4 {
+            let y = self.coeffs[i] * (x + self.y2[i]) - self.x2[i];
+            self.x2[i] = self.x1[i];
+            self.x1[i] = x;
+            self.y2[i] = self.y1[i];
+            self.y1[i] = y;
+            x = y;
+        }
+        x
+    }
+}
+
+// splits a signal into two signals 90 degrees apart
+#[derive(Clone, Copy)]
+struct Hilbert {
+    chain_i: AllpassChain,
+    chain_q: AllpassChain,
+    // the i path is delayed by a sample
+    last_i: f32,
+}
+
+impl Hilbert {
+    fn new() -> Hilbert {
+        Hilbert {
+            chain_i: AllpassChain::new(HILBERT_COEFFS_I),
+            chain_q: AllpassChain::new(HILBERT_COEFFS_Q),
+            last_i: 0.0,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.chain_i.reset();
+        self.chain_q.reset();
+        self.last_i = 0.0;
+    }
+
+    fn process(&mut self, input: f32) -> (f32, f32) {
+        let i = std::mem::replace(&mut self.last_i, self.chain_i.process(input));
+        let q = self.chain_q.process(input);
+        (i, q)
+    }
+}
+
+// moves every frequency in the input up by the same amount, which unlike pitch shifting
+// breaks up harmonic relationships, for metallic and detuned flavours
+pub struct FreqShifter {
+    sample_rate: f32,
+    shift_hz: f32,
+    phase: f32,
+    left: Hilbert,
+    right: Hilbert,
+}
+
+#[allow(dead_code)]
+impl FreqShifter {
+    pub fn new(sample_rate: f32) -> FreqShifter {
+        FreqShifter {
+            sample_rate,
+            shift_hz: 100.0,
+            phase: 0.0,
+            left: Hilbert::new(),
+            right: Hilbert::new(),
+        }
+    }
+}
+
+impl WetEffect for FreqShifter {
+    fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+    }
+
+    fn set_frequency(&mut self, frequency_hz: f32) {
+        self.shift_hz = frequency_hz;
+    }
+
+    fn reset(&mut self) {
+        self.phase = 0.0;
+        self.left.reset();
+        self.right.reset();
+    }
+
+    fn process(&mut self, input: StereoPair<f32>, _beat_time: f64) -> StereoPair<f32> {
+        let angle = self.phase * std::f32::consts::TAU;
+        let (sin, cos) = angle.sin_cos();
+        self.phase = (self.phase + self.shift_hz / self.sample_rate).fract();
+
+        let (left_i, left_q) = self.left.process(input.left);
+        let (right_i, right_q) = self.right.process(input.right);
+        StereoPair::new(left_i * cos - left_q * sin, right_i * cos - right_q * sin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hilbert_quadrature() {
+        // for a sine in the audio band the two outputs have the same level and are 90 degrees apart,
+        // so together they have a flat envelope
+        let sample_rate = 44100.0;
+        let mut hilbert = Hilbert::new();
+        for n in 0..4000 {
+            let x = (n as f32 * 1000.0 / sample_rate * std::f32::consts::TAU).sin();
+            let (i, q) = hilbert.process(x);
+            if n > 2000 {
+                assert!(((i * i + q * q).sqrt() - 1.0).abs() < 0.01);
+            }
+        }
+    }
+
+    #[test]
+    fn test_freq_shifter_level() {
+        // shifting a sine keeps its level
+        let sample_rate = 44100.0;
+        let mut shifter = FreqShifter::new(sample_rate);
+        shifter.set_frequency(250.0);
+        let mut peak: f32 = 0.0;
+        for n in 0..4000 {
+            let x = (n as f32 * 1000.0 / sample_rate * std::f32::consts::TAU).sin();
+            let out = shifter.process(StereoPair::new(x, x), 0.0);
+            if n > 2000 {
+                peak = peak.max(out.left.abs());
+                assert_eq!(out.left, out.right);
+            }
+        }
+        assert!((peak - 1.0).abs() < 0.02);
+    }
+}
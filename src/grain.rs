@@ -1,19 +1,41 @@
-use crate::ramped_value::RampedValue;
+use crate::ramped_value::{FadeShape, RampedValue};
 
 // Q: it would be nice if we could support the cases where fractional delays make sense
 // and when it doesn't
 
+// what an editor can draw of a grain as it plays, or a test can check, without its insides
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GrainView {
+    // how far through its duration, from 0 to 1
+    pub progress: f32,
+    // the delay it reads next
+    pub read_position: f32,
+    // the level it last played at, fades, envelopes and all
+    pub window_gain: f32,
+    pub reverse: bool,
+    // how far it moves through the audio each sample, whichever way it's going
+    pub speed: f32,
+}
+
 // a rather short lived thing that plays a single faded grain
 // the duration includes two fade durations
 pub struct Grain {
-    scheduled_wait: usize,       // how long to wait before starting
-    delay_pos: f32,              // current delay position, ticks *down* to read forwards
-    duration: usize,             // how long the grain lasts in ticks
-    fade_duration: usize,        // how many samples to fade over (in and out)
-    elapsed_sample_count: usize, // how many samples have been output
-    offset: f32,                 // the initial delay time where the grain starts
-    sample_increment: f32,       // how much to increment the delay position each tick
-    fade_ramp: RampedValue,      // the fade in/out ramp
+    scheduled_wait: usize,         // how long to wait before starting
+    delay_pos: f32,                // current delay position, ticks *down* to read forwards
+    duration: usize,               // how long the grain lasts in ticks
+    fade_duration: usize,          // how many samples to fade over (in and out)
+    fade_in_duration: usize,       // the fade in alone, no longer than the fade
+    elapsed_sample_count: usize,   // how many samples have been output
+    offset: f32,                   // the initial delay time where the grain starts
+    sample_increment: f32,         // how much to increment the delay position each tick
+    fade_ramp: RampedValue,        // the fade in/out ramp
+    gain: RampedValue,             // on top of the fade, settles back to unity from where it's set
+    speed_ramp: RampedValue,       // scales the increment, and the level along with it
+    fade_shape: FadeShape,         // the law the fades in and out follow
+    envelope: RampedValue,         // the loop's attack and decay, across the whole grain
+    attack_duration: usize,        // how long the envelope takes to rise
+    decay_duration: Option<usize>, // how long it then takes to die away, none holds it
+    pan: f32,                      // where the grain sits between left (-1) and right (1)
 }
 
 #[allow(dead_code)]
@@ -21,7 +43,8 @@ impl Grain {
     // offset: the initial delay time where the grain starts
     // duration: how long the grain lasts
     // fade: number of samples to fade in and out (this is within the duration above)
-    // speed: how fast to play the grain, 1 is normal, 0.5 is half speed
+    // speed: how fast to play the grain, 1 is normal, 0.5 is half speed or an octave down
+    // the duration stays as given whatever the speed, so a repitched grain still fills its slot
     pub fn new(
         scheduled_wait: usize,
         offset: f32,
@@ -61,10 +84,18 @@ impl Grain {
             delay_pos: start_delay,
             duration: actual_duration,
             fade_duration: actual_fade,
+            fade_in_duration: actual_fade,
             elapsed_sample_count: 0,
             offset: offset,
             sample_increment: sample_increment,
             fade_ramp: RampedValue::new(1.0),
+            gain: RampedValue::new(1.0),
+            speed_ramp: RampedValue::new(1.0),
+            fade_shape: FadeShape::Linear,
+            envelope: RampedValue::new(1.0),
+            attack_duration: 0,
+            decay_duration: None,
+            pan: 0.0,
         }
     }
 
@@ -81,20 +112,81 @@ impl Grain {
 
         if self.elapsed_sample_count == 0 && self.scheduled_wait == 0 {
             self.fade_ramp.set(0.0);
-            self.fade_ramp.ramp(1.0, self.fade_duration);
+            self.fade_ramp.ramp(1.0, self.fade_in_duration);
         } else if self.elapsed_sample_count == (self.duration - self.fade_duration) {
             self.fade_ramp.set(1.0);
             self.fade_ramp.ramp(0.0, self.fade_duration);
         }
+        // once the attack is over
+        if self.elapsed_sample_count == self.attack_duration + 1 {
+            if let Some(decay) = self.decay_duration {
+                self.envelope.ramp(0.0, decay);
+            }
+        }
 
         let return_delay = self.delay_pos;
-        self.delay_pos = self.delay_pos - self.sample_increment;
+        let speed = self.speed_ramp.tick();
+        self.delay_pos -= self.sample_increment * speed as f32;
         self.elapsed_sample_count = self.elapsed_sample_count + 1;
 
-        let win = self.fade_ramp.tick();
+        let fade = self.fade_shape.apply(self.fade_ramp.tick());
+        let win = fade * self.gain.tick() * self.envelope.tick() * speed;
         (return_delay, win as f32)
     }
 
+    // starts the grain at gain, which ramps back to unity over duration once the grain plays
+    pub fn set_gain_envelope(&mut self, gain: f32, duration: usize) {
+        self.gain.set(gain as f64);
+        self.gain.ramp(1.0, duration);
+    }
+
+    // rises from silence over attack as the grain starts, then dies away over decay if given
+    // on top of the fades, so a loop can pump in or die away each time it repeats
+    pub fn set_envelope(&mut self, attack: usize, decay: Option<usize>) {
+        self.attack_duration = attack;
+        self.decay_duration = decay;
+        self.envelope.set(0.0);
+        self.envelope.ramp(1.0, attack);
+    }
+
+    // varispeed, e.g. a tape stop ramping down to 0, the grain gets quieter as it slows
+    // the grain still lasts as long, it just gets less far through the audio
+    pub fn set_speed_ramp(&mut self, speed_ramp: RampedValue) {
+        self.speed_ramp = speed_ramp;
+    }
+
+    // a quicker fade in, e.g. for a loop that cuts in straight away, the fade out is kept
+    pub fn set_fade_in(&mut self, fade_in: usize) {
+        self.fade_in_duration = fade_in.min(self.fade_duration);
+    }
+
+    pub fn set_fade_shape(&mut self, fade_shape: FadeShape) {
+        self.fade_shape = fade_shape;
+    }
+
+    pub fn set_pan(&mut self, pan: f32) {
+        self.pan = pan.clamp(-1.0, 1.0);
+    }
+
+    pub fn pan(&self) -> f32 {
+        self.pan
+    }
+
+    // a steady gain on top of the fade
+    pub fn set_gain(&mut self, gain: f32) {
+        self.gain = RampedValue::new(gain as f64);
+    }
+
+    // the signed playback increment, negative when reversed
+    pub fn speed(&self) -> f32 {
+        self.sample_increment
+    }
+
+    // changes the speed part way through, keeping the direction
+    pub fn set_speed(&mut self, speed: f32) {
+        self.sample_increment = speed.copysign(self.sample_increment);
+    }
+
     pub fn stop(&mut self) {
         // if already fading out don't stop it
         if self.elapsed_sample_count > (self.duration - self.fade_duration) {
@@ -105,6 +197,42 @@ impl Grain {
         self.duration = self.elapsed_sample_count + self.fade_duration;
     }
 
+    // fades the grain out over fade from whatever level it's at, sooner than stop would
+    // for a grain giving up its voice, a grain still waiting is dropped as it hasn't been heard
+    pub fn fade_out_quickly(&mut self, fade: usize) {
+        if self.is_waiting() {
+            self.duration = 0;
+            return;
+        }
+        // already ending sooner
+        if self.duration - self.elapsed_sample_count <= fade + 1 {
+            return;
+        }
+        // the gain reaches silence on the last sample, and the fade out is never started
+        self.gain.ramp(0.0, fade);
+        self.duration = self.elapsed_sample_count + fade + 1;
+        self.fade_duration = 0;
+    }
+
+    // the window gain tick last returned, on the way to picking the quietest grain
+    pub fn level(&self) -> f32 {
+        if self.is_waiting() || self.elapsed_sample_count == 0 {
+            return 0.0;
+        }
+        let fade = self.fade_shape.apply(self.fade_ramp.value());
+        (fade * self.gain.value() * self.envelope.value() * self.speed_ramp.value()) as f32
+    }
+
+    pub fn view(&self) -> GrainView {
+        GrainView {
+            progress: self.elapsed_sample_count as f32 / self.duration.max(1) as f32,
+            read_position: self.delay_pos,
+            window_gain: self.level(),
+            reverse: self.sample_increment < 0.0,
+            speed: self.sample_increment.abs() * self.speed_ramp.value() as f32,
+        }
+    }
+
     pub fn is_finished(&self) -> bool {
         return self.elapsed_sample_count == self.duration || self.duration == 0;
     }
@@ -118,7 +246,7 @@ impl Grain {
     }
 
     pub fn is_fading_in(&self) -> bool {
-        return self.elapsed_sample_count < self.fade_duration;
+        return self.elapsed_sample_count < self.fade_in_duration;
     }
     pub fn is_fading_out(&self) -> bool {
         return self.elapsed_sample_count > (self.duration - self.fade_duration);
@@ -132,11 +260,19 @@ impl Grain {
     pub fn duration(&self) -> usize {
         return self.duration;
     }
+    pub fn scheduled_wait(&self) -> usize {
+        self.scheduled_wait
+    }
+    // the delay the grain reads next
+    pub fn delay_pos(&self) -> f32 {
+        self.delay_pos
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_utils::all_near;
 
     #[test]
     fn test_grain() {
@@ -207,6 +343,27 @@ mod tests {
         assert_eq!(out, expected);
     }
 
+    #[test]
+    fn test_grain_gain_envelope() {
+        let mut grain = Grain::new(0, 10.0, 6, 0, false, 1.0);
+        grain.set_gain_envelope(2.0, 3);
+
+        let expected = vec![
+            (9.0, 1.75),
+            (8.0, 1.5),
+            (7.0, 1.25),
+            (6.0, 1.0),
+            (5.0, 1.0),
+            (4.0, 1.0),
+        ];
+        let mut out = vec![];
+        for _i in 0..expected.len() {
+            out.push(grain.tick());
+        }
+
+        assert_eq!(out, expected);
+    }
+
     #[test]
     fn test_grain_stop() {
         let mut grain = Grain::new(0, 20.0, 15, 3, false, 1.0);
@@ -244,6 +401,53 @@ mod tests {
         assert!(grain.is_finished());
     }
 
+    #[test]
+    fn test_grain_view() {
+        let mut grain = Grain::new(0, 10.0, 4, 0, true, 0.5);
+        grain.set_gain(0.5);
+        grain.tick();
+        grain.tick();
+        assert_eq!(
+            grain.view(),
+            GrainView {
+                progress: 0.5,
+                read_position: 7.0,
+                window_gain: 0.5,
+                reverse: true,
+                speed: 0.5,
+            }
+        );
+    }
+
+    #[test]
+    fn test_grain_fade_out_quickly() {
+        let mut grain = Grain::new(0, 20.0, 15, 3, false, 1.0);
+        assert_eq!(grain.level(), 0.0);
+        grain.tick();
+        grain.tick();
+        assert_eq!(grain.level(), 0.5);
+
+        // from part way through the fade in, without jumping back up
+        grain.fade_out_quickly(3);
+        let out: Vec<(f32, f32)> = (0..5).map(|_| grain.tick()).collect();
+        assert_eq!(
+            out,
+            vec![
+                (17.0, 0.5625),
+                (16.0, 0.5),
+                (15.0, 0.25),
+                (14.0, 0.0),
+                (0.0, 0.0)
+            ]
+        );
+        assert!(grain.is_finished());
+
+        // a grain still waiting just goes
+        let mut grain = Grain::new(4, 20.0, 15, 3, false, 1.0);
+        grain.fade_out_quickly(3);
+        assert!(grain.is_finished());
+    }
+
     #[test]
     fn test_grain_reverse() {
         let mut grain = Grain::new(0, 10.0, 5, 0, true, 1.0);
@@ -292,6 +496,54 @@ mod tests {
         assert_eq!(out, expected);
     }
 
+    #[test]
+    fn test_grain_speed_ramp() {
+        let mut grain = Grain::new(0, 10.0, 6, 0, false, 1.0);
+        let mut speed_ramp = RampedValue::new(1.0);
+        speed_ramp.ramp(0.0, 3);
+        grain.set_speed_ramp(speed_ramp);
+        let out: Vec<(f32, f32)> = (0..6).map(|_| grain.tick()).collect();
+        // slowing to a stop over four samples, and fading with it
+        assert_eq!(
+            out,
+            vec![
+                (9.0, 0.75),
+                (8.25, 0.5),
+                (7.75, 0.25),
+                (7.5, 0.0),
+                (7.5, 0.0),
+                (7.5, 0.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_grain_fade_shape() {
+        let mut grain = Grain::new(0, 10.0, 6, 2, false, 1.0);
+        grain.set_fade_shape(FadeShape::RaisedCosine);
+        let window: Vec<f32> = (0..6).map(|_| grain.tick().1).collect();
+        // the linear fade is in thirds, shaped it starts and ends more gently
+        all_near(&window, &vec![0.25, 0.75, 1.0, 1.0, 0.75, 0.25], 0.0001);
+    }
+
+    #[test]
+    fn test_grain_envelope() {
+        let mut grain = Grain::new(0, 10.0, 8, 0, false, 1.0);
+        grain.set_envelope(2, Some(3));
+        let window: Vec<f32> = (0..8).map(|_| grain.tick().1).collect();
+        all_near(
+            &window,
+            &vec![1.0 / 3.0, 2.0 / 3.0, 1.0, 0.75, 0.5, 0.25, 0.0, 0.0],
+            0.0001,
+        );
+
+        // without a decay the envelope holds once it is up
+        let mut grain = Grain::new(0, 10.0, 4, 0, false, 1.0);
+        grain.set_envelope(1, None);
+        let window: Vec<f32> = (0..4).map(|_| grain.tick().1).collect();
+        all_near(&window, &vec![0.5, 1.0, 1.0, 1.0], 0.0001);
+    }
+
     #[test]
     fn test_grain_half_speed() {
         let mut grain = Grain::new(0, 10.0, 5, 0, false, 0.5);
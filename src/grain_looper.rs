@@ -1,33 +1,215 @@
-use crate::grain::Grain;
-use crate::grain_player::GrainPlayer;
+use crate::audio_frame::{AudioFrame, MAX_CHANNELS};
+use crate::dry_ramps::DryRamps;
+use crate::grain::{Grain, GrainView};
+use crate::grain_player::{GrainPlayer, VoiceStealing};
+use crate::groove::GrooveTemplate;
+use crate::length_pattern::LengthPattern;
+use crate::lookahead_queue::LookaheadQueue;
 use crate::loop_scheduler::LoopEvent;
-use crate::loop_scheduler::LoopScheduler;
-use crate::ramped_value::RampedValue;
-use crate::stereo_pair::AudioSampleOps;
+use crate::loop_scheduler::{LoopScheduler, Stutter, MAX_LOOP_EVENTS};
+use crate::process_context::ProcessContextLite;
+use crate::ramped_value::{FadeShape, RampedValue};
+use crate::random::Random;
+use crate::region_fit::{fit_grain, RegionFit, RegionPolicy};
+use crate::sections::NUM_SECTIONS;
+use crate::stretch::{Stretch, StretchSettings};
+use crate::waveform::WaveformPoint;
+use nih_plug::prelude::Enum;
+
+// how far back we allow to scrub through, what 100000 samples hold at 48k
+// the buffers are sized for this at the sample rate, so it is as long at any rate
+pub const HISTORY_SECONDS: f32 = 100000.0 / 48000.0;
+// which way each new grain plays
+#[derive(Enum, Debug, Clone, Copy, PartialEq)]
+pub enum ReverseMode {
+    #[id = "forward"]
+    #[name = "Forward"]
+    Forward,
+    #[id = "reverse"]
+    #[name = "Reverse"]
+    Reverse,
+    // forwards, then backwards, then forwards again
+    #[id = "alternate"]
+    #[name = "Alternate"]
+    Alternate,
+    // the whole loop read back to front, rather than each grain, so the stutters and legato
+    // grains that start part way through the loop play the matching part from its end
+    #[id = "reverse-whole"]
+    #[name = "Reverse Whole"]
+    ReverseWhole,
+}
+
+// so the jitter runs the same way each time the looper is reset
+const JITTER_SEED: u32 = 0x5eed;
+// the longest fade is this share of the history, long enough for ambient swells
+const MAX_FADE_HISTORY_FRACTION: f32 = 0.25;
+// the top of the fade params' range, which set_fade_time turns into exactly max_fade_samples
+pub const MAX_FADE_SECONDS: f32 = HISTORY_SECONDS * MAX_FADE_HISTORY_FRACTION;
+// how much dry comes in under a length change at full smoothness
+const MAX_TRANSITION_DRY_BLEND: f32 = 0.5;
+// what a grain after a change of speed or direction starts at, as the crossfade into it
+// mixes unrelated audio, which sums about 3dB quieter than the audio either side of it
+const SPEED_CHANGE_GAIN: f32 = std::f32::consts::SQRT_2;
+// a 64th note, any shorter and automation glitches could have a loop start every few samples
+const DEFAULT_MIN_GRID_BEATS: f32 = 1.0 / 16.0;
+// how many resolved events can be waiting between the scheduler and the player, as the
+// groove pushes them late, on top of those resolved ahead of time
+const LOOKAHEAD_QUEUE_CAPACITY: usize = 32;
+// how much of a block is mixed back together at a time, when the loop and the dry are summed
+const SUMMED_CHUNK: usize = 64;
+// how long a change in the dry/wet mix takes, to avoid zipper noise
+const MIX_RAMP_SECONDS: f32 = 0.01;
+// how long soloing the loop takes to kick in and let go
+const SOLO_RAMP_SECONDS: f32 = 0.01;
+// how quickly an instant capture cuts in, just enough to avoid a click
+const INSTANT_FADE_SECONDS: f32 = 0.002;
+// when free running, the scheduler clock counts seconds, which is the same as beats at 60 bpm
+const FREE_RUNNING_TEMPO: f32 = 60.0;
+
+// a loop event that has been resolved into sample domain actions,
+// so it can be applied later without looking at the looper settings again
+enum ResolvedEvent {
+    StartGrain(Grain, RegionFit),
+    // a grain played as the loop stops, which doesn't keep it looping
+    StartTail(Grain),
+    SkipGrain,
+    StopGrains,
+    RampDry { target: f64, duration: usize },
+    EndTail,
+}
+
+// timing changes waiting for the next loop to start, when changes are quantized
+// a section change always waits, quantized or not
+#[derive(Default, Clone, Copy)]
+struct StagedChanges {
+    grid: Option<f32>,
+    loop_offset: Option<f32>,
+    reverse: Option<bool>,
+    section: Option<Option<usize>>,
+}
 
-// how much of the buffer we allow to scrub through
-// TODO set these to be seconds
-const LOOPABLE_REGION_LENGTH: usize = 100000;
-const MAX_FADE_TIME_SAMPLES: usize = 10000;
-const MAX_LOOP_LENGTH: usize = LOOPABLE_REGION_LENGTH / 2;
+impl StagedChanges {
+    fn is_empty(&self) -> bool {
+        self.grid.is_none()
+            && self.loop_offset.is_none()
+            && self.reverse.is_none()
+            && self.section.is_none()
+    }
+}
 
 // uses a grain player to create loops
 // owns two delay lines, one continously being
 // written to by the input, one that is outputting loop
 // when a new loop is started, the output delay line is
 // copied to the input delay line
-pub struct GrainLooper<T: AudioSampleOps> {
+pub struct GrainLooper<T: AudioFrame> {
     grain_player: GrainPlayer<T>,
     loop_scheduler: LoopScheduler,
     is_looping: bool,
     sample_rate: f32,
 
     loop_offset_beats: f32,
+    // the fade asked for, and the fade used after cutting it to the grid
+    requested_fade_samples: usize,
     fade_duration_samples: usize,
-    dry_ramp: RampedValue,
+    // the dry's own fade in and out, none to fade with the loop
+    requested_dry_fade_samples: Option<usize>,
+    // the static buffer's margin is sized for fades up to this
+    max_fade_samples: usize,
+    // length changes get their own fade, and a bit of dry under them when smooth enough
+    transition_smoothness: f32,
+    transition_fade_samples: usize,
+    transition_dry_blend: f32,
+    // in clock units, the grid is never shorter than this
+    min_grid: f32,
+    // what the loop waits for to start, see set_launch_quantization
+    launch_quantization: Option<f32>,
+    // when set the loop starts the moment it is turned on, with a quick fade
+    instant_capture: bool,
+    // the loop starting was captured instantly, so it cuts in over the quick fade
+    instant_start: bool,
+    dry_ramps: DryRamps,
     reverse: bool,
+    // ping-pong, each new loop plays the other way to the one before
+    alternate: bool,
+    alternate_flipped: bool,
+    // a reversed grain plays from where it would be with the loop mirrored, see ReverseMode
+    reverse_whole: bool,
+    reverse_compensation: bool,
     speed: f32,
+    // the speed glides to this, carrying the grains playing with it
+    speed_target: f32,
+    speed_glide: RampedValue,
+    speed_glide_samples: usize,
+    pitch_ratio: f32,
+    time_stretch: Option<StretchSettings>,
+    // lifts the level through the crossfade when the speed or direction changes
+    speed_gain_compensation: bool,
+    // the signed speed of the last grain started, none before the first of a loop
+    last_grain_speed: Option<f32>,
+    // the loop slows to a stop while set, and speeds back up when cleared
+    tape_stopped: bool,
+    tape_stop_samples: usize,
+    // the law of the grains' fades, and of the dry's as it crosses over with them
+    fade_shape: FadeShape,
+    // how far each new grain's offset can wander, as a share of the loop length
+    offset_jitter: f32,
+    // how far either side of the centre each repeat can be panned, at random
+    stereo_spread: f32,
+    // the pan of the repeat playing, which its legato grains and tail carry on at
+    grain_pan: f32,
+    random: Random,
+    // each repeat rises over the attack and dies away over the decay, as shares of the loop
+    // no decay holds the repeat once it is up
+    loop_attack: f32,
+    loop_decay: Option<f32>,
+    // how loud each repeat is against the one before, 1 repeats forever
+    feedback: f32,
+    // the level of the pass playing, none before the first of a loop
+    pass_level: Option<f32>,
+    feedback_ramp: RampedValue,
     tempo: f32,
+    // when false the loop ignores the host tempo and position and runs on its own clock in seconds
+    sync: bool,
+    free_running_time: f64,
+    realign_pending: bool,
+    // 0 is all dry, 1 is all loop when looping
+    mix: f32,
+    mix_ramp: RampedValue,
+    // how much of the dry is left under the loop, 0 fades it out entirely
+    dry_duck_level: f32,
+    dry_duck_ramp: RampedValue,
+    // mutes the dry and brings the loop up to unity on top of the mix, 1 is fully soloed
+    solo: bool,
+    solo_ramp: RampedValue,
+
+    // events from the loop scheduler are resolved this many samples before they are due
+    lookahead_samples: usize,
+    resolved_events: LookaheadQueue<ResolvedEvent>,
+    // an event was dropped for want of room in the queue
+    queue_overflowed: bool,
+
+    // when set, length, offset and direction changes wait for the next loop to start
+    quantize_changes: bool,
+    staged: StagedChanges,
+
+    // what to do with grains that would read outside the buffers, and what happened to the last one
+    region_policy: RegionPolicy,
+    region_fit: RegionFit,
+    // how late live playing reaches us, so how much further back the loop reads
+    capture_latency: usize,
+
+    // pushes loop events on some 16ths late, scaled by the amount
+    groove: GrooveTemplate,
+    groove_amount: f32,
+
+    // the audio stored for each section, allocated up front
+    section_regions: Vec<Vec<T>>,
+    // the section the loop plays instead of what it captured
+    section: Option<usize>,
+    // audio from elsewhere, played by the next loop to start rather than capturing
+    imported_region: Vec<T>,
+    import_pending: bool,
 }
 
 pub fn seconds_to_beats(seconds: f32, tempo: f32) -> f32 {
@@ -42,6 +224,11 @@ pub fn seconds_to_samples(seconds: f32, sample_rate: f32) -> usize {
     (seconds * sample_rate) as usize
 }
 
+// how many samples of history the buffers hold at the sample rate
+pub fn loopable_region_length(sample_rate: f32) -> usize {
+    seconds_to_samples(HISTORY_SECONDS, sample_rate)
+}
+
 pub fn samples_to_beats(samples: usize, tempo: f32, sample_rate: f32) -> f32 {
     samples as f32 / sample_rate * tempo / 60.0
 }
@@ -50,16 +237,32 @@ pub fn beats_to_samples(beats: f32, tempo: f32, sample_rate: f32) -> f32 {
     beats * 60.0 / tempo * sample_rate
 }
 
+// returns the (wet, dry) gains of an equal power crossfade
+pub fn equal_power_gains(mix: f32) -> (f32, f32) {
+    // keep the ends exact so a full mix is bit identical to no mix at all
+    if mix >= 1.0 {
+        return (1.0, 0.0);
+    }
+    if mix <= 0.0 {
+        return (0.0, 1.0);
+    }
+    let angle = mix * std::f32::consts::FRAC_PI_2;
+    (angle.sin(), angle.cos())
+}
+
 // Loops segments of audio, with the ability to scrub through the loop
 // sets loop offset and duration in seconds
 #[allow(dead_code)]
-impl<T: AudioSampleOps> GrainLooper<T> {
+impl<T: AudioFrame> GrainLooper<T> {
+    // allocates the buffers for the sample rate, so isn't for the audio thread
+    // a loop can be up to half the history long
     pub fn new(sample_rate: f32) -> GrainLooper<T> {
+        let region_length = loopable_region_length(sample_rate);
         GrainLooper::new_with_length(
             sample_rate,
-            LOOPABLE_REGION_LENGTH,
-            MAX_FADE_TIME_SAMPLES,
-            MAX_LOOP_LENGTH,
+            region_length,
+            (region_length as f32 * MAX_FADE_HISTORY_FRACTION) as usize,
+            region_length / 2,
         )
     }
 
@@ -69,425 +272,2428 @@ impl<T: AudioSampleOps> GrainLooper<T> {
         max_fade_time: usize,
         max_loop_length: usize,
     ) -> GrainLooper<T> {
+        let grain_player =
+            GrainPlayer::new_with_length(loopable_region_length, max_fade_time, max_loop_length);
+        let section_regions = (0..NUM_SECTIONS)
+            .map(|_| vec![T::default(); grain_player.region_length()])
+            .collect();
+        let imported_region = vec![T::default(); grain_player.region_length()];
         GrainLooper {
-            grain_player: GrainPlayer::new_with_length(
-                loopable_region_length,
-                max_fade_time,
-                max_loop_length,
-            ),
+            grain_player,
             loop_scheduler: LoopScheduler::new(),
             is_looping: false,
             sample_rate,
 
             loop_offset_beats: 0.0,
+            requested_fade_samples: 0,
             fade_duration_samples: 0,
-
-            dry_ramp: RampedValue::new(1.0),
+            requested_dry_fade_samples: None,
+            max_fade_samples: max_fade_time,
+            transition_smoothness: 0.5,
+            transition_fade_samples: 0,
+            transition_dry_blend: 0.0,
+            min_grid: DEFAULT_MIN_GRID_BEATS,
+            launch_quantization: None,
+            instant_capture: false,
+            instant_start: false,
+
+            dry_ramps: DryRamps::new(1.0),
             reverse: false,
+            alternate: false,
+            alternate_flipped: false,
+            reverse_whole: false,
+            reverse_compensation: false,
             speed: 1.0,
+            speed_target: 1.0,
+            speed_glide: RampedValue::new(1.0),
+            speed_glide_samples: 0,
+            pitch_ratio: 1.0,
+            time_stretch: None,
+            speed_gain_compensation: false,
+            last_grain_speed: None,
+            tape_stopped: false,
+            tape_stop_samples: 0,
+            fade_shape: FadeShape::Linear,
+            offset_jitter: 0.0,
+            stereo_spread: 0.0,
+            grain_pan: 0.0,
+            random: Random::new(JITTER_SEED),
+            loop_attack: 0.0,
+            loop_decay: None,
+            feedback: 1.0,
+            pass_level: None,
+            feedback_ramp: RampedValue::new(1.0),
             tempo: 120.0,
+            sync: true,
+            free_running_time: 0.0,
+            realign_pending: false,
+            mix: 1.0,
+            mix_ramp: RampedValue::new(1.0),
+            dry_duck_level: 0.0,
+            dry_duck_ramp: RampedValue::new(0.0),
+            solo: false,
+            solo_ramp: RampedValue::new(0.0),
+
+            lookahead_samples: 0,
+            resolved_events: LookaheadQueue::new(LOOKAHEAD_QUEUE_CAPACITY),
+            queue_overflowed: false,
+
+            quantize_changes: false,
+            staged: StagedChanges::default(),
+
+            region_policy: RegionPolicy::MoveOffset,
+            region_fit: RegionFit::Fits,
+            capture_latency: 0,
+
+            groove: GrooveTemplate::straight(),
+            groove_amount: 1.0,
+
+            section_regions,
+            section: None,
+            imported_region,
+            import_pending: false,
         }
     }
 
     pub fn reset(&mut self) {
         self.grain_player.reset();
         self.loop_scheduler.reset();
+        self.resolved_events.clear();
+        self.queue_overflowed = false;
         self.is_looping = false;
-        self.dry_ramp.set(1.0);
+        self.instant_start = false;
+        self.dry_ramps.set(1.0);
+        self.apply_staged_changes();
+        self.region_fit = RegionFit::Fits;
+        self.section = None;
+        self.import_pending = false;
+        self.last_grain_speed = None;
+        self.pass_level = None;
+        self.feedback_ramp.set(1.0);
+        self.alternate_flipped = false;
+        self.tape_stopped = false;
+        self.grain_pan = 0.0;
+        self.random = Random::new(JITTER_SEED);
     }
 
+    // the buffers stay the size they were made for, so they hold more or less history
+    // than HISTORY_SECONDS at another rate, make a new looper to resize them
     pub fn set_sample_rate(&mut self, sample_rate: f32) {
         self.sample_rate = sample_rate;
-        self.update_scheduler_fade();
+        self.update_fade();
     }
 
     pub fn set_tempo(&mut self, bpm: f32) {
         // since everything is scheduled in beats, we don't need to update much
         // but the offset needs to stay the same number of samples if we are looping
-        if self.is_looping {
+        // when free running the offset is in seconds so the tempo doesn't matter
+        if self.is_looping && self.sync {
             let ratio = bpm / self.tempo;
             self.loop_offset_beats *= ratio;
         }
         self.tempo = bpm;
-        self.update_scheduler_fade();
+        self.update_fade();
+    }
+
+    // follow the host tempo and position, or run on an internal clock in seconds
+    // the loop is stopped when this changes, as the scheduled events are in the old clock
+    pub fn set_sync(&mut self, sync: bool) {
+        if sync == self.sync {
+            return;
+        }
+        self.sync = sync;
+        self.free_running_time = 0.0;
+        self.stop_now();
+        self.update_fade();
+    }
+
+    // stops the loop straight away rather than on the grid, fading out the grains and
+    // bringing back the dry, and drops anything still scheduled
+    pub fn stop_now(&mut self) {
+        self.stop_now_with_dry_ramp(self.dry_fade_samples());
+    }
+
+    // as stop_now, with the dry coming back over dry_ramp_samples
+    pub fn stop_now_with_dry_ramp(&mut self, dry_ramp_samples: usize) {
+        if self.stop_grains_now() {
+            self.dry_ramps.ramp(1.0, dry_ramp_samples);
+        }
+    }
+
+    // as stop_now, but the dry stays ducked until the next bar line
+    // bar_length is in beats, or seconds when free running
+    pub fn stop_now_dry_at_bar(&mut self, bar_length: f32) {
+        if self.stop_grains_now() {
+            self.loop_scheduler.fade_in_dry_on_grid(bar_length);
+        }
+    }
+
+    // returns whether there was a loop to stop
+    fn stop_grains_now(&mut self) -> bool {
+        let was_looping = self.loop_scheduler.is_looping() || self.is_looping;
+        if was_looping {
+            self.grain_player.stop_all_grains();
+            self.grain_player.stop_looping();
+        }
+        self.loop_scheduler.reset();
+        self.resolved_events.clear();
+        self.is_looping = false;
+        was_looping
+    }
+
+    // the host position jumped, e.g. the transport restarted somewhere else
+    // the loop keeps going, moved onto the grid at the new position on the next tick
+    pub fn realign(&mut self) {
+        self.realign_pending = true;
     }
 
-    pub fn set_fade_time(&mut self, fade_beats: f32) {
-        let fade_samples = beats_to_samples(fade_beats, self.tempo, self.sample_rate) as usize;
-        debug_assert!(fade_samples <= MAX_FADE_TIME_SAMPLES);
+    pub fn is_synced(&self) -> bool {
+        self.sync
+    }
+
+    // the tempo the scheduler clock runs at
+    fn clock_tempo(&self) -> f32 {
+        if self.sync {
+            self.tempo
+        } else {
+            FREE_RUNNING_TEMPO
+        }
+    }
+
+    // converts seconds to the units of the scheduler clock, beats when synced, seconds when not
+    fn seconds_to_clock(&self, seconds: f32) -> f32 {
+        seconds_to_beats(seconds, self.clock_tempo())
+    }
+
+    fn samples_to_clock(&self, samples: usize) -> f32 {
+        samples_to_beats(samples, self.clock_tempo(), self.sample_rate)
+    }
+
+    fn clock_to_samples(&self, clock: f32) -> f32 {
+        beats_to_samples(clock, self.clock_tempo(), self.sample_rate)
+    }
+
+    // in seconds whatever the clock, as the fade params are shown in ms
+    pub fn set_fade_time(&mut self, fade_seconds: f32) {
+        // at higher sample rates the same fade param takes more samples than the buffers have room for
+        let fade_samples = seconds_to_samples(fade_seconds, self.sample_rate);
+        self.requested_fade_samples = fade_samples.min(self.max_fade_samples);
+        self.update_fade();
+    }
+
+    // how long the dry takes to go and come back, so it can switch quickly under a long crossfade
+    // in seconds like the loop's fade, none fades it with the loop
+    pub fn set_dry_fade_time(&mut self, fade_seconds: Option<f32>) {
+        self.requested_dry_fade_samples =
+            fade_seconds.map(|fade_seconds| seconds_to_samples(fade_seconds, self.sample_rate));
+    }
+
+    fn dry_fade_samples(&self) -> usize {
+        self.requested_dry_fade_samples
+            .unwrap_or(self.fade_duration_samples)
+    }
 
-        self.fade_duration_samples = fade_samples.clamp(0, MAX_FADE_TIME_SAMPLES);
-        println!("fade duration samples: {}", self.fade_duration_samples);
-        self.update_scheduler_fade();
+    // a fade longer than the grid would pile up grains faster than they finish,
+    // so it is cut to the grid and each loop only overlaps the one before it
+    fn update_fade(&mut self) {
+        let grid_samples = self.clock_to_samples(self.loop_scheduler.grid_interval()) as usize;
+        self.fade_duration_samples = self.requested_fade_samples.min(grid_samples);
+        self.loop_scheduler
+            .set_fade_lead_in(self.samples_to_clock(self.fade_duration_samples));
+
+        let transition_fade = self.fade_duration_samples as f32 * 2.0 * self.transition_smoothness;
+        self.transition_fade_samples = (transition_fade as usize)
+            .min(self.max_fade_samples)
+            .min(grid_samples);
+        self.transition_dry_blend =
+            (2.0 * self.transition_smoothness - 1.0).max(0.0) * MAX_TRANSITION_DRY_BLEND;
+        let blend_time = self.samples_to_clock(self.transition_fade_samples);
+        self.loop_scheduler
+            .set_transition_blend((self.transition_dry_blend > 0.0).then_some(blend_time));
+    }
+
+    // how length changes go over, 0 cuts hard to the new length, 0.5 overlaps the legato grain
+    // by the fade like any other loop, and past that the overlap grows and the dry blends in under it
+    pub fn set_transition_smoothness(&mut self, smoothness: f32) {
+        self.transition_smoothness = smoothness.clamp(0.0, 1.0);
+        self.update_fade();
+    }
+
+    // in beats, or seconds when free running
+    pub fn set_min_grid(&mut self, min_grid: f32) {
+        self.min_grid = min_grid;
+    }
+
+    // hold back length, offset and direction changes until the next loop starts,
+    // so tweaks while looping land on the grid
+    pub fn set_quantize_changes(&mut self, quantize_changes: bool) {
+        self.quantize_changes = quantize_changes;
+        if !quantize_changes {
+            self.apply_staged_changes();
+        }
+    }
+
+    // changes are only held back while there is a loop to wait for
+    fn should_stage_changes(&self) -> bool {
+        self.quantize_changes && self.loop_scheduler.is_looping()
+    }
+
+    fn apply_staged_changes(&mut self) {
+        let staged = std::mem::take(&mut self.staged);
+        if let Some(grid) = staged.grid {
+            self.loop_scheduler.set_grid_interval(grid);
+            self.update_fade();
+        }
+        if let Some(loop_offset) = staged.loop_offset {
+            self.loop_offset_beats = loop_offset;
+        }
+        if let Some(reverse) = staged.reverse {
+            self.reverse = reverse;
+        }
+        if let Some(section) = staged.section {
+            self.section = section;
+            if self.loop_scheduler.is_looping() {
+                self.load_section();
+            }
+        }
     }
 
-    fn update_scheduler_fade(&mut self) {
-        self.loop_scheduler.set_fade_lead_in(samples_to_beats(
-            self.fade_duration_samples,
-            self.tempo,
-            self.sample_rate,
-        ));
+    // as apply_staged_changes, but the grid only changes from the loop about to start
+    fn commit_staged_changes(&mut self) {
+        if let Some(grid) = self.staged.grid.take() {
+            self.loop_scheduler.set_grid_interval_from_next_loop(grid);
+            self.update_fade();
+        }
+        self.apply_staged_changes();
     }
 
     // offset the loop in the buffer, i.e. "scrub"
     pub fn set_loop_offset(&mut self, offset_beats: f32) {
+        if self.should_stage_changes() {
+            self.staged.loop_offset = Some(offset_beats);
+            return;
+        }
         self.loop_offset_beats = offset_beats;
     }
 
+    // as set_loop_offset, but in seconds whether or not we are synced
+    pub fn set_loop_offset_seconds(&mut self, offset_seconds: f32) {
+        self.set_loop_offset(self.seconds_to_clock(offset_seconds));
+    }
+
     // how long the loop is
     pub fn set_grid(&mut self, duration_beats: f32) {
+        let duration_beats = duration_beats.max(self.min_grid);
+        if self.should_stage_changes() {
+            self.staged.grid = Some(duration_beats);
+            return;
+        }
         self.loop_scheduler.set_grid_interval(duration_beats);
+        self.update_fade();
+    }
+
+    // as set_grid, but in seconds whether or not we are synced
+    pub fn set_grid_seconds(&mut self, duration_seconds: f32) {
+        self.set_grid(self.seconds_to_clock(duration_seconds));
+    }
+
+    // as set_grid, but a whole number of samples
+    pub fn set_grid_samples(&mut self, duration_samples: usize) {
+        self.set_grid(self.samples_to_clock(duration_samples));
     }
 
     // note that the loop_start_point_seconds is toward the past, as we want to loop something that has already started
     pub fn start_looping(&mut self) {
+        self.instant_start = self.instant_capture;
+        self.loop_scheduler
+            .set_launch_quantization(if self.instant_capture {
+                Some(0.0)
+            } else {
+                self.launch_quantization
+            });
+        self.last_grain_speed = None;
+        self.pass_level = None;
+        self.alternate_flipped = false;
         self.loop_scheduler.start_looping();
         self.grain_player.start_looping();
+        self.load_section();
     }
 
-    fn schedule_grain(&mut self, wait: usize, duration: usize, offset_reduction: f32) {
-        // wait might go away
-        self.grain_player.schedule_grain(Grain::new(
-            wait,
-            beats_to_samples(
-                self.loop_offset_beats - offset_reduction,
-                self.tempo,
-                self.sample_rate,
-            ) as f32,
-            duration + self.fade_duration_samples,
-            self.fade_duration_samples,
-            self.reverse,
-            self.speed,
-        ));
+    // unlinked, each channel's dry can be ramped on its own with ramp_dry_channel
+    // the loop starting and stopping still ramps every channel
+    pub fn set_dry_link(&mut self, linked: bool) {
+        self.dry_ramps.set_linked(linked);
     }
 
-    pub fn stop_looping(&mut self) {
-        self.loop_scheduler.stop_looping();
-        self.grain_player.stop_looping();
+    pub fn ramp_dry_channel(&mut self, channel: usize, target: f32, duration_samples: usize) {
+        self.dry_ramps
+            .ramp_channel(channel, target as f64, duration_samples);
     }
 
-    pub fn set_reverse(&mut self, reverse: bool) {
-        self.reverse = reverse;
+    // keeps the audio the loop is playing as this section, while the loop is on
+    pub fn store_section(&mut self, section: usize) {
+        if self.is_loop_requested() {
+            self.grain_player
+                .capture_region(&mut self.section_regions[section]);
+        }
     }
 
-    pub fn set_speed(&mut self, speed: f32) {
-        self.speed = speed;
+    pub fn region_length(&self) -> usize {
+        self.grain_player.region_length()
     }
 
-    pub fn tick(&mut self, input: T, beat_time: f64) -> T {
-        let events = self.loop_scheduler.tick(beat_time as f32);
+    // what the loop can play, newest first as region_frame reads it, in a couple of slice copies
+    pub fn capture_region(&self, region: &mut [T]) {
+        self.grain_player.capture_region(region);
+    }
 
-        for event in events {
-            match event {
-                LoopEvent::StartGrain { duration } => {
-                    self.schedule_grain(
-                        0,
-                        beats_to_samples(duration, self.tempo, self.sample_rate) as usize,
-                        0.0,
-                    );
-                    self.is_looping = true;
-                }
-                LoopEvent::StartLegatoGrain {
-                    duration,
-                    offset_reduction,
-                } => {
-                    self.schedule_grain(
-                        0,
-                        beats_to_samples(duration, self.tempo, self.sample_rate) as usize,
-                        offset_reduction,
-                    );
-                    self.is_looping = true;
-                }
-                LoopEvent::StopGrain => {
-                    // we stop them all
-                    self.grain_player.stop_all_grains();
-                }
-                LoopEvent::FadeInDry => {
-                    self.dry_ramp.ramp(1.0, self.fade_duration_samples);
-                }
-                LoopEvent::FadeOutDry => {
-                    self.dry_ramp.ramp(0.0, self.fade_duration_samples);
-                }
-                _ => {}
-            }
+    // what the loop can play delay samples back, from the newest at 0
+    pub fn region_frame(&self, delay: usize) -> T {
+        self.grain_player.region_frame(delay)
+    }
+
+    // a decimated view of the audio the loop can play, oldest first, for drawing it
+    pub fn snapshot_region(&self, points: &mut [WaveformPoint]) {
+        self.grain_player.snapshot_region(points);
+    }
+
+    // plays the audio stored for the section rather than capturing, or captures again with None
+    // while looping the section changes as the next loop starts, crossfading from the last one
+    pub fn set_section(&mut self, section: Option<usize>) {
+        if section == self.staged.section.unwrap_or(self.section) {
+            return;
+        }
+        if self.loop_scheduler.is_looping() {
+            self.staged.section = Some(section);
+            return;
         }
+        self.staged.section = None;
+        self.section = section;
+    }
 
-        let dry = input;
+    // plays the region as if it was the input around the loop start, newest first as
+    // capture_region leaves it, so the first import_margin frames come after the loop start
+    // from the next loop start, or straight away if the loop is on, crossfading from what it played
+    // swapped rather than copied, leaving the last import in its place, false if it doesn't fit
+    pub fn import_region(&mut self, region: &mut Vec<T>) -> bool {
+        if region.len() != self.imported_region.len() {
+            return false;
+        }
+        std::mem::swap(&mut self.imported_region, region);
 
-        let looped = self.grain_player.tick(input);
+        self.import_pending = true;
+        if self.is_loop_requested() {
+            self.load_section();
+        }
+        true
+    }
 
-        let dry_level = self.dry_ramp.tick();
-        looped + dry * dry_level as f32
+    // how many of an imported region's frames come after the loop start
+    pub fn import_margin(&self) -> usize {
+        self.grain_player.static_buffer_margin()
     }
 
-    fn num_playing_grains(&self) -> usize {
-        self.grain_player.num_playing_grains()
+    fn load_section(&mut self) {
+        if self.import_pending {
+            self.import_pending = false;
+            self.grain_player.load_region(&self.imported_region);
+            return;
+        }
+        if let Some(section) = self.section {
+            self.grain_player
+                .load_region(&self.section_regions[section]);
+        }
     }
 
-    pub fn is_looping(&self) -> bool {
-        self.is_looping
+    // how far ahead of time the scheduler events are turned into grains
+    // they still play on the sample they're due, so this adds no latency
+    // off the audio thread, as the queue is made again with room for every tick of the
+    // lookahead to resolve as many events as the scheduler hands out at once
+    pub fn set_lookahead(&mut self, lookahead_samples: usize) {
+        self.lookahead_samples = lookahead_samples;
+        self.resolved_events =
+            LookaheadQueue::new(LOOKAHEAD_QUEUE_CAPACITY + lookahead_samples * MAX_LOOP_EVENTS);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::vec;
+    // what to do when a grain would read audio that isn't in the buffers
+    pub fn set_region_policy(&mut self, region_policy: RegionPolicy) {
+        self.region_policy = region_policy;
+    }
 
-    use super::*;
-    use crate::test_utils::all_near;
+    // the round trip from the output back to the input, in samples
+    pub fn set_capture_latency(&mut self, capture_latency: usize) {
+        self.capture_latency = capture_latency;
+    }
 
-    struct IncreasingInteger {
-        count: usize,
+    // what happened to the most recent grain
+    pub fn region_fit(&self) -> RegionFit {
+        self.region_fit
     }
 
-    impl IncreasingInteger {
-        pub fn new(start_value: usize) -> Self {
-            Self { count: start_value }
+    fn resolve_grain(
+        &self,
+        wait: usize,
+        duration: usize,
+        offset_reduction: f32,
+        reverse: bool,
+        fade: usize,
+    ) -> ResolvedEvent {
+        let length = duration + fade;
+        let mut offset = self.clock_to_samples(self.loop_offset_beats - offset_reduction)
+            + self.capture_latency as f32;
+        // a reversed grain ends where a forward one starts, which only covers the same audio at
+        // normal speed, so move it to play exactly what the forward grain would have played
+        // time-stretched, the loop moves through the audio at its speed whatever the pitch
+        let speed = match self.time_stretch {
+            Some(_) => self.speed,
+            None => self.playback_rate(),
+        };
+        if reverse && self.reverse_compensation {
+            offset += length.saturating_sub(1) as f32 * (1.0 - speed);
+        }
+        let (fitted, fit) = fit_grain(
+            offset,
+            length,
+            speed,
+            reverse,
+            self.grain_player.readable_range(),
+            self.region_policy,
+        );
+        match fitted {
+            // wait might go away
+            Some((offset, length)) => {
+                let mut grain = Grain::new(wait, offset, length, fade, reverse, speed);
+                grain.set_pan(self.grain_pan);
+                ResolvedEvent::StartGrain(grain, fit)
+            }
+            None => ResolvedEvent::SkipGrain,
         }
     }
 
-    impl Iterator for IncreasingInteger {
-        type Item = usize;
-        fn next(&mut self) -> Option<usize> {
-            let out = self.count;
-            self.count += 1;
-            Some(out)
-        }
+    // the groove moves the start of each loop, and everything else due at the same time
+    pub fn set_groove(&mut self, groove: GrooveTemplate) {
+        self.groove = groove;
     }
 
-    // fixture that automatically ticks the input with an increaing integer
-    // and provides the relevant beat time
-    struct GrainLooperFixture {
-        pub looper: GrainLooper<f32>,
-        pub input: IncreasingInteger,
-        pub beat_time: f64,
-        pub beat_time_increment: f64,
+    pub fn set_groove_amount(&mut self, groove_amount: f32) {
+        self.groove_amount = groove_amount;
     }
 
-    impl GrainLooperFixture {
-        fn new() -> GrainLooperFixture {
-            let mut f = GrainLooperFixture {
-                looper: GrainLooper::new_with_length(10.0, 20, 4, 10),
-                input: IncreasingInteger::new(10),
-                beat_time: 0.0,
-                beat_time_increment: 0.1,
-            };
+    // how late the groove makes events due at this clock time
+    fn groove_delay_samples(&self, time: f64) -> usize {
+        let delay = self.groove.delay_at(time) * self.groove_amount;
+        self.clock_to_samples(delay * 0.25).round() as usize
+    }
 
-            f.looper.set_tempo(60.0);
-            f
-        }
+    // a grain starting at time lasts until the next one starts, both pushed late by the groove
+    fn grain_duration_samples(&self, time: f64, duration: f32) -> usize {
+        (self.clock_to_samples(duration).round() as usize
+            + self.groove_delay_samples(time + duration as f64))
+        .saturating_sub(self.groove_delay_samples(time))
+    }
 
-        fn check_output(&mut self, expected: &Vec<f32>) {
-            let mut out = vec![];
-            for _i in 0..expected.len() {
-                out.push(
-                    self.looper
-                        .tick(self.input.next().unwrap() as f32, self.beat_time),
+    // time is the clock time the event is due at
+    fn resolve_event(&mut self, event: LoopEvent, time: f64) -> Option<ResolvedEvent> {
+        match event {
+            LoopEvent::StartGrain { duration } => {
+                let reverse = self.next_grain_reverse();
+                // the stutters all start from the beginning of the loop, so mirrored from its end
+                let through = if reverse && self.reverse_whole {
+                    let loop_length = self.loop_scheduler.loop_length();
+                    loop_length - loop_length / self.loop_scheduler.stutter_divisions() as f32
+                } else {
+                    0.0
+                };
+                let jitter =
+                    through + self.next_offset_jitter() - self.loop_scheduler.pattern_offset();
+                self.grain_pan = self.next_grain_pan();
+                let duration_samples = self.grain_duration_samples(time, duration);
+                let mut resolved = self.resolve_grain(
+                    0,
+                    duration_samples,
+                    jitter,
+                    reverse,
+                    self.fade_duration_samples,
                 );
-                self.beat_time += self.beat_time_increment;
+                if let ResolvedEvent::StartGrain(grain, _) = &mut resolved {
+                    // only the fade in is quick, the fade out still crosses over with the next loop
+                    if self.instant_start {
+                        grain.set_fade_in(self.instant_fade_samples());
+                    }
+                    let share = |share: f32| (share * duration_samples as f32) as usize;
+                    grain.set_envelope(share(self.loop_attack), self.loop_decay.map(share));
+                }
+                Some(resolved)
             }
-            assert_eq!(out, expected.clone());
+            LoopEvent::StartLegatoGrain {
+                duration,
+                offset_reduction,
+            } => Some(self.resolve_grain(
+                0,
+                self.grain_duration_samples(time, duration),
+                // the grain runs to the end of the loop, which mirrored is its beginning
+                if self.reverse && self.reverse_whole {
+                    0.0
+                } else {
+                    offset_reduction
+                },
+                self.reverse,
+                self.transition_fade_samples,
+            )),
+            // the tail plays the other way to the loop
+            LoopEvent::ReverseTail { duration } => match self.resolve_grain(
+                0,
+                self.grain_duration_samples(time, duration),
+                0.0,
+                !self.reverse,
+                self.fade_duration_samples,
+            ) {
+                ResolvedEvent::StartGrain(grain, _) => Some(ResolvedEvent::StartTail(grain)),
+                _ => None,
+            },
+            LoopEvent::EndTail => Some(ResolvedEvent::EndTail),
+            // we stop them all
+            LoopEvent::StopGrain => Some(ResolvedEvent::StopGrains),
+            LoopEvent::FadeInDry => Some(ResolvedEvent::RampDry {
+                target: 1.0,
+                duration: self.dry_fade_samples(),
+            }),
+            // the dry going comes after the loop's first grain
+            LoopEvent::FadeOutDry => Some(ResolvedEvent::RampDry {
+                target: 0.0,
+                duration: if std::mem::take(&mut self.instant_start) {
+                    self.instant_fade_samples()
+                } else {
+                    self.dry_fade_samples()
+                },
+            }),
+            LoopEvent::BlendDry => Some(ResolvedEvent::RampDry {
+                target: self.transition_dry_blend as f64,
+                duration: self.transition_fade_samples,
+            }),
+            LoopEvent::UnblendDry => Some(ResolvedEvent::RampDry {
+                target: 0.0,
+                duration: self.transition_fade_samples,
+            }),
+            _ => None,
         }
+    }
 
-        fn set_tempo(&mut self, tempo: f32) {
-            self.looper.set_tempo(tempo);
-            let bps = tempo / 60.0;
-            self.beat_time_increment = (bps / self.looper.sample_rate) as f64;
+    // time-stretched, the grain is played as many short ones
+    fn play_grain(&mut self, grain: Grain) {
+        match self.time_stretch {
+            Some(settings) => {
+                self.grain_player
+                    .schedule_stretch(Stretch::new(&grain, settings, self.pitch_ratio))
+            }
+            None => self.grain_player.schedule_grain(grain),
         }
     }
 
-    #[test]
-    fn test_beats_to_samples() {
-        let tempo = 120.0;
-        let sample_rate = 10.0;
-        assert_eq!(beats_to_samples(1.0, tempo, sample_rate), 5.0);
-        assert_eq!(beats_to_samples(0.5, tempo, sample_rate), 2.5);
-        assert_eq!(beats_to_samples(0.1, 60.0, 10.0), 1.0);
+    fn apply_event(&mut self, event: ResolvedEvent) {
+        match event {
+            ResolvedEvent::StartGrain(mut grain, fit) => {
+                self.compensate_speed_change(&mut grain);
+                self.next_pass();
+                self.play_grain(grain);
+                self.is_looping = true;
+                self.region_fit = fit;
+            }
+            // still looping, just silent until a grain fits again
+            ResolvedEvent::SkipGrain => {
+                self.next_pass();
+                self.is_looping = true;
+                self.region_fit = RegionFit::Skipped;
+            }
+            ResolvedEvent::StartTail(grain) => {
+                self.play_grain(grain);
+            }
+            ResolvedEvent::StopGrains => {
+                self.grain_player.stop_all_grains();
+            }
+            // the static buffer is kept until now, as the tail plays from it
+            ResolvedEvent::EndTail => {
+                self.grain_player.stop_looping();
+                self.dry_ramps.ramp(1.0, self.dry_fade_samples());
+            }
+            ResolvedEvent::RampDry { target, duration } => {
+                self.dry_ramps.ramp(target, duration);
+            }
+        }
     }
 
-    #[test]
-    fn test_grain_looper_nicely() {
-        let mut looper_fixture = GrainLooperFixture::new();
+    pub fn stop_looping(&mut self) {
+        self.loop_scheduler.stop_looping();
+        self.grain_player.stop_looping();
+    }
 
-        let expected1 = (10..15).map(|x| x as f32).collect();
+    // as stop_looping, but the loop plays once more the other way from the next grid line,
+    // swelling back into the dry as it ends
+    pub fn stop_looping_with_reverse_tail(&mut self) {
+        let tail_length = self.loop_scheduler.grid_interval();
+        self.loop_scheduler.stop_looping_with_tail(tail_length);
+    }
 
-        // checks that the tick returns each of expected
-        looper_fixture.check_output(&expected1);
+    // restarts the loop from its beginning at the next min grid line,
+    // crossfading out of the grain playing over the fade, or cutting it when there is none
+    pub fn retrigger(&mut self) {
+        self.loop_scheduler.retrigger(self.min_grid);
+    }
 
-        // can set things on the looper inside the fixture
-        looper_fixture.looper.set_grid(0.5);
+    pub fn set_reverse(&mut self, reverse: bool) {
+        if self.should_stage_changes() {
+            self.staged.reverse = Some(reverse);
+            return;
+        }
+        self.reverse = reverse;
+    }
 
-        // and check again
-        let expected2 = (15..20).map(|x| x as f32).collect();
-        looper_fixture.check_output(&expected2);
+    // alternate plays the loop forwards and backwards in turn, starting forwards
+    pub fn set_reverse_mode(&mut self, mode: ReverseMode) {
+        self.set_reverse(mode == ReverseMode::Reverse || mode == ReverseMode::ReverseWhole);
+        self.alternate = mode == ReverseMode::Alternate;
+        self.reverse_whole = mode == ReverseMode::ReverseWhole;
     }
 
-    #[test]
-    fn test_grain_looper_loop() {
-        // test a 5 sample loop, no fading, not using static buffer yet
-        let mut looper_fixture = GrainLooperFixture::new();
+    // which way the next loop plays, flipping each time when alternating
+    fn next_grain_reverse(&mut self) -> bool {
+        if !self.alternate {
+            return self.reverse;
+        }
+        let reverse = self.reverse != self.alternate_flipped;
+        self.alternate_flipped = !self.alternate_flipped;
+        reverse
+    }
 
-        let expected1 = (10..15).map(|x| x as f32).collect();
-        looper_fixture.check_output(&expected1);
+    // when set, reversing plays the same audio as forwards at any speed
+    pub fn set_reverse_compensation(&mut self, reverse_compensation: bool) {
+        self.reverse_compensation = reverse_compensation;
+    }
 
-        looper_fixture.looper.set_fade_time(0.0);
-        looper_fixture.looper.set_loop_offset(0.5);
-        looper_fixture.looper.set_grid(0.5);
-        looper_fixture.looper.start_looping();
+    pub fn set_speed_gain_compensation(&mut self, speed_gain_compensation: bool) {
+        self.speed_gain_compensation = speed_gain_compensation;
+    }
 
-        looper_fixture.check_output(&expected1);
-        looper_fixture.check_output(&expected1);
-        looper_fixture.check_output(&expected1);
+    // a grain at a new speed or direction starts louder, settling back to unity over the fade
+    fn compensate_speed_change(&mut self, grain: &mut Grain) {
+        let changed = self
+            .last_grain_speed
+            .is_some_and(|speed| speed != grain.speed());
+        self.last_grain_speed = Some(grain.speed());
+        if changed && self.speed_gain_compensation {
+            grain.set_gain_envelope(SPEED_CHANGE_GAIN, self.fade_duration_samples);
+        }
+    }
 
-        // stop looping
-        looper_fixture.looper.stop_looping();
+    pub fn set_fade_shape(&mut self, fade_shape: FadeShape) {
+        self.fade_shape = fade_shape;
+        self.grain_player.set_fade_shape(fade_shape);
+    }
 
-        let expected_back_to_dry = (30..35).map(|x| x as f32).collect();
-        looper_fixture.check_output(&expected_back_to_dry);
+    // which grain makes way when there's no voice free, e.g. for dense retriggering
+    pub fn set_voice_stealing(&mut self, voice_stealing: VoiceStealing) {
+        self.grain_player.set_voice_stealing(voice_stealing);
     }
 
-    #[test]
-    fn test_grain_looper_loop_offset() {
-        // check that we can change the offset of the loop as its looping
-        let mut looper_fixture = GrainLooperFixture::new();
+    // slows the loop to a stop like a tape machine, or brings it back up to speed
+    pub fn set_tape_stop(&mut self, stopped: bool) {
+        if stopped == self.tape_stopped {
+            return;
+        }
+        self.tape_stopped = stopped;
+        let target = if stopped { 0.0 } else { 1.0 };
+        self.grain_player.ramp_speed(target, self.tape_stop_samples);
+    }
 
-        let expected1 = (10..20).map(|x| x as f32).collect();
-        looper_fixture.check_output(&expected1);
+    // how long the tape takes to stop, and to start again
+    pub fn set_tape_stop_time(&mut self, seconds: f32) {
+        self.tape_stop_samples = seconds_to_samples(seconds, self.sample_rate);
+    }
 
-        looper_fixture.looper.set_fade_time(0.0);
+    // moves the grid on from the host's bar lines, only while synced as free running has none
+    pub fn set_grid_offset(&mut self, offset_beats: f32) {
+        let offset = if self.sync { offset_beats } else { 0.0 };
+        self.loop_scheduler.set_grid_offset(offset);
+    }
 
-        // with a tempo of 60 and a sample rate of 10, one sample is 0.1 beats
-        looper_fixture.looper.set_loop_offset(0.5);
-        looper_fixture.looper.set_grid(0.5);
+    // pushes every other grid line late, 0.5 is straight, see LoopScheduler::set_swing
+    pub fn set_swing(&mut self, swing: f32) {
+        self.loop_scheduler.set_swing(swing);
+    }
 
-        looper_fixture.looper.start_looping();
+    // the chance of each repeat of the loop playing, from 0 to 1
+    pub fn set_repeat_probability(&mut self, probability: f32) {
+        self.loop_scheduler.set_repeat_probability(probability);
+    }
 
-        let expected2 = (15..20).map(|x| x as f32).collect();
-        looper_fixture.check_output(&expected2);
+    // what the loop waits for to start, in beats or seconds when free running
+    // none waits for the next grid line, and zero starts straight away
+    pub fn set_launch_quantization(&mut self, quantization: Option<f32>) {
+        self.launch_quantization = quantization;
+    }
 
-        // offset by one earlier compared to the previous loop
-        looper_fixture.looper.set_loop_offset(0.6);
+    // start_looping starts the loop straight away rather than waiting for the grid, for
+    // free-form looping, the loop and the dry cross over quickly rather than over the fade
+    pub fn set_instant_capture(&mut self, instant_capture: bool) {
+        self.instant_capture = instant_capture;
+    }
 
-        let expected3 = (14..19).map(|x| x as f32).collect();
-        looper_fixture.check_output(&expected3);
+    // the quick fade an instant capture cuts in over, never longer than the usual fade
+    fn instant_fade_samples(&self) -> usize {
+        seconds_to_samples(INSTANT_FADE_SECONDS, self.sample_rate).min(self.fade_duration_samples)
     }
 
-    #[test]
-    fn test_grain_looper_fade_is_flat() {
-        // when we loop a DC signal we expect the fades to maintain the DC level
-        let mut looper = GrainLooper::new_with_length(10.0, 50, 4, 10);
-        looper.set_tempo(60.0);
-        let mut out = vec![];
-        for i in 0..8 {
-            out.push(looper.tick(1.0, i as f64 / 10.0));
-        }
-        // start looping immediately
-        // two samples fade
-        looper.set_fade_time(0.2);
-        // set offset to be the loop length to loop the most recent 5 samples
-        looper.set_loop_offset(0.5);
-        looper.set_grid(0.5);
-        looper.start_looping();
-        for i in 8..15 {
-            out.push(looper.tick(1.0, i as f64 / 10.0));
+    // the loop waits for length counts of interval before it starts, 0 starts as usual
+    // from the next start_looping, see LoopScheduler::set_count_in
+    pub fn set_count_in(&mut self, length: u32, interval: f32) {
+        self.loop_scheduler.set_count_in(length, interval);
+    }
+
+    pub fn count_in_remaining(&self) -> u32 {
+        self.loop_scheduler.count_in_remaining()
+    }
+
+    pub fn set_length_pattern(&mut self, pattern: Option<LengthPattern>) {
+        self.loop_scheduler.set_length_pattern(pattern);
+    }
+
+    // the loop plays this many times then fades back to the dry, none loops until stopped
+    pub fn set_one_shot(&mut self, passes: Option<u32>) {
+        self.loop_scheduler.set_pass_limit(passes);
+    }
+
+    // from the next loop on, each loop is cut short and restarted, without moving the grid
+    pub fn set_stutter(&mut self, stutter: Stutter) {
+        self.loop_scheduler.set_stutter(stutter);
+    }
+
+    // each new grain starts up to this share of the loop length either side of the offset
+    pub fn set_offset_jitter(&mut self, offset_jitter: f32) {
+        self.offset_jitter = offset_jitter;
+    }
+
+    // each repeat is panned at random up to this far either side, 0 leaves them all central
+    // only a stereo looper pans, there's nowhere to pan a mono or surround one to
+    pub fn set_stereo_spread(&mut self, stereo_spread: f32) {
+        self.stereo_spread = stereo_spread;
+    }
+
+    fn next_grain_pan(&mut self) -> f32 {
+        if self.stereo_spread == 0.0 {
+            return 0.0;
+        }
+        self.random.bipolar() * self.stereo_spread
+    }
+
+    // how much to take off the offset for the next grain, keeping it inside the loopable region
+    fn next_offset_jitter(&mut self) -> f32 {
+        if self.offset_jitter == 0.0 {
+            return 0.0;
+        }
+        let jitter =
+            self.random.bipolar() * self.offset_jitter * self.loop_scheduler.grid_interval();
+        let oldest = self.samples_to_clock(self.grain_player.loopable_region_length());
+        -jitter.clamp(-self.loop_offset_beats, oldest - self.loop_offset_beats)
+    }
+
+    // each repeat swells in over the attack and dies away over the decay
+    // attack and decay are shares of the loop, 0 decay holds each repeat rather than dying away
+    // from the next loop on, the time-stretched loop isn't enveloped
+    pub fn set_loop_envelope(&mut self, attack: f32, decay: f32) {
+        self.loop_attack = attack;
+        self.loop_decay = (decay > 0.0).then_some(decay);
+    }
+
+    // each repeat is this much quieter than the last, so the loop dies away like a delay
+    pub fn set_feedback(&mut self, feedback: f32) {
+        self.feedback = feedback;
+    }
+
+    // the first pass of a loop plays at full level, each after it comes in quieter over the fade
+    fn next_pass(&mut self) {
+        let level = self.pass_level.map_or(1.0, |level| level * self.feedback);
+        self.pass_level = Some(level);
+        self.feedback_ramp
+            .ramp(level as f64, self.fade_duration_samples);
+    }
+
+    pub fn set_speed(&mut self, speed: f32) {
+        if speed == self.speed_target {
+            return;
+        }
+        self.speed_target = speed;
+        if self.speed_glide_samples == 0 {
+            self.speed = speed;
+            return;
+        }
+        self.speed_glide = RampedValue::new(self.speed as f64);
+        self.speed_glide
+            .ramp(speed as f64, self.speed_glide_samples);
+    }
+
+    // how long speed changes take, like varispeed, rather than each new grain jumping to the speed
+    pub fn set_speed_glide(&mut self, seconds: f32) {
+        self.speed_glide_samples = seconds_to_samples(seconds, self.sample_rate);
+    }
+
+    // moves the speed on through a glide, taking the grains that are playing along with it
+    // time-stretched, the grains play at the pitch, so the glide only reaches new loops
+    fn tick_speed_glide(&mut self) {
+        if self.speed == self.speed_target {
+            return;
+        }
+        self.speed = self.speed_glide.tick() as f32;
+        if self.time_stretch.is_none() {
+            let rate = self.playback_rate();
+            self.grain_player.set_speed(rate);
+            // a glide is not a jump, so it shouldn't lift the level of the next grain
+            self.last_grain_speed = self.last_grain_speed.map(|last| rate.copysign(last));
+        }
+    }
+
+    // repitches the loop by resampling, grains keep their length so the loop stays on the grid
+    pub fn set_pitch(&mut self, semitones: f32) {
+        self.pitch_ratio = 2.0_f32.powf(semitones / 12.0);
+    }
+
+    // slows or speeds the loop without changing its pitch, by playing it as overlapping grains
+    // of grain_seconds, density of them at once
+    pub fn set_time_stretch(&mut self, enabled: bool, grain_seconds: f32, density: f32) {
+        self.time_stretch = enabled.then(|| StretchSettings {
+            grain_size: seconds_to_samples(grain_seconds, self.sample_rate),
+            density,
+        });
+    }
+
+    // how far through the audio a grain moves each sample
+    fn playback_rate(&self) -> f32 {
+        self.speed * self.pitch_ratio
+    }
+
+    // blend the loop with the dry signal, 1 replaces the dry signal entirely while looping
+    pub fn set_mix(&mut self, mix: f32) {
+        if mix == self.mix {
+            return;
+        }
+        self.mix = mix;
+        self.mix_ramp.ramp(
+            mix as f64,
+            seconds_to_samples(MIX_RAMP_SECONDS, self.sample_rate),
+        );
+    }
+
+    // how loud the dry carries on under the loop, so the loop can sit on top of the live signal
+    // the loop is heard in full whatever this is, unlike the mix
+    pub fn set_dry_duck_level(&mut self, level: f32) {
+        if level == self.dry_duck_level {
+            return;
+        }
+        self.dry_duck_level = level;
+        self.dry_duck_ramp.ramp(
+            level as f64,
+            seconds_to_samples(MIX_RAMP_SECONDS, self.sample_rate),
+        );
+    }
+
+    // hear only the loop, at full level, whatever the mix
+    // the mix is left alone, so letting go goes back to it
+    pub fn set_solo(&mut self, solo: bool) {
+        if solo == self.solo {
+            return;
+        }
+        self.solo = solo;
+        self.solo_ramp.ramp(
+            if solo { 1.0 } else { 0.0 },
+            seconds_to_samples(SOLO_RAMP_SECONDS, self.sample_rate),
+        );
+    }
+
+    pub fn tick(&mut self, input: T, beat_time: f64) -> T {
+        let (wet, dry) = self.tick_split(input, beat_time);
+        wet + dry
+    }
+
+    // as tick, but returns the loop (wet) and the dry signal separately, each with its gain applied
+    // when not synced, beat_time is ignored
+    pub fn tick_split(&mut self, input: T, beat_time: f64) -> (T, T) {
+        // if the engine state is inconsistent, pass the input through until we are reset
+        if self.has_fault() {
+            return (T::default(), input);
+        }
+
+        let clock_time = if self.sync {
+            beat_time
+        } else {
+            let time = self.free_running_time;
+            self.free_running_time += 1.0 / self.sample_rate as f64;
+            time
+        };
+
+        // the scheduler runs ahead of the audio by the lookahead
+        let lookahead = self.samples_to_clock(self.lookahead_samples) as f64;
+        let scheduler_time = clock_time + lookahead;
+
+        // events land on the sample nearest when they are due, so rounding in the clock, e.g.
+        // after a tempo change, can't leave one a sample late
+        let due_window = self.seconds_to_clock(0.5 / self.sample_rate) as f64;
+
+        // only the host clock jumps
+        if std::mem::take(&mut self.realign_pending) && self.sync {
+            self.loop_scheduler.realign(scheduler_time);
+        }
+
+        // staged changes go in just before the loop that they apply to starts
+        if !self.staged.is_empty() {
+            match self.loop_scheduler.next_loop_time() {
+                Some(next_loop_time) if next_loop_time <= scheduler_time + due_window => {
+                    self.commit_staged_changes()
+                }
+                None if !self.loop_scheduler.is_looping() => self.apply_staged_changes(),
+                _ => (),
+            }
+        }
+
+        let events = self.loop_scheduler.tick_within(scheduler_time, due_window);
+
+        for event in events {
+            if let Some(resolved) = self.resolve_event(event, scheduler_time) {
+                let delay = self.lookahead_samples + self.groove_delay_samples(scheduler_time);
+                if !self.resolved_events.push(delay, resolved) {
+                    self.queue_overflowed = true;
+                }
+            }
+        }
+
+        while let Some(resolved) = self.resolved_events.pop_due() {
+            self.apply_event(resolved);
+        }
+        self.resolved_events.tick();
+
+        self.tick_speed_glide();
+        let looped = self.grain_player.tick(input);
+        self.mix(input, looped)
+    }
+
+    // applies the gains to the loop and the dry, moving their ramps on a sample
+    fn mix(&mut self, dry: T, looped: T) -> (T, T) {
+        let dry_levels = self.dry_ramps.tick();
+        let (wet_gain, mix_dry_gain) = equal_power_gains(self.mix_ramp.tick() as f32);
+        let solo = self.solo_ramp.tick() as f32;
+        let wet_gain = (wet_gain + (1.0 - wet_gain) * solo) * self.feedback_ramp.tick() as f32;
+        // the mix only holds back the dry as far as the loop is replacing it
+        // and the dry only fades as far as the duck level
+        let fade_shape = self.fade_shape;
+        let duck = self.dry_duck_ramp.tick() as f32;
+        let dry_gain = |dry_level: f32| {
+            let dry_level = fade_shape.apply(dry_level as f64) as f32;
+            let dry_level = duck + (1.0 - duck) * dry_level;
+            (dry_level + (1.0 - dry_level) * mix_dry_gain) * (1.0 - solo)
+        };
+
+        let dry = if self.dry_ramps.is_linked() {
+            dry * dry_gain(dry_levels[0])
+        } else {
+            let mut channels = [0.0; MAX_CHANNELS];
+            dry.write_channels(&mut channels[..T::NUM_CHANNELS]);
+            for (sample, dry_level) in channels.iter_mut().zip(dry_levels) {
+                *sample *= dry_gain(dry_level);
+            }
+            T::from_channels(&channels[..T::NUM_CHANNELS])
+        };
+        (looped * wet_gain, dry)
+    }
+
+    // process a block in place, taking the host state for the whole block from the context
+    pub fn tick_block(&mut self, context: &ProcessContextLite, block: &mut [T]) {
+        self.apply_context(context);
+        self.process_summed(block, |i| context.beat_time_at(i));
+    }
+
+    // as tick for each sample of input, with the beat time of each, into output
+    pub fn process_block(&mut self, input: &[T], output: &mut [T], beat_times: &[f64]) {
+        debug_assert!(output.len() >= input.len() && beat_times.len() >= input.len());
+        let output = &mut output[..input.len()];
+        output.copy_from_slice(input);
+        self.process_summed(output, |i| beat_times[i]);
+    }
+
+    // the dry is held a chunk at a time on the stack, so nothing is allocated
+    fn process_summed(&mut self, block: &mut [T], beat_time_at: impl Fn(usize) -> f64) {
+        let mut dry_block = [T::default(); SUMMED_CHUNK];
+        let mut start = 0;
+        for chunk in block.chunks_mut(SUMMED_CHUNK) {
+            let dry_block = &mut dry_block[..chunk.len()];
+            self.process_split(chunk, dry_block, |i| beat_time_at(start + i));
+            for (sample, dry) in chunk.iter_mut().zip(dry_block.iter()) {
+                *sample += *dry;
+            }
+            start += chunk.len();
+        }
+    }
+
+    // as tick_block, but the block is replaced by the wet signal and the dry is written separately
+    pub fn tick_block_split(
+        &mut self,
+        context: &ProcessContextLite,
+        block: &mut [T],
+        dry_block: &mut [T],
+    ) {
+        debug_assert!(dry_block.len() >= block.len());
+        self.apply_context(context);
+        self.process_split(block, dry_block, |i| context.beat_time_at(i));
+    }
+
+    // as tick_split for each sample, but the runs of samples that nothing happens on besides the
+    // grains playing go through the grain player as a block
+    fn process_split(
+        &mut self,
+        block: &mut [T],
+        dry_block: &mut [T],
+        beat_time_at: impl Fn(usize) -> f64,
+    ) {
+        let mut start = 0;
+        while start < block.len() {
+            let (length, scheduler_time) = self.quiet_run((start..block.len()).map(&beat_time_at));
+            if length == 0 {
+                (block[start], dry_block[start]) =
+                    self.tick_split(block[start], beat_time_at(start));
+                start += 1;
+                continue;
+            }
+
+            let run = start..start + length;
+            if !self.sync {
+                for _ in run.clone() {
+                    self.free_running_time += 1.0 / self.sample_rate as f64;
+                }
+            }
+            let due_window = self.seconds_to_clock(0.5 / self.sample_rate) as f64;
+            let events = self.loop_scheduler.tick_within(scheduler_time, due_window);
+            debug_assert!(events.is_empty());
+            for _ in run.clone() {
+                self.resolved_events.tick();
+            }
+
+            dry_block[run.clone()].copy_from_slice(&block[run.clone()]);
+            self.grain_player
+                .process_block(&dry_block[run.clone()], &mut block[run.clone()]);
+            for (sample, dry) in block[run.clone()].iter_mut().zip(&mut dry_block[run]) {
+                (*sample, *dry) = self.mix(*dry, *sample);
+            }
+            start += length;
+        }
+    }
+
+    // how many of the samples at these beat times nothing would happen on but the grains playing
+    // and the gains moving, with the scheduler time of the last of them
+    fn quiet_run(&self, beat_times: impl Iterator<Item = f64>) -> (usize, f64) {
+        let busy = self.has_fault()
+            || self.realign_pending
+            || !self.staged.is_empty()
+            || self.speed != self.speed_target;
+        if busy {
+            return (0, 0.0);
+        }
+        let lookahead = self.samples_to_clock(self.lookahead_samples) as f64;
+        let due_window = self.seconds_to_clock(0.5 / self.sample_rate) as f64;
+        let queued = self.resolved_events.ticks_to_next().unwrap_or(usize::MAX);
+        let mut free_running_time = self.free_running_time;
+        let mut length = 0;
+        let mut last_time = 0.0;
+        for beat_time in beat_times.take(queued) {
+            let clock_time = if self.sync {
+                beat_time
+            } else {
+                let time = free_running_time;
+                free_running_time += 1.0 / self.sample_rate as f64;
+                time
+            };
+            let scheduler_time = clock_time + lookahead;
+            let in_order = length == 0 || scheduler_time >= last_time;
+            if !in_order
+                || !self
+                    .loop_scheduler
+                    .is_quiet_until(scheduler_time, due_window)
+            {
+                break;
+            }
+            last_time = scheduler_time;
+            length += 1;
+        }
+        (length, last_time)
+    }
+
+    fn apply_context(&mut self, context: &ProcessContextLite) {
+        if context.sample_rate != self.sample_rate {
+            self.set_sample_rate(context.sample_rate);
+        }
+        if context.tempo != self.tempo {
+            self.set_tempo(context.tempo);
+        }
+    }
+
+    fn num_playing_grains(&self) -> usize {
+        self.grain_player.num_playing_grains()
+    }
+
+    // the grains playing the loop, for drawing them
+    pub fn active_grains(&self) -> impl Iterator<Item = GrainView> + '_ {
+        self.grain_player.active_grains()
+    }
+
+    pub fn is_looping(&self) -> bool {
+        self.is_looping
+    }
+
+    // each loop starts fading in this far ahead of its grid line, reading from the offset, so
+    // the loop runs this far ahead of the audio it repeats
+    pub fn fade_samples(&self) -> usize {
+        self.fade_duration_samples
+    }
+
+    // whether looping has been started, even if the first grain is still waiting for the grid
+    pub fn is_loop_requested(&self) -> bool {
+        self.loop_scheduler.is_looping()
+    }
+
+    // how many times the loop has started over since looping was started
+    pub fn repeat_count(&self) -> u32 {
+        self.loop_scheduler.loop_count()
+    }
+
+    // how far back the loop starts from where looping started
+    pub fn loop_offset_samples(&self) -> f32 {
+        self.clock_to_samples(self.loop_offset_beats) + self.capture_latency as f32
+    }
+
+    pub fn loop_length_samples(&self) -> f32 {
+        self.clock_to_samples(self.loop_scheduler.grid_interval())
+    }
+
+    pub fn loop_length_seconds(&self) -> f32 {
+        beats_to_seconds(self.loop_scheduler.grid_interval(), self.clock_tempo())
+    }
+
+    // in beats, or seconds when free running, None when not looping
+    pub fn time_to_next_loop(&self) -> Option<f32> {
+        self.loop_scheduler.time_to_next_loop()
+    }
+
+    // true when the engine has detected an inconsistency and is passing through the input,
+    // call reset to recover
+    pub fn has_fault(&self) -> bool {
+        self.loop_scheduler.has_fault() || self.queue_overflowed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec;
+
+    use super::*;
+    use crate::frame::Frame;
+    use crate::grain_player::MAX_GRAINS;
+    use crate::stereo_pair::StereoPair;
+    use crate::test_utils::all_near;
+    use std::fmt::Debug;
+
+    struct IncreasingInteger {
+        count: usize,
+    }
+
+    impl IncreasingInteger {
+        pub fn new(start_value: usize) -> Self {
+            Self { count: start_value }
+        }
+    }
+
+    impl Iterator for IncreasingInteger {
+        type Item = usize;
+        fn next(&mut self) -> Option<usize> {
+            let out = self.count;
+            self.count += 1;
+            Some(out)
+        }
+    }
+
+    // fixture that automatically ticks the input with an increaing integer
+    // and provides the relevant beat time
+    // the integer is turned into the sample type, so the same expected values
+    // can be checked for mono and stereo loopers
+    struct GrainLooperFixture<T: AudioFrame> {
+        pub looper: GrainLooper<T>,
+        pub input: IncreasingInteger,
+        pub beat_time: f64,
+        pub beat_time_increment: f64,
+        to_sample: fn(f32) -> T,
+    }
+
+    // the right channel is inverted so that mixing up channels shows in the output
+    fn stereo_sample(x: f32) -> StereoPair<f32> {
+        StereoPair::new(x, -x)
+    }
+
+    // each channel different, and one silent
+    fn quad_sample(x: f32) -> Frame<4> {
+        Frame([x, -x, 2.0 * x, 0.0])
+    }
+
+    impl GrainLooperFixture<f32> {
+        fn new() -> GrainLooperFixture<f32> {
+            GrainLooperFixture::new_with_sample(|x| x)
+        }
+    }
+
+    impl GrainLooperFixture<StereoPair<f32>> {
+        fn new_stereo() -> GrainLooperFixture<StereoPair<f32>> {
+            GrainLooperFixture::new_with_sample(stereo_sample)
+        }
+    }
+
+    impl GrainLooperFixture<Frame<4>> {
+        fn new_quad() -> GrainLooperFixture<Frame<4>> {
+            GrainLooperFixture::new_with_sample(quad_sample)
+        }
+    }
+
+    impl<T: AudioFrame + PartialEq + Debug> GrainLooperFixture<T> {
+        fn new_with_sample(to_sample: fn(f32) -> T) -> GrainLooperFixture<T> {
+            let mut f = GrainLooperFixture {
+                looper: GrainLooper::new_with_length(10.0, 20, 4, 10),
+                input: IncreasingInteger::new(10),
+                beat_time: 0.0,
+                beat_time_increment: 0.1,
+                to_sample,
+            };
+
+            f.looper.set_tempo(60.0);
+            f
+        }
+
+        // ticks the looper with the next input, returning the output
+        fn tick(&mut self) -> T {
+            let input = (self.to_sample)(self.input.next().unwrap() as f32);
+            let out = self.looper.tick(input, self.beat_time);
+            self.beat_time += self.beat_time_increment;
+            out
+        }
+
+        fn check_output(&mut self, expected: &Vec<f32>) {
+            let mut out = vec![];
+            for _i in 0..expected.len() {
+                out.push(self.tick());
+            }
+            let expected: Vec<T> = expected.iter().map(|&x| (self.to_sample)(x)).collect();
+            assert_eq!(out, expected);
+        }
+
+        fn set_tempo(&mut self, tempo: f32) {
+            self.looper.set_tempo(tempo);
+            let bps = tempo / 60.0;
+            self.beat_time_increment = (bps / self.looper.sample_rate) as f64;
+        }
+    }
+
+    #[test]
+    fn test_beats_to_samples() {
+        let tempo = 120.0;
+        let sample_rate = 10.0;
+        assert_eq!(beats_to_samples(1.0, tempo, sample_rate), 5.0);
+        assert_eq!(beats_to_samples(0.5, tempo, sample_rate), 2.5);
+        assert_eq!(beats_to_samples(0.1, 60.0, 10.0), 1.0);
+    }
+
+    #[test]
+    fn test_grain_looper_nicely() {
+        let mut looper_fixture = GrainLooperFixture::new();
+
+        let expected1 = (10..15).map(|x| x as f32).collect();
+
+        // checks that the tick returns each of expected
+        looper_fixture.check_output(&expected1);
+
+        // can set things on the looper inside the fixture
+        looper_fixture.looper.set_grid(0.5);
+
+        // and check again
+        let expected2 = (15..20).map(|x| x as f32).collect();
+        looper_fixture.check_output(&expected2);
+    }
+
+    fn check_grain_looper_loop<T: AudioFrame + PartialEq + Debug>(
+        mut looper_fixture: GrainLooperFixture<T>,
+    ) {
+        // test a 5 sample loop, no fading, not using static buffer yet
+        let expected1 = (10..15).map(|x| x as f32).collect();
+        looper_fixture.check_output(&expected1);
+
+        looper_fixture.looper.set_fade_time(0.0);
+        looper_fixture.looper.set_loop_offset(0.5);
+        looper_fixture.looper.set_grid(0.5);
+        looper_fixture.looper.start_looping();
+
+        looper_fixture.check_output(&expected1);
+        looper_fixture.check_output(&expected1);
+        looper_fixture.check_output(&expected1);
+
+        // stop looping
+        looper_fixture.looper.stop_looping();
+
+        let expected_back_to_dry = (30..35).map(|x| x as f32).collect();
+        looper_fixture.check_output(&expected_back_to_dry);
+    }
+
+    #[test]
+    fn test_grain_looper_loop() {
+        check_grain_looper_loop(GrainLooperFixture::new());
+    }
+
+    #[test]
+    fn test_grain_looper_loop_stereo() {
+        check_grain_looper_loop(GrainLooperFixture::new_stereo());
+    }
+
+    #[test]
+    fn test_grain_looper_loop_quad() {
+        check_grain_looper_loop(GrainLooperFixture::new_quad());
+    }
+
+    #[test]
+    fn test_grain_looper_free_running() {
+        // when not synced the loop runs on its own clock, even if the host is stopped
+        let mut looper_fixture = GrainLooperFixture::new();
+        looper_fixture.looper.set_sync(false);
+        // tempo is ignored too
+        looper_fixture.set_tempo(137.0);
+        looper_fixture.beat_time_increment = 0.0;
+
+        let expected1 = (10..15).map(|x| x as f32).collect();
+        looper_fixture.check_output(&expected1);
+
+        looper_fixture.looper.set_fade_time(0.0);
+        // with a sample rate of 10, one sample is 0.1 seconds
+        looper_fixture.looper.set_loop_offset_seconds(0.5);
+        looper_fixture.looper.set_grid_samples(5);
+        looper_fixture.looper.start_looping();
+
+        looper_fixture.check_output(&expected1);
+        looper_fixture.check_output(&expected1);
+
+        // going back to synced stops the loop
+        looper_fixture.looper.set_sync(true);
+        assert!(!looper_fixture.looper.is_loop_requested());
+        let expected_back_to_dry = (25..30).map(|x| x as f32).collect();
+        looper_fixture.check_output(&expected_back_to_dry);
+    }
+
+    #[test]
+    fn test_grain_looper_loop_offset() {
+        // check that we can change the offset of the loop as its looping
+        let mut looper_fixture = GrainLooperFixture::new();
+
+        let expected1 = (10..20).map(|x| x as f32).collect();
+        looper_fixture.check_output(&expected1);
+
+        looper_fixture.looper.set_fade_time(0.0);
+
+        // with a tempo of 60 and a sample rate of 10, one sample is 0.1 beats
+        looper_fixture.looper.set_loop_offset(0.5);
+        looper_fixture.looper.set_grid(0.5);
+
+        looper_fixture.looper.start_looping();
+
+        let expected2 = (15..20).map(|x| x as f32).collect();
+        looper_fixture.check_output(&expected2);
+
+        // offset by one earlier compared to the previous loop
+        looper_fixture.looper.set_loop_offset(0.6);
+
+        let expected3 = (14..19).map(|x| x as f32).collect();
+        looper_fixture.check_output(&expected3);
+    }
+
+    #[test]
+    fn test_grain_looper_offset_out_of_range() {
+        // the fixture buffers only hold 18 samples of history
+        let mut looper_fixture = GrainLooperFixture::new();
+        let expected1 = (10..30).map(|x| x as f32).collect();
+        looper_fixture.check_output(&expected1);
+
+        looper_fixture.looper.set_fade_time(0.0);
+        looper_fixture.looper.set_loop_offset(2.5);
+        looper_fixture.looper.set_grid(0.5);
+        looper_fixture.looper.start_looping();
+
+        // moved to the oldest audio there is
+        let expected2 = (12..17).map(|x| x as f32).collect();
+        looper_fixture.check_output(&expected2);
+        assert_eq!(looper_fixture.looper.region_fit(), RegionFit::Moved);
+
+        looper_fixture.looper.set_region_policy(RegionPolicy::Skip);
+        looper_fixture.check_output(&vec![0.0; 5]);
+        assert_eq!(looper_fixture.looper.region_fit(), RegionFit::Skipped);
+        assert!(looper_fixture.looper.is_looping());
+
+        // back in range
+        looper_fixture.looper.set_loop_offset(0.5);
+        looper_fixture.check_output(&vec![25.0, 26.0, 27.0, 28.0, 29.0]);
+        assert_eq!(looper_fixture.looper.region_fit(), RegionFit::Fits);
+    }
+
+    #[test]
+    fn test_grain_looper_quantized_changes() {
+        // with quantized changes, a new length and offset wait for the current loop to finish
+        let mut looper_fixture = GrainLooperFixture::new();
+        looper_fixture.looper.set_quantize_changes(true);
+
+        let expected1 = (10..20).map(|x| x as f32).collect();
+        looper_fixture.check_output(&expected1);
+
+        looper_fixture.looper.set_fade_time(0.0);
+        looper_fixture.looper.set_loop_offset(0.5);
+        looper_fixture.looper.set_grid(0.5);
+        looper_fixture.looper.start_looping();
+
+        looper_fixture.check_output(&vec![15.0, 16.0]);
+        looper_fixture.looper.set_grid(0.3);
+        looper_fixture.looper.set_loop_offset(0.4);
+        // the rest of the loop is unchanged
+        looper_fixture.check_output(&vec![17.0, 18.0, 19.0]);
+
+        // then the shorter loop, starting one sample later
+        let loop2 = vec![16.0, 17.0, 18.0];
+        looper_fixture.check_output(&loop2);
+        looper_fixture.check_output(&loop2);
+
+        // changes go straight through once we stop
+        looper_fixture.looper.stop_looping();
+        looper_fixture.looper.set_grid(0.5);
+        assert!(looper_fixture.looper.staged.is_empty());
+    }
+
+    #[test]
+    fn test_grain_looper_fade_is_flat() {
+        // when we loop a DC signal we expect the fades to maintain the DC level
+        let mut looper = GrainLooper::new_with_length(10.0, 50, 4, 10);
+        looper.set_tempo(60.0);
+        let mut out = vec![];
+        for i in 0..8 {
+            out.push(looper.tick(1.0, i as f64 / 10.0));
+        }
+        // start looping immediately
+        // two samples fade
+        looper.set_fade_time(0.2);
+        // set offset to be the loop length to loop the most recent 5 samples
+        looper.set_loop_offset(0.5);
+        looper.set_grid(0.5);
+        looper.start_looping();
+        for i in 8..15 {
+            out.push(looper.tick(1.0, i as f64 / 10.0));
+        }
+
+        assert_eq!(
+            out,
+            vec![1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0]
+        );
+
+        // stop looping
+        out.clear();
+        looper.stop_looping();
+        for i in 15..20 {
+            out.push(looper.tick(1.0, i as f64 / 10.0));
+        }
+
+        // expect the dry to fade back in from whatever the loop was doing
+        assert_eq!(out, vec![1.0, 1.0, 1.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn test_grain_looper_fade_keeps_its_time_at_any_tempo() {
+        let mut looper = GrainLooper::<f32>::new_with_length(1000.0, 2000, 200, 1000);
+        looper.set_sync(true);
+        looper.set_grid(1.0);
+        for tempo in [120.0, 90.0] {
+            looper.set_tempo(tempo);
+            looper.set_fade_time(0.02);
+            looper.set_dry_fade_time(Some(0.005));
+            assert_eq!(looper.fade_samples(), 20, "{} bpm", tempo);
+            assert_eq!(looper.dry_fade_samples(), 5, "{} bpm", tempo);
+        }
+    }
+
+    #[test]
+    fn test_grain_looper_max_fade_param_reaches_the_cap() {
+        // the top of the fade params' range is the longest fade the buffers have room for
+        for sample_rate in [44100.0, 48000.0, 96000.0] {
+            let mut looper = GrainLooper::<f32>::new(sample_rate);
+            looper.set_sync(true);
+            looper.set_tempo(120.0);
+            looper.set_grid(4.0);
+            looper.set_fade_time(MAX_FADE_SECONDS);
+            assert_eq!(
+                looper.fade_samples(),
+                looper.max_fade_samples,
+                "{}",
+                sample_rate
+            );
+        }
+    }
+
+    #[test]
+    fn test_equal_power_gains() {
+        let (wet, dry) = equal_power_gains(0.5);
+        assert!((wet * wet + dry * dry - 1.0).abs() < 0.0001);
+        assert_eq!(equal_power_gains(1.0), (1.0, 0.0));
+        assert_eq!(equal_power_gains(0.0), (0.0, 1.0));
+    }
+
+    #[test]
+    fn test_grain_looper_mix() {
+        // with the mix all the way down the dry carries on while looping
+        let mut looper_fixture = GrainLooperFixture::new();
+        looper_fixture.looper.set_mix(0.0);
+
+        let expected1 = (10..15).map(|x| x as f32).collect();
+        looper_fixture.check_output(&expected1);
+
+        looper_fixture.looper.set_fade_time(0.0);
+        looper_fixture.looper.set_loop_offset(0.5);
+        looper_fixture.looper.set_grid(0.5);
+        looper_fixture.looper.start_looping();
+
+        let expected2 = (15..25).map(|x| x as f32).collect();
+        looper_fixture.check_output(&expected2);
+
+        // half way, the dry and loop are summed with equal power
+        looper_fixture.looper.set_mix(0.5);
+        let (wet, dry) = equal_power_gains(0.5);
+        let expected3 = (25..30)
+            .zip(10..15)
+            .map(|(d, l)| d as f32 * dry + l as f32 * wet)
+            .collect();
+        let mut out = vec![];
+        for _ in 0..5 {
+            out.push(looper_fixture.tick());
+        }
+        all_near(&out, &expected3, 0.0001);
+    }
+
+    #[test]
+    fn test_grain_looper_stop_now() {
+        // stopping now brings the dry back without waiting for the grid
+        let mut looper_fixture = GrainLooperFixture::new();
+        let expected1 = (10..15).map(|x| x as f32).collect();
+        looper_fixture.check_output(&expected1);
+
+        looper_fixture.looper.set_fade_time(0.0);
+        looper_fixture.looper.set_loop_offset(0.5);
+        looper_fixture.looper.set_grid(0.5);
+        looper_fixture.looper.start_looping();
+
+        let expected2 = (10..13).map(|x| x as f32).collect();
+        looper_fixture.check_output(&expected2);
+
+        looper_fixture.looper.stop_now();
+        assert!(!looper_fixture.looper.is_loop_requested());
+        let expected3 = (18..25).map(|x| x as f32).collect();
+        looper_fixture.check_output(&expected3);
+    }
+
+    #[test]
+    fn test_grain_looper_groove() {
+        // at 30 bpm a 16th is 5 samples, and the off beat 16ths are pushed a sample late
+        let mut looper_fixture = GrainLooperFixture::new();
+        looper_fixture.set_tempo(30.0);
+        looper_fixture
+            .looper
+            .set_groove(GrooveTemplate::from_steps(&[0.0, 0.2]).unwrap());
+        let expected1 = (10..20).map(|x| x as f32).collect();
+        looper_fixture.check_output(&expected1);
+
+        looper_fixture.looper.set_fade_time(0.0);
+        looper_fixture.looper.set_loop_offset(0.25);
+        looper_fixture.looper.set_grid(0.25);
+        looper_fixture.looper.start_looping();
+
+        // the loop on the beat plays on into the late one
+        let on_beat = vec![15.0, 16.0, 17.0, 18.0, 19.0, 20.0];
+        let off_beat = vec![15.0, 16.0, 17.0, 18.0];
+        looper_fixture.check_output(&on_beat);
+        looper_fixture.check_output(&off_beat);
+        looper_fixture.check_output(&on_beat);
+        looper_fixture.check_output(&off_beat);
+    }
+
+    #[test]
+    fn test_grain_looper_section_switch_on_grid() {
+        // switching section while looping waits for the next loop, fading from one to the other
+        let mut looper_fixture = GrainLooperFixture::new();
+        let expected1 = (10..15).map(|x| x as f32).collect();
+        looper_fixture.check_output(&expected1);
+
+        looper_fixture.looper.set_fade_time(0.0);
+        looper_fixture.looper.set_loop_offset(0.5);
+        looper_fixture.looper.set_grid(0.5);
+        looper_fixture.looper.start_looping();
+        looper_fixture.check_output(&expected1);
+        looper_fixture.looper.store_section(1);
+        looper_fixture.looper.stop_now();
+        let expected2 = (20..25).map(|x| x as f32).collect();
+        looper_fixture.check_output(&expected2);
+
+        looper_fixture.looper.set_fade_time(0.1);
+        looper_fixture.looper.start_looping();
+        let expected3 = vec![22.5, 21.0, 22.0, 23.0, 24.0, 22.5, 21.0];
+        looper_fixture.check_output(&expected3);
+        looper_fixture.looper.set_section(Some(1));
+        // the last loop fades out on its own audio as the section fades in
+        let expected4 = vec![
+            22.0,
+            23.0,
+            24.0,
+            0.5 * 24.0 + 0.5 * 11.0,
+            11.0,
+            12.0,
+            13.0,
+            14.0,
+        ];
+        looper_fixture.check_output(&expected4);
+        let expected5 = vec![12.5, 11.0, 12.0, 13.0, 14.0];
+        looper_fixture.check_output(&expected5);
+    }
+
+    #[test]
+    fn test_grain_looper_reverse_tail() {
+        // the loop plays once more backwards after the grid, and the dry comes back after that
+        let mut looper_fixture = GrainLooperFixture::new();
+        let expected1 = (10..15).map(|x| x as f32).collect();
+        looper_fixture.check_output(&expected1);
+
+        looper_fixture.looper.set_fade_time(0.0);
+        looper_fixture.looper.set_loop_offset(0.5);
+        looper_fixture.looper.set_grid(0.5);
+        looper_fixture.looper.start_looping();
+
+        let expected2 = (10..13).map(|x| x as f32).collect();
+        looper_fixture.check_output(&expected2);
+
+        looper_fixture.looper.stop_looping_with_reverse_tail();
+        assert!(!looper_fixture.looper.is_loop_requested());
+        let expected3 = vec![13.0, 14.0, 14.0, 13.0, 12.0, 11.0, 10.0];
+        looper_fixture.check_output(&expected3);
+        let expected4 = (25..30).map(|x| x as f32).collect();
+        looper_fixture.check_output(&expected4);
+    }
+
+    #[test]
+    fn test_grain_looper_retrigger() {
+        let mut looper_fixture = GrainLooperFixture::new();
+        let expected1 = (10..15).map(|x| x as f32).collect();
+        looper_fixture.check_output(&expected1);
+
+        looper_fixture.looper.set_fade_time(0.0);
+        looper_fixture.looper.set_min_grid(0.2);
+        looper_fixture.looper.set_loop_offset(0.5);
+        looper_fixture.looper.set_grid(0.5);
+        looper_fixture.looper.start_looping();
+        looper_fixture.check_output(&vec![10.0]);
+
+        // restarts at the next min grid line, and carries on from there
+        looper_fixture.looper.retrigger();
+        let expected2 = vec![10.0, 11.0, 12.0, 13.0, 14.0, 10.0, 11.0];
+        looper_fixture.check_output(&expected2);
+    }
+
+    #[test]
+    fn test_grain_looper_stop_now_dry_at_bar() {
+        // the dry stays out until the bar line, which is a beat and a half here
+        let mut looper_fixture = GrainLooperFixture::new();
+        let expected1 = (10..15).map(|x| x as f32).collect();
+        looper_fixture.check_output(&expected1);
+
+        looper_fixture.looper.set_fade_time(0.0);
+        looper_fixture.looper.set_loop_offset(0.5);
+        looper_fixture.looper.set_grid(0.5);
+        looper_fixture.looper.start_looping();
+
+        let expected2 = (10..13).map(|x| x as f32).collect();
+        looper_fixture.check_output(&expected2);
+
+        looper_fixture.looper.stop_now_dry_at_bar(1.5);
+        looper_fixture.check_output(&vec![0.0; 7]);
+        let expected3 = (25..30).map(|x| x as f32).collect();
+        looper_fixture.check_output(&expected3);
+    }
+
+    #[test]
+    fn test_grain_looper_section() {
+        let mut looper_fixture = GrainLooperFixture::new();
+        let expected1 = (10..15).map(|x| x as f32).collect();
+        looper_fixture.check_output(&expected1);
+
+        looper_fixture.looper.set_fade_time(0.0);
+        looper_fixture.looper.set_loop_offset(0.5);
+        looper_fixture.looper.set_grid(0.5);
+        looper_fixture.looper.start_looping();
+        looper_fixture.check_output(&expected1);
+        looper_fixture.looper.store_section(1);
+
+        looper_fixture.looper.stop_now();
+        let expected2 = (20..25).map(|x| x as f32).collect();
+        looper_fixture.check_output(&expected2);
+
+        // looping again plays the stored audio rather than the last half beat
+        looper_fixture.looper.set_section(Some(1));
+        looper_fixture.looper.start_looping();
+        looper_fixture.check_output(&expected1);
+
+        // and going back to capturing leaves it playing until the loop starts again
+        looper_fixture.looper.set_section(None);
+        looper_fixture.check_output(&expected1);
+    }
+
+    #[test]
+    fn test_grain_looper_import_region() {
+        let mut looper_fixture = GrainLooperFixture::new();
+        let expected1 = (10..15).map(|x| x as f32).collect();
+        looper_fixture.check_output(&expected1);
+
+        // the imported audio plays as though it was the input just before the loop started
+        let mut region = vec![0.0; looper_fixture.looper.region_length()];
+        let margin = looper_fixture.looper.import_margin();
+        for (slot, x) in region[margin..margin + 5].iter_mut().rev().zip(100..105) {
+            *slot = x as f32;
+        }
+        assert!(looper_fixture.looper.import_region(&mut region));
+        assert!(!looper_fixture.looper.import_region(&mut vec![0.0; 1]));
+        looper_fixture.looper.set_fade_time(0.0);
+        looper_fixture.looper.set_loop_offset(0.5);
+        looper_fixture.looper.set_grid(0.5);
+        looper_fixture.looper.start_looping();
+        let expected2 = (100..105).map(|x| x as f32).collect();
+        looper_fixture.check_output(&expected2);
+        looper_fixture.check_output(&expected2);
+
+        // looping again captures the input as usual
+        looper_fixture.looper.stop_now();
+        looper_fixture.check_output(&(25..30).map(|x| x as f32).collect());
+        looper_fixture.looper.start_looping();
+        looper_fixture.check_output(&(25..30).map(|x| x as f32).collect());
+    }
+
+    #[test]
+    fn test_grain_looper_staggered_dry() {
+        // the loop takes over both channels, then the left one's dry comes back on its own
+        let mut looper_fixture = GrainLooperFixture::new_stereo();
+        let expected1 = (10..15).map(|x| x as f32).collect();
+        looper_fixture.check_output(&expected1);
+
+        looper_fixture.looper.set_dry_link(false);
+        looper_fixture.looper.set_fade_time(0.0);
+        looper_fixture.looper.set_loop_offset(0.5);
+        looper_fixture.looper.set_grid(0.5);
+        looper_fixture.looper.start_looping();
+        let (wet, dry) = looper_fixture
+            .looper
+            .tick_split(StereoPair::new(15.0, -15.0), 0.5);
+        assert_eq!(wet, StereoPair::new(10.0, -10.0));
+        assert_eq!(dry, StereoPair::new(0.0, 0.0));
+
+        looper_fixture.looper.ramp_dry_channel(0, 1.0, 0);
+        let (wet, dry) = looper_fixture
+            .looper
+            .tick_split(StereoPair::new(16.0, -16.0), 0.6);
+        assert_eq!(wet, StereoPair::new(11.0, -11.0));
+        assert_eq!(dry, StereoPair::new(16.0, 0.0));
+
+        // linking again brings the right channel in line with the left
+        looper_fixture.looper.set_dry_link(true);
+        let (_, dry) = looper_fixture
+            .looper
+            .tick_split(StereoPair::new(17.0, -17.0), 0.7);
+        assert_eq!(dry, StereoPair::new(17.0, -17.0));
+    }
+
+    #[test]
+    fn test_grain_looper_capture_latency() {
+        // the loop reaches two samples further back than the three sample offset
+        let mut looper_fixture = GrainLooperFixture::new();
+        let expected1 = (10..15).map(|x| x as f32).collect();
+        looper_fixture.check_output(&expected1);
+
+        looper_fixture.looper.set_capture_latency(2);
+        looper_fixture.looper.set_fade_time(0.0);
+        looper_fixture.looper.set_loop_offset(0.3);
+        looper_fixture.looper.set_grid(0.5);
+        looper_fixture.looper.start_looping();
+        looper_fixture.check_output(&expected1);
+    }
+
+    #[test]
+    fn test_grain_looper_tiny_grid() {
+        // the grid is held at a 64th, and the fade cut to it
+        let mut looper_fixture = GrainLooperFixture::new();
+        looper_fixture.set_tempo(15.0);
+        looper_fixture.looper.set_fade_time(0.5);
+        looper_fixture.looper.set_grid(0.0);
+        assert_eq!(looper_fixture.looper.loop_length_samples(), 2.5);
+        assert_eq!(looper_fixture.looper.fade_duration_samples, 2);
+
+        looper_fixture.looper.set_min_grid(0.25);
+        looper_fixture.looper.set_grid(0.0);
+        assert_eq!(looper_fixture.looper.loop_length_samples(), 10.0);
+        // the grid has room for the whole fade now, but it is capped at the fixture's longest fade
+        assert_eq!(looper_fixture.looper.fade_duration_samples, 4);
+    }
+
+    #[test]
+    fn test_grain_looper_grid_automated_to_nothing() {
+        let mut looper_fixture = GrainLooperFixture::new();
+        // a 64th is two and a half samples
+        looper_fixture.set_tempo(15.0);
+        let expected1 = (10..20).map(|x| x as f32).collect();
+        looper_fixture.check_output(&expected1);
+        looper_fixture.looper.set_fade_time(0.1);
+        looper_fixture.looper.set_loop_offset(0.25);
+        looper_fixture.looper.set_grid(0.25);
+        looper_fixture.looper.start_looping();
+
+        let mut grid = 1.0;
+        let mut max_grains = 0;
+        for i in 0..2000 {
+            // a glitchy ramp down, jumping about on the way
+            grid *= 0.99;
+            let glitch = if i % 7 == 0 { 1e-9 } else { grid };
+            looper_fixture.looper.set_grid(glitch);
+            looper_fixture.tick();
+            assert!(!looper_fixture.looper.has_fault());
+            max_grains = max_grains.max(looper_fixture.looper.grain_player.num_playing_grains());
+        }
+        assert!(looper_fixture.looper.is_looping());
+        // never more often than the shortest grid, and never running out of grains
+        assert!(looper_fixture.looper.repeat_count() as f32 <= 2000.0 / 2.5);
+        assert!(max_grains < MAX_GRAINS);
+    }
+
+    #[test]
+    fn test_grain_looper_solo() {
+        // soloing while the mix is all the way down brings in the loop only
+        let mut looper_fixture = GrainLooperFixture::new();
+        looper_fixture.looper.set_mix(0.0);
+
+        let expected1 = (10..15).map(|x| x as f32).collect();
+        looper_fixture.check_output(&expected1);
+
+        looper_fixture.looper.set_fade_time(0.0);
+        looper_fixture.looper.set_loop_offset(0.5);
+        looper_fixture.looper.set_grid(0.5);
+        looper_fixture.looper.start_looping();
+
+        let expected2 = (15..20).map(|x| x as f32).collect();
+        looper_fixture.check_output(&expected2);
+
+        looper_fixture.looper.set_solo(true);
+        let expected3 = (10..15).map(|x| x as f32).collect();
+        looper_fixture.check_output(&expected3);
+
+        // letting go goes back to the mix
+        looper_fixture.looper.set_solo(false);
+        let expected4 = (25..30).map(|x| x as f32).collect();
+        looper_fixture.check_output(&expected4);
+    }
+
+    #[test]
+    fn test_grain_looper_fault_passes_through() {
+        let mut looper_fixture = GrainLooperFixture::new();
+
+        let expected1 = (10..15).map(|x| x as f32).collect();
+        looper_fixture.check_output(&expected1);
+
+        looper_fixture.looper.set_fade_time(0.0);
+        looper_fixture.looper.set_loop_offset(0.5);
+        looper_fixture.looper.set_grid(0.5);
+        looper_fixture.looper.start_looping();
+        // starting twice is inconsistent
+        looper_fixture.looper.start_looping();
+        assert!(looper_fixture.looper.has_fault());
+
+        let expected2 = (15..25).map(|x| x as f32).collect();
+        looper_fixture.check_output(&expected2);
+
+        looper_fixture.looper.reset();
+        assert!(!looper_fixture.looper.has_fault());
+        assert!(!looper_fixture.looper.is_loop_requested());
+    }
+
+    fn overlap_fade(loop_grain_contents: Vec<f32>, fades: Vec<f32>, fade_len: usize) -> Vec<f32> {
+        let faded_grain = loop_grain_contents
+            .iter()
+            .zip(fades.iter())
+            .map(|(a, b)| a * b)
+            .collect::<Vec<f32>>();
+
+        let mut loop_overlapped = vec![];
+        let overlap_len = fades.len() - fade_len;
+        for i in 0..overlap_len {
+            let overlapped = faded_grain.get(i + overlap_len).unwrap_or(&0.0);
+            loop_overlapped.push(faded_grain[i] + overlapped);
         }
+        loop_overlapped
+    }
+
+    fn check_grain_looper_fade<T: AudioFrame + PartialEq + Debug>(
+        mut looper_fixture: GrainLooperFixture<T>,
+    ) {
+        // test that a single loop fades into the next loop
+        let expected1 = (10..20).map(|x| x as f32).collect();
+        looper_fixture.check_output(&expected1);
+
+        // two samples fade
+        looper_fixture.looper.set_fade_time(0.1);
+
+        // set offset to be the loop length to loop the most recent 4 samples, 16,17,18,19
+        looper_fixture.looper.set_loop_offset(0.5);
+        looper_fixture.looper.set_grid(0.5);
+
+        looper_fixture.looper.start_looping();
+
+        // loop len 5 but with one sample fade at start and end
+        let loop_grain_contents = vec![15.0, 16.0, 17.0, 18.0, 19.0, 20.0];
+        let fades = vec![0.5, 1.0, 1.0, 1.0, 1.0, 0.5];
+
+        let loop_overlapped = overlap_fade(loop_grain_contents.clone(), fades.clone(), 1);
+
+        looper_fixture.check_output(&loop_overlapped);
+        looper_fixture.check_output(&loop_overlapped);
+        looper_fixture.check_output(&loop_overlapped);
+
+        looper_fixture.looper.stop_looping();
+
+        let expected_end = vec![27.5, 36.0, 37.0, 38.0, 39.0, 40.0];
+
+        looper_fixture.check_output(&expected_end);
+    }
+
+    #[test]
+    fn test_grain_looper_fade() {
+        check_grain_looper_fade(GrainLooperFixture::new());
+    }
+
+    #[test]
+    fn test_grain_looper_fade_stereo() {
+        check_grain_looper_fade(GrainLooperFixture::new_stereo());
+    }
+
+    #[test]
+    fn test_grain_looper_tweak_loop() {
+        // test that we can change the offset and length and reversal of the loop
+        let mut looper_fixture = GrainLooperFixture::new();
+
+        let expected1 = (10..18).map(|x| x as f32).collect();
+        looper_fixture.check_output(&expected1);
+
+        looper_fixture.looper.set_fade_time(0.0);
+        // set offset to be the loop length to loop the most recent 4 samples (8,9,10,11)
+        looper_fixture.looper.set_loop_offset(0.0);
+        looper_fixture.looper.set_grid(0.4);
+        looper_fixture.looper.start_looping();
+
+        let first_loop = vec![18.0, 19.0, 20.0, 21.0];
+        looper_fixture.check_output(&first_loop);
+
+        // offset the loop backwards by 2 samples (16,17,18)
+        looper_fixture.looper.set_loop_offset(0.2);
+        looper_fixture.looper.set_grid(0.3);
+        // change the length of the loop to be 3 samples (18,17,16)
+        looper_fixture.looper.set_reverse(true);
+        // slow the loop down by half (18,17.5,17)
+        looper_fixture.looper.set_speed(0.5);
+
+        let second_loop = vec![18.0, 17.5, 17.0];
+
+        looper_fixture.check_output(&second_loop);
+        looper_fixture.check_output(&second_loop);
+        looper_fixture.check_output(&second_loop);
+    }
+
+    #[test]
+    fn test_grain_looper_reverse_whole() {
+        let mut looper_fixture = GrainLooperFixture::new();
+        looper_fixture.check_output(&(10..18).map(|x| x as f32).collect());
+
+        // an eighth of a beat a sample, so the stutters land exactly on a sample
+        looper_fixture.set_tempo(75.0);
+        looper_fixture.looper.set_fade_time(0.0);
+        looper_fixture.looper.set_loop_offset(0.25);
+        looper_fixture.looper.set_grid(0.5);
+        looper_fixture.looper.set_reverse_compensation(true);
+        looper_fixture.looper.set_stutter(Stutter::Half);
+        looper_fixture.looper.start_looping();
+        looper_fixture.check_output(&vec![18.0, 19.0]);
+        // the loop is 16 to 19, so each half is 16 and 17
+        looper_fixture.check_output(&vec![16.0, 17.0, 16.0, 17.0]);
+
+        // each half reversed in place
+        looper_fixture.looper.set_reverse_mode(ReverseMode::Reverse);
+        looper_fixture.check_output(&vec![17.0, 16.0, 17.0, 16.0]);
+
+        // the loop reversed, so the stutters come from its end
+        looper_fixture
+            .looper
+            .set_reverse_mode(ReverseMode::ReverseWhole);
+        looper_fixture.check_output(&vec![19.0, 18.0, 19.0, 18.0]);
+        looper_fixture.check_output(&vec![19.0, 18.0, 19.0, 18.0]);
+    }
+
+    #[test]
+    fn test_grain_looper_alternate() {
+        let mut looper_fixture = GrainLooperFixture::new();
+        looper_fixture.check_output(&(10..18).map(|x| x as f32).collect());
+
+        looper_fixture.looper.set_fade_time(0.0);
+        looper_fixture.looper.set_loop_offset(0.2);
+        looper_fixture.looper.set_grid(0.4);
+        looper_fixture.looper.set_reverse_compensation(true);
+        looper_fixture
+            .looper
+            .set_reverse_mode(ReverseMode::Alternate);
+        looper_fixture.looper.start_looping();
+
+        let forward_loop = vec![16.0, 17.0, 18.0, 19.0];
+        let reverse_loop = vec![19.0, 18.0, 17.0, 16.0];
+        looper_fixture.check_output(&forward_loop);
+        looper_fixture.check_output(&reverse_loop);
+        looper_fixture.check_output(&forward_loop);
 
-        assert_eq!(
-            out,
-            vec![1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0]
-        );
+        looper_fixture.looper.set_reverse_mode(ReverseMode::Reverse);
+        looper_fixture.check_output(&reverse_loop);
+        looper_fixture.check_output(&reverse_loop);
+    }
 
-        // stop looping
-        out.clear();
-        looper.stop_looping();
-        for i in 15..20 {
-            out.push(looper.tick(1.0, i as f64 / 10.0));
+    #[test]
+    fn test_grain_looper_active_grains() {
+        let mut looper_fixture = GrainLooperFixture::new();
+        looper_fixture.check_output(&(10..18).map(|x| x as f32).collect());
+        assert_eq!(looper_fixture.looper.active_grains().count(), 0);
+
+        // a grain for each loop, going the way the loop does
+        looper_fixture.looper.set_fade_time(0.0);
+        looper_fixture.looper.set_loop_offset(0.2);
+        looper_fixture.looper.set_grid(0.4);
+        looper_fixture.looper.set_reverse_compensation(true);
+        looper_fixture
+            .looper
+            .set_reverse_mode(ReverseMode::Alternate);
+        looper_fixture.looper.start_looping();
+        // once it has played its last sample it's finished, until the next loop's starts
+        for reverse in [false, true, false] {
+            let mut progress = Vec::new();
+            for _ in 0..4 {
+                looper_fixture.tick();
+                for view in looper_fixture.looper.active_grains() {
+                    assert_eq!(view.reverse, reverse);
+                    assert_eq!(view.speed, 1.0);
+                    progress.push(view.progress);
+                }
+            }
+            assert_eq!(progress, vec![0.25, 0.5, 0.75]);
         }
+    }
 
-        // expect the dry to fade back in from whatever the loop was doing
-        assert_eq!(out, vec![1.0, 1.0, 1.0, 1.0, 1.0]);
+    #[test]
+    fn test_grain_looper_reverse_compensation() {
+        // as tweak_loop, but the reversed loop plays the audio the forward one would have
+        let mut looper_fixture = GrainLooperFixture::new();
+
+        let expected1 = (10..18).map(|x| x as f32).collect();
+        looper_fixture.check_output(&expected1);
+
+        looper_fixture.looper.set_fade_time(0.0);
+        looper_fixture.looper.set_reverse_compensation(true);
+        looper_fixture.looper.set_loop_offset(0.2);
+        looper_fixture.looper.set_grid(0.4);
+        looper_fixture.looper.set_speed(0.5);
+        looper_fixture.looper.start_looping();
+
+        let forward_loop = vec![16.0, 16.5, 17.0, 17.5];
+        looper_fixture.check_output(&forward_loop);
+
+        looper_fixture.looper.set_reverse(true);
+        let reverse_loop = vec![17.5, 17.0, 16.5, 16.0];
+        looper_fixture.check_output(&reverse_loop);
+        looper_fixture.check_output(&reverse_loop);
     }
 
-    fn overlap_fade(loop_grain_contents: Vec<f32>, fades: Vec<f32>, fade_len: usize) -> Vec<f32> {
-        let faded_grain = loop_grain_contents
-            .iter()
-            .zip(fades.iter())
-            .map(|(a, b)| a * b)
-            .collect::<Vec<f32>>();
+    #[test]
+    fn test_grain_looper_pitch() {
+        let mut looper_fixture = GrainLooperFixture::new();
 
-        let mut loop_overlapped = vec![];
-        let overlap_len = fades.len() - fade_len;
-        for i in 0..overlap_len {
-            let overlapped = faded_grain.get(i + overlap_len).unwrap_or(&0.0);
-            loop_overlapped.push(faded_grain[i] + overlapped);
+        let expected1 = (10..18).map(|x| x as f32).collect();
+        looper_fixture.check_output(&expected1);
+
+        looper_fixture.looper.set_fade_time(0.0);
+        looper_fixture.looper.set_loop_offset(0.2);
+        looper_fixture.looper.set_grid(0.4);
+        // an octave down plays at half speed, but the loop is still 4 samples long
+        looper_fixture.looper.set_pitch(-12.0);
+        looper_fixture.looper.start_looping();
+
+        let octave_down = vec![16.0, 16.5, 17.0, 17.5];
+        looper_fixture.check_output(&octave_down);
+        looper_fixture.check_output(&octave_down);
+
+        // pitch and speed multiply
+        looper_fixture.looper.set_speed(0.5);
+        looper_fixture.looper.set_pitch(12.0);
+        let unshifted = vec![16.0, 17.0, 18.0, 19.0];
+        looper_fixture.check_output(&unshifted);
+        looper_fixture.check_output(&unshifted);
+    }
+
+    #[test]
+    fn test_grain_looper_time_stretch() {
+        let mut looper_fixture = GrainLooperFixture::new();
+        looper_fixture.check_output(&(10..18).map(|x| x as f32).collect());
+
+        looper_fixture.looper.set_fade_time(0.0);
+        looper_fixture.looper.set_loop_offset(0.0);
+        looper_fixture.looper.set_grid(1.0);
+        looper_fixture.looper.set_speed(0.5);
+        looper_fixture.looper.set_time_stretch(true, 0.4, 2.0);
+        looper_fixture.looper.start_looping();
+        let output: Vec<f32> = (0..32).map(|_| looper_fixture.tick()).collect();
+        // half speed, but the grains play at the pitch of the audio
+        let grain = looper_fixture.looper.grain_player.most_recent_grain();
+        assert_eq!(grain.map(|grain| grain.speed()), Some(1.0));
+        // and the loop, which starts on the grid two samples in, is still as long as the grid
+        all_near(&output[12..22].to_vec(), &output[22..32].to_vec(), 0.0001);
+    }
+
+    #[test]
+    fn test_grain_looper_offset_jitter() {
+        let loops = |jitter: f32| {
+            let mut looper_fixture = GrainLooperFixture::new();
+            looper_fixture.check_output(&(10..18).map(|x| x as f32).collect());
+            looper_fixture.looper.set_fade_time(0.0);
+            looper_fixture.looper.set_loop_offset(0.2);
+            looper_fixture.looper.set_grid(0.4);
+            looper_fixture.looper.set_offset_jitter(jitter);
+            looper_fixture.looper.start_looping();
+            let loops: Vec<Vec<f32>> = (0..4)
+                .map(|_| (0..4).map(|_| looper_fixture.tick()).collect())
+                .collect();
+            assert!(!looper_fixture.looper.has_fault());
+            loops
+        };
+        let steady = loops(0.0);
+        assert!(steady.iter().all(|repeat| *repeat == steady[0]));
+
+        // each loop starts somewhere within half a loop of the offset, which is two samples
+        let jittered = loops(0.5);
+        assert!(jittered.iter().any(|repeat| *repeat != steady[0]));
+        for repeat in jittered.iter() {
+            assert!((repeat[0] - steady[0][0]).abs() <= 2.0, "{:?}", jittered);
         }
-        loop_overlapped
+        // and the same each time
+        assert_eq!(loops(0.5), jittered);
     }
 
     #[test]
-    fn test_grain_looper_fade() {
-        // test that a single loop fades into the next loop
+    fn test_grain_looper_stereo_spread() {
+        let pans = |spread: f32| {
+            let mut looper_fixture = GrainLooperFixture::new_stereo();
+            looper_fixture.check_output(&(10..18).map(|x| x as f32).collect());
+            looper_fixture.looper.set_fade_time(0.0);
+            looper_fixture.looper.set_loop_offset(0.2);
+            looper_fixture.looper.set_grid(0.4);
+            looper_fixture.looper.set_stereo_spread(spread);
+            looper_fixture.looper.start_looping();
+            // the input is the same either side, only inverted, so the pan is what sets them apart
+            let pans: Vec<Vec<f32>> = (0..4)
+                .map(|_| {
+                    (0..4)
+                        .map(|_| {
+                            let out = looper_fixture.tick();
+                            let level = out.left.max(-out.right);
+                            (-out.right - out.left) / level
+                        })
+                        .collect()
+                })
+                .collect();
+            assert!(!looper_fixture.looper.has_fault());
+            pans
+        };
+        assert!(pans(0.0).iter().flatten().all(|&pan| pan == 0.0));
+
+        // each repeat is panned as a whole, somewhere within the spread
+        let spread = pans(0.5);
+        for repeat in spread.iter() {
+            assert!(repeat.iter().all(|pan| (pan - repeat[0]).abs() < 1e-5));
+            assert!(repeat[0].abs() <= 0.5, "{:?}", spread);
+        }
+        assert!(spread.iter().any(|repeat| repeat[0] != spread[0][0]));
+    }
+
+    #[test]
+    fn test_grain_looper_tape_stop() {
         let mut looper_fixture = GrainLooperFixture::new();
+        looper_fixture.check_output(&(10..18).map(|x| x as f32).collect());
 
-        let expected1 = (10..20).map(|x| x as f32).collect();
-        looper_fixture.check_output(&expected1);
+        looper_fixture.looper.set_fade_time(0.0);
+        looper_fixture.looper.set_loop_offset(0.2);
+        looper_fixture.looper.set_grid(0.4);
+        looper_fixture.looper.set_tape_stop_time(0.3);
+        looper_fixture.looper.start_looping();
+        looper_fixture.check_output(&vec![16.0, 17.0, 18.0, 19.0]);
+
+        // over four samples the loop slows, reading less far each sample and getting quieter
+        looper_fixture.looper.set_tape_stop(true);
+        let stopping: Vec<f32> = (0..4).map(|_| looper_fixture.tick()).collect();
+        all_near(
+            &stopping,
+            &vec![16.0 * 0.75, 16.75 * 0.5, 17.25 * 0.25, 0.0],
+            0.0001,
+        );
+        // new loops stay stopped
+        looper_fixture.check_output(&vec![0.0; 4]);
 
-        // two samples fade
-        looper_fixture.looper.set_fade_time(0.1);
+        // and come back up to speed on release
+        looper_fixture.looper.set_tape_stop(false);
+        looper_fixture.check_output(&vec![16.0 * 0.25, 16.25 * 0.5, 16.75 * 0.75, 17.5]);
+        looper_fixture.check_output(&vec![16.0, 17.0, 18.0, 19.0]);
+    }
 
-        // set offset to be the loop length to loop the most recent 4 samples, 16,17,18,19
-        looper_fixture.looper.set_loop_offset(0.5);
-        looper_fixture.looper.set_grid(0.5);
+    #[test]
+    fn test_grain_looper_history_follows_sample_rate() {
+        for sample_rate in [44100.0, 96000.0] {
+            let looper = GrainLooper::<f32>::new(sample_rate);
+            let oldest = looper.grain_player.readable_range().oldest + 2.0;
+            assert!((oldest / sample_rate - HISTORY_SECONDS).abs() < 0.001);
+        }
+    }
+
+    #[test]
+    fn test_grain_looper_loop_envelope() {
+        let mut looper_fixture = GrainLooperFixture::new();
+        looper_fixture.check_output(&(10..18).map(|x| x as f32).collect());
 
+        looper_fixture.looper.set_fade_time(0.0);
+        looper_fixture.looper.set_loop_offset(0.2);
+        looper_fixture.looper.set_grid(0.4);
+        looper_fixture.looper.set_loop_envelope(0.25, 0.5);
         looper_fixture.looper.start_looping();
+        // up over the first sample, and down over the next two, every repeat
+        for _ in 0..2 {
+            let out: Vec<f32> = (0..4).map(|_| looper_fixture.tick()).collect();
+            all_near(
+                &out,
+                &vec![16.0 * 0.5, 17.0, 18.0 * 2.0 / 3.0, 19.0 / 3.0],
+                0.0001,
+            );
+        }
+    }
 
-        // loop len 5 but with one sample fade at start and end
-        let loop_grain_contents = vec![15.0, 16.0, 17.0, 18.0, 19.0, 20.0];
-        let fades = vec![0.5, 1.0, 1.0, 1.0, 1.0, 0.5];
+    #[test]
+    fn test_grain_looper_dry_fade_time() {
+        let mut looper_fixture = GrainLooperFixture::new();
+        looper_fixture.check_output(&(10..18).map(|x| x as f32).collect());
 
-        let loop_overlapped = overlap_fade(loop_grain_contents.clone(), fades.clone(), 1);
+        looper_fixture.looper.set_fade_time(0.2);
+        looper_fixture.looper.set_dry_fade_time(Some(0.0));
+        looper_fixture.looper.set_loop_offset(0.4);
+        looper_fixture.looper.set_grid(0.4);
+        looper_fixture.looper.start_looping();
+        looper_fixture.check_output(&vec![18.0, 19.0]);
+        // the loop fades in over two samples, but the dry is cut straight away
+        let out: Vec<f32> = (0..4).map(|_| looper_fixture.tick()).collect();
+        all_near(
+            &out,
+            &vec![14.0 / 3.0, 15.0 * 2.0 / 3.0, 16.0, 17.0],
+            0.0001,
+        );
+    }
 
-        looper_fixture.check_output(&loop_overlapped);
-        looper_fixture.check_output(&loop_overlapped);
-        looper_fixture.check_output(&loop_overlapped);
+    #[test]
+    fn test_grain_looper_dry_duck_level() {
+        let mut looper_fixture = GrainLooperFixture::new();
+        looper_fixture.check_output(&(10..18).map(|x| x as f32).collect());
 
+        looper_fixture.looper.set_fade_time(0.0);
+        looper_fixture.looper.set_loop_offset(0.2);
+        looper_fixture.looper.set_grid(0.4);
+        looper_fixture.looper.set_dry_duck_level(0.5);
+        looper_fixture.looper.start_looping();
+        // the dry carries on at half level under the loop
+        looper_fixture.check_output(&vec![16.0 + 9.0, 17.0 + 9.5, 18.0 + 10.0, 19.0 + 10.5]);
         looper_fixture.looper.stop_looping();
+        looper_fixture.check_output(&vec![22.0, 23.0, 24.0, 25.0]);
+    }
 
-        let expected_end = vec![27.5, 36.0, 37.0, 38.0, 39.0, 40.0];
+    #[test]
+    fn test_grain_looper_instant_capture() {
+        let mut looper_fixture = GrainLooperFixture::new();
+        looper_fixture.check_output(&(10..17).map(|x| x as f32).collect());
 
-        looper_fixture.check_output(&expected_end);
+        looper_fixture.looper.set_fade_time(0.2);
+        looper_fixture.looper.set_loop_offset(0.4);
+        looper_fixture.looper.set_grid(0.4);
+        looper_fixture.looper.set_instant_capture(true);
+        looper_fixture.looper.start_looping();
+        // off the grid, the loop cuts in on the next sample without fading over the dry
+        looper_fixture.check_output(&vec![13.0, 14.0, 15.0, 16.0]);
+        // and the repeats cross over with the usual fade
+        let repeat: Vec<f32> = (0..4).map(|_| looper_fixture.tick()).collect();
+        all_near(
+            &repeat,
+            &vec![15.0 + 2.0 / 3.0, 15.0 + 1.0 / 3.0, 15.0, 16.0],
+            0.0001,
+        );
+        assert!(!looper_fixture.looper.has_fault());
     }
 
     #[test]
-    fn test_grain_looper_tweak_loop() {
-        // test that we can change the offset and length and reversal of the loop
+    fn test_grain_looper_speed_glide() {
         let mut looper_fixture = GrainLooperFixture::new();
+        looper_fixture.check_output(&(10..18).map(|x| x as f32).collect());
 
-        let expected1 = (10..18).map(|x| x as f32).collect();
-        looper_fixture.check_output(&expected1);
+        looper_fixture.looper.set_fade_time(0.0);
+        looper_fixture.looper.set_loop_offset(0.4);
+        looper_fixture.looper.set_grid(0.4);
+        looper_fixture.looper.set_speed_glide(0.3);
+        looper_fixture.looper.start_looping();
+        looper_fixture.check_output(&vec![14.0, 15.0, 16.0, 17.0]);
+
+        // the loop playing slows down over four samples, rather than the next one jumping
+        looper_fixture.looper.set_speed(0.5);
+        looper_fixture.check_output(&vec![14.0, 14.875, 15.625, 16.25]);
+        looper_fixture.check_output(&vec![14.0, 14.5, 15.0, 15.5]);
+        assert!(!looper_fixture.looper.has_fault());
+    }
+
+    #[test]
+    fn test_grain_looper_feedback() {
+        let mut looper_fixture = GrainLooperFixture::new();
+        looper_fixture.check_output(&(10..18).map(|x| x as f32).collect());
 
         looper_fixture.looper.set_fade_time(0.0);
-        // set offset to be the loop length to loop the most recent 4 samples (8,9,10,11)
         looper_fixture.looper.set_loop_offset(0.0);
         looper_fixture.looper.set_grid(0.4);
+        looper_fixture.looper.set_feedback(0.5);
         looper_fixture.looper.start_looping();
 
         let first_loop = vec![18.0, 19.0, 20.0, 21.0];
         looper_fixture.check_output(&first_loop);
+        for level in [0.5, 0.25, 0.125] {
+            let repeat: Vec<f32> = first_loop.iter().map(|x| x * level).collect();
+            looper_fixture.check_output(&repeat);
+        }
 
-        // offset the loop backwards by 2 samples (16,17,18)
-        looper_fixture.looper.set_loop_offset(0.2);
-        looper_fixture.looper.set_grid(0.3);
-        // change the length of the loop to be 3 samples (18,17,16)
-        looper_fixture.looper.set_reverse(true);
-        // slow the loop down by half (18,17.5,17)
-        looper_fixture.looper.set_speed(0.5);
-
-        let second_loop = vec![18.0, 17.5, 17.0];
-
-        looper_fixture.check_output(&second_loop);
-        looper_fixture.check_output(&second_loop);
-        looper_fixture.check_output(&second_loop);
+        // a new loop starts back at full level
+        looper_fixture.looper.stop_looping();
+        looper_fixture.check_output(&vec![34.0, 35.0, 36.0, 37.0]);
+        looper_fixture.looper.start_looping();
+        looper_fixture.check_output(&vec![38.0, 39.0, 40.0, 41.0]);
+        looper_fixture.check_output(&vec![19.0, 19.5, 20.0, 20.5]);
     }
 
     #[test]
@@ -512,6 +2718,41 @@ mod tests {
         looper_fixture.check_output(&loop_samples);
     }
 
+    // the loop around a reverse, with or without the gain compensation
+    fn reverse_change_output(compensation: bool) -> Vec<f32> {
+        let mut looper_fixture = GrainLooperFixture::new();
+        looper_fixture.check_output(&(10..20).map(|x| x as f32).collect());
+        looper_fixture
+            .looper
+            .set_speed_gain_compensation(compensation);
+        looper_fixture.looper.set_fade_time(0.2);
+        looper_fixture.looper.set_loop_offset(0.5);
+        looper_fixture.looper.set_grid(0.5);
+        looper_fixture.looper.start_looping();
+        for _ in 0..10 {
+            looper_fixture.tick();
+        }
+        looper_fixture.looper.set_reverse(true);
+        (0..15).map(|_| looper_fixture.tick()).collect()
+    }
+
+    #[test]
+    fn test_grain_looper_speed_gain_compensation() {
+        let plain = reverse_change_output(false);
+        let compensated = reverse_change_output(true);
+        // louder only while the reversed grain fades in
+        let louder: Vec<usize> = (0..plain.len())
+            .filter(|&i| compensated[i] > plain[i] + 0.0001)
+            .collect();
+        assert!(!louder.is_empty() && louder.len() <= 2, "{:?}", louder);
+        let last = louder[louder.len() - 1];
+        all_near(
+            &compensated[last + 1..].to_vec(),
+            &plain[last + 1..].to_vec(),
+            0.0001,
+        );
+    }
+
     #[test]
     fn test_grain_looper_short_to_long() {
         // test that if a short loop is changed to a longer loop, it still starts in the same place
@@ -544,11 +2785,10 @@ mod tests {
         looper_fixture.check_output(&loop_two);
     }
 
-    #[test]
-    fn test_grain_looper_change_tempo() {
+    fn check_grain_looper_change_tempo<T: AudioFrame + PartialEq + Debug>(
+        mut looper_fixture: GrainLooperFixture<T>,
+    ) {
         // test that if tempo changes, the loop length changes
-        let mut looper_fixture = GrainLooperFixture::new();
-
         let loop_beats = 0.4;
 
         let initial = vec![10.0, 11.0, 12.0, 13.0, 14.0, 15.0, 16.0, 17.0];
@@ -565,14 +2805,237 @@ mod tests {
         // double tempo
         looper_fixture.set_tempo(120.0);
 
+        // the beat times no longer land exactly on the grid, but the loops still do
         let loop2 = vec![14.0, 15.0];
-        looper_fixture.check_output(&loop2);
-        looper_fixture.check_output(&loop2);
-        looper_fixture.check_output(&loop2);
+        for _ in 0..8 {
+            looper_fixture.check_output(&loop2);
+        }
+    }
 
-        // this goes wrong... why? something wrong with static buffer now tempo changes?
-        let loop_wrong = vec![0.0, 14.0, 15.0];
-        looper_fixture.check_output(&loop_wrong);
-        looper_fixture.check_output(&loop2);
+    // the dry gain heard while looping, across a change of length
+    fn transition_dry_gains(smoothness: f32) -> Vec<f32> {
+        let mut looper_fixture = GrainLooperFixture::new();
+        looper_fixture.check_output(&(10..20).map(|x| x as f32).collect());
+        looper_fixture.looper.set_fade_time(0.2);
+        looper_fixture.looper.set_transition_smoothness(smoothness);
+        looper_fixture.looper.set_loop_offset(0.5);
+        looper_fixture.looper.set_grid(0.5);
+        looper_fixture.looper.start_looping();
+        for _ in 0..10 {
+            looper_fixture.tick();
+        }
+
+        looper_fixture.looper.set_grid(0.3);
+        (0..15)
+            .map(|_| {
+                let input = looper_fixture.input.next().unwrap() as f32;
+                let (_, dry) = looper_fixture
+                    .looper
+                    .tick_split(input, looper_fixture.beat_time);
+                looper_fixture.beat_time += looper_fixture.beat_time_increment;
+                dry / input
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_grain_looper_transition_smoothness() {
+        // up to halfway the dry stays out
+        assert!(transition_dry_gains(0.5).iter().all(|&gain| gain == 0.0));
+        assert_eq!(transition_dry_gains(0.0), transition_dry_gains(0.5));
+
+        // fully smooth, the dry comes up towards half under the change and goes again
+        let gains = transition_dry_gains(1.0);
+        let peak = gains.iter().cloned().fold(0.0, f32::max);
+        assert!(
+            peak > 0.2 && peak <= MAX_TRANSITION_DRY_BLEND,
+            "{:?}",
+            gains
+        );
+        assert_eq!(*gains.last().unwrap(), 0.0);
+
+        // the transition fade follows the smoothness, still capped by the buffers
+        let mut looper_fixture = GrainLooperFixture::new();
+        looper_fixture.looper.set_grid(0.5);
+        looper_fixture.looper.set_fade_time(0.1);
+        looper_fixture.looper.set_transition_smoothness(0.0);
+        assert_eq!(looper_fixture.looper.transition_fade_samples, 0);
+        looper_fixture.looper.set_transition_smoothness(1.0);
+        assert_eq!(looper_fixture.looper.transition_fade_samples, 2);
+        looper_fixture.looper.set_fade_time(0.3);
+        assert_eq!(looper_fixture.looper.transition_fade_samples, 4);
+    }
+
+    #[test]
+    fn test_grain_looper_tempo_ramp() {
+        let mut looper_fixture = GrainLooperFixture::new();
+        looper_fixture.check_output(&(10..18).map(|x| x as f32).collect());
+        looper_fixture.looper.set_fade_time(0.0);
+        looper_fixture.looper.set_loop_offset(0.4);
+        looper_fixture.looper.set_grid(0.4);
+        looper_fixture.looper.start_looping();
+
+        // speeding up every sample, the loops get shorter but never leave a gap or overlap
+        for i in 0..60 {
+            looper_fixture.set_tempo(60.0 + i as f32);
+            let sample = looper_fixture.tick();
+            assert!((13.99..17.01).contains(&sample), "{} at {}", sample, i);
+        }
+        assert!(!looper_fixture.looper.has_fault());
+    }
+
+    #[test]
+    fn test_grain_looper_change_tempo() {
+        check_grain_looper_change_tempo(GrainLooperFixture::new());
+    }
+
+    #[test]
+    fn test_grain_looper_change_tempo_stereo() {
+        check_grain_looper_change_tempo(GrainLooperFixture::new_stereo());
+    }
+
+    #[test]
+    fn test_grain_looper_tick_block() {
+        // processing in blocks gives the same as ticking each sample with its beat time
+        let mut looper_sample = GrainLooper::<f32>::new_with_length(10.0, 20, 4, 10);
+        let mut looper_block = GrainLooper::<f32>::new_with_length(10.0, 20, 4, 10);
+        for looper in [&mut looper_sample, &mut looper_block] {
+            looper.set_tempo(60.0);
+            looper.set_fade_time(0.1);
+            looper.set_loop_offset(0.5);
+            looper.set_grid(0.5);
+            looper.start_looping();
+        }
+
+        let input: Vec<f32> = (10..40).map(|x| x as f32).collect();
+
+        let mut expected = vec![];
+        for (i, x) in input.iter().enumerate() {
+            expected.push(looper_sample.tick(*x, i as f64 * 0.1));
+        }
+
+        let mut out = input.clone();
+        for (block_index, block) in out.chunks_mut(8).enumerate() {
+            let context = ProcessContextLite {
+                tempo: 60.0,
+                beat_time: block_index as f64 * 0.8,
+                sample_rate: 10.0,
+                is_playing: true,
+                time_sig: (4, 4),
+            };
+            looper_block.tick_block(&context, block);
+        }
+
+        all_near(&out, &expected, 0.0001);
+    }
+
+    #[test]
+    fn test_grain_looper_process_block() {
+        // the runs between events go through as blocks, which shouldn't change what comes out
+        let new_looper = || {
+            let mut looper = GrainLooper::<StereoPair<f32>>::new(1000.0);
+            looper.set_tempo(120.0);
+            looper.set_lookahead(3);
+            looper.set_fade_time(0.01);
+            looper.set_loop_offset(0.25);
+            looper.set_grid(0.125);
+            looper.set_stereo_spread(0.5);
+            looper.set_offset_jitter(0.25);
+            looper
+        };
+        let mut looper_sample = new_looper();
+        let mut looper_block = new_looper();
+        let beat_times: Vec<f64> = (0..3000).map(|i| i as f64 / 500.0).collect();
+        let input: Vec<StereoPair<f32>> = (0..3000)
+            .map(|i| StereoPair::new((i as f32 * 0.05).sin(), (i as f32 * 0.03).cos()))
+            .collect();
+
+        let mut expected = vec![];
+        let mut out = vec![StereoPair::default(); input.len()];
+        // blocks of odd sizes, looping for a while in the middle
+        let mut start = 0;
+        for (block_index, length) in [97, 131, 64, 300, 7, 1, 400, 500, 500, 1000]
+            .iter()
+            .enumerate()
+        {
+            if block_index == 2 || block_index == 7 {
+                looper_sample.start_looping();
+                looper_block.start_looping();
+            }
+            if block_index == 5 {
+                looper_sample.stop_looping();
+                looper_block.stop_looping();
+            }
+            let run = start..start + length;
+            for i in run.clone() {
+                expected.push(looper_sample.tick(input[i], beat_times[i]));
+            }
+            looper_block.process_block(
+                &input[run.clone()],
+                &mut out[run.clone()],
+                &beat_times[run],
+            );
+            start += length;
+        }
+        assert!(!looper_block.has_fault());
+        assert!(looper_sample.num_playing_grains() > 0);
+        // the rolling buffer is read from the end of the block, which can round the
+        // interpolation differently
+        for (i, (out, expected)) in out.iter().zip(expected.iter()).enumerate() {
+            let error = (out.left - expected.left).abs() + (out.right - expected.right).abs();
+            assert!(error < 1e-5, "sample {}", i);
+        }
+    }
+
+    #[test]
+    fn test_grain_looper_tick_split() {
+        let mut looper_fixture = GrainLooperFixture::new();
+        looper_fixture.looper.set_mix(0.5);
+        looper_fixture.check_output(&(10..15).map(|x| x as f32).collect());
+
+        looper_fixture.looper.set_fade_time(0.0);
+        looper_fixture.looper.set_loop_offset(0.5);
+        looper_fixture.looper.set_grid(0.5);
+        looper_fixture.looper.start_looping();
+
+        let (wet_gain, dry_gain) = equal_power_gains(0.5);
+        for i in 15..25 {
+            let (wet, dry) = looper_fixture
+                .looper
+                .tick_split(i as f32, looper_fixture.beat_time);
+            looper_fixture.beat_time += looper_fixture.beat_time_increment;
+            assert!((dry - i as f32 * dry_gain).abs() < 0.0001);
+            // the five samples before looping started repeat
+            assert!((wet - (10 + (i - 15) % 5) as f32 * wet_gain).abs() < 0.0001);
+        }
+    }
+
+    #[test]
+    fn test_grain_looper_lookahead_matches_immediate() {
+        // resolving events ahead of time should not change what comes out,
+        // as long as the changes are made more than the lookahead before the grid
+        let mut immediate = GrainLooperFixture::new();
+        let mut lookahead = GrainLooperFixture::new();
+        lookahead.looper.set_lookahead(2);
+
+        let mut outputs = vec![];
+        for f in [&mut immediate, &mut lookahead] {
+            let mut out = vec![];
+            for i in 0..40 {
+                if i == 6 {
+                    f.looper.set_fade_time(0.1);
+                    f.looper.set_loop_offset(0.5);
+                    f.looper.set_grid(0.5);
+                    f.looper.start_looping();
+                }
+                if i == 27 {
+                    f.looper.stop_looping();
+                }
+                out.push(f.tick());
+            }
+            outputs.push(out);
+        }
+
+        all_near(&outputs[0], &outputs[1], 0.0001);
     }
 }
@@ -1,21 +1,71 @@
-use crate::grain::Grain;
-use crate::{delay_line::DelayLine, stereo_pair::AudioSampleOps};
+use crate::grain::{Grain, GrainView};
+use crate::ramped_value::{FadeShape, RampedValue};
+use crate::region_fit::ReadableRange;
+use crate::stretch::Stretch;
+use crate::waveform::{WaveformPoint, READS_PER_POINT};
+use crate::{audio_frame::AudioFrame, delay_line::DelayLine};
+use nih_plug::prelude::Enum;
+use std::iter::once;
 
 pub const MAX_GRAINS: usize = 10;
+// how quickly a grain that has its voice stolen fades out, short enough to barely be heard
+// under the grain taking its place, long enough not to click
+pub const STEAL_FADE_SAMPLES: usize = 64;
+// how many samples of a grain the block path reads before mixing them in
+const MIX_CHUNK: usize = 64;
+// how many samples are mixed at once, as many as fit in the widest vector registers
+// there's no explicit simd, it's left to the compiler, see bench_mix_scaled
+const MIX_LANES: usize = 8;
+
+// which grain gives up its voice when a grain is scheduled with every voice busy
+#[derive(Enum, Debug, Clone, Copy, PartialEq)]
+pub enum VoiceStealing {
+    // the grain that has played longest, usually the loop before last
+    #[id = "oldest"]
+    #[name = "Oldest"]
+    Oldest,
+    // the grain playing quietest, e.g. one fading out or decayed away
+    #[id = "quietest"]
+    #[name = "Quietest"]
+    Quietest,
+}
+
+// which buffer a grain reads from
+// loading a region leaves the grains already playing on the audio they started with
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum GrainSource {
+    // whichever buffer is in use
+    Current,
+    Rolling,
+    PreviousStatic,
+}
 
-pub struct GrainPlayer<T: AudioSampleOps> {
+pub struct GrainPlayer<T: AudioFrame> {
     grains: Vec<Grain>,
     // this is the buffer that is always being written to
     rolling_buffer: DelayLine<T>,
-    // this is the buffer that is only written to when looping, and when
-    //the loopable region goes out of scope of the rolling buffer we switch to this one
+    // the loopable region frozen as looping starts, so it's kept however long the loop plays
     static_buffer: DelayLine<T>,
+    // what the static buffer held before a region was loaded, for grains still fading out of it
+    previous_static_buffer: DelayLine<T>,
+    grain_sources: [GrainSource; MAX_GRAINS],
+    voice_stealing: VoiceStealing,
+    // a grain that gave up its voice, fading out on the side
+    // stealing again before it has gone cuts it, though that's only a few samples early
+    stolen: Grain,
+    stolen_source: GrainSource,
+    // starts the short grains of a time-stretched loop as they're due
+    stretch: Option<Stretch>,
+    // varispeed for every grain, each grain ticks a copy of it, see ramp_speed
+    speed_ramp: RampedValue,
+    fade_shape: FadeShape,
 
     // ticks up as the rolling buffer scrolls left
     rolling_offset: usize,
     use_static_buffer: bool,
     loopable_region_length: usize,
     static_buffer_margin: usize,
+    // still recording the margin after the loop start into the static buffer
     is_filling_static_buffer: bool,
 }
 
@@ -23,7 +73,7 @@ pub struct GrainPlayer<T: AudioSampleOps> {
 // handles the rolling and static buffers so that existing loopable region is frozen when looping for along time,
 //  whilst at the same time new content is instantly available
 #[allow(dead_code)]
-impl<T: AudioSampleOps> GrainPlayer<T> {
+impl<T: AudioFrame> GrainPlayer<T> {
     pub fn new_with_length(
         loopable_region_length: usize,
         max_fade_time: usize,
@@ -34,6 +84,7 @@ impl<T: AudioSampleOps> GrainPlayer<T> {
         // rolling buffer must be length of loopable region plus the static buffer
         let delay_line_length_rolling = loopable_region_length + delay_line_length_static;
         let delay_line_static = DelayLine::new(delay_line_length_static);
+        let previous_delay_line_static = DelayLine::new(delay_line_length_static);
         let delay_line_rolling = DelayLine::new(delay_line_length_rolling);
 
         let mut grains_init = vec![];
@@ -45,6 +96,14 @@ impl<T: AudioSampleOps> GrainPlayer<T> {
             grains: grains_init,
             rolling_buffer: delay_line_rolling,
             static_buffer: delay_line_static,
+            previous_static_buffer: previous_delay_line_static,
+            grain_sources: [GrainSource::Current; MAX_GRAINS],
+            voice_stealing: VoiceStealing::Oldest,
+            stolen: Grain::new(0, 0.0, 0, 0, false, 0.0),
+            stolen_source: GrainSource::Current,
+            stretch: None,
+            speed_ramp: RampedValue::new(1.0),
+            fade_shape: FadeShape::Linear,
             rolling_offset: 0,
             use_static_buffer: false,
             loopable_region_length: loopable_region_length,
@@ -53,19 +112,88 @@ impl<T: AudioSampleOps> GrainPlayer<T> {
         }
     }
 
-    pub fn schedule_grain(&mut self, grain: Grain) {
-        // todo look at all the params and make sure it will not read beyond the buffer
-        for i in 0..self.grains.len() {
-            if self.grains[i].is_finished() {
-                self.grains[i] = grain;
-                return;
-            }
+    pub fn schedule_grain(&mut self, mut grain: Grain) {
+        grain.set_speed_ramp(self.speed_ramp);
+        grain.set_fade_shape(self.fade_shape);
+        // the looper fits the grain into readable_range before it gets here
+        let i = match self.grains.iter().position(|grain| grain.is_finished()) {
+            Some(i) => i,
+            None => self.steal_voice(),
+        };
+        self.grains[i] = grain;
+        self.grain_sources[i] = GrainSource::Current;
+    }
+
+    pub fn set_voice_stealing(&mut self, voice_stealing: VoiceStealing) {
+        self.voice_stealing = voice_stealing;
+    }
+
+    // frees a voice by fading its grain out on the side, returning the voice
+    fn steal_voice(&mut self) -> usize {
+        let playing = self
+            .grains
+            .iter()
+            .enumerate()
+            .filter(|(_, grain)| grain.is_playing());
+        let victim = match self.voice_stealing {
+            VoiceStealing::Oldest => playing
+                .max_by_key(|(_, grain)| grain.elapsed_sample_count())
+                .map(|(i, _)| i),
+            // the older of two grains as quiet
+            VoiceStealing::Quietest => playing
+                .min_by(|(_, a), (_, b)| {
+                    a.level()
+                        .total_cmp(&b.level())
+                        .then(b.elapsed_sample_count().cmp(&a.elapsed_sample_count()))
+                })
+                .map(|(i, _)| i),
+        };
+        // with every grain still to start, the one starting last is the one to lose
+        let victim = victim.unwrap_or_else(|| {
+            (0..self.grains.len())
+                .max_by_key(|&i| self.grains[i].scheduled_wait())
+                .unwrap_or(0)
+        });
+        let mut stolen = Grain::new(0, 0.0, 0, 0, false, 0.0);
+        std::mem::swap(&mut stolen, &mut self.grains[victim]);
+        stolen.fade_out_quickly(STEAL_FADE_SAMPLES);
+        self.stolen = stolen;
+        self.stolen_source = self.grain_sources[victim];
+        victim
+    }
+
+    // plays a loop as short overlapping grains, in place of the time-stretch already playing
+    pub fn schedule_stretch(&mut self, stretch: Stretch) {
+        self.stretch = Some(stretch);
+    }
+
+    // ramps the speed of every grain, playing or yet to be scheduled, to target over duration
+    pub fn ramp_speed(&mut self, target: f32, duration: usize) {
+        self.speed_ramp.ramp(target as f64, duration);
+        for grain in self.grains.iter_mut() {
+            grain.set_speed_ramp(self.speed_ramp);
+        }
+    }
+
+    // for the grains scheduled from now on
+    pub fn set_fade_shape(&mut self, fade_shape: FadeShape) {
+        self.fade_shape = fade_shape;
+    }
+
+    // sets the speed of every grain still to play, keeping their directions
+    pub fn set_speed(&mut self, speed: f32) {
+        for grain in self.grains.iter_mut().filter(|grain| !grain.is_finished()) {
+            grain.set_speed(speed);
         }
     }
 
     pub fn reset(&mut self) {
+        self.stretch = None;
+        self.speed_ramp = RampedValue::new(1.0);
         self.rolling_buffer.reset();
         self.static_buffer.reset();
+        self.previous_static_buffer.reset();
+        self.grain_sources = [GrainSource::Current; MAX_GRAINS];
         self.is_filling_static_buffer = false;
         self.use_static_buffer = false;
         self.rolling_offset = 0;
@@ -75,9 +203,13 @@ impl<T: AudioSampleOps> GrainPlayer<T> {
     // reference point to when we started looping.
     // this is the rolling offset
     // it kind of sucks
+    // the region up to now is copied into the static buffer in one go, which is played from
+    // straight away, and the margin after it is recorded in as the input comes
     pub fn start_looping(&mut self) {
-        self.is_filling_static_buffer = true;
-        self.use_static_buffer = false;
+        self.static_buffer
+            .freeze_from(&self.rolling_buffer, self.static_buffer_margin);
+        self.is_filling_static_buffer = self.static_buffer_margin > 0;
+        self.use_static_buffer = true;
         self.rolling_offset = 0;
     }
 
@@ -89,81 +221,144 @@ impl<T: AudioSampleOps> GrainPlayer<T> {
     pub fn tick(&mut self, input: T) -> T {
         self.rolling_buffer.tick(input);
         self.rolling_offset += 1;
-        self.tick_static_buffer_copy();
-
-        let out;
+        self.record_static_margin(input);
+        if let Some(grain) = self.stretch.as_mut().and_then(|stretch| stretch.tick()) {
+            self.schedule_grain(grain);
+        }
 
-        if self.use_static_buffer {
-            out = GrainPlayer::<T>::read_grains(
-                &mut self.grains,
-                &self.static_buffer,
-                self.static_buffer_margin,
-            );
-        } else {
-            out = GrainPlayer::<T>::read_grains(
-                &mut self.grains,
-                &self.rolling_buffer,
-                self.rolling_offset,
-            );
+        let mut out = T::default();
+        let grains = self.grains.iter_mut().chain(once(&mut self.stolen));
+        let sources = self.grain_sources.iter().chain(once(&self.stolen_source));
+        for (grain, source) in grains.zip(sources) {
+            let (delay_line, offset) = match (source, self.use_static_buffer) {
+                (GrainSource::Current, true) => (&self.static_buffer, self.static_buffer_margin),
+                (GrainSource::Current, false) | (GrainSource::Rolling, _) => {
+                    (&self.rolling_buffer, self.rolling_offset)
+                }
+                (GrainSource::PreviousStatic, _) => {
+                    (&self.previous_static_buffer, self.static_buffer_margin)
+                }
+            };
+            out += GrainPlayer::<T>::read_grain(grain, delay_line, offset);
         }
+        // in step with the copies the grains tick
+        self.speed_ramp.tick();
         out
     }
 
-    fn read_grains(grains: &mut Vec<Grain>, delay_line: &DelayLine<T>, rolling_offset: usize) -> T {
-        let mut out = Default::default();
+    // as ticking each sample in turn, but grain by grain over the block where nothing changes
+    // between the samples, i.e. no time-stretch starting grains
+    pub fn process_block(&mut self, input: &[T], output: &mut [T]) {
+        debug_assert!(output.len() >= input.len());
+        let num_samples = input.len();
+        if self.stretch.is_some() {
+            for (sample, out) in input.iter().zip(output.iter_mut()) {
+                *out = self.tick(*sample);
+            }
+            return;
+        }
 
-        // accumulate output of all grains
-        for grain in grains.iter_mut() {
-            if grain.is_finished() {
-                continue;
+        self.rolling_buffer.write_block(input);
+        if self.is_filling_static_buffer {
+            for sample in input {
+                self.rolling_offset += 1;
+                self.record_static_margin(*sample);
             }
-            if grain.is_waiting() {
-                grain.tick();
+        } else {
+            self.rolling_offset += num_samples;
+        }
+
+        // with the whole block written, the rolling buffer is read as it was at the end of it
+        let output = &mut output[..num_samples];
+        output.fill(T::default());
+        let grains = self.grains.iter_mut().chain(once(&mut self.stolen));
+        let sources = self.grain_sources.iter().chain(once(&self.stolen_source));
+        for (grain, source) in grains.zip(sources) {
+            if grain.is_finished() {
                 continue;
             }
-            let (delay_pos, amplitude) = grain.tick();
-            let delay = delay_pos + rolling_offset as f32;
-
-            if delay >= 0.0 && delay < delay_line.len() as f32 {
-                out = out + delay_line.read_interpolated(delay) * amplitude;
-            } else {
-                debug_assert!(
-                    delay >= 0.0 && delay < delay_line.len() as f32,
-                    "delay is outside buffer. delay_pos: {:?}, rolling_offset: {:?}",
-                    delay_pos,
-                    rolling_offset,
-                );
+            let (delay_line, offset) = match (source, self.use_static_buffer) {
+                (GrainSource::Current, true) => (&self.static_buffer, self.static_buffer_margin),
+                (GrainSource::Current, false) | (GrainSource::Rolling, _) => {
+                    (&self.rolling_buffer, self.rolling_offset)
+                }
+                (GrainSource::PreviousStatic, _) => {
+                    (&self.previous_static_buffer, self.static_buffer_margin)
+                }
+            };
+            // the reads can't be vectorized, they're all over the buffer, but the mixing can
+            let mut reads = [T::default(); MIX_CHUNK];
+            let mut windows = [0.0; MIX_CHUNK];
+            for chunk in output.chunks_mut(MIX_CHUNK) {
+                let reads = &mut reads[..chunk.len()];
+                let windows = &mut windows[..chunk.len()];
+                for (read, window) in reads.iter_mut().zip(windows.iter_mut()) {
+                    (*read, *window) =
+                        GrainPlayer::<T>::read_grain_unscaled(grain, delay_line, offset);
+                }
+                mix_scaled(chunk, reads, windows);
             }
         }
-        out
+        self.speed_ramp.tick_many(num_samples);
     }
 
-    // that when the loopable region exits the rolling buffer, we can use the static one
-    fn tick_static_buffer_copy(&mut self) {
-        // don't tick it if its full and we're using it, or if we're not looping
-        if self.use_static_buffer || !self.is_filling_static_buffer {
-            return;
+    fn read_grain(grain: &mut Grain, delay_line: &DelayLine<T>, rolling_offset: usize) -> T {
+        let (sample, amplitude) =
+            GrainPlayer::<T>::read_grain_unscaled(grain, delay_line, rolling_offset);
+        sample * amplitude
+    }
+
+    // as read_grain, but the window is left for the caller to apply
+    fn read_grain_unscaled(
+        grain: &mut Grain,
+        delay_line: &DelayLine<T>,
+        rolling_offset: usize,
+    ) -> (T, f32) {
+        if grain.is_finished() {
+            return (T::default(), 0.0);
         }
-        // fill the static buffer with the loop region
-        // we do this by reading the rolling buffer at a delay of the loopable region
-        self.static_buffer
-            .tick(self.rolling_buffer.read(self.loopable_region_length));
+        if grain.is_waiting() {
+            grain.tick();
+            return (T::default(), 0.0);
+        }
+        // playing faster than 1x, the read filters out what would alias
+        let speed = grain.speed().abs();
+        let (delay_pos, amplitude) = grain.tick();
+        let delay = delay_pos + rolling_offset as f32;
+
+        if delay >= 0.0 && delay < delay_line.len() as f32 {
+            let sample = delay_line.read_band_limited(delay, speed);
+            (sample.pan(grain.pan()), amplitude)
+        } else {
+            debug_assert!(
+                delay >= 0.0 && delay < delay_line.len() as f32,
+                "delay is outside buffer. delay_pos: {:?}, rolling_offset: {:?}",
+                delay_pos,
+                rolling_offset,
+            );
+            (T::default(), 0.0)
+        }
+    }
 
-        // when the rolling offset has reached the end of the loopable region, and the fade allowance
-        // we switch to the static buffer
-        if self.rolling_offset >= self.ticks_before_switch_to_static_buffer() {
-            self.is_filling_static_buffer = false;
-            self.use_static_buffer = true;
+    // the input after the loop start goes into the margin of the frozen region, the newest at 0,
+    // for loops lengthened past the start and fades running over it
+    // call after the rolling offset has moved on for the input
+    fn record_static_margin(&mut self, input: T) {
+        if !self.is_filling_static_buffer {
+            return;
+        }
+        match self.static_buffer_margin.checked_sub(self.rolling_offset) {
+            Some(delay) => {
+                self.static_buffer.set(delay, input);
+                self.is_filling_static_buffer = delay > 0;
+            }
+            None => self.is_filling_static_buffer = false,
         }
     }
 
     // todo: alternative tick that can loop over a delay line of
     // things that can't be interpolated or whatnot  might need different impl
 
-    fn ticks_before_switch_to_static_buffer(&self) -> usize {
-        self.static_buffer.len()
-    }
-
     fn is_filling_static_buffer(&self) -> bool {
         self.is_filling_static_buffer
     }
@@ -180,7 +375,9 @@ impl<T: AudioSampleOps> GrainPlayer<T> {
         &self.rolling_buffer
     }
 
+    // a stolen grain is left to finish its fade out, which is quicker
     pub fn stop_all_grains(&mut self) {
+        self.stretch = None;
         for grain in self.grains.iter_mut() {
             grain.stop();
         }
@@ -193,6 +390,20 @@ impl<T: AudioSampleOps> GrainPlayer<T> {
             .count()
     }
 
+    // the grains playing, including one fading out after giving up its voice
+    // read positions are delays into the region, as capture_region copies it
+    pub fn active_grains(&self) -> impl Iterator<Item = GrainView> + '_ {
+        let margin = self.static_buffer_margin as f32;
+        self.grains
+            .iter()
+            .chain(once(&self.stolen))
+            .filter(|grain| grain.is_playing())
+            .map(move |grain| GrainView {
+                read_position: grain.delay_pos() + margin,
+                ..grain.view()
+            })
+    }
+
     pub fn num_playing_grains(&self) -> usize {
         self.grains
             .iter()
@@ -217,6 +428,125 @@ impl<T: AudioSampleOps> GrainPlayer<T> {
     pub fn loopable_region_length(&self) -> usize {
         self.loopable_region_length
     }
+
+    // how many samples capture_region and load_region work with
+    pub fn region_length(&self) -> usize {
+        self.static_buffer.len()
+    }
+
+    // how far into a region the loop starts, the samples before it are what came after
+    pub fn static_buffer_margin(&self) -> usize {
+        self.static_buffer_margin
+    }
+
+    // copies what the static buffer holds, or will hold once it has filled, newest first
+    // anything the loop could read that hasn't been recorded yet is left silent
+    // in a couple of slice copies, as region_frame for each delay would
+    pub fn capture_region(&self, region: &mut [T]) {
+        if self.use_static_buffer {
+            self.static_buffer.read_block(0, region);
+            return;
+        }
+        let unrecorded = self
+            .static_buffer_margin
+            .saturating_sub(self.rolling_offset)
+            .min(region.len());
+        let (unrecorded, recorded) = region.split_at_mut(unrecorded);
+        unrecorded.fill(T::default());
+        let delay = self
+            .rolling_offset
+            .saturating_sub(self.static_buffer_margin);
+        self.rolling_buffer.read_block(delay, recorded);
+    }
+
+    // a decimated view of what capture_region would copy, oldest first, for drawing it
+    pub fn snapshot_region(&self, points: &mut [WaveformPoint]) {
+        let span = self.region_length() as f32 / points.len() as f32;
+        let reads = READS_PER_POINT.min(span.ceil() as usize).max(1);
+        for (index, point) in points.iter_mut().rev().enumerate() {
+            let mut view = WaveformPoint::EMPTY;
+            for read in 0..reads {
+                let delay = (index as f32 + read as f32 / reads as f32) * span;
+                view.add(&self.region_frame(delay as usize));
+            }
+            *point = view;
+        }
+    }
+
+    // the frame capture_region would copy at delay
+    pub fn region_frame(&self, delay: usize) -> T {
+        if self.use_static_buffer {
+            return self.static_buffer.read(delay);
+        }
+        // where the static buffer reads at delay, the rolling buffer reads here
+        match (delay + self.rolling_offset).checked_sub(self.static_buffer_margin) {
+            Some(delay) if delay < self.rolling_buffer.len() => self.rolling_buffer.read(delay),
+            _ => T::default(),
+        }
+    }
+
+    // plays grains from a region copied by capture_region, until looping starts again
+    // grains already playing carry on with the audio they started with, so the loop
+    // crossfades into the region as they fade out
+    // a grain still fading out of a region loaded before that goes silent
+    pub fn load_region(&mut self, region: &[T]) {
+        let previous = if self.use_static_buffer {
+            std::mem::swap(&mut self.static_buffer, &mut self.previous_static_buffer);
+            GrainSource::PreviousStatic
+        } else {
+            GrainSource::Rolling
+        };
+        let grains = self.grains.iter_mut().chain(once(&mut self.stolen));
+        let sources = self
+            .grain_sources
+            .iter_mut()
+            .chain(once(&mut self.stolen_source));
+        for (grain, source) in grains.zip(sources) {
+            match source {
+                GrainSource::Current => *source = previous,
+                GrainSource::PreviousStatic if previous == GrainSource::PreviousStatic => {
+                    *grain = Grain::new(0, 0.0, 0, 0, false, 0.0)
+                }
+                _ => (),
+            }
+        }
+        self.static_buffer.load_block(region);
+        self.is_filling_static_buffer = false;
+        self.use_static_buffer = true;
+    }
+
+    // the delays a grain can read from without going outside the buffers,
+    // with a sample spare either side for interpolation
+    pub fn readable_range(&self) -> ReadableRange {
+        ReadableRange {
+            newest: 1.0 - self.static_buffer_margin as f32,
+            oldest: self.loopable_region_length as f32 - 2.0,
+        }
+    }
+}
+
+// adds each input at its gain to the output, a fixed number of lanes at a time so the
+// compiler can keep them in vector registers, for any frame
+// it does for stereo and surround frames, a mono frame vectorizes as well without the lanes
+fn mix_scaled<T: AudioFrame>(output: &mut [T], input: &[T], gains: &[f32]) {
+    let mut output_lanes = output.chunks_exact_mut(MIX_LANES);
+    let mut input_lanes = input.chunks_exact(MIX_LANES);
+    let mut gain_lanes = gains.chunks_exact(MIX_LANES);
+    for ((output, input), gains) in (&mut output_lanes)
+        .zip(&mut input_lanes)
+        .zip(&mut gain_lanes)
+    {
+        for lane in 0..MIX_LANES {
+            output[lane] += input[lane] * gains[lane];
+        }
+    }
+    let remainder = output_lanes.into_remainder().iter_mut();
+    for ((output, input), gain) in remainder
+        .zip(input_lanes.remainder())
+        .zip(gain_lanes.remainder())
+    {
+        *output += *input * *gain;
+    }
 }
 
 #[cfg(test)]
@@ -225,6 +555,8 @@ mod tests {
 
     use super::*;
     const sample_rate: f32 = 10.0;
+    use crate::frame::Frame;
+    use crate::stereo_pair::StereoPair;
     use crate::test_utils::all_near;
 
     #[test]
@@ -317,13 +649,12 @@ mod tests {
         let mut input_iter = input.iter();
 
         let mut output = vec![];
-        // for first 10 samples (loopable region length + max loop) we should be filling the static buffer but not using it
-        for i in 0..10 {
-            assert!(
-                !player.is_using_static_buffer(),
-                "is using static buffer after {}",
-                i
-            );
+        // the region is frozen straight away, only the margin after the start is still to fill
+        assert!(player.is_using_static_buffer());
+        let expected_frozen = vec![2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 0.0, 0.0];
+        assert_eq!(*player.static_buffer().buffer(), expected_frozen);
+        for _i in 0..2 {
+            assert!(player.is_using_static_buffer());
             assert!(player.is_filling_static_buffer());
             output.push(player.tick(*input_iter.next().unwrap()));
         }
@@ -334,7 +665,7 @@ mod tests {
         let static_buffer = player.static_buffer().buffer().clone();
         assert_eq!(*static_buffer, expected_static);
 
-        for _i in 0..10 {
+        for _i in 0..18 {
             assert!(player.is_using_static_buffer());
             assert!(!player.is_filling_static_buffer());
             output.push(player.tick(*input_iter.next().unwrap()));
@@ -352,6 +683,90 @@ mod tests {
         assert_eq!(output, vec![0.0; 20]);
     }
 
+    #[test]
+    fn test_grain_player_capture_region() {
+        let mut player = GrainPlayer::<f32>::new_with_length(8, 0, 2);
+        for input in 0..10 {
+            player.tick(input as f32);
+        }
+        player.start_looping();
+        player.tick(10.0);
+
+        // the same whether the static buffer has filled or not, apart from what was still to come
+        let mut early = vec![0.0; player.region_length()];
+        player.capture_region(&mut early);
+        assert_eq!(
+            early,
+            vec![0.0, 10.0, 9.0, 8.0, 7.0, 6.0, 5.0, 4.0, 3.0, 2.0]
+        );
+        for input in 11..30 {
+            player.tick(input as f32);
+        }
+        assert!(player.is_using_static_buffer());
+        let mut late = vec![0.0; player.region_length()];
+        player.capture_region(&mut late);
+        assert_eq!(
+            late,
+            vec![11.0, 10.0, 9.0, 8.0, 7.0, 6.0, 5.0, 4.0, 3.0, 2.0]
+        );
+
+        // a loaded region plays just as it was captured
+        player.start_looping();
+        player.load_region(&early);
+        player.schedule_grain(Grain::new(0, 1.0, 3, 0, false, 1.0));
+        let output: Vec<f32> = (0..3).map(|_| player.tick(0.0)).collect();
+        assert_eq!(output, vec![9.0, 10.0, 0.0]);
+
+        // off the rolling buffer, the copy reads just as each frame does
+        player.reset();
+        for ticks in [0, 1, 5, 20] {
+            player.stop_looping();
+            for _ in 0..ticks {
+                player.tick(ticks as f32);
+            }
+            let mut region = vec![-1.0; player.region_length()];
+            player.capture_region(&mut region);
+            let frames: Vec<f32> = (0..region.len())
+                .map(|delay| player.region_frame(delay))
+                .collect();
+            assert_eq!(region, frames, "{} ticks", ticks);
+        }
+    }
+
+    #[test]
+    fn test_grain_player_snapshot_region() {
+        let mut player = GrainPlayer::<f32>::new_with_length(8, 0, 2);
+        for input in 0..30 {
+            player.tick(input as f32);
+        }
+        player.start_looping();
+        for input in 30..50 {
+            player.tick(input as f32);
+        }
+
+        // oldest first, each point covering two samples of the region
+        let mut points = [WaveformPoint::default(); 5];
+        player.snapshot_region(&mut points);
+        let spans: Vec<(f32, f32)> = points.iter().map(|point| (point.min, point.max)).collect();
+        assert_eq!(
+            spans,
+            vec![
+                (22.0, 23.0),
+                (24.0, 25.0),
+                (26.0, 27.0),
+                (28.0, 29.0),
+                (30.0, 31.0)
+            ]
+        );
+
+        // more points than samples repeat them rather than reading past the region
+        let mut points = [WaveformPoint::default(); 20];
+        player.snapshot_region(&mut points);
+        assert_eq!(points[0].min, 22.0);
+        assert_eq!(points[1].min, 22.0);
+        assert_eq!(points[19].max, 31.0);
+    }
+
     #[test]
     fn test_grain_player_output() {
         let mut player = GrainPlayer::<f32>::new_with_length(10, 0, 10);
@@ -488,6 +903,129 @@ mod tests {
         all_near(&out, &expected, 0.0001);
     }
 
+    #[test]
+    fn test_grain_player_steals_voices() {
+        for (voice_stealing, stolen_offset) in [
+            (VoiceStealing::Oldest, 200.0),
+            (VoiceStealing::Quietest, 201.0),
+        ] {
+            let mut player = GrainPlayer::<f32>::new_with_length(400, 0, 10);
+            player.set_voice_stealing(voice_stealing);
+            for _ in 0..400 {
+                player.tick(1.0);
+            }
+            player.start_looping();
+            // each grain a sample younger than the last, the second one quieter than the rest
+            for i in 0..MAX_GRAINS {
+                let mut grain = Grain::new(0, 200.0 + i as f32, 300, 0, false, 1.0);
+                if i == 1 {
+                    grain.set_gain(0.5);
+                }
+                player.schedule_grain(grain);
+                player.tick(1.0);
+            }
+            assert_eq!(player.num_finished_grains(), 0);
+
+            player.schedule_grain(Grain::new(0, 100.0, 300, 0, false, 1.0));
+            let offsets: Vec<f32> = player.grains.iter().map(|grain| grain.offset()).collect();
+            assert!(offsets.contains(&100.0));
+            assert!(!offsets.contains(&stolen_offset), "{:?}", voice_stealing);
+
+            // the stolen grain fades out rather than cutting off
+            assert_eq!(player.stolen.offset(), stolen_offset);
+            let level = player.tick(1.0);
+            assert!(level > MAX_GRAINS as f32 - 0.5, "{:?}", voice_stealing);
+            for _ in 0..STEAL_FADE_SAMPLES {
+                player.tick(1.0);
+            }
+            assert!(player.stolen.is_finished());
+            assert_eq!(player.num_playing_grains(), MAX_GRAINS);
+        }
+    }
+
+    #[test]
+    fn test_grain_player_active_grains() {
+        let mut player = GrainPlayer::<f32>::new_with_length(100, 10, 10);
+        for _ in 0..200 {
+            player.tick(1.0);
+        }
+        player.start_looping();
+        player.schedule_grain(Grain::new(0, 50.0, 10, 0, false, 1.0));
+        player.schedule_grain(Grain::new(0, 50.0, 10, 0, true, 2.0));
+        // still waiting, so not active yet
+        player.schedule_grain(Grain::new(5, 50.0, 10, 0, false, 1.0));
+        player.tick(1.0);
+
+        // read positions line up with capture_region, past the margin for the audio to come
+        let views: Vec<GrainView> = player.active_grains().collect();
+        assert_eq!(views.len(), 2);
+        assert_eq!(views[0].progress, 0.1);
+        assert_eq!(views[0].read_position, 48.0 + 20.0);
+        assert!(!views[0].reverse);
+        assert_eq!(views[1].read_position, 42.0 + 20.0);
+        assert!(views[1].reverse);
+        assert_eq!(views[1].speed, 2.0);
+        assert!(views.iter().all(|view| view.window_gain > 0.0));
+
+        for _ in 0..10 {
+            player.tick(1.0);
+        }
+        assert_eq!(player.active_grains().count(), 1);
+    }
+
+    #[test]
+    fn test_mix_scaled() {
+        // a whole set of lanes and a few over
+        let input: Vec<f32> = (0..MIX_LANES + 3).map(|x| x as f32).collect();
+        let gains: Vec<f32> = (0..MIX_LANES + 3).map(|x| 1.0 / (x + 1) as f32).collect();
+        let mut output = vec![1.0; MIX_LANES + 3];
+        mix_scaled(&mut output, &input, &gains);
+        let expected: Vec<f32> = input
+            .iter()
+            .zip(gains.iter())
+            .map(|(x, gain)| 1.0 + x * gain)
+            .collect();
+        assert_eq!(output, expected);
+    }
+
+    // cargo test --release bench_mix_scaled -- --ignored --nocapture
+    // against mixing a sample at a time, around 1.3x faster for stereo and 5x for surround
+    #[test]
+    #[ignore]
+    fn bench_mix_scaled() {
+        fn mix_each<T: AudioFrame>(output: &mut [T], input: &[T], gains: &[f32]) {
+            for ((output, input), gain) in output.iter_mut().zip(input).zip(gains) {
+                *output += *input * *gain;
+            }
+        }
+        fn time<T: AudioFrame>(mix: fn(&mut [T], &[T], &[f32]), frame: T) -> f64 {
+            let input = vec![frame; MIX_CHUNK];
+            let gains = vec![0.5; MIX_CHUNK];
+            let mut output = vec![T::default(); MIX_CHUNK];
+            let runs = 1_000_000;
+            let start = std::time::Instant::now();
+            for _ in 0..runs {
+                mix(
+                    std::hint::black_box(&mut output),
+                    std::hint::black_box(&input),
+                    std::hint::black_box(&gains),
+                );
+            }
+            start.elapsed().as_nanos() as f64 / (runs * MIX_CHUNK) as f64
+        }
+        fn compare<T: AudioFrame>(name: &str, frame: T) {
+            println!(
+                "{}: {:.3}ns a frame in lanes, {:.3}ns one at a time",
+                name,
+                time(mix_scaled, frame),
+                time(mix_each, frame)
+            );
+        }
+        compare("mono", 1.0);
+        compare("stereo", StereoPair::new(1.0, -1.0));
+        compare("surround", Frame::<6>::default());
+    }
+
     fn test_grain_player_lengthen_grain() {
         // test the scenario where the grain is lengthened when already using the static buffer
     }
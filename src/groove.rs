@@ -0,0 +1,147 @@
+use nih_plug::prelude::Enum;
+
+// the most 16ths a groove can have before it repeats, a bar of 4/4
+pub const MAX_GROOVE_STEPS: usize = 16;
+// each step can be pushed back by up to a whole 16th
+const MAX_STEP_DELAY: f32 = 1.0;
+
+// how late each 16th lands, in 16ths, repeating every len steps
+// grooves only push notes late, so the loop can never start before it was asked to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GrooveTemplate {
+    steps: [f32; MAX_GROOVE_STEPS],
+    len: usize,
+}
+
+#[allow(dead_code)]
+impl GrooveTemplate {
+    pub fn straight() -> GrooveTemplate {
+        GrooveTemplate {
+            steps: [0.0; MAX_GROOVE_STEPS],
+            len: 1,
+        }
+    }
+
+    // None if there are no steps or too many
+    pub fn from_steps(steps: &[f32]) -> Option<GrooveTemplate> {
+        if steps.is_empty() || steps.len() > MAX_GROOVE_STEPS {
+            return None;
+        }
+        let mut template = GrooveTemplate {
+            steps: [0.0; MAX_GROOVE_STEPS],
+            len: steps.len(),
+        };
+        for (step, &delay) in template.steps.iter_mut().zip(steps) {
+            *step = delay.clamp(0.0, MAX_STEP_DELAY);
+        }
+        Some(template)
+    }
+
+    // a groove written out as delays separated by commas or whitespace, e.g. "0, 0.16"
+    pub fn parse(text: &str) -> Option<GrooveTemplate> {
+        let mut steps = [0.0; MAX_GROOVE_STEPS];
+        let mut len = 0;
+        for word in text
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|word| !word.is_empty())
+        {
+            if len == MAX_GROOVE_STEPS {
+                return None;
+            }
+            steps[len] = word.parse().ok()?;
+            len += 1;
+        }
+        GrooveTemplate::from_steps(&steps[..len])
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    // in 16ths, for whatever starts at this beat time
+    // anything between 16ths takes the delay of the nearest one
+    pub fn delay_at(&self, beat_time: f64) -> f32 {
+        let sixteenth = (beat_time * 4.0).round() as i64;
+        self.steps[sixteenth.rem_euclid(self.len as i64) as usize]
+    }
+}
+
+#[derive(Enum, Debug, Clone, Copy, PartialEq)]
+pub enum GroovePreset {
+    #[id = "straight"]
+    #[name = "Straight"]
+    Straight,
+    #[id = "swing-54"]
+    #[name = "Swing 54%"]
+    Swing54,
+    #[id = "swing-58"]
+    #[name = "Swing 58%"]
+    Swing58,
+    #[id = "swing-62"]
+    #[name = "Swing 62%"]
+    Swing62,
+    #[id = "swing-66"]
+    #[name = "Swing 66%"]
+    Swing66,
+}
+
+// the off beat 16th lands at this fraction of the 8th
+fn swing(fraction: f32) -> GrooveTemplate {
+    GrooveTemplate::from_steps(&[0.0, 2.0 * fraction - 1.0]).unwrap()
+}
+
+impl GroovePreset {
+    pub fn template(&self) -> GrooveTemplate {
+        match self {
+            GroovePreset::Straight => GrooveTemplate::straight(),
+            GroovePreset::Swing54 => swing(0.54),
+            GroovePreset::Swing58 => swing(0.58),
+            GroovePreset::Swing62 => swing(0.62),
+            GroovePreset::Swing66 => swing(0.66),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_groove_delay_at() {
+        let groove = GrooveTemplate::from_steps(&[0.0, 0.5, 0.1]).unwrap();
+        assert_eq!(groove.delay_at(0.0), 0.0);
+        assert_eq!(groove.delay_at(0.25), 0.5);
+        assert_eq!(groove.delay_at(0.5), 0.1);
+        assert_eq!(groove.delay_at(0.75), 0.0);
+        // close to a 16th counts as on it
+        assert_eq!(groove.delay_at(0.26), 0.5);
+        assert_eq!(groove.delay_at(-0.25), 0.1);
+
+        assert_eq!(GrooveTemplate::straight().delay_at(0.25), 0.0);
+    }
+
+    #[test]
+    fn test_groove_parse() {
+        assert_eq!(
+            GrooveTemplate::parse("0, 0.25 0.5,\n1"),
+            GrooveTemplate::from_steps(&[0.0, 0.25, 0.5, 1.0])
+        );
+        // steps are clamped
+        assert_eq!(
+            GrooveTemplate::parse("-1 2"),
+            GrooveTemplate::from_steps(&[0.0, 1.0])
+        );
+        assert_eq!(GrooveTemplate::parse(""), None);
+        assert_eq!(GrooveTemplate::parse("0, x"), None);
+        assert_eq!(GrooveTemplate::parse(&"0 ".repeat(17)), None);
+    }
+
+    #[test]
+    fn test_groove_swing() {
+        let groove = GroovePreset::Swing66.template();
+        assert_eq!(groove.len(), 2);
+        assert_abs_diff_eq!(groove.delay_at(0.25), 0.32, epsilon = 1e-6);
+        assert_eq!(groove.delay_at(0.5), 0.0);
+    }
+}
@@ -0,0 +1,298 @@
+use crate::process_context::HostTransport;
+use crate::{Metaloop, DEFAULT_TRIGGER_NOTE};
+use nih_plug::prelude::Plugin;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::Ordering;
+
+// runs the plugin the way a host would, without one, for testing process and update_params
+// and for the phase check
+// audio goes in and comes out as one vec per channel, cut into blocks, and while the transport
+// plays its position moves on by each block, as a host's would
+
+pub struct HostSim {
+    pub plugin: Metaloop,
+    // what the host reports for the next block, change it to simulate the host
+    pub transport: HostTransport,
+    block_size: usize,
+}
+
+// audio for each channel, sample by sample from f(channel, index)
+pub fn channels(
+    num_channels: usize,
+    num_samples: usize,
+    f: impl Fn(usize, usize) -> f32,
+) -> Vec<Vec<f32>> {
+    (0..num_channels)
+        .map(|channel| (0..num_samples).map(|index| f(channel, index)).collect())
+        .collect()
+}
+
+#[allow(dead_code)]
+impl HostSim {
+    // playing from the start at 120bpm in 4/4
+    pub fn new(sample_rate: f32, num_channels: usize, block_size: usize) -> HostSim {
+        let mut plugin = Metaloop::default();
+        plugin.prepare(sample_rate, block_size, num_channels);
+        plugin.reset();
+        HostSim {
+            plugin,
+            transport: HostTransport {
+                playing: true,
+                sample_rate,
+                tempo: Some(120.0),
+                pos_beats: Some(0.0),
+                time_sig_numerator: Some(4),
+                time_sig_denominator: Some(4),
+            },
+            block_size,
+        }
+    }
+
+    // as pressing the loop's trigger note
+    pub fn press_trigger(&mut self) {
+        self.plugin.note_trigger.note_on(DEFAULT_TRIGGER_NOTE as u8);
+        self.plugin
+            .note_trigger
+            .note_off(DEFAULT_TRIGGER_NOTE as u8);
+    }
+
+    // as turning the scrub cc to this offset, normalized
+    // before the first block there is nothing playing to pick up, so it takes over straight away
+    pub fn scrub_offset(&mut self, offset: f32) {
+        let scrub_cc = self.plugin.params.scrub_cc.load(Ordering::Relaxed);
+        self.plugin.cc_scrub.handle_cc(scrub_cc, scrub_cc, offset);
+    }
+
+    // as an editor importing a file, with the background task decoding it straight away
+    // the next block loads it
+    pub fn import(&mut self, path: &Path) -> io::Result<()> {
+        self.plugin.import.request(path);
+        if self.plugin.import.take_request() {
+            self.plugin.import.set_lead(self.plugin.import_lead());
+        }
+        self.plugin.import.decode().map(|_| ())
+    }
+
+    // the fade lead of whichever looper is running, see GrainLooper::fade_samples
+    pub fn fade_samples(&self) -> usize {
+        match &self.plugin.surround {
+            Some(surround) => surround.grain_looper.fade_samples(),
+            None => self.plugin.grain_looper.fade_samples(),
+        }
+    }
+
+    // processes the input a block at a time, returning the main output and the loop output
+    pub fn process(&mut self, input: &[Vec<f32>]) -> (Vec<Vec<f32>>, Vec<Vec<f32>>) {
+        let num_samples = input.first().map_or(0, |samples| samples.len());
+        let mut output = input.to_vec();
+        let mut loop_output = vec![vec![0.0; num_samples]; input.len()];
+        let mut start = 0;
+        while start < num_samples {
+            let end = (start + self.block_size).min(num_samples);
+            let mut channels: Vec<&mut [f32]> = output
+                .iter_mut()
+                .map(|samples| &mut samples[start..end])
+                .collect();
+            let mut loop_channels: Vec<&mut [f32]> = loop_output
+                .iter_mut()
+                .map(|samples| &mut samples[start..end])
+                .collect();
+            self.plugin
+                .process_channels(&mut channels, Some(&mut loop_channels), self.transport);
+            self.advance(end - start);
+            start = end;
+        }
+        (output, loop_output)
+    }
+
+    fn advance(&mut self, num_samples: usize) {
+        if !self.transport.playing {
+            return;
+        }
+        let beats_per_sample =
+            self.transport.tempo.unwrap_or(120.0) / 60.0 / self.transport.sample_rate as f64;
+        self.transport.pos_beats = self
+            .transport
+            .pos_beats
+            .map(|pos| pos + num_samples as f64 * beats_per_sample);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grain_looper::seconds_to_samples;
+
+    fn ramp(num_channels: usize, num_samples: usize) -> Vec<Vec<f32>> {
+        channels(num_channels, num_samples, |channel, index| {
+            (channel as f32 + 1.0) * index as f32 / num_samples as f32
+        })
+    }
+
+    fn split(input: &[Vec<f32>], range: std::ops::Range<usize>) -> Vec<Vec<f32>> {
+        input
+            .iter()
+            .map(|samples| samples[range.clone()].to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn test_host_sim_dry_keeps_its_channels() {
+        // mono and stereo go through the stereo looper, more channels through the surround one
+        for num_channels in [1, 2, 4] {
+            let mut sim = HostSim::new(1000.0, num_channels, 64);
+            let input = ramp(num_channels, 300);
+            let (output, loop_output) = sim.process(&input);
+            assert_eq!(output, input, "{} channels", num_channels);
+            assert!(loop_output.iter().flatten().all(|&sample| sample == 0.0));
+        }
+    }
+
+    #[test]
+    fn test_host_sim_tempo_and_time_sig_fallbacks() {
+        let mut sim = HostSim::new(1000.0, 2, 64);
+        sim.transport.tempo = None;
+        sim.transport.time_sig_numerator = None;
+        sim.transport.time_sig_denominator = None;
+        let context = sim.plugin.block_context(sim.transport, 100);
+        assert_eq!(context.tempo, sim.plugin.params.fallback_tempo.value());
+        assert_eq!(context.bar_beats(), 4.0);
+
+        // with no position the clock runs on by itself
+        sim.transport.pos_beats = None;
+        let next = sim.plugin.block_context(sim.transport, 100);
+        assert_eq!(next.beat_time, context.beat_time_at(100));
+
+        // and while stopped, carries on from where the host left off
+        sim.transport.tempo = Some(120.0);
+        sim.transport.pos_beats = Some(8.0);
+        sim.plugin.block_context(sim.transport, 500);
+        sim.transport.playing = false;
+        assert_eq!(sim.plugin.block_context(sim.transport, 100).beat_time, 9.0);
+    }
+
+    #[test]
+    fn test_host_sim_trigger_loops_the_input() {
+        // a quarter note at 120bpm, in samples
+        let loop_length = 500;
+        for num_channels in [2, 4] {
+            let mut sim = HostSim::new(1000.0, num_channels, 64);
+            let input = ramp(num_channels, 10 * loop_length);
+            sim.process(&split(&input, 0..2 * loop_length));
+
+            // the trigger is picked up by the next block, which starts the loop on the grid
+            sim.press_trigger();
+            let (output, loop_output) =
+                sim.process(&split(&input, 2 * loop_length..10 * loop_length));
+            for samples in output.iter() {
+                for index in 2 * loop_length..4 * loop_length {
+                    let repeat = samples[index + loop_length];
+                    assert!(
+                        (samples[index] - repeat).abs() < 1e-5,
+                        "{} channels",
+                        num_channels
+                    );
+                }
+            }
+            // by then the dry has gone, so only the loop is heard
+            for (samples, loop_samples) in output.iter().zip(loop_output.iter()) {
+                assert_eq!(samples[loop_length..], loop_samples[loop_length..]);
+                assert!(samples[loop_length..].iter().any(|&sample| sample != 0.0));
+            }
+            // and an editor can draw what's looping
+            let waveform = sim.plugin.waveform.latest();
+            assert!(waveform.version > 0);
+            assert!(waveform.points.iter().any(|point| point.max > 0.0));
+        }
+    }
+
+    #[test]
+    fn test_host_sim_lookahead_lands_on_the_grid() {
+        // a beat at 120bpm, in samples
+        let beat = 24000;
+        let input = ramp(2, 5 * beat);
+        let mut outputs = vec![];
+        // as prepared, with the events resolved ahead of time, and with them resolved as they're due
+        for lookahead in [None, Some(0)] {
+            let mut sim = HostSim::new(48000.0, 2, 512);
+            if let Some(lookahead) = lookahead {
+                sim.plugin.grain_looper.set_lookahead(lookahead);
+            }
+            sim.process(&split(&input, 0..3 * beat / 2));
+            sim.press_trigger();
+            let (output, loop_output) = sim.process(&split(&input, 3 * beat / 2..5 * beat));
+
+            // the loop fades in ahead of the next beat, so it's all in by the grid line
+            let start = loop_output[0].iter().position(|&sample| sample != 0.0);
+            assert_eq!(
+                start,
+                Some(beat / 2 - sim.fade_samples()),
+                "{:?}",
+                lookahead
+            );
+            outputs.push(output);
+        }
+        assert_eq!(outputs[0], outputs[1]);
+    }
+
+    #[test]
+    fn test_host_sim_smart_fade_stays_within_bounds() {
+        // at 120bpm, so a fade read as beats would come out at half the samples
+        let sample_rate = 48000.0;
+        let (shortest, longest) = (0.005, 0.05);
+        let steady = |_: usize, index: usize| (index as f32 / 50.0).sin();
+        let hits = |_: usize, index: usize| if index % 3000 < 20 { 1.0 } else { 0.0 };
+        for (name, input) in [
+            ("steady", channels(2, 48000, steady)),
+            ("hits", channels(2, 48000, hits)),
+        ] {
+            let mut sim = HostSim::new(sample_rate, 2, 512);
+            sim.process(&input);
+            let plugin = &mut sim.plugin;
+            let fade = Metaloop::smart_fade(
+                &plugin.grain_looper,
+                &plugin.splice_analyzer,
+                shortest,
+                longest,
+            );
+            plugin.grain_looper.set_fade_time(fade);
+            let samples = plugin.grain_looper.fade_samples();
+            let bounds = seconds_to_samples(shortest, sample_rate)
+                ..=seconds_to_samples(longest, sample_rate);
+            assert!(bounds.contains(&samples), "{}: {} samples", name, samples);
+        }
+    }
+
+    #[test]
+    fn test_host_sim_import_loops_the_file() {
+        let path = std::env::temp_dir().join("metaloop_test_host_sim_import.wav");
+        let mut file = std::fs::File::create(&path).unwrap();
+        crate::wav::write_wav(&mut file, &[0.5; 4000], 1, 1000).unwrap();
+        drop(file);
+
+        for num_channels in [2, 4] {
+            let mut sim = HostSim::new(1000.0, num_channels, 64);
+            let silence = channels(num_channels, 2000, |_, _| 0.0);
+            sim.process(&silence);
+
+            // once decoded, the next block loops the file without a trigger
+            sim.import(&path).unwrap();
+            let (output, loop_output) = sim.process(&silence);
+            for (samples, loop_samples) in output.iter().zip(loop_output.iter()) {
+                assert!(
+                    loop_samples[1000..]
+                        .iter()
+                        .all(|&sample| (sample - 0.5).abs() < 1e-3),
+                    "{} channels",
+                    num_channels
+                );
+                assert_eq!(samples[1000..], loop_samples[1000..]);
+            }
+            // and the file is kept to import again with the plugin's state
+            let saved = sim.plugin.params.import_path.lock().unwrap().clone();
+            assert_eq!(saved, Some(path.clone()));
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,112 @@
+// maps the keys from a root note up onto fixed loop offsets, so a keyboard scrubs through slices
+// the root plays the latest loop, and each key above it starts a step further back in the past
+// the last key held sets the offset, letting it go hands over to the highest key still held,
+// and once every key is let go the offset is back with whatever else sets it
+pub struct KeyScrub {
+    enabled: bool,
+    root: u8,
+    // a bit for each key held, keys below the root are left to the rest of the midi
+    held: u128,
+    last: Option<u8>,
+}
+
+#[allow(dead_code)]
+impl KeyScrub {
+    pub fn new(root: u8) -> KeyScrub {
+        KeyScrub {
+            enabled: false,
+            root,
+            held: 0,
+            last: None,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.held = 0;
+        self.last = None;
+    }
+
+    // turning it off lets go of the keys, as we won't see their note offs
+    pub fn set_enabled(&mut self, enabled: bool) {
+        if !enabled {
+            self.reset();
+        }
+        self.enabled = enabled;
+    }
+
+    // as would the steps of the keys held, moving the root lets go of them
+    pub fn set_root(&mut self, root: u8) {
+        if root != self.root {
+            self.root = root;
+            self.reset();
+        }
+    }
+
+    pub fn note_on(&mut self, note: u8) {
+        if !self.enabled || note < self.root || note > 127 {
+            return;
+        }
+        self.held |= 1 << note;
+        self.last = Some(note);
+    }
+
+    pub fn note_off(&mut self, note: u8) {
+        if note > 127 {
+            return;
+        }
+        self.held &= !(1 << note);
+        if self.last == Some(note) {
+            self.last = (self.held != 0).then(|| 127 - self.held.leading_zeros() as u8);
+        }
+    }
+
+    // how many steps back from the root the key playing is, none while no key is held
+    pub fn steps(&self) -> Option<u32> {
+        self.last.map(|note| (note - self.root) as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_scrub() {
+        let mut scrub = KeyScrub::new(48);
+        scrub.note_on(50);
+        assert_eq!(scrub.steps(), None);
+
+        scrub.set_enabled(true);
+        // below the root isn't a step
+        scrub.note_on(47);
+        assert_eq!(scrub.steps(), None);
+        scrub.note_on(48);
+        assert_eq!(scrub.steps(), Some(0));
+        scrub.note_on(55);
+        scrub.note_on(52);
+        assert_eq!(scrub.steps(), Some(4));
+
+        // the last key held wins, then the highest still held
+        scrub.note_off(48);
+        assert_eq!(scrub.steps(), Some(4));
+        scrub.note_off(52);
+        assert_eq!(scrub.steps(), Some(7));
+        scrub.note_off(55);
+        assert_eq!(scrub.steps(), None);
+    }
+
+    #[test]
+    fn test_key_scrub_lets_go() {
+        let mut scrub = KeyScrub::new(60);
+        scrub.set_enabled(true);
+        scrub.note_on(127);
+        assert_eq!(scrub.steps(), Some(67));
+        scrub.set_root(48);
+        assert_eq!(scrub.steps(), None);
+        scrub.note_on(49);
+        scrub.set_enabled(false);
+        assert_eq!(scrub.steps(), None);
+        scrub.note_off(49);
+        assert_eq!(scrub.steps(), None);
+    }
+}
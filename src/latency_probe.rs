@@ -0,0 +1,119 @@
+// measures the round trip from our output back to our input, with the output looped back
+// by cable or by holding a mic to a speaker
+// a single click goes out, and the first sample to come back above the threshold marks the latency
+// loops captured from live playing are late by this much, as the player is hearing the output
+
+// loud enough to come back through a mic, quiet enough not to hurt
+const CLICK_LEVEL: f32 = 0.5;
+// anything quieter than this coming back is taken as noise
+const DETECT_THRESHOLD: f32 = 0.1;
+// give up if nothing has come back after this long
+const TIMEOUT_SECONDS: f32 = 1.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProbeResult {
+    Idle,
+    Listening,
+    Measured(usize),
+    TimedOut,
+}
+
+pub struct LatencyProbe {
+    result: ProbeResult,
+    elapsed: usize,
+    timeout: usize,
+}
+
+#[allow(dead_code)]
+impl LatencyProbe {
+    pub fn new(sample_rate: f32) -> LatencyProbe {
+        LatencyProbe {
+            result: ProbeResult::Idle,
+            elapsed: 0,
+            timeout: (TIMEOUT_SECONDS * sample_rate) as usize,
+        }
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.timeout = (TIMEOUT_SECONDS * sample_rate) as usize;
+    }
+
+    pub fn reset(&mut self) {
+        self.result = ProbeResult::Idle;
+        self.elapsed = 0;
+    }
+
+    // the click goes out on the next tick
+    pub fn start(&mut self) {
+        self.result = ProbeResult::Listening;
+        self.elapsed = 0;
+    }
+
+    pub fn is_listening(&self) -> bool {
+        self.result == ProbeResult::Listening
+    }
+
+    pub fn result(&self) -> ProbeResult {
+        self.result
+    }
+
+    // what to output instead of the plugin's output while listening
+    pub fn tick(&mut self, input: f32) -> f32 {
+        if !self.is_listening() {
+            return 0.0;
+        }
+        // the click itself might come straight back with no latency at all
+        if self.elapsed > 0 && input.abs() >= DETECT_THRESHOLD {
+            self.result = ProbeResult::Measured(self.elapsed);
+            return 0.0;
+        }
+        let output = if self.elapsed == 0 { CLICK_LEVEL } else { 0.0 };
+        self.elapsed += 1;
+        if self.elapsed > self.timeout {
+            self.result = ProbeResult::TimedOut;
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // runs the probe with its output coming back latency samples later
+    fn loop_back(probe: &mut LatencyProbe, latency: usize, num_samples: usize) {
+        let mut line = vec![0.0; latency + 1];
+        for _ in 0..num_samples {
+            let input = line.remove(0);
+            line.push(probe.tick(input));
+        }
+    }
+
+    #[test]
+    fn test_latency_probe_measures() {
+        let mut probe = LatencyProbe::new(100.0);
+        assert_eq!(probe.tick(1.0), 0.0);
+        assert_eq!(probe.result(), ProbeResult::Idle);
+
+        probe.start();
+        loop_back(&mut probe, 7, 20);
+        // the click goes out on tick 0, and is heard on tick 8
+        assert_eq!(probe.result(), ProbeResult::Measured(8));
+
+        probe.start();
+        loop_back(&mut probe, 0, 5);
+        assert_eq!(probe.result(), ProbeResult::Measured(1));
+    }
+
+    #[test]
+    fn test_latency_probe_times_out() {
+        let mut probe = LatencyProbe::new(10.0);
+        probe.start();
+        for _ in 0..10 {
+            probe.tick(0.05);
+        }
+        assert!(probe.is_listening());
+        probe.tick(0.0);
+        assert_eq!(probe.result(), ProbeResult::TimedOut);
+    }
+}
@@ -0,0 +1,109 @@
+use nih_plug::prelude::Enum;
+
+// a short sequence of loop lengths and offsets, one step for each loop, repeating every len steps
+// programmed as a pattern rather than automated by hand, e.g. four quarter length steps to ratchet
+
+// the most steps a pattern can have before it repeats
+pub const PATTERN_STEPS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PatternStep {
+    // a share of the loop's length
+    pub length: f32,
+    // how much further back the step's loop starts, in beats
+    pub offset: f32,
+}
+
+impl PatternStep {
+    pub fn plain() -> PatternStep {
+        PatternStep {
+            length: 1.0,
+            offset: 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LengthPattern {
+    steps: [PatternStep; PATTERN_STEPS],
+    len: usize,
+}
+
+#[allow(dead_code)]
+impl LengthPattern {
+    // None if there are no steps or too many
+    pub fn from_steps(steps: &[PatternStep]) -> Option<LengthPattern> {
+        if steps.is_empty() || steps.len() > PATTERN_STEPS {
+            return None;
+        }
+        let mut pattern = LengthPattern {
+            steps: [PatternStep::plain(); PATTERN_STEPS],
+            len: steps.len(),
+        };
+        pattern.steps[..steps.len()].copy_from_slice(steps);
+        Some(pattern)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    // the step for the loop with this index, counting from the first loop
+    pub fn step(&self, index: usize) -> PatternStep {
+        self.steps[index % self.len]
+    }
+}
+
+#[derive(Enum, Debug, Clone, Copy, PartialEq)]
+pub enum StepLength {
+    #[id = "quarter"]
+    #[name = "1/4"]
+    Quarter,
+    #[id = "third"]
+    #[name = "1/3"]
+    Third,
+    #[id = "half"]
+    #[name = "1/2"]
+    Half,
+    #[id = "whole"]
+    #[name = "1"]
+    Whole,
+    #[id = "double"]
+    #[name = "2"]
+    Double,
+}
+
+impl StepLength {
+    pub fn ratio(&self) -> f32 {
+        match self {
+            StepLength::Quarter => 0.25,
+            StepLength::Third => 1.0 / 3.0,
+            StepLength::Half => 0.5,
+            StepLength::Whole => 1.0,
+            StepLength::Double => 2.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_length_pattern() {
+        assert_eq!(LengthPattern::from_steps(&[]), None);
+        let step = PatternStep {
+            length: 0.5,
+            offset: 0.25,
+        };
+        assert_eq!(LengthPattern::from_steps(&[step; PATTERN_STEPS + 1]), None);
+
+        // repeats after the last step
+        let pattern = LengthPattern::from_steps(&[PatternStep::plain(), step, step]).unwrap();
+        assert_eq!(pattern.len(), 3);
+        assert_eq!(pattern.step(0), PatternStep::plain());
+        assert_eq!(pattern.step(2), step);
+        assert_eq!(pattern.step(3), PatternStep::plain());
+        assert_eq!(pattern.step(4), step);
+    }
+}
@@ -0,0 +1,107 @@
+// halves or doubles the loop length each time a trigger goes on, on top of the length param
+// so a pad can step the length without the param having to be automated
+// the momentary halve and double only last while held, for fills
+pub struct LengthScale {
+    scale: f32,
+    momentary: f32,
+    halve_held: bool,
+    double_held: bool,
+}
+
+#[allow(dead_code)]
+impl LengthScale {
+    pub fn new() -> LengthScale {
+        LengthScale {
+            scale: 1.0,
+            momentary: 1.0,
+            halve_held: false,
+            double_held: false,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.scale = 1.0;
+        self.momentary = 1.0;
+    }
+
+    // on top of the steps, until let go, holding both cancels out
+    pub fn set_momentary(&mut self, halve: bool, double: bool) {
+        self.momentary = match (halve, double) {
+            (true, false) => 0.5,
+            (false, true) => 2.0,
+            _ => 1.0,
+        };
+    }
+
+    // a step that would take the length outside min..max is ignored
+    pub fn update(&mut self, halve: bool, double: bool, length: f32, min: f32, max: f32) {
+        if halve && !self.halve_held && length * self.scale * 0.5 >= min {
+            self.scale *= 0.5;
+        }
+        if double && !self.double_held && length * self.scale * 2.0 <= max {
+            self.scale *= 2.0;
+        }
+        self.halve_held = halve;
+        self.double_held = double;
+    }
+
+    // the length param may have moved since, so this is clamped too
+    pub fn apply(&self, length: f32, min: f32, max: f32) -> f32 {
+        (length * self.scale * self.momentary).clamp(min, max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_length_scale() {
+        let mut scale = LengthScale::new();
+        scale.update(true, false, 1.0, 0.25, 4.0);
+        assert_eq!(scale.apply(1.0, 0.25, 4.0), 0.5);
+        // held, so only once
+        scale.update(true, false, 1.0, 0.25, 4.0);
+        assert_eq!(scale.apply(1.0, 0.25, 4.0), 0.5);
+        scale.update(false, true, 1.0, 0.25, 4.0);
+        scale.update(false, false, 1.0, 0.25, 4.0);
+        scale.update(false, true, 1.0, 0.25, 4.0);
+        assert_eq!(scale.apply(1.0, 0.25, 4.0), 2.0);
+
+        scale.reset();
+        assert_eq!(scale.apply(1.0, 0.25, 4.0), 1.0);
+    }
+
+    #[test]
+    fn test_length_scale_momentary() {
+        let mut scale = LengthScale::new();
+        scale.update(true, false, 1.0, 0.25, 4.0);
+        scale.set_momentary(true, false);
+        assert_eq!(scale.apply(1.0, 0.25, 4.0), 0.25);
+        scale.set_momentary(true, true);
+        assert_eq!(scale.apply(1.0, 0.25, 4.0), 0.5);
+        scale.set_momentary(false, true);
+        assert_eq!(scale.apply(1.0, 0.25, 4.0), 1.0);
+        // letting go goes back to the stepped length
+        scale.set_momentary(false, false);
+        assert_eq!(scale.apply(1.0, 0.25, 4.0), 0.5);
+        // and is kept in bounds while held
+        scale.set_momentary(true, false);
+        assert_eq!(scale.apply(0.25, 0.25, 4.0), 0.25);
+    }
+
+    #[test]
+    fn test_length_scale_bounds() {
+        let mut scale = LengthScale::new();
+        for _ in 0..4 {
+            scale.update(false, true, 1.0, 0.25, 4.0);
+            scale.update(false, false, 1.0, 0.25, 4.0);
+        }
+        assert_eq!(scale.apply(1.0, 0.25, 4.0), 4.0);
+        // halving goes straight back down from the top
+        scale.update(true, false, 1.0, 0.25, 4.0);
+        assert_eq!(scale.apply(1.0, 0.25, 4.0), 2.0);
+        // a longer length param is kept in bounds
+        assert_eq!(scale.apply(4.0, 0.25, 4.0), 4.0);
+    }
+}
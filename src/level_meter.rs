@@ -0,0 +1,102 @@
+use crate::audio_frame::{AudioFrame, MAX_CHANNELS};
+use atomic_float::AtomicF32;
+use std::sync::atomic::Ordering;
+
+// the loudest sample and the power of some frames, across all their channels
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Levels {
+    peak: f32,
+    sum_squares: f32,
+    num_samples: usize,
+}
+
+#[allow(dead_code)]
+impl Levels {
+    pub fn of_block<T: AudioFrame>(frames: &[T]) -> Levels {
+        let mut levels = Levels::default();
+        for frame in frames {
+            levels.add(frame);
+        }
+        levels
+    }
+
+    pub fn add<T: AudioFrame>(&mut self, frame: &T) {
+        let mut channels = [0.0; MAX_CHANNELS];
+        frame.write_channels(&mut channels[..T::NUM_CHANNELS]);
+        for sample in &channels[..T::NUM_CHANNELS] {
+            self.peak = self.peak.max(sample.abs());
+            self.sum_squares += sample * sample;
+        }
+        self.num_samples += T::NUM_CHANNELS;
+    }
+
+    pub fn peak(&self) -> f32 {
+        self.peak
+    }
+
+    pub fn rms(&self) -> f32 {
+        if self.num_samples == 0 {
+            return 0.0;
+        }
+        (self.sum_squares / self.num_samples as f32).sqrt()
+    }
+}
+
+// levels of one signal, written by the audio thread every block and read by the editor
+// the peak is held until it is taken, so a peak between two reads isn't missed
+// the rms is of the last block only, the editor smooths it however it likes
+#[derive(Default)]
+pub struct LevelMeter {
+    peak: AtomicF32,
+    rms: AtomicF32,
+}
+
+#[allow(dead_code)]
+impl LevelMeter {
+    pub fn publish(&self, levels: &Levels) {
+        self.peak.fetch_max(levels.peak(), Ordering::Relaxed);
+        self.rms.store(levels.rms(), Ordering::Relaxed);
+    }
+
+    // the loudest sample since the last take, above 1 is clipping
+    pub fn take_peak(&self) -> f32 {
+        self.peak.swap(0.0, Ordering::Relaxed)
+    }
+
+    pub fn rms(&self) -> f32 {
+        self.rms.load(Ordering::Relaxed)
+    }
+}
+
+// the input before the looper, the loop with its effects and gain, and what goes out of the plugin
+#[derive(Default)]
+pub struct LevelMeters {
+    pub input: LevelMeter,
+    pub wet: LevelMeter,
+    pub output: LevelMeter,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stereo_pair::StereoPair;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_levels() {
+        let levels = Levels::of_block(&[StereoPair::new(0.5, -1.0), StereoPair::new(0.5, 1.0)]);
+        assert_eq!(levels.peak(), 1.0);
+        assert_abs_diff_eq!(levels.rms(), (2.5f32 / 4.0).sqrt(), epsilon = 1e-6);
+        assert_eq!(Levels::of_block::<f32>(&[]).rms(), 0.0);
+    }
+
+    #[test]
+    fn test_level_meter_holds_peak() {
+        let meter = LevelMeter::default();
+        meter.publish(&Levels::of_block(&[1.5, 0.0]));
+        meter.publish(&Levels::of_block(&[0.5, 0.5]));
+        assert_eq!(meter.rms(), 0.5);
+        assert_eq!(meter.take_peak(), 1.5);
+        assert_eq!(meter.take_peak(), 0.0);
+    }
+}
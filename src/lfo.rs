@@ -0,0 +1,101 @@
+use nih_plug::prelude::Enum;
+
+#[derive(Enum, Debug, Clone, Copy, PartialEq)]
+pub enum LfoShape {
+    #[id = "sine"]
+    #[name = "Sine"]
+    Sine,
+    #[id = "triangle"]
+    #[name = "Triangle"]
+    Triangle,
+    #[id = "square"]
+    #[name = "Square"]
+    Square,
+}
+
+impl LfoShape {
+    // value in -1..1 for a phase in 0..1
+    pub fn value(&self, phase: f32) -> f32 {
+        match self {
+            LfoShape::Sine => (phase * std::f32::consts::TAU).sin(),
+            // starts at 0 and rises, like the sine
+            LfoShape::Triangle => {
+                if phase < 0.25 {
+                    phase * 4.0
+                } else if phase < 0.75 {
+                    2.0 - phase * 4.0
+                } else {
+                    phase * 4.0 - 4.0
+                }
+            }
+            LfoShape::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+        }
+    }
+}
+
+// an lfo locked to the song position, so it has no state and can't drift from the host
+pub struct BeatLfo {
+    shape: LfoShape,
+    period_beats: f32,
+}
+
+#[allow(dead_code)]
+impl BeatLfo {
+    pub fn new(shape: LfoShape, period_beats: f32) -> BeatLfo {
+        BeatLfo {
+            shape,
+            period_beats,
+        }
+    }
+
+    pub fn set_shape(&mut self, shape: LfoShape) {
+        self.shape = shape;
+    }
+
+    // how many beats one cycle takes
+    pub fn set_period(&mut self, period_beats: f32) {
+        self.period_beats = period_beats;
+    }
+
+    // phase 0..1 at a song position, one cycle starts on every multiple of the period
+    pub fn phase_at(&self, beat_time: f64) -> f32 {
+        (beat_time / self.period_beats as f64).rem_euclid(1.0) as f32
+    }
+
+    pub fn value_at(&self, beat_time: f64) -> f32 {
+        self.shape.value(self.phase_at(beat_time))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lfo_shapes() {
+        for shape in [LfoShape::Sine, LfoShape::Triangle] {
+            assert!(shape.value(0.0).abs() < 0.0001);
+            assert!((shape.value(0.25) - 1.0).abs() < 0.0001);
+            assert!(shape.value(0.5).abs() < 0.0001);
+            assert!((shape.value(0.75) + 1.0).abs() < 0.0001);
+        }
+        assert_eq!(LfoShape::Square.value(0.1), 1.0);
+        assert_eq!(LfoShape::Square.value(0.6), -1.0);
+    }
+
+    #[test]
+    fn test_beat_lfo_phase() {
+        let lfo = BeatLfo::new(LfoShape::Sine, 2.0);
+        assert_eq!(lfo.phase_at(0.0), 0.0);
+        assert_eq!(lfo.phase_at(1.0), 0.5);
+        assert_eq!(lfo.phase_at(5.0), 0.5);
+        // before the song start, e.g. in a count in
+        assert_eq!(lfo.phase_at(-0.5), 0.75);
+    }
+}
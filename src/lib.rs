@@ -1,18 +1,137 @@
 use nih_plug::{prelude::*, wrapper::vst3::vst3_sys::vst::LegacyMidiCCOutEvent};
-use std::sync::Arc;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, AtomicU8, Ordering};
+use std::sync::{Arc, Mutex};
 
+mod audio_file;
+mod audio_frame;
+mod auto_pan;
+mod background_task;
+mod bend_scrub;
+mod cc_scrub;
 mod countdown_trigger;
 mod delay_line;
+mod dry_ramps;
+mod fallback_clock;
+mod frame;
+mod freq_shifter;
 mod grain;
 mod grain_looper;
 mod grain_player;
+mod groove;
+mod host_sim;
+mod key_scrub;
+mod latency_probe;
+mod length_pattern;
+mod length_scale;
+mod level_meter;
+mod lfo;
+mod lookahead_queue;
+mod loop_export;
+mod loop_import;
 mod loop_scheduler;
+mod loop_status;
+mod midi_learn;
+mod note_trigger;
+mod note_value;
+mod output_mode;
+mod param_format;
+mod performance;
+mod phase_check;
+mod process_context;
 mod ramped_value;
+mod random;
+mod region_fit;
+mod resampler;
+mod ring_mod;
 mod scheduler;
+mod sections;
+mod soft_takeover;
+mod splice_analyzer;
 mod stereo_pair;
+mod stretch;
+#[cfg(test)]
 mod test_utils;
-use grain_looper::GrainLooper;
+mod transient_trigger;
+mod transport_stop;
+mod wav;
+mod waveform;
+mod wet_chain;
+mod wet_effect;
+use audio_frame::{AudioFrame, MAX_CHANNELS};
+use auto_pan::AutoPan;
+use background_task::BackgroundTask;
+use bend_scrub::BendScrub;
+use cc_scrub::CcScrub;
+use fallback_clock::FallbackClock;
+use frame::Frame;
+use grain_looper::{
+    loopable_region_length, seconds_to_samples, GrainLooper, ReverseMode, MAX_FADE_SECONDS,
+};
+use grain_player::VoiceStealing;
+use groove::GroovePreset;
+use key_scrub::KeyScrub;
+use latency_probe::{LatencyProbe, ProbeResult};
+use length_pattern::{LengthPattern, PatternStep, StepLength, PATTERN_STEPS};
+use length_scale::LengthScale;
+use level_meter::{LevelMeters, Levels};
+use lfo::LfoShape;
+use loop_export::LoopExport;
+use loop_import::LoopImport;
+use loop_scheduler::{CountInUnit, LaunchQuantization, Stutter};
+use loop_status::{LoopStatus, LoopStatusSnapshot};
+use midi_learn::{LearnTarget, MidiLearn, LEARN_TARGETS, NUM_TARGETS, UNMAPPED};
+use note_trigger::{NoteTrigger, NoteTriggerMode, ReleaseDry};
+use note_value::{NoteFeel, NoteValue};
+use output_mode::OutputMode;
+use performance::{LoopState, Performance};
+use process_context::{HostTransport, ProcessContextLite};
+use ramped_value::FadeShape;
+use region_fit::RegionPolicy;
+use sections::{SectionSettings, SectionSlot, Sections};
+use splice_analyzer::SpliceAnalyzer;
 use stereo_pair::StereoPair;
+use stretch::{MAX_DENSITY, MIN_DENSITY};
+use transient_trigger::TransientTrigger;
+use transport_stop::TransportStopMode;
+use waveform::WaveformSnapshot;
+use wet_chain::WetChain;
+use wet_effect::{ColorEffect, ColorSlot};
+
+// the fine offset covers +/- a 32nd of a beat, i.e. a 128th note
+const MAX_FINE_OFFSET_BEATS: f32 = 1.0 / 32.0;
+// the grid can be moved on by up to a bar of 4/4
+const MAX_GRID_OFFSET_BEATS: f32 = 4.0;
+// longest free running loop length and offset, keeps within the looper's buffers at 48k
+const MAX_FREE_SECONDS: f32 = 1.0;
+const MIN_FREE_SECONDS: f32 = 0.01;
+// the bottom left pad on a lot of drum controllers
+const DEFAULT_TRIGGER_NOTE: i32 = 36;
+// the octave above, clear of the trigger note
+const DEFAULT_KEY_SCRUB_ROOT: i32 = 48;
+// enough for 5.1, quad leaves the last two channels silent
+const SURROUND_CHANNELS: usize = 6;
+// order of the effects on the loop
+const COLOR_SLOT: usize = 0;
+const AUTO_PAN_SLOT: usize = 1;
+// the mod wheel
+const DEFAULT_SCRUB_CC: u8 = 1;
+// long enough to smooth out the steps of a 7 bit cc
+const CC_SCRUB_SLEW_SECONDS: f32 = 0.05;
+// the bend wheel is 14 bit so only needs a little smoothing
+const BEND_SCRUB_SLEW_SECONDS: f32 = 0.01;
+// how far ahead of time the loop's events are resolved into grains, a few ms
+// changes made closer to a grid line than this wait for the next one
+const LOOKAHEAD_SECONDS: f32 = 0.002;
+// how often the waveform is snapshotted for an editor, about as often as it's redrawn
+const WAVEFORM_SECONDS: f32 = 1.0 / 30.0;
+
+// every layout has an aux output with as many channels as the main one, carrying only the loop
+// whatever the output mode, so the loop and the dry can go to different mixer channels
+const LOOP_OUTPUT_NAMES: PortNames = PortNames {
+    aux_outputs: &["Loop"],
+    ..PortNames::const_default()
+};
 
 // This is a shortened version of the gain example with most comments removed, check out
 // https://github.com/robbert-vdh/nih-plug/blob/master/plugins/examples/gain/src/lib.rs to get
@@ -20,8 +139,88 @@ use stereo_pair::StereoPair;
 
 struct Metaloop {
     params: Arc<MetaloopParams>,
+    sample_rate: f32,
     grain_looper: GrainLooper<StereoPair<f32>>,
-    output: StereoPair<f32>,
+    // effects on the loop, in the order of COLOR_SLOT and AUTO_PAN_SLOT
+    wet_chain: WetChain,
+    note_trigger: NoteTrigger,
+    transient_trigger: TransientTrigger,
+    cc_scrub: CcScrub,
+    midi_learn: MidiLearn,
+    bend_scrub: BendScrub,
+    key_scrub: KeyScrub,
+    // the beat position when the host doesn't give us one, or is stopped
+    fallback_clock: FallbackClock,
+    was_playing: bool,
+    // a mono copy of the input for choosing the smart fade, and the fade it chose
+    splice_analyzer: SpliceAnalyzer,
+    captured_fade: Option<f32>,
+    // the stored timing of each section, the audio is kept by the looper
+    sections: Sections,
+    store_section_held: bool,
+    // the halve and double triggers, on top of the length param
+    length_scale: LengthScale,
+    // takes over the output while calibrating the capture latency
+    latency_probe: LatencyProbe,
+    calibrate_held: bool,
+    check_phase_held: bool,
+    import_held: bool,
+    export_held: bool,
+    // the last few bars of loop changes, and replaying them
+    performance: Performance,
+    replay_held: bool,
+    retrigger_held: bool,
+    // scratch space the size of the largest block, for handing frames to the looper
+    block: Vec<StereoPair<f32>>,
+    dry_block: Vec<StereoPair<f32>>,
+    // used instead of the stereo looper when the layout has more than two channels
+    surround: Option<SurroundLooper>,
+    // published every block, for an editor or host bridge to read
+    status: Arc<LoopStatus>,
+    meters: Arc<LevelMeters>,
+    // snapshotted every WAVEFORM_SECONDS rather than every block, as it reads the whole region
+    waveform: Arc<WaveformSnapshot>,
+    waveform_countdown: usize,
+    // request a path on this to have the loop written there by the background task
+    export: Arc<LoopExport>,
+    // or to have the file there decoded and looped
+    import: Arc<LoopImport>,
+    // an imported file keeps the loop on until one of the loop controls takes over
+    import_latched: bool,
+}
+
+// looping for layouts with more than two channels
+// the effects on the loop are stereo, so are skipped
+struct SurroundLooper {
+    grain_looper: GrainLooper<Frame<SURROUND_CHANNELS>>,
+    block: Vec<Frame<SURROUND_CHANNELS>>,
+    dry_block: Vec<Frame<SURROUND_CHANNELS>>,
+}
+
+// one step of the length pattern, nested in MetaloopParams once for each step
+#[derive(Params)]
+struct PatternStepParams {
+    #[id = "length"]
+    pub length: EnumParam<StepLength>,
+
+    /// How much further back the step's loop starts
+    #[id = "offset"]
+    pub offset: FloatParam,
+}
+
+impl Default for PatternStepParams {
+    fn default() -> Self {
+        Self {
+            length: EnumParam::new("Step Length", StepLength::Whole),
+            offset: FloatParam::new(
+                "Step Offset",
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 4.0 },
+            )
+            .with_step_size(0.0625)
+            .with_unit(" beats"),
+        }
+    }
 }
 
 #[derive(Params)]
@@ -29,63 +228,873 @@ struct MetaloopParams {
     /// The parameter's ID is used to identify the parameter in the wrappred plugin API. As long as
     /// these IDs remain constant, you can rename and reorder these fields as you wish. The
     /// parameters are exposed to the host in the same order they were defined.
-    /// Loop length in seconds
+    /// Loop length as a note value
     #[id = "loop-length"]
-    pub loop_length: FloatParam,
+    pub loop_length: EnumParam<NoteValue>,
+
+    /// Makes the loop length a triplet or dotted one
+    #[id = "loop-feel"]
+    pub loop_feel: EnumParam<NoteFeel>,
+
+    /// Halves the loop length each time it is turned on, starting at the next loop
+    #[id = "halve-length"]
+    pub halve_length: BoolParam,
+
+    /// Doubles the loop length each time it is turned on, starting at the next loop
+    #[id = "double-length"]
+    pub double_length: BoolParam,
+
+    /// Halves the loop length while held, going back on release, for fills
+    #[id = "hold-half"]
+    pub hold_half: BoolParam,
+
+    /// Doubles the loop length while held, going back on release
+    #[id = "hold-double"]
+    pub hold_double: BoolParam,
 
     #[id = "loop-offset"]
     pub loop_offset: FloatParam,
 
+    /// Added to the coarse offset, for detailed scrubbing automation
+    #[id = "loop-offset-fine"]
+    pub loop_offset_fine: FloatParam,
+
+    /// Rounds the offset to the nearest slice, so scrubbing jumps between slices of the beat.
+    /// The fine offset is added after, and the free running offset is never snapped
+    #[id = "offset-snap"]
+    pub offset_snap: BoolParam,
+
+    /// The slice the offset snaps to
+    #[id = "offset-snap-value"]
+    pub offset_snap_value: EnumParam<NoteValue>,
+
+    /// Moves each repeat's start at random, up to this share of the loop either side of the offset
+    #[id = "offset-jitter"]
+    pub offset_jitter: FloatParam,
+
+    /// Pans each repeat at random, up to this far either side of the centre
+    #[id = "stereo-spread"]
+    pub stereo_spread: FloatParam,
+
+    /// On while the offset is being scrubbed, so the scrub follows straight away rather than
+    /// waiting for the next loop. Automate it along with the offset to record a scrub
+    #[id = "scrub-active"]
+    pub scrub_active: BoolParam,
+
     #[id = "loop"]
     pub loop_param: BoolParam,
 
+    /// When the loop starts once turned on, on its own grid, straight away, or on the next beat
+    /// or bar whatever its length
+    #[id = "launch-quantization"]
+    pub launch_quantization: EnumParam<LaunchQuantization>,
+
+    /// Counts this many beats or bars once the loop is turned on, before it starts
+    #[id = "count-in"]
+    pub count_in: IntParam,
+
+    #[id = "count-in-unit"]
+    pub count_in_unit: EnumParam<CountInUnit>,
+
+    /// Starts the loop the moment it is turned on, cutting in quickly, for free-form looping
+    #[id = "instant-capture"]
+    pub instant_capture: BoolParam,
+
+    /// Moves the loop grid on from the host's bar lines, e.g. to loop from the off-beat
+    #[id = "grid-offset"]
+    pub grid_offset: FloatParam,
+
+    /// Pushes every other grid line late, so short loops lock to a swung feel
+    #[id = "grid-swing"]
+    pub grid_swing: FloatParam,
+
+    /// Restarts the loop from its beginning at the next 64th each time it is turned on
+    #[id = "retrigger"]
+    pub retrigger: BoolParam,
+
+    /// Hold to slow the loop to a stop like a tape machine, it speeds back up on release
+    #[id = "tape-stop"]
+    pub tape_stop: BoolParam,
+
+    /// How long the tape takes to stop, and to get back up to speed
+    #[id = "tape-stop-time"]
+    pub tape_stop_time: FloatParam,
+
+    /// Forwards, backwards, or alternating between the two each loop
     #[id = "reverse"]
-    pub reverse_param: BoolParam,
+    pub reverse_param: EnumParam<ReverseMode>,
+
+    /// Keep playing the same audio when reversing at speeds other than 1x
+    #[id = "reverse-in-place"]
+    pub reverse_in_place: BoolParam,
+
+    /// When the loop stops on the grid, play it once more the other way as the dry comes back
+    #[id = "reverse-tail"]
+    pub reverse_tail: BoolParam,
 
     #[id = "fade"]
     pub fade: FloatParam,
+
+    /// How long the dry takes to go as the loop starts and come back as it stops, separate from the
+    /// loop's crossfade so the dry can switch quickly under a long one
+    #[id = "dry-fade"]
+    pub dry_fade: FloatParam,
+
+    /// Pick the fade from the audio each time a loop is captured, between the shortest fade and the fade
+    #[id = "smart-fade"]
+    pub smart_fade: BoolParam,
+
+    #[id = "fade-min"]
+    pub fade_min: FloatParam,
+
+    /// The law the fades follow, linear suits a loop of steady sound, equal power one of changing sound
+    #[id = "fade-shape"]
+    pub fade_shape: EnumParam<FadeShape>,
+
+    /// Which grain is faded out quickly to make way when every voice is busy
+    #[id = "voice-stealing"]
+    pub voice_stealing: EnumParam<VoiceStealing>,
+
+    /// How length changes go over, from a hard cut, through the fade, to the dry blending in under them
+    #[id = "transition-smoothness"]
+    pub transition_smoothness: FloatParam,
+
+    /// Playback speed of new grains, also changes their pitch
+    #[id = "speed"]
+    pub speed: FloatParam,
+
+    /// How long the speed takes to get to a new setting, sweeping like varispeed
+    #[id = "speed-glide"]
+    pub speed_glide: FloatParam,
+
+    /// Repitches the loop in semitones without changing its length
+    #[id = "pitch"]
+    pub pitch: FloatParam,
+
+    /// Speed changes how fast the loop moves through its audio, but not its pitch
+    #[id = "time-stretch"]
+    pub time_stretch: BoolParam,
+
+    /// How long each of the short grains a time-stretched loop is played with lasts
+    #[id = "stretch-grain-size"]
+    pub stretch_grain_size: FloatParam,
+
+    /// How many of the short grains overlap at once, more is smoother but blurrier
+    #[id = "stretch-density"]
+    pub stretch_density: FloatParam,
+
+    /// Keep the level up through the crossfade when the speed or direction changes
+    #[id = "speed-gain-compensation"]
+    pub speed_gain_compensation: BoolParam,
+
+    /// The chance of each repeat playing, the dry comes back through the repeats that don't
+    #[id = "repeat-probability"]
+    pub repeat_probability: FloatParam,
+
+    /// Restarts the loop within itself, for beat repeat rolls
+    #[id = "stutter"]
+    pub stutter: EnumParam<Stutter>,
+
+    /// Steps through a pattern of lengths and offsets, a step for each loop
+    #[id = "length-pattern"]
+    pub length_pattern: BoolParam,
+
+    #[id = "pattern-steps"]
+    pub pattern_steps: IntParam,
+
+    #[nested(array, group = "Pattern Step")]
+    pub pattern: [PatternStepParams; PATTERN_STEPS],
+
+    /// How loud each repeat of the loop is against the one before, below 100% the loop dies away
+    #[id = "feedback"]
+    pub feedback: FloatParam,
+
+    /// How long each repeat takes to rise, as a share of the loop, so the loop pumps in
+    #[id = "loop-attack"]
+    pub loop_attack: FloatParam,
+
+    /// How long each repeat then takes to die away, as a share of the loop, 0 holds it
+    #[id = "loop-decay"]
+    pub loop_decay: FloatParam,
+
+    /// How much of the loop is heard against the dry signal while looping
+    #[id = "mix"]
+    pub mix: FloatParam,
+
+    /// How loud the dry carries on under the loop while looping, so the loop can sit on top
+    #[id = "dry-duck-level"]
+    pub dry_duck_level: FloatParam,
+
+    /// Hold to hear only the loop, at full level
+    #[id = "solo"]
+    pub solo: BoolParam,
+
+    /// Routing of the loop and dry signal to the main outputs
+    #[id = "output-mode"]
+    pub output_mode: EnumParam<OutputMode>,
+
+    /// Timing feel applied to where each loop starts
+    #[id = "groove"]
+    pub groove: EnumParam<GroovePreset>,
+
+    #[id = "groove-amount"]
+    pub groove_amount: FloatParam,
+
+    /// Hold back length, offset and direction changes until the next loop starts
+    #[id = "quantize-changes"]
+    pub quantize_changes: BoolParam,
+
+    /// Follow the host tempo, or free run using the lengths in seconds
+    #[id = "sync"]
+    pub sync: BoolParam,
+
+    /// Tempo used when the host doesn't report one
+    #[id = "fallback-tempo"]
+    pub fallback_tempo: FloatParam,
+
+    /// What the loop does while the host transport is stopped
+    #[id = "transport-stop"]
+    pub transport_stop: EnumParam<TransportStopMode>,
+
+    /// Loop length when not synced
+    #[id = "free-length"]
+    pub free_length: FloatParam,
+
+    /// Loop offset when not synced
+    #[id = "free-offset"]
+    pub free_offset: FloatParam,
+
+    /// What to do when the offset and length ask for audio the buffers don't hold
+    #[id = "region-policy"]
+    pub region_policy: EnumParam<RegionPolicy>,
+
+    /// The section playing, switched at the next bar. A stored section replaces the loop's audio and timing
+    #[id = "section"]
+    pub section: EnumParam<SectionSlot>,
+
+    /// Stores the loop's audio and timing in the selected section
+    #[id = "store-section"]
+    pub store_section: BoolParam,
+
+    /// Plays the loop going on and off, and its length and offset changes, from the last few bars again
+    #[id = "replay-performance"]
+    pub replay_performance: BoolParam,
+
+    #[id = "color-on"]
+    pub color_enabled: BoolParam,
+
+    /// Effect coloring the loop
+    #[id = "color"]
+    pub color: EnumParam<ColorEffect>,
+
+    /// Ring mod carrier or frequency shift
+    #[id = "color-freq"]
+    pub color_freq: FloatParam,
+
+    /// Lock the ring mod carrier to the beat, so each repeat of the loop is the same
+    #[id = "color-sync"]
+    pub color_sync: BoolParam,
+
+    #[id = "color-mix"]
+    pub color_mix: FloatParam,
+
+    #[id = "pan-on"]
+    pub pan_enabled: BoolParam,
+
+    /// Length of one left-right cycle of the auto-pan on the loop
+    #[id = "pan-rate"]
+    pub pan_rate: EnumParam<NoteValue>,
+
+    #[id = "pan-depth"]
+    pub pan_depth: FloatParam,
+
+    #[id = "pan-shape"]
+    pub pan_shape: EnumParam<LfoShape>,
+
+    /// The midi note that turns the loop on and off, along with the loop parameter
+    #[id = "trigger-note"]
+    pub trigger_note: IntParam,
+
+    #[id = "trigger-mode"]
+    pub trigger_mode: EnumParam<NoteTriggerMode>,
+
+    /// How the dry comes back when a momentary trigger note is let go
+    #[id = "release-dry"]
+    pub release_dry: EnumParam<ReleaseDry>,
+
+    /// Maps the keys from the root up onto loop offsets, each a step further back, so a keyboard
+    /// scrubs through slices of the past while the loop runs
+    #[id = "key-scrub"]
+    pub key_scrub: BoolParam,
+
+    #[id = "key-scrub-root"]
+    pub key_scrub_root: IntParam,
+
+    #[id = "key-scrub-step"]
+    pub key_scrub_step: EnumParam<NoteValue>,
+
+    /// Plays the loop a set number of times then fades back to the dry, until it is turned off
+    #[id = "one-shot"]
+    pub one_shot: BoolParam,
+
+    #[id = "one-shot-passes"]
+    pub one_shot_passes: IntParam,
+
+    /// Starts the loop on the grid once the input jumps above the threshold, for hands-free
+    /// looping, the loop stays on until this is turned off
+    #[id = "auto-capture"]
+    pub auto_capture: BoolParam,
+
+    #[id = "auto-threshold"]
+    pub auto_threshold: FloatParam,
+
+    /// The midi cc that scrubs the loop offset, set by learning
+    #[persist = "scrub-cc"]
+    pub scrub_cc: AtomicU8,
+
+    /// While on, the next midi cc received becomes the scrub cc
+    #[id = "scrub-learn"]
+    pub scrub_learn: BoolParam,
+
+    /// The param that the next midi cc received is mapped to, while learning
+    #[id = "learn-target"]
+    pub learn_target: EnumParam<LearnTarget>,
+
+    /// While on, the next midi cc received is mapped to the learn target
+    #[id = "midi-learn"]
+    pub midi_learn: BoolParam,
+
+    /// The midi cc mapped to each learn target, set by learning
+    #[persist = "loop-cc"]
+    pub loop_cc: AtomicU8,
+    #[persist = "length-cc"]
+    pub length_cc: AtomicU8,
+    #[persist = "reverse-cc"]
+    pub reverse_cc: AtomicU8,
+    #[persist = "speed-cc"]
+    pub speed_cc: AtomicU8,
+
+    /// The round trip from our output back to our input in samples, set by calibrating.
+    /// Captured loops reach this much further back, so live playing lands on the grid
+    #[persist = "capture-latency"]
+    pub capture_latency: AtomicU32,
+
+    /// With the output looped back to the input, turning this on sends a click and measures
+    /// how long it takes to come back
+    #[id = "calibrate-latency"]
+    pub calibrate_latency: BoolParam,
+
+    /// Turning this on loops a test tone through a plugin of its own, the way this one is run,
+    /// and logs it if the loop doesn't cancel against the tone, e.g. slipped or flipped
+    #[id = "check-phase"]
+    pub check_phase: BoolParam,
+
+    /// The wav or aiff file last imported into the loop, set by an editor
+    #[persist = "import-path"]
+    pub import_path: Arc<Mutex<Option<PathBuf>>>,
+
+    /// Turning this on imports the import path into the loop again, which keeps looping it
+    /// until a loop control takes over
+    #[id = "import-loop"]
+    pub import_loop: BoolParam,
+
+    /// Where an exported loop is written, set by an editor
+    #[persist = "export-path"]
+    pub export_path: Arc<Mutex<Option<PathBuf>>>,
+
+    /// Turning this on writes what the loop can play to the export path, or to
+    /// "metaloop loop.wav" in the temp folder if there isn't one
+    #[id = "export-loop"]
+    pub export_loop: BoolParam,
+
+    /// How far the pitch bend wheel scrubs the offset either way
+    #[id = "bend-range"]
+    pub bend_range: FloatParam,
+
+    /// How long the scrub takes to glide back when the bend wheel is released
+    #[id = "bend-return"]
+    pub bend_return: FloatParam,
 }
 
 impl Default for Metaloop {
     fn default() -> Self {
+        let params = Arc::new(MetaloopParams::default());
         Self {
-            params: Arc::new(MetaloopParams::default()),
+            export: Arc::new(LoopExport::new(params.export_path.clone())),
+            import: Arc::new(LoopImport::new(params.import_path.clone())),
+            params,
+            sample_rate: 44100.0,
             grain_looper: GrainLooper::new(44100.0),
-            output: StereoPair::default(),
+            wet_chain: Metaloop::build_wet_chain(),
+            note_trigger: NoteTrigger::new(DEFAULT_TRIGGER_NOTE as u8, NoteTriggerMode::Latch),
+            transient_trigger: TransientTrigger::new(),
+            cc_scrub: CcScrub::new(0),
+            midi_learn: MidiLearn::new(),
+            bend_scrub: BendScrub::new(0, 0),
+            key_scrub: KeyScrub::new(DEFAULT_KEY_SCRUB_ROOT as u8),
+            fallback_clock: FallbackClock::new(),
+            was_playing: false,
+            splice_analyzer: SpliceAnalyzer::new(loopable_region_length(44100.0)),
+            captured_fade: None,
+            sections: Sections::new(),
+            store_section_held: false,
+            length_scale: LengthScale::new(),
+            latency_probe: LatencyProbe::new(44100.0),
+            calibrate_held: false,
+            check_phase_held: false,
+            import_held: false,
+            export_held: false,
+            performance: Performance::new(),
+            replay_held: false,
+            retrigger_held: false,
+            block: Vec::new(),
+            dry_block: Vec::new(),
+            surround: None,
+            status: Arc::new(LoopStatus::default()),
+            meters: Arc::new(LevelMeters::default()),
+            waveform: Arc::new(WaveformSnapshot::default()),
+            waveform_countdown: 0,
+            import_latched: false,
+        }
+    }
+}
+
+impl MetaloopParams {
+    fn learned_cc(&self, target: LearnTarget) -> &AtomicU8 {
+        match target {
+            LearnTarget::Loop => &self.loop_cc,
+            LearnTarget::Length => &self.length_cc,
+            LearnTarget::Reverse => &self.reverse_cc,
+            LearnTarget::Speed => &self.speed_cc,
         }
     }
+
+    // the cc of each learn target, by the target's index
+    fn cc_mapping(&self) -> [u8; NUM_TARGETS] {
+        LEARN_TARGETS.map(|target| self.learned_cc(target).load(Ordering::Relaxed))
+    }
+
+    // a cc only moves one target, so it is taken off any other it was mapped to
+    fn map_cc(&self, target: LearnTarget, cc: u8) {
+        for other in LEARN_TARGETS {
+            let other_cc = self.learned_cc(other);
+            if other != target && other_cc.load(Ordering::Relaxed) == cc {
+                other_cc.store(UNMAPPED, Ordering::Relaxed);
+            }
+        }
+        self.learned_cc(target).store(cc, Ordering::Relaxed);
+    }
 }
 
 impl Default for MetaloopParams {
     fn default() -> Self {
         Self {
-            loop_length: FloatParam::new(
-                "Length",
-                0.1,
-                FloatRange::Skewed {
-                    min: 0.01,
-                    max: 1.0,
-                    factor: FloatRange::skew_factor(-2.0),
+            loop_length: EnumParam::new("Length", NoteValue::Quarter),
+            loop_feel: EnumParam::new("Length Feel", NoteFeel::Straight),
+            halve_length: BoolParam::new("Halve Length", false),
+            double_length: BoolParam::new("Double Length", false),
+            hold_half: BoolParam::new("Hold Half", false),
+            hold_double: BoolParam::new("Hold Double", false),
+
+            loop_offset: FloatParam::new("Offset", 0.1, FloatRange::Linear { min: 0.0, max: 1.0 })
+                .with_value_to_string(param_format::v2s_beats_to_sixteenths())
+                .with_string_to_value(param_format::s2v_sixteenths_to_beats()),
+
+            loop_offset_fine: FloatParam::new(
+                "Fine Offset",
+                0.0,
+                FloatRange::Linear {
+                    min: -MAX_FINE_OFFSET_BEATS,
+                    max: MAX_FINE_OFFSET_BEATS,
                 },
             )
-            .with_unit(" s"),
+            .with_value_to_string(param_format::v2s_beats_to_sixteenths())
+            .with_string_to_value(param_format::s2v_sixteenths_to_beats()),
 
-            loop_offset: FloatParam::new("Offset", 0.1, FloatRange::Linear { min: 0.0, max: 1.0 })
-                .with_unit(" s"),
+            offset_snap: BoolParam::new("Offset Snap", false),
+            offset_snap_value: EnumParam::new("Offset Snap To", NoteValue::Sixteenth),
+
+            offset_jitter: FloatParam::new(
+                "Offset Jitter",
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(0))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+
+            stereo_spread: FloatParam::new(
+                "Stereo Spread",
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(0))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+
+            scrub_active: BoolParam::new("Scrub Active", false),
 
             fade: FloatParam::new(
                 "Fade",
                 0.02,
                 FloatRange::Skewed {
                     min: 0.005,
-                    max: 0.1,
+                    max: MAX_FADE_SECONDS,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_value_to_string(param_format::v2s_seconds_to_ms())
+            .with_string_to_value(param_format::s2v_ms_to_seconds()),
+            dry_fade: FloatParam::new(
+                "Dry Fade",
+                0.02,
+                FloatRange::Skewed {
+                    min: 0.001,
+                    max: MAX_FADE_SECONDS,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_value_to_string(param_format::v2s_seconds_to_ms())
+            .with_string_to_value(param_format::s2v_ms_to_seconds()),
+
+            smart_fade: BoolParam::new("Smart Fade", false),
+            fade_min: FloatParam::new(
+                "Shortest Fade",
+                0.005,
+                FloatRange::Skewed {
+                    min: 0.005,
+                    max: MAX_FADE_SECONDS,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_value_to_string(param_format::v2s_seconds_to_ms())
+            .with_string_to_value(param_format::s2v_ms_to_seconds()),
+            fade_shape: EnumParam::new("Fade Shape", FadeShape::Linear),
+            voice_stealing: EnumParam::new("Voice Stealing", VoiceStealing::Oldest),
+            transition_smoothness: FloatParam::new(
+                "Transition Smoothness",
+                0.5,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(0))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+
+            speed: FloatParam::new(
+                "Speed",
+                1.0,
+                FloatRange::Skewed {
+                    min: 0.25,
+                    max: 4.0,
+                    // puts 1x roughly in the middle
+                    factor: FloatRange::skew_factor(-1.2),
+                },
+            )
+            .with_unit("x")
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+            speed_glide: FloatParam::new(
+                "Speed Glide",
+                0.0,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: 2.0,
+                    factor: FloatRange::skew_factor(-1.5),
+                },
+            )
+            .with_unit(" s")
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+            pitch: FloatParam::new(
+                "Pitch",
+                0.0,
+                FloatRange::Linear {
+                    min: -24.0,
+                    max: 24.0,
+                },
+            )
+            .with_step_size(0.01)
+            .with_unit(" st")
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+            time_stretch: BoolParam::new("Time Stretch", false),
+            stretch_grain_size: FloatParam::new(
+                "Stretch Grain Size",
+                0.06,
+                FloatRange::Skewed {
+                    min: 0.01,
+                    max: 0.25,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" s")
+            .with_value_to_string(formatters::v2s_f32_rounded(3)),
+            stretch_density: FloatParam::new(
+                "Stretch Density",
+                MIN_DENSITY,
+                FloatRange::Linear {
+                    min: MIN_DENSITY,
+                    max: MAX_DENSITY,
+                },
+            )
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            speed_gain_compensation: BoolParam::new("Speed Gain Compensation", true),
+
+            repeat_probability: FloatParam::new(
+                "Repeat Probability",
+                1.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(0))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+            stutter: EnumParam::new("Stutter", Stutter::Off),
+            length_pattern: BoolParam::new("Length Pattern", false),
+            pattern_steps: IntParam::new(
+                "Pattern Steps",
+                4,
+                IntRange::Linear {
+                    min: 1,
+                    max: PATTERN_STEPS as i32,
+                },
+            ),
+            pattern: Default::default(),
+            feedback: FloatParam::new("Feedback", 1.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+                .with_unit("%")
+                .with_value_to_string(formatters::v2s_f32_percentage(0))
+                .with_string_to_value(formatters::s2v_f32_percentage()),
+            loop_attack: FloatParam::new(
+                "Loop Attack",
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(0))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+            loop_decay: FloatParam::new(
+                "Loop Decay",
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(0))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+            mix: FloatParam::new("Mix", 1.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+                .with_unit("%")
+                .with_value_to_string(formatters::v2s_f32_percentage(0))
+                .with_string_to_value(formatters::s2v_f32_percentage()),
+            dry_duck_level: FloatParam::new(
+                "Dry While Looping",
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(0))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+
+            solo: BoolParam::new("Solo Loop", false),
+            output_mode: EnumParam::new("Output", OutputMode::Mix),
+
+            groove: EnumParam::new("Groove", GroovePreset::Straight),
+            groove_amount: FloatParam::new(
+                "Groove Amount",
+                1.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(0))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+
+            quantize_changes: BoolParam::new("Quantize Changes", false),
+
+            sync: BoolParam::new("Sync", true),
+
+            fallback_tempo: FloatParam::new(
+                "Fallback Tempo",
+                120.0,
+                FloatRange::Linear {
+                    min: 20.0,
+                    max: 300.0,
+                },
+            )
+            .with_unit(" bpm")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+
+            transport_stop: EnumParam::new("Transport Stop", TransportStopMode::KeepLooping),
+
+            free_length: FloatParam::new(
+                "Free Length",
+                0.25,
+                FloatRange::Skewed {
+                    min: MIN_FREE_SECONDS,
+                    max: MAX_FREE_SECONDS,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" s")
+            .with_value_to_string(formatters::v2s_f32_rounded(3)),
+
+            free_offset: FloatParam::new(
+                "Free Offset",
+                0.1,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: MAX_FREE_SECONDS,
+                },
+            )
+            .with_unit(" s")
+            .with_value_to_string(formatters::v2s_f32_rounded(3)),
+
+            region_policy: EnumParam::new("Out Of Range", RegionPolicy::MoveOffset),
+            section: EnumParam::new("Section", SectionSlot::A),
+            store_section: BoolParam::new("Store Section", false),
+            replay_performance: BoolParam::new("Replay Performance", false),
+
+            color_enabled: BoolParam::new("Color On", true),
+            color: EnumParam::new("Color", ColorEffect::Off),
+
+            color_freq: FloatParam::new(
+                "Color Freq",
+                100.0,
+                FloatRange::Skewed {
+                    min: 1.0,
+                    max: 2000.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_value_to_string(formatters::v2s_f32_hz_then_khz(1))
+            .with_string_to_value(formatters::s2v_f32_hz_then_khz()),
+
+            color_sync: BoolParam::new("Color Sync", false),
+
+            color_mix: FloatParam::new("Color Mix", 1.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+                .with_unit("%")
+                .with_value_to_string(formatters::v2s_f32_percentage(0))
+                .with_string_to_value(formatters::s2v_f32_percentage()),
+
+            pan_enabled: BoolParam::new("Pan On", true),
+            pan_rate: EnumParam::new("Pan Rate", NoteValue::Half),
+
+            pan_depth: FloatParam::new("Pan Depth", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+                .with_unit("%")
+                .with_value_to_string(formatters::v2s_f32_percentage(0))
+                .with_string_to_value(formatters::s2v_f32_percentage()),
+
+            pan_shape: EnumParam::new("Pan Shape", LfoShape::Sine),
+
+            trigger_note: IntParam::new(
+                "Trigger Note",
+                DEFAULT_TRIGGER_NOTE,
+                IntRange::Linear { min: 0, max: 127 },
+            )
+            .with_value_to_string(formatters::v2s_i32_note_formatter())
+            .with_string_to_value(formatters::s2v_i32_note_formatter()),
+
+            trigger_mode: EnumParam::new("Trigger Mode", NoteTriggerMode::Latch),
+            release_dry: EnumParam::new("Dry On Release", ReleaseDry::WithLoop),
+            key_scrub: BoolParam::new("Key Scrub", false),
+            key_scrub_root: IntParam::new(
+                "Key Scrub Root",
+                DEFAULT_KEY_SCRUB_ROOT,
+                IntRange::Linear { min: 0, max: 127 },
+            )
+            .with_value_to_string(formatters::v2s_i32_note_formatter())
+            .with_string_to_value(formatters::s2v_i32_note_formatter()),
+            key_scrub_step: EnumParam::new("Key Scrub Step", NoteValue::Sixteenth),
+            one_shot: BoolParam::new("One Shot", false),
+            one_shot_passes: IntParam::new(
+                "One Shot Passes",
+                1,
+                IntRange::Linear { min: 1, max: 16 },
+            ),
+            auto_capture: BoolParam::new("Auto Capture", false),
+            auto_threshold: FloatParam::new(
+                "Auto Threshold",
+                util::db_to_gain(-12.0),
+                FloatRange::Skewed {
+                    min: util::db_to_gain(-48.0),
+                    max: 1.0,
+                    factor: FloatRange::gain_skew_factor(-48.0, 0.0),
+                },
+            )
+            .with_unit(" dB")
+            .with_value_to_string(formatters::v2s_f32_gain_to_db(1))
+            .with_string_to_value(formatters::s2v_f32_gain_to_db()),
+
+            scrub_cc: AtomicU8::new(DEFAULT_SCRUB_CC),
+            scrub_learn: BoolParam::new("Learn Scrub CC", false).non_automatable(),
+            learn_target: EnumParam::new("Learn Target", LearnTarget::Loop).non_automatable(),
+            midi_learn: BoolParam::new("Learn CC", false).non_automatable(),
+            loop_cc: AtomicU8::new(UNMAPPED),
+            length_cc: AtomicU8::new(UNMAPPED),
+            reverse_cc: AtomicU8::new(UNMAPPED),
+            speed_cc: AtomicU8::new(UNMAPPED),
+            capture_latency: AtomicU32::new(0),
+            calibrate_latency: BoolParam::new("Calibrate Latency", false).non_automatable(),
+            check_phase: BoolParam::new("Check Phase", false).non_automatable(),
+            import_path: Arc::new(Mutex::new(None)),
+            import_loop: BoolParam::new("Import Loop", false).non_automatable(),
+            export_path: Arc::new(Mutex::new(None)),
+            export_loop: BoolParam::new("Export Loop", false).non_automatable(),
+
+            bend_range: FloatParam::new(
+                "Bend Range",
+                0.25,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_unit(" beats"),
+
+            bend_return: FloatParam::new(
+                "Bend Return",
+                0.1,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: 1.0,
                     factor: FloatRange::skew_factor(-2.0),
                 },
             )
             .with_unit(" s"),
 
             loop_param: BoolParam::new("Loop", false),
-            reverse_param: BoolParam::new("Reverse", false),
+            launch_quantization: EnumParam::new("Launch", LaunchQuantization::Loop),
+            count_in: IntParam::new("Count In", 0, IntRange::Linear { min: 0, max: 8 }),
+            count_in_unit: EnumParam::new("Count In Unit", CountInUnit::Bars),
+            instant_capture: BoolParam::new("Instant Capture", false),
+            grid_offset: FloatParam::new(
+                "Grid Offset",
+                0.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: MAX_GRID_OFFSET_BEATS,
+                },
+            )
+            .with_step_size(0.25)
+            .with_value_to_string(param_format::v2s_beats_to_sixteenths())
+            .with_string_to_value(param_format::s2v_sixteenths_to_beats()),
+            grid_swing: FloatParam::new(
+                "Grid Swing",
+                0.5,
+                FloatRange::Linear {
+                    min: 0.5,
+                    max: 0.75,
+                },
+            )
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(0))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+            retrigger: BoolParam::new("Retrigger", false),
+            tape_stop: BoolParam::new("Tape Stop", false),
+            tape_stop_time: FloatParam::new(
+                "Tape Stop Time",
+                0.5,
+                FloatRange::Skewed {
+                    min: 0.05,
+                    max: 4.0,
+                    factor: FloatRange::skew_factor(-1.5),
+                },
+            )
+            .with_unit(" s")
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+            reverse_param: EnumParam::new("Reverse", ReverseMode::Forward),
+            reverse_in_place: BoolParam::new("Reverse In Place", true),
+            reverse_tail: BoolParam::new("Reverse Tail", false),
         }
     }
 }
@@ -100,20 +1109,50 @@ impl Plugin for Metaloop {
 
     // The first audio IO layout is used as the default. The other layouts may be selected either
     // explicitly or automatically by the host or the user depending on the plugin API/backend.
-    const AUDIO_IO_LAYOUTS: &'static [AudioIOLayout] = &[AudioIOLayout {
-        main_input_channels: NonZeroU32::new(2),
-        main_output_channels: NonZeroU32::new(2),
+    const AUDIO_IO_LAYOUTS: &'static [AudioIOLayout] = &[
+        AudioIOLayout {
+            main_input_channels: NonZeroU32::new(2),
+            main_output_channels: NonZeroU32::new(2),
+
+            aux_input_ports: &[],
+            aux_output_ports: &[new_nonzero_u32(2)],
+
+            // Individual ports and the layout as a whole can be named here. By default these names
+            // are generated as needed. This layout will be called 'Stereo', while a layout with
+            // only one input and output channel would be called 'Mono'.
+            names: LOOP_OUTPUT_NAMES,
+        },
+        // the loop still runs in stereo, so the stereo effects fold back down to mono
+        AudioIOLayout {
+            main_input_channels: NonZeroU32::new(1),
+            main_output_channels: NonZeroU32::new(1),
+
+            aux_input_ports: &[],
+            aux_output_ports: &[new_nonzero_u32(1)],
+
+            names: LOOP_OUTPUT_NAMES,
+        },
+        AudioIOLayout {
+            main_input_channels: NonZeroU32::new(4),
+            main_output_channels: NonZeroU32::new(4),
 
-        aux_input_ports: &[],
-        aux_output_ports: &[],
+            aux_input_ports: &[],
+            aux_output_ports: &[new_nonzero_u32(4)],
 
-        // Individual ports and the layout as a whole can be named here. By default these names
-        // are generated as needed. This layout will be called 'Stereo', while a layout with
-        // only one input and output channel would be called 'Mono'.
-        names: PortNames::const_default(),
-    }];
+            names: LOOP_OUTPUT_NAMES,
+        },
+        AudioIOLayout {
+            main_input_channels: NonZeroU32::new(6),
+            main_output_channels: NonZeroU32::new(6),
 
-    const MIDI_INPUT: MidiConfig = MidiConfig::None;
+            aux_input_ports: &[],
+            aux_output_ports: &[new_nonzero_u32(6)],
+
+            names: LOOP_OUTPUT_NAMES,
+        },
+    ];
+
+    const MIDI_INPUT: MidiConfig = MidiConfig::MidiCCs;
     const MIDI_OUTPUT: MidiConfig = MidiConfig::None;
 
     const SAMPLE_ACCURATE_AUTOMATION: bool = true;
@@ -122,26 +1161,40 @@ impl Plugin for Metaloop {
     // messages here. The type implements the `SysExMessage` trait, which allows conversion to and
     // from plain byte buffers.
     type SysExMessage = ();
-    // More advanced plugins can use this to run expensive background tasks. See the field's
-    // documentation for more information. `()` means that the plugin does not have any background
-    // tasks.
-    type BackgroundTask = ();
+    type BackgroundTask = BackgroundTask;
 
     fn params(&self) -> Arc<dyn Params> {
         self.params.clone()
     }
 
+    fn task_executor(&mut self) -> TaskExecutor<Self> {
+        let export = self.export.clone();
+        let import = self.import.clone();
+        Box::new(move |task| background_task::run(task, &export, &import))
+    }
+
     fn initialize(
         &mut self,
-        _audio_io_layout: &AudioIOLayout,
+        audio_io_layout: &AudioIOLayout,
         buffer_config: &BufferConfig,
-        _context: &mut impl InitContext<Self>,
+        context: &mut impl InitContext<Self>,
     ) -> bool {
         // Resize buffers and perform other potentially expensive initialization operations here.
         // The `reset()` function is always called right after this function. You can remove this
         // function if you do not need it.
-        self.grain_looper
-            .set_sample_rate(buffer_config.sample_rate as f32);
+        let num_channels = audio_io_layout
+            .main_input_channels
+            .map(NonZeroU32::get)
+            .unwrap_or(0);
+        self.prepare(
+            buffer_config.sample_rate,
+            buffer_config.max_buffer_size as usize,
+            num_channels as usize,
+        );
+
+        // the loop and dry both come out on the same sample the input goes in,
+        // any lookahead is only used to schedule the loop ahead of time
+        context.set_latency_samples(0);
 
         true
     }
@@ -150,64 +1203,756 @@ impl Plugin for Metaloop {
         // Reset buffers and envelopes here. This can be called from the audio thread and may not
         // allocate. You can remove this function if you do not need it.
         self.grain_looper.reset();
+        if let Some(surround) = &mut self.surround {
+            surround.grain_looper.reset();
+        }
+        self.note_trigger.reset();
+        self.transient_trigger.reset();
+        self.cc_scrub.reset();
+        self.midi_learn.reset();
+        self.bend_scrub.reset();
+        self.key_scrub.reset();
+        self.wet_chain.reset();
+        self.fallback_clock.reset();
+        self.splice_analyzer.reset();
+        self.captured_fade = None;
+        self.sections.reset();
+        self.length_scale.reset();
+        self.latency_probe.reset();
+        self.performance.reset();
+        self.import_latched = false;
     }
 
     fn process(
         &mut self,
         buffer: &mut Buffer,
-        _aux: &mut AuxiliaryBuffers,
+        aux: &mut AuxiliaryBuffers,
         context: &mut impl ProcessContext<Self>,
     ) -> ProcessStatus {
-        self.update_params();
-
-        // set the tempo
-        self.grain_looper
-            .set_tempo(context.transport().tempo.unwrap() as f32);
-
-        // todo: beat time only updates once per buffer
-        let beat_time = context.transport().pos_beats().unwrap();
-
-        // todo: this is utter bollocks, output will be delayed by one sample
-        for channel_samples in buffer.iter_samples() {
-            let _num_samples = channel_samples.len();
-
-            let mut input: StereoPair<f32> = StereoPair::default();
-            let mut left = true;
-
-            let samples = channel_samples.into_iter();
-            for sample in samples {
-                if left {
-                    input.left = sample.clone();
-                    *sample = self.output.left();
-                } else {
-                    input.right = sample.clone();
-                    *sample = self.output.right();
+        // the engine passes the input through once it finds itself in an inconsistent state,
+        // so recover here rather than letting it panic the host
+        if self.grain_looper.has_fault() {
+            context.execute_background(BackgroundTask::LooperReset { surround: false });
+            self.grain_looper.reset();
+        }
+        if let Some(surround) = &mut self.surround {
+            if surround.grain_looper.has_fault() {
+                context.execute_background(BackgroundTask::LooperReset { surround: true });
+                surround.grain_looper.reset();
+            }
+        }
+
+        if self.run_latency_probe(buffer, context) {
+            return ProcessStatus::Normal;
+        }
+
+        // checked in this layout, on a plugin of its own, so this one carries on as it was
+        let check_phase = self.params.check_phase.value();
+        if check_phase && !self.check_phase_held {
+            context.execute_background(BackgroundTask::PhaseCheck {
+                num_channels: buffer.channels(),
+                sample_rate: self.sample_rate,
+            });
+        }
+        self.check_phase_held = check_phase;
+
+        self.cc_scrub.set_learning(self.params.scrub_learn.value());
+        self.midi_learn.set_learning(self.params.midi_learn.value());
+        self.key_scrub.set_enabled(self.params.key_scrub.value());
+        self.key_scrub
+            .set_root(self.params.key_scrub_root.value() as u8);
+
+        // the loop only starts and stops on the grid, and the offset is only read when a grain
+        // starts, so handling midi once per block is enough
+        while let Some(event) = context.next_event() {
+            match event {
+                NoteEvent::NoteOn { note, .. } => {
+                    self.note_trigger.note_on(note);
+                    self.key_scrub.note_on(note);
+                }
+                NoteEvent::NoteOff { note, .. } => {
+                    self.note_trigger.note_off(note);
+                    self.key_scrub.note_off(note);
                 }
+                NoteEvent::MidiCC { cc, value, .. } => {
+                    let scrub_cc = self.params.scrub_cc.load(Ordering::Relaxed);
+                    if let Some(learned) = self.cc_scrub.handle_cc(scrub_cc, cc, value) {
+                        self.params.scrub_cc.store(learned, Ordering::Relaxed);
+                    }
+                    let target = self.params.learn_target.value();
+                    let mapping = self.params.cc_mapping();
+                    if let Some(learned) = self.midi_learn.handle_cc(&mapping, target, cc, value) {
+                        self.params.map_cc(target, learned);
+                    }
+                }
+                NoteEvent::MidiPitchBend { value, .. } => self.bend_scrub.handle_bend(value),
+                _ => (),
             }
+        }
 
-            self.output = self.grain_looper.tick(input, beat_time);
+        let host = HostTransport::from_transport(context.transport());
+        let loop_output = aux.outputs.first_mut().map(|output| output.as_slice());
+        self.process_channels(buffer.as_slice(), loop_output, host);
+        // the paths are read by the background task, off the audio thread
+        let export_loop = self.params.export_loop.value();
+        if export_loop && !self.export_held {
+            self.export.request_again();
+        }
+        self.export_held = export_loop;
+        if self.capture_export() {
+            context.execute_background(BackgroundTask::ExportLoop);
         }
+        let import_loop = self.params.import_loop.value();
+        if self.import.take_request() || (import_loop && !self.import_held) {
+            self.import.set_lead(self.import_lead());
+            context.execute_background(BackgroundTask::ImportLoop);
+        }
+        self.import_held = import_loop;
 
         ProcessStatus::Normal
     }
 }
 
 impl Metaloop {
-    pub fn update_params(&mut self) {
-        self.grain_looper.set_grid(self.params.loop_length.value());
+    // sizes everything for the sample rate, block size and channel count the host will use
+    fn prepare(&mut self, sample_rate: f32, max_buffer_size: usize, num_channels: usize) {
+        self.sample_rate = sample_rate;
+        // the history is the same length in seconds at any rate, so the buffers are made again
+        self.grain_looper = GrainLooper::new(sample_rate);
+        let lookahead_samples = seconds_to_samples(LOOKAHEAD_SECONDS, sample_rate);
+        self.grain_looper.set_lookahead(lookahead_samples);
+        self.splice_analyzer = SpliceAnalyzer::new(loopable_region_length(sample_rate));
+        self.wet_chain.set_sample_rate(sample_rate);
+        self.latency_probe.set_sample_rate(sample_rate);
+        self.cc_scrub
+            .set_slew_samples(seconds_to_samples(CC_SCRUB_SLEW_SECONDS, sample_rate));
+        self.bend_scrub
+            .set_follow_samples(seconds_to_samples(BEND_SCRUB_SLEW_SECONDS, sample_rate));
+        self.block.resize(max_buffer_size, StereoPair::default());
+        self.dry_block
+            .resize(max_buffer_size, StereoPair::default());
+
+        self.surround = if num_channels > 2 {
+            let mut grain_looper = GrainLooper::new(sample_rate);
+            grain_looper.set_lookahead(lookahead_samples);
+            Some(SurroundLooper {
+                grain_looper,
+                block: vec![Frame::default(); max_buffer_size],
+                dry_block: vec![Frame::default(); max_buffer_size],
+            })
+        } else {
+            None
+        };
+        self.export
+            .prepare(self.grain_looper.region_length(), num_channels, sample_rate);
+        self.import.prepare(
+            self.grain_looper.region_length(),
+            self.grain_looper.import_margin(),
+            num_channels,
+            sample_rate,
+        );
+    }
+
+    // the running looper's offset, for the loop to play an imported file from the top
+    fn import_lead(&self) -> usize {
+        let offset_samples = match &self.surround {
+            Some(surround) => surround.grain_looper.loop_offset_samples(),
+            None => self.grain_looper.loop_offset_samples(),
+        };
+        offset_samples.round() as usize
+    }
+
+    // hands a decoded file to the looper, returns whether there was one
+    fn load_import(&mut self) -> bool {
+        match &mut self.surround {
+            Some(surround) => self.import.load_surround(&mut surround.grain_looper),
+            None => self.import.load(&mut self.grain_looper),
+        }
+    }
 
-        if self.params.loop_param.value() && !self.grain_looper.is_looping() {
-            self.grain_looper.set_loop_offset(0.1);
-            self.grain_looper.start_looping();
-        } else if !self.params.loop_param.value() && self.grain_looper.is_looping() {
-            self.grain_looper.stop_looping();
+    // copies the loop for the background task to write, if an export was asked for
+    fn capture_export(&self) -> bool {
+        match &self.surround {
+            Some(surround) => self.export.capture_surround(&surround.grain_looper),
+            None => self.export.capture(&self.grain_looper),
         }
-        self.grain_looper
-            .set_loop_offset(self.params.loop_offset.value());
-        self.grain_looper
-            .set_reverse(self.params.reverse_param.value());
+    }
+
+    // the host state for a block, with our own clock and the params filling in what the host leaves out
+    fn block_context(&mut self, host: HostTransport, num_samples: usize) -> ProcessContextLite {
+        let is_playing = host.playing;
+        if is_playing != self.was_playing {
+            self.was_playing = is_playing;
+            self.handle_transport_change(is_playing);
+        }
+
+        // not every host has a tempo or a position, so we keep our own clock to fall back on
+        // the position also stands still while stopped, so then our clock carries on from it
+        let tempo = host
+            .tempo
+            .map(|tempo| tempo as f32)
+            .unwrap_or_else(|| self.params.fallback_tempo.value());
+        let beat_time = self.fallback_clock.next_block(
+            host.pos_beats.filter(|_| is_playing),
+            tempo,
+            host.sample_rate,
+            num_samples,
+        );
+        ProcessContextLite {
+            tempo,
+            beat_time,
+            sample_rate: host.sample_rate,
+            is_playing,
+            time_sig: (
+                host.time_sig_numerator.unwrap_or(4),
+                host.time_sig_denominator.unwrap_or(4),
+            ),
+        }
+    }
+
+    // everything process does once the midi is handled, straight on the host's channels,
+    // so tests can run a block the way a host would without needing one
+    fn process_channels(
+        &mut self,
+        channels: &mut [&mut [f32]],
+        loop_output: Option<&mut [&mut [f32]]>,
+        host: HostTransport,
+    ) {
+        let num_samples = channels.first().map_or(0, |samples| samples.len());
+        let process_context = self.block_context(host, num_samples);
+        if self.load_import() {
+            self.import_latched = true;
+        }
+        self.update_params(num_samples, &process_context);
+
+        let output_mode = self.params.output_mode.value();
+
+        if let Some(surround) = &mut self.surround {
+            let block = &mut surround.block[..num_samples];
+            let dry_block = &mut surround.dry_block[..num_samples];
+            read_frames(channels, block);
+            self.splice_analyzer.push_frames(block);
+            let input_levels = Levels::of_block(block);
+            self.transient_trigger.process(input_levels.peak());
+            surround
+                .grain_looper
+                .tick_block_split(&process_context, block, dry_block);
+            let wet_levels = Levels::of_block(block);
+            let output_levels = write_frames(channels, block, dry_block, output_mode);
+            if let Some(loop_output) = loop_output {
+                write_wet_frames(loop_output, block);
+            }
+            self.status
+                .publish(Self::looper_status(&surround.grain_looper));
+            Self::publish_waveform(
+                &self.waveform,
+                &mut self.waveform_countdown,
+                &surround.grain_looper,
+                num_samples,
+                self.sample_rate,
+            );
+            self.publish_levels(&input_levels, &wet_levels, &output_levels);
+        } else {
+            let block = &mut self.block[..num_samples];
+            let dry_block = &mut self.dry_block[..num_samples];
+            read_frames(channels, block);
+            self.splice_analyzer.push_frames(block);
+            let input_levels = Levels::of_block(block);
+            self.transient_trigger.process(input_levels.peak());
+            self.grain_looper
+                .tick_block_split(&process_context, block, dry_block);
+            self.wet_chain.process_block(&process_context, block);
+            let wet_levels = Levels::of_block(block);
+            let output_levels = write_frames(channels, block, dry_block, output_mode);
+            if let Some(loop_output) = loop_output {
+                write_wet_frames(loop_output, block);
+            }
+            self.status.publish(Self::looper_status(&self.grain_looper));
+            Self::publish_waveform(
+                &self.waveform,
+                &mut self.waveform_countdown,
+                &self.grain_looper,
+                num_samples,
+                self.sample_rate,
+            );
+            self.publish_levels(&input_levels, &wet_levels, &output_levels);
+        }
+    }
+
+    fn build_wet_chain() -> WetChain {
+        let mut wet_chain = WetChain::new();
+        let color = wet_chain.push(Box::new(ColorSlot::new(44100.0)));
+        let auto_pan = wet_chain.push(Box::new(AutoPan::new()));
+        debug_assert_eq!((color, auto_pan), (COLOR_SLOT, AUTO_PAN_SLOT));
+        wet_chain
+    }
+
+    // while calibrating, the click and the silence after it replace everything else
+    // returns whether the probe took the block
+    fn run_latency_probe(
+        &mut self,
+        buffer: &mut Buffer,
+        context: &mut impl ProcessContext<Self>,
+    ) -> bool {
+        let calibrate = self.params.calibrate_latency.value();
+        if calibrate && !self.calibrate_held {
+            self.latency_probe.start();
+        }
+        self.calibrate_held = calibrate;
+        if !self.latency_probe.is_listening() {
+            return false;
+        }
+
+        for mut channel_samples in buffer.iter_samples() {
+            let num_channels = channel_samples.len().max(1);
+            let input = channel_samples
+                .iter_mut()
+                .map(|sample| *sample)
+                .sum::<f32>()
+                / num_channels as f32;
+            let output = self.latency_probe.tick(input);
+            for sample in channel_samples {
+                *sample = output;
+            }
+        }
+
+        match self.latency_probe.result() {
+            ProbeResult::Measured(latency) => {
+                context.execute_background(BackgroundTask::LatencyMeasured(latency));
+                self.params
+                    .capture_latency
+                    .store(latency as u32, Ordering::Relaxed);
+            }
+            ProbeResult::TimedOut => context.execute_background(BackgroundTask::LatencyTimedOut),
+            _ => (),
+        }
+        true
+    }
+
+    pub fn update_params(&mut self, num_samples: usize, context: &ProcessContextLite) {
+        // when free running, a bar is as many seconds as it would be beats
+        let bar_length = context.bar_beats();
+        self.note_trigger
+            .set_note(self.params.trigger_note.value() as u8);
+        self.note_trigger.set_mode(self.params.trigger_mode.value());
+        // set when a momentary note was let go this block, rather than the loop param turned off
+        let release_bar = self.note_trigger.take_release().then_some(bar_length);
+        let loop_param = self
+            .midi_learn
+            .value(LearnTarget::Loop, &self.params.loop_param);
+        self.transient_trigger
+            .set_armed(self.params.auto_capture.value());
+        self.transient_trigger
+            .set_threshold(self.params.auto_threshold.value());
+        let controls_on =
+            loop_param || self.note_trigger.is_active() || self.transient_trigger.is_active();
+        self.import_latched &= !controls_on;
+        let loop_on = (controls_on || self.import_latched)
+            && (self.was_playing
+                || self.params.transport_stop.value() == TransportStopMode::KeepLooping);
+
+        self.bend_scrub.set_range(self.params.bend_range.value());
+        self.bend_scrub.set_return_samples(seconds_to_samples(
+            self.params.bend_return.value(),
+            self.sample_rate,
+        ));
+        // when free running, a beat of bend is a second
+        let bend = self.bend_scrub.process(num_samples);
+        // a key held scrubs in place of the offset param and cc, from the latest loop back
+        let key = self
+            .key_scrub
+            .steps()
+            .map(|steps| steps as f32 * self.params.key_scrub_step.value().beats());
+
+        let loop_offset = if self.params.sync.value() {
+            let coarse = self.params.loop_offset.value();
+            let coarse = self.cc_scrub.process(coarse, num_samples).unwrap_or(coarse);
+            self.loop_offset_beats(key.unwrap_or(coarse) + bend)
+        } else {
+            let offset = self.params.free_offset.value() / MAX_FREE_SECONDS;
+            let offset = self.cc_scrub.process(offset, num_samples).unwrap_or(offset);
+            (key.unwrap_or(offset * MAX_FREE_SECONDS) + bend).max(0.0)
+        };
+        let sync = self.params.sync.value();
+        let (length, min_length, max_length) = if sync {
+            // halving and doubling keep to the feel
+            let feel = self.params.loop_feel.value();
+            (
+                self.midi_learn
+                    .value(LearnTarget::Length, &self.params.loop_length)
+                    .beats_with_feel(feel),
+                NoteValue::ThirtySecond.beats_with_feel(feel),
+                NoteValue::Bar.beats_with_feel(feel),
+            )
+        } else {
+            (
+                self.midi_learn
+                    .value(LearnTarget::Length, &self.params.free_length),
+                MIN_FREE_SECONDS,
+                MAX_FREE_SECONDS,
+            )
+        };
+        self.length_scale.set_momentary(
+            self.params.hold_half.value(),
+            self.params.hold_double.value(),
+        );
+        self.length_scale.update(
+            self.params.halve_length.value(),
+            self.params.double_length.value(),
+            length,
+            min_length,
+            max_length,
+        );
+        let live_timing = SectionSettings {
+            sync,
+            length: self.length_scale.apply(length, min_length, max_length),
+            offset: loop_offset,
+            reverse: self
+                .midi_learn
+                .value(LearnTarget::Reverse, &self.params.reverse_param),
+            speed: self
+                .midi_learn
+                .value(LearnTarget::Speed, &self.params.speed),
+        };
+
+        self.sections.select(self.params.section.value());
+        self.sections.advance(
+            context.beat_time,
+            context.beat_time_at(num_samples),
+            bar_length,
+        );
+        let timing = self.sections.active_settings().unwrap_or(live_timing);
+
+        let replay = self.params.replay_performance.value();
+        if replay && !self.replay_held {
+            self.performance
+                .toggle_replay(context.beat_time, bar_length);
+        }
+        self.replay_held = replay;
+        let (loop_on, timing) = match self.performance.replayed_state(context.beat_time) {
+            Some(state) => (
+                state.on,
+                SectionSettings {
+                    length: state.length,
+                    offset: state.offset,
+                    ..timing
+                },
+            ),
+            None => {
+                let state = LoopState {
+                    on: loop_on,
+                    length: timing.length,
+                    offset: timing.offset,
+                };
+                self.performance.record(context.beat_time, state);
+                (loop_on, timing)
+            }
+        };
+
+        let store_pressed = self.params.store_section.value() && !self.store_section_held;
+        self.store_section_held = self.params.store_section.value();
+        let store = store_pressed.then_some(self.sections.active());
+        let retrigger = self.params.retrigger.value() && !self.retrigger_held;
+        self.retrigger_held = self.params.retrigger.value();
+        let launch = self.params.launch_quantization.value().interval(bar_length);
+        let count_in = self.params.count_in.value() as u32;
+        let count_in_interval = self.params.count_in_unit.value().interval(bar_length);
+
+        let stored = match &mut self.surround {
+            Some(surround) => {
+                surround.grain_looper.set_launch_quantization(launch);
+                surround
+                    .grain_looper
+                    .set_count_in(count_in, count_in_interval);
+                Self::update_looper(
+                    &mut surround.grain_looper,
+                    &self.params,
+                    loop_on,
+                    release_bar,
+                    timing,
+                    &self.splice_analyzer,
+                    &mut self.captured_fade,
+                );
+                if retrigger {
+                    surround.grain_looper.retrigger();
+                }
+                Self::update_section(&mut surround.grain_looper, store, self.sections.stored())
+            }
+            None => {
+                self.grain_looper.set_launch_quantization(launch);
+                self.grain_looper.set_count_in(count_in, count_in_interval);
+                Self::update_looper(
+                    &mut self.grain_looper,
+                    &self.params,
+                    loop_on,
+                    release_bar,
+                    timing,
+                    &self.splice_analyzer,
+                    &mut self.captured_fade,
+                );
+                if retrigger {
+                    self.grain_looper.retrigger();
+                }
+                Self::update_section(&mut self.grain_looper, store, self.sections.stored())
+            }
+        };
+        if stored {
+            self.sections.store(timing);
+        }
+
+        self.wet_chain
+            .set_enabled(COLOR_SLOT, self.params.color_enabled.value());
+        self.wet_chain
+            .set_enabled(AUTO_PAN_SLOT, self.params.pan_enabled.value());
+        self.wet_chain.update_params(&self.params);
+    }
+
+    fn handle_transport_change(&mut self, is_playing: bool) {
+        let mode = self.params.transport_stop.value();
+        match &mut self.surround {
+            Some(surround) => Self::transport_changed(&mut surround.grain_looper, is_playing, mode),
+            None => Self::transport_changed(&mut self.grain_looper, is_playing, mode),
+        }
+    }
+
+    // stopping may stop the loop, and starting moves it onto the grid at the new position
+    fn transport_changed<T: AudioFrame>(
+        grain_looper: &mut GrainLooper<T>,
+        is_playing: bool,
+        mode: TransportStopMode,
+    ) {
+        if is_playing {
+            grain_looper.realign();
+        } else if mode == TransportStopMode::Stop {
+            grain_looper.stop_now();
+        }
+    }
+
+    fn publish_levels(&self, input: &Levels, wet: &Levels, output: &Levels) {
+        self.meters.input.publish(input);
+        self.meters.wet.publish(wet);
+        self.meters.output.publish(output);
+    }
+
+    // if it's skipped because the editor is reading, it's tried again next block
+    fn publish_waveform<T: AudioFrame>(
+        waveform: &WaveformSnapshot,
+        countdown: &mut usize,
+        grain_looper: &GrainLooper<T>,
+        num_samples: usize,
+        sample_rate: f32,
+    ) {
+        *countdown = countdown.saturating_sub(num_samples);
+        if *countdown == 0 && waveform.publish(|points| grain_looper.snapshot_region(points)) {
+            *countdown = seconds_to_samples(WAVEFORM_SECONDS, sample_rate);
+        }
+    }
+
+    fn looper_status<T: AudioFrame>(grain_looper: &GrainLooper<T>) -> LoopStatusSnapshot {
+        LoopStatusSnapshot {
+            is_looping: grain_looper.is_loop_requested(),
+            repeat_count: grain_looper.repeat_count(),
+            loop_length_seconds: grain_looper.loop_length_seconds(),
+            time_to_next_loop: grain_looper.time_to_next_loop().unwrap_or(-1.0),
+            count_in: grain_looper.count_in_remaining(),
+            region_fit: grain_looper.region_fit(),
+        }
+    }
+
+    // plays the audio of the active section if it has any, and stores the loop in it when asked
+    // returns whether there was a loop to store
+    fn update_section<T: AudioFrame>(
+        grain_looper: &mut GrainLooper<T>,
+        store: Option<SectionSlot>,
+        stored: Option<SectionSlot>,
+    ) -> bool {
+        grain_looper.set_section(stored.map(|slot| slot.index()));
+        match store {
+            Some(slot) if grain_looper.is_loop_requested() => {
+                grain_looper.store_section(slot.index());
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // the fade in seconds for splicing the loop just captured, between shortest and longest
+    // the loop starts offset samples back, and ends a loop length on from there,
+    // which may not have been recorded yet
+    fn smart_fade<T: AudioFrame>(
+        grain_looper: &GrainLooper<T>,
+        splice_analyzer: &SpliceAnalyzer,
+        shortest: f32,
+        longest: f32,
+    ) -> f32 {
+        let start = grain_looper.loop_offset_samples() as usize;
+        match start.checked_sub(grain_looper.loop_length_samples() as usize) {
+            Some(end) => splice_analyzer.fade_for_splices(&[start, end], shortest, longest),
+            None => splice_analyzer.fade_for_splices(&[start], shortest, longest),
+        }
+    }
+
+    // timing comes from the params, or the section playing
+    fn update_looper<T: AudioFrame>(
+        grain_looper: &mut GrainLooper<T>,
+        params: &MetaloopParams,
+        loop_on: bool,
+        release_bar: Option<f32>,
+        timing: SectionSettings,
+        splice_analyzer: &SpliceAnalyzer,
+        captured_fade: &mut Option<f32>,
+    ) {
+        // changing the clock stops the loop, it restarts below if the loop is on
+        grain_looper.set_sync(timing.sync);
+        grain_looper.set_grid_offset(params.grid_offset.value());
+        grain_looper.set_swing(params.grid_swing.value());
+        grain_looper.set_instant_capture(params.instant_capture.value());
+        grain_looper
+            .set_quantize_changes(params.quantize_changes.value() && !params.scrub_active.value());
+
+        if timing.sync {
+            grain_looper.set_grid(timing.length);
+        } else {
+            grain_looper.set_grid_seconds(timing.length);
+        }
+
+        let capturing = loop_on && !grain_looper.is_loop_requested();
+        if capturing {
+            grain_looper.set_loop_offset(0.1);
+            grain_looper.start_looping();
+        } else if !loop_on && grain_looper.is_loop_requested() {
+            match (release_bar, params.release_dry.value()) {
+                (Some(_), ReleaseDry::Instant) => grain_looper.stop_now_with_dry_ramp(0),
+                (Some(_), ReleaseDry::Fade) => grain_looper.stop_now(),
+                (Some(bar), ReleaseDry::NextBar) => grain_looper.stop_now_dry_at_bar(bar),
+                _ if params.reverse_tail.value() => grain_looper.stop_looping_with_reverse_tail(),
+                _ => grain_looper.stop_looping(),
+            }
+        }
+        if timing.sync {
+            grain_looper.set_loop_offset(timing.offset);
+        } else {
+            grain_looper.set_loop_offset_seconds(timing.offset);
+        }
+        grain_looper.set_reverse_mode(timing.reverse);
+        grain_looper.set_reverse_compensation(params.reverse_in_place.value());
+        grain_looper.set_region_policy(params.region_policy.value());
+        grain_looper.set_capture_latency(params.capture_latency.load(Ordering::Relaxed) as usize);
+
+        if capturing && params.smart_fade.value() {
+            let shortest = params.fade_min.value().min(params.fade.value());
+            let longest = params.fade.value();
+            *captured_fade = Some(Self::smart_fade(
+                grain_looper,
+                splice_analyzer,
+                shortest,
+                longest,
+            ));
+        }
+        let fade = match captured_fade {
+            Some(fade) if params.smart_fade.value() => *fade,
+            _ => params.fade.value(),
+        };
+        grain_looper.set_fade_time(fade);
+        grain_looper.set_dry_fade_time(Some(params.dry_fade.value()));
+        grain_looper.set_fade_shape(params.fade_shape.value());
+        grain_looper.set_voice_stealing(params.voice_stealing.value());
+        grain_looper.set_transition_smoothness(params.transition_smoothness.value());
+        grain_looper.set_speed_glide(params.speed_glide.value());
+        grain_looper.set_speed(timing.speed);
+        grain_looper.set_pitch(params.pitch.value());
+        grain_looper.set_time_stretch(
+            params.time_stretch.value(),
+            params.stretch_grain_size.value(),
+            params.stretch_density.value(),
+        );
+        grain_looper.set_speed_gain_compensation(params.speed_gain_compensation.value());
+        grain_looper.set_tape_stop_time(params.tape_stop_time.value());
+        grain_looper.set_tape_stop(params.tape_stop.value());
+        grain_looper.set_offset_jitter(params.offset_jitter.value());
+        grain_looper.set_stereo_spread(params.stereo_spread.value());
+        grain_looper.set_repeat_probability(params.repeat_probability.value());
+        grain_looper.set_stutter(params.stutter.value());
+        let steps = params.pattern.each_ref().map(|step| PatternStep {
+            length: step.length.value().ratio(),
+            offset: step.offset.value(),
+        });
+        grain_looper.set_length_pattern(
+            params
+                .length_pattern
+                .value()
+                .then(|| LengthPattern::from_steps(&steps[..params.pattern_steps.value() as usize]))
+                .flatten(),
+        );
+        grain_looper.set_one_shot(
+            params
+                .one_shot
+                .value()
+                .then_some(params.one_shot_passes.value() as u32),
+        );
+        grain_looper.set_feedback(params.feedback.value());
+        grain_looper.set_loop_envelope(params.loop_attack.value(), params.loop_decay.value());
+        grain_looper.set_mix(params.mix.value());
+        grain_looper.set_dry_duck_level(params.dry_duck_level.value());
+        grain_looper.set_solo(params.solo.value());
+        grain_looper.set_groove(params.groove.value().template());
+        grain_looper.set_groove_amount(params.groove_amount.value());
+    }
+
+    // coarse and fine offset combined, never reading into the future
+    // snapping rounds the coarse offset, and whatever is scrubbing it, but not the fine one
+    fn loop_offset_beats(&self, coarse: f32) -> f32 {
+        let coarse = if self.params.offset_snap.value() {
+            self.params.offset_snap_value.value().snap(coarse)
+        } else {
+            coarse
+        };
+        (coarse + self.params.loop_offset_fine.value()).max(0.0)
+    }
+}
+
+// copies the host channels into a block of frames
+fn read_frames<T: AudioFrame>(channels: &[&mut [f32]], block: &mut [T]) {
+    let num_channels = channels.len().min(MAX_CHANNELS);
+    let mut frame_channels = [0.0; MAX_CHANNELS];
+    for (index, frame) in block.iter_mut().enumerate() {
+        for (channel, samples) in frame_channels.iter_mut().zip(channels) {
+            *channel = samples[index];
+        }
+        *frame = T::from_channels(&frame_channels[..num_channels]);
+    }
+}
+
+// copies a frame out to the host channels, at index within the block
+fn write_frame<T: AudioFrame>(channels: &mut [&mut [f32]], index: usize, frame: T) {
+    let num_channels = channels.len().min(MAX_CHANNELS);
+    let mut frame_channels = [0.0; MAX_CHANNELS];
+    frame.write_channels(&mut frame_channels[..num_channels]);
+    for (samples, channel) in channels.iter_mut().zip(frame_channels.iter()) {
+        samples[index] = *channel;
+    }
+}
+
+// routes the wet and dry blocks to the host channels, returning the levels of what went out
+fn write_frames<T: AudioFrame>(
+    channels: &mut [&mut [f32]],
+    block: &[T],
+    dry_block: &[T],
+    output_mode: OutputMode,
+) -> Levels {
+    let mut levels = Levels::default();
+    for (index, (wet, dry)) in block.iter().zip(dry_block.iter()).enumerate() {
+        let output = output_mode.apply(*wet, *dry);
+        levels.add(&output);
+        write_frame(channels, index, output);
+    }
+    levels
+}
 
-        self.grain_looper.set_fade_time(self.params.fade.value());
+// copies the loop on its own to an aux output
+fn write_wet_frames<T: AudioFrame>(channels: &mut [&mut [f32]], block: &[T]) {
+    for (index, wet) in block.iter().enumerate() {
+        write_frame(channels, index, *wet);
     }
 }
 
@@ -218,7 +1963,12 @@ impl ClapPlugin for Metaloop {
     const CLAP_SUPPORT_URL: Option<&'static str> = None;
 
     // Don't forget to change these features
-    const CLAP_FEATURES: &'static [ClapFeature] = &[ClapFeature::AudioEffect, ClapFeature::Stereo];
+    const CLAP_FEATURES: &'static [ClapFeature] = &[
+        ClapFeature::AudioEffect,
+        ClapFeature::Stereo,
+        ClapFeature::Mono,
+        ClapFeature::Surround,
+    ];
 }
 
 impl Vst3Plugin for Metaloop {
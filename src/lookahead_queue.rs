@@ -0,0 +1,129 @@
+use std::collections::VecDeque;
+
+// a bounded queue of events that become due a number of ticks after they were pushed
+// events come out in the order they are due, and in the order they were pushed when
+// they are due on the same tick
+pub struct LookaheadQueue<E> {
+    events: VecDeque<(u64, E)>,
+    capacity: usize,
+    current_tick: u64,
+}
+
+#[allow(dead_code)]
+impl<E> LookaheadQueue<E> {
+    pub fn new(capacity: usize) -> LookaheadQueue<E> {
+        LookaheadQueue {
+            events: VecDeque::with_capacity(capacity),
+            capacity,
+            current_tick: 0,
+        }
+    }
+
+    // returns false and drops the event if the queue is full
+    pub fn push(&mut self, delay: usize, event: E) -> bool {
+        if self.events.len() >= self.capacity {
+            return false;
+        }
+        let due = self.current_tick + delay as u64;
+        // usually the event goes on the end, so look from there
+        let index = self
+            .events
+            .iter()
+            .rposition(|&(event_due, _)| event_due <= due)
+            .map_or(0, |i| i + 1);
+        self.events.insert(index, (due, event));
+        true
+    }
+
+    // pops the next event that is due this tick, call repeatedly until None
+    pub fn pop_due(&mut self) -> Option<E> {
+        match self.events.front() {
+            Some(&(due, _)) if due <= self.current_tick => self.events.pop_front().map(|(_, e)| e),
+            _ => None,
+        }
+    }
+
+    // how many ticks until the next event is due, 0 if one is due this tick
+    pub fn ticks_to_next(&self) -> Option<usize> {
+        self.events
+            .front()
+            .map(|&(due, _)| due.saturating_sub(self.current_tick) as usize)
+    }
+
+    // advance time by one tick, call after all due events have been popped
+    pub fn tick(&mut self) {
+        self.current_tick += 1;
+    }
+
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drain<E>(queue: &mut LookaheadQueue<E>) -> Vec<E> {
+        let mut out = vec![];
+        while let Some(e) = queue.pop_due() {
+            out.push(e);
+        }
+        queue.tick();
+        out
+    }
+
+    #[test]
+    fn test_lookahead_queue_zero_delay() {
+        let mut queue = LookaheadQueue::new(4);
+        queue.push(0, 'a');
+        queue.push(0, 'b');
+        assert_eq!(drain(&mut queue), vec!['a', 'b']);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_lookahead_queue_delay() {
+        let mut queue = LookaheadQueue::new(4);
+        queue.push(2, 'a');
+        assert_eq!(drain(&mut queue), vec![]);
+        queue.push(1, 'b');
+        assert_eq!(drain(&mut queue), vec![]);
+        assert_eq!(drain(&mut queue), vec!['a', 'b']);
+        assert_eq!(drain(&mut queue), vec![]);
+    }
+
+    #[test]
+    fn test_lookahead_queue_shorter_delay() {
+        let mut queue = LookaheadQueue::new(4);
+        queue.push(3, 'a');
+        queue.push(3, 'b');
+        assert_eq!(drain(&mut queue), vec![]);
+        // pushed later but due sooner
+        queue.push(0, 'c');
+        queue.push(1, 'd');
+        assert_eq!(drain(&mut queue), vec!['c']);
+        assert_eq!(drain(&mut queue), vec!['d']);
+        assert_eq!(drain(&mut queue), vec!['a', 'b']);
+    }
+
+    #[test]
+    fn test_lookahead_queue_full() {
+        let mut queue = LookaheadQueue::new(2);
+        assert!(queue.push(1, 'a'));
+        assert!(queue.push(1, 'b'));
+        assert!(!queue.push(1, 'c'));
+        assert_eq!(queue.len(), 2);
+
+        queue.clear();
+        assert!(queue.is_empty());
+    }
+}
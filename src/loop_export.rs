@@ -0,0 +1,222 @@
+use crate::audio_frame::{AudioFrame, MAX_CHANNELS};
+use crate::frame::Frame;
+use crate::grain_looper::GrainLooper;
+use crate::stereo_pair::StereoPair;
+use crate::wav::write_wav;
+use crate::SURROUND_CHANNELS;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+// writes what the loop can play to a wav file, so a good accident can be kept
+// asked for from any thread, the audio thread copies the region into a buffer made ahead of time
+// at the end of its next block, as the looper holds it, and the background task interleaves
+// that and writes it out
+// the audio thread never touches the path, it's only read by the background task
+
+// where a loop goes when no export path was set, in the temp folder
+const DEFAULT_FILE_NAME: &str = "metaloop loop.wav";
+
+#[derive(Default)]
+pub struct LoopExport {
+    requested: AtomicBool,
+    // shared with the params, so the export path is saved with the plugin's state
+    path: Arc<Mutex<Option<PathBuf>>>,
+    // only the one for the looper the plugin is running is sized
+    stereo: Mutex<ExportRegion<StereoPair<f32>>>,
+    surround: Mutex<ExportRegion<Frame<SURROUND_CHANNELS>>>,
+}
+
+#[derive(Default)]
+struct ExportRegion<T: AudioFrame> {
+    // newest first, as GrainLooper::capture_region leaves it
+    frames: Vec<T>,
+    // the plugin's, which the frames are written out as
+    num_channels: usize,
+    sample_rate: f32,
+    // there's audio the background task hasn't written yet
+    captured: bool,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl<T: AudioFrame> ExportRegion<T> {
+    fn prepare(&mut self, region_length: usize, num_channels: usize, sample_rate: f32) {
+        self.frames = vec![T::default(); region_length];
+        self.num_channels = num_channels.clamp(1, MAX_CHANNELS);
+        self.sample_rate = sample_rate;
+        self.captured = false;
+    }
+
+    // interleaved and oldest first, as it was played, if there's a capture to write
+    fn take_samples(&mut self) -> Option<Vec<f32>> {
+        if !std::mem::take(&mut self.captured) {
+            return None;
+        }
+        let mut samples = vec![0.0; self.frames.len() * self.num_channels];
+        for (frame, samples) in self
+            .frames
+            .iter()
+            .rev()
+            .zip(samples.chunks_exact_mut(self.num_channels))
+        {
+            frame.write_channels(samples);
+        }
+        Some(samples)
+    }
+}
+
+// from the audio thread, returns whether there's now audio for the background task to write
+// while the last export is still waiting to be written, this is tried again next block
+fn capture<T: AudioFrame>(
+    requested: &AtomicBool,
+    region: &Mutex<ExportRegion<T>>,
+    grain_looper: &GrainLooper<T>,
+) -> bool {
+    if !requested.load(Ordering::Acquire) {
+        return false;
+    }
+    let Ok(mut region) = region.try_lock() else {
+        return false;
+    };
+    if region.captured || region.frames.len() != grain_looper.region_length() {
+        return false;
+    }
+    requested.store(false, Ordering::Release);
+    grain_looper.capture_region(&mut region.frames);
+    region.captured = true;
+    true
+}
+
+#[allow(dead_code)]
+impl LoopExport {
+    pub fn new(path: Arc<Mutex<Option<PathBuf>>>) -> Self {
+        Self {
+            path,
+            ..Self::default()
+        }
+    }
+
+    // off the audio thread, as it allocates
+    // sized for whichever looper the plugin runs for this many channels
+    pub fn prepare(&self, region_length: usize, num_channels: usize, sample_rate: f32) {
+        let mut stereo = lock(&self.stereo);
+        let mut surround = lock(&self.surround);
+        *stereo = ExportRegion::default();
+        *surround = ExportRegion::default();
+        if num_channels > 2 {
+            surround.prepare(region_length, num_channels, sample_rate);
+        } else {
+            stereo.prepare(region_length, num_channels, sample_rate);
+        }
+    }
+
+    // from an editor or anything else off the audio thread, also saved as the export path
+    pub fn request(&self, path: impl Into<PathBuf>) {
+        *lock(&self.path) = Some(path.into());
+        self.request_again();
+    }
+
+    // from any thread, exports to the export path as it is, or the default one
+    pub fn request_again(&self) {
+        self.requested.store(true, Ordering::Release);
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.requested.load(Ordering::Acquire)
+    }
+
+    // from the audio thread, copies the loop in a couple of slice copies
+    // either one does nothing when the plugin was prepared for the other looper
+    pub fn capture(&self, grain_looper: &GrainLooper<StereoPair<f32>>) -> bool {
+        capture(&self.requested, &self.stereo, grain_looper)
+    }
+
+    pub fn capture_surround(&self, grain_looper: &GrainLooper<Frame<SURROUND_CHANNELS>>) -> bool {
+        capture(&self.requested, &self.surround, grain_looper)
+    }
+
+    // from the background task, writes what was captured to the export path
+    pub fn write(&self) -> io::Result<PathBuf> {
+        let (samples, num_channels, sample_rate) = {
+            let mut stereo = lock(&self.stereo);
+            let mut surround = lock(&self.surround);
+            if let Some(samples) = stereo.take_samples() {
+                (samples, stereo.num_channels, stereo.sample_rate)
+            } else if let Some(samples) = surround.take_samples() {
+                (samples, surround.num_channels, surround.sample_rate)
+            } else {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "no loop was captured",
+                ));
+            }
+        };
+        let path = lock(&self.path)
+            .clone()
+            .unwrap_or_else(|| std::env::temp_dir().join(DEFAULT_FILE_NAME));
+        let mut writer = BufWriter::new(File::create(&path)?);
+        write_wav(
+            &mut writer,
+            &samples,
+            num_channels as u16,
+            sample_rate.round() as u32,
+        )?;
+        writer.flush()?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stereo_pair::StereoPair;
+
+    #[test]
+    fn test_loop_export() {
+        let sample_rate = 100.0;
+        let mut looper = GrainLooper::<StereoPair<f32>>::new(sample_rate);
+        let export = LoopExport::default();
+        export.prepare(looper.region_length(), 2, sample_rate);
+        for index in 0..looper.region_length() {
+            looper.tick(StereoPair::new(index as f32, -(index as f32)), 0.0);
+        }
+
+        // nothing to write until it's asked for
+        assert!(!export.capture(&looper));
+        assert!(export.write().is_err());
+
+        let path = std::env::temp_dir().join("metaloop_test_loop_export.wav");
+        export.request(&path);
+        assert!(export.is_requested());
+        assert!(export.capture(&looper));
+        assert!(!export.is_requested());
+        assert_eq!(export.write().unwrap(), path);
+        assert!(export.write().is_err());
+        // the path is kept, to export to again
+        export.request_again();
+        assert!(export.capture(&looper));
+        assert_eq!(export.write().unwrap(), path);
+
+        // the region oldest first, after the 44 byte header
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(bytes.len(), 44 + looper.region_length() * 2 * 4);
+        let samples: Vec<f32> = bytes[44..]
+            .chunks_exact(4)
+            .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+            .collect();
+        for (frame, delay) in samples
+            .chunks_exact(2)
+            .zip((0..looper.region_length()).rev())
+        {
+            let expected = looper.region_frame(delay);
+            assert_eq!(frame, [expected.left, expected.right]);
+        }
+        assert!(samples.iter().any(|&sample| sample != 0.0));
+    }
+}
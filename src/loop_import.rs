@@ -0,0 +1,287 @@
+use crate::audio_file::decode;
+use crate::audio_frame::{AudioFrame, MAX_CHANNELS};
+use crate::frame::Frame;
+use crate::grain_looper::GrainLooper;
+use crate::resampler::resample;
+use crate::stereo_pair::StereoPair;
+use crate::SURROUND_CHANNELS;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+// loads a wav or aiff file into the loop, so it plays grains from any audio, not just the input
+// asked for from any thread, the audio thread hands the decoding to the background task, which
+// leaves the file at the plugin's rate in a region made ahead of time, laid out as the looper
+// plays it, then the audio thread swaps that in for the looper's own at its next block
+// the file starts as far before the loop start as the loop's offset, so the loop plays it from
+// the top, and scrubbing the offset back from there runs into silence
+// once loaded, the plugin keeps the loop on over the file until one of the loop controls takes over
+
+// extra input samples decoded past what fits, so the end of the resampled audio isn't cut short
+const RESAMPLE_SPARE: usize = 64;
+
+#[derive(Default)]
+pub struct LoopImport {
+    requested: AtomicBool,
+    // shared with the params, so the file last imported is saved with the plugin's state
+    path: Arc<Mutex<Option<PathBuf>>>,
+    // how far before the loop start the file starts, in samples
+    lead: AtomicUsize,
+    // only the one for the looper the plugin is running is sized
+    stereo: Mutex<ImportRegion<StereoPair<f32>>>,
+    surround: Mutex<ImportRegion<Frame<SURROUND_CHANNELS>>>,
+    // there's decoded audio the audio thread hasn't loaded yet
+    ready: AtomicBool,
+}
+
+#[derive(Default)]
+struct ImportRegion<T: AudioFrame> {
+    // newest first, as GrainLooper::import_region takes it
+    frames: Vec<T>,
+    // how many of the newest frames come after the loop start, and are left silent
+    margin: usize,
+    // the plugin's, which a mono file is mixed down to
+    num_channels: usize,
+    sample_rate: f32,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+// a mono file goes to every channel, a mono plugin hears the file mixed down,
+// and otherwise each channel goes to its own, with any the file doesn't have left silent
+fn write_frame(channels: &[Vec<f32>], index: usize, frame: &mut [f32]) {
+    match (channels, frame.len()) {
+        ([mono], _) => frame.fill(mono[index]),
+        (_, 1) => {
+            frame[0] =
+                channels.iter().map(|samples| samples[index]).sum::<f32>() / channels.len() as f32
+        }
+        _ => {
+            for (channel, sample) in frame.iter_mut().enumerate() {
+                *sample = channels.get(channel).map_or(0.0, |samples| samples[index]);
+            }
+        }
+    }
+}
+
+impl<T: AudioFrame> ImportRegion<T> {
+    fn prepare(
+        &mut self,
+        region_length: usize,
+        margin: usize,
+        num_channels: usize,
+        sample_rate: f32,
+    ) {
+        self.frames = vec![T::default(); region_length];
+        self.margin = margin.min(region_length);
+        self.num_channels = num_channels.clamp(1, MAX_CHANNELS);
+        self.sample_rate = sample_rate;
+    }
+
+    // how many frames of the file fit, starting lead frames before the loop start
+    fn capacity(&self, lead: usize) -> usize {
+        (self.margin + lead).min(self.frames.len())
+    }
+
+    // as much as fits from the start of the file, starting lead frames before the loop start
+    // returns how many frames that was
+    fn fill(&mut self, channels: &[Vec<f32>], lead: usize) -> usize {
+        self.frames.fill(T::default());
+        let start = self.capacity(lead);
+        let len = channels[0].len().min(start);
+        let mut frame = [0.0; MAX_CHANNELS];
+        let frame = &mut frame[..self.num_channels];
+        // newest first, so the file runs down from start
+        let played = &mut self.frames[start - len..start];
+        for (index, slot) in played.iter_mut().rev().enumerate() {
+            write_frame(channels, index, frame);
+            *slot = T::from_channels(frame);
+        }
+        len
+    }
+}
+
+// from the audio thread, swaps what was decoded into the looper, leaving the looper's old
+// import for the next file to be decoded over
+fn load<T: AudioFrame>(
+    ready: &AtomicBool,
+    region: &Mutex<ImportRegion<T>>,
+    grain_looper: &mut GrainLooper<T>,
+) -> bool {
+    if !ready.load(Ordering::Acquire) {
+        return false;
+    }
+    let Ok(mut region) = region.try_lock() else {
+        return false;
+    };
+    ready.store(false, Ordering::Release);
+    grain_looper.import_region(&mut region.frames)
+}
+
+#[allow(dead_code)]
+impl LoopImport {
+    pub fn new(path: Arc<Mutex<Option<PathBuf>>>) -> Self {
+        Self {
+            path,
+            ..Self::default()
+        }
+    }
+
+    // off the audio thread, as it allocates
+    // sized for whichever looper the plugin runs for this many channels
+    pub fn prepare(
+        &self,
+        region_length: usize,
+        margin: usize,
+        num_channels: usize,
+        sample_rate: f32,
+    ) {
+        let mut stereo = lock(&self.stereo);
+        let mut surround = lock(&self.surround);
+        *stereo = ImportRegion::default();
+        *surround = ImportRegion::default();
+        if num_channels > 2 {
+            surround.prepare(region_length, margin, num_channels, sample_rate);
+        } else {
+            stereo.prepare(region_length, margin, num_channels, sample_rate);
+        }
+        self.ready.store(false, Ordering::Release);
+    }
+
+    // from an editor or anything else off the audio thread, also saved as the import path
+    pub fn request(&self, path: impl Into<PathBuf>) {
+        *lock(&self.path) = Some(path.into());
+        self.requested.store(true, Ordering::Release);
+    }
+
+    // from the audio thread, whether to have the background task decode a file asked for
+    pub fn take_request(&self) -> bool {
+        self.requested.swap(false, Ordering::AcqRel)
+    }
+
+    // from the audio thread as it hands the decoding off, the loop's offset in samples
+    pub fn set_lead(&self, lead_samples: usize) {
+        self.lead.store(lead_samples, Ordering::Release);
+    }
+
+    // from the background task, returns the file and how many seconds of it will play
+    pub fn decode(&self) -> io::Result<(PathBuf, f32)> {
+        let path = lock(&self.path)
+            .clone()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no file was asked for"))?;
+        let decoded = decode(&std::fs::read(&path)?)?;
+
+        let mut stereo = lock(&self.stereo);
+        let mut surround = lock(&self.surround);
+        let lead = self.lead.load(Ordering::Acquire);
+        let (capacity, sample_rate) = if surround.frames.is_empty() {
+            (stereo.capacity(lead), stereo.sample_rate)
+        } else {
+            (surround.capacity(lead), surround.sample_rate)
+        };
+        // only as much as fits is resampled
+        let ratio = sample_rate / decoded.sample_rate;
+        let needed = (capacity as f32 / ratio).ceil() as usize + RESAMPLE_SPARE;
+        let channels: Vec<Vec<f32>> = decoded
+            .channels
+            .iter()
+            .map(|samples| {
+                let samples = &samples[..needed.min(samples.len())];
+                resample(samples, decoded.sample_rate, sample_rate)
+            })
+            .collect();
+
+        let len = if surround.frames.is_empty() {
+            stereo.fill(&channels, lead)
+        } else {
+            surround.fill(&channels, lead)
+        };
+        self.ready.store(true, Ordering::Release);
+        Ok((path, len as f32 / sample_rate))
+    }
+
+    // from the audio thread, swaps what was decoded into the looper, returns whether it was
+    // while the background task is still writing it, this is tried again next block
+    // either one does nothing when the plugin was prepared for the other looper
+    pub fn load(&self, grain_looper: &mut GrainLooper<StereoPair<f32>>) -> bool {
+        load(&self.ready, &self.stereo, grain_looper)
+    }
+
+    pub fn load_surround(&self, grain_looper: &mut GrainLooper<Frame<SURROUND_CHANNELS>>) -> bool {
+        load(&self.ready, &self.surround, grain_looper)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grain_looper::loopable_region_length;
+    use crate::stereo_pair::StereoPair;
+    use crate::wav::write_wav;
+
+    #[test]
+    fn test_write_frame() {
+        let stereo = vec![vec![0.5], vec![-0.25]];
+        let mut frame = [1.0; 3];
+        write_frame(&stereo, 0, &mut frame);
+        assert_eq!(frame, [0.5, -0.25, 0.0]);
+        let mut mono_frame = [1.0];
+        write_frame(&stereo, 0, &mut mono_frame);
+        assert_eq!(mono_frame, [0.125]);
+        write_frame(&[vec![0.75]], 0, &mut frame);
+        assert_eq!(frame, [0.75; 3]);
+    }
+
+    #[test]
+    fn test_loop_import() {
+        let sample_rate = 100.0;
+        let mut looper = GrainLooper::<StereoPair<f32>>::new(sample_rate);
+        let import = LoopImport::default();
+        import.prepare(
+            looper.region_length(),
+            looper.import_margin(),
+            2,
+            sample_rate,
+        );
+        assert!(!import.take_request());
+        assert!(import.decode().is_err());
+        assert!(!import.load(&mut looper));
+
+        // a mono file at twice the rate, a half second of it
+        let path = std::env::temp_dir().join("metaloop_test_loop_import.wav");
+        let samples: Vec<f32> = (0..100).map(|index| index as f32 / 100.0).collect();
+        let mut file = std::fs::File::create(&path).unwrap();
+        write_wav(&mut file, &samples, 1, 200).unwrap();
+        drop(file);
+
+        import.request(&path);
+        assert!(import.take_request());
+        assert!(!import.take_request());
+        import.set_lead(10);
+        let (decoded, seconds) = import.decode().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(decoded, path);
+        assert_eq!(seconds, 0.5);
+        // and stays saved, to import again
+        assert_eq!(*lock(&import.path), Some(path));
+
+        // once loaded, the loop is ready to play it
+        assert!(import.load(&mut looper));
+        assert!(!import.load(&mut looper));
+        looper.start_looping();
+        // the file starts the lead before the loop start, and is silent either side of it
+        let margin = looper.region_length() - loopable_region_length(sample_rate);
+        assert_eq!(margin, looper.import_margin());
+        let start = margin + 9;
+        let middle = looper.region_frame(start - 25);
+        assert!((middle.left - 0.5).abs() < 0.01, "{}", middle.left);
+        assert_eq!(middle.left, middle.right);
+        assert_ne!(looper.region_frame(start - 1), StereoPair::default());
+        assert_eq!(looper.region_frame(start + 1), StereoPair::default());
+        assert_ne!(looper.region_frame(start - 49), StereoPair::default());
+        assert_eq!(looper.region_frame(start - 50), StereoPair::default());
+    }
+}
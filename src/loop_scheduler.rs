@@ -1,6 +1,12 @@
 // This handles the actual events that control what the looper does
 // according to the beat time
-use crate::scheduler::Scheduler;
+// nothing here depends on the clock being in beats, so a free running looper
+// drives it with seconds instead
+use crate::countdown_trigger::CountdownTrigger;
+use crate::length_pattern::LengthPattern;
+use crate::random::Random;
+use crate::scheduler::{DueEvents, Scheduler, MAX_DUE_EVENTS};
+use nih_plug::prelude::Enum;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum LoopEvent {
@@ -15,24 +21,162 @@ pub enum LoopEvent {
     FadeOutDry, // fade out the dry signal
     FadeInDry,  // fade in the dry signal
     NextLoop,   // start the next loop, recurs
+    ReverseTail {
+        duration: f32,
+    }, // play the loop once more, reversed, as it stops
+    EndTail,    // the tail is over, so the dry comes back
+    BlendDry,   // bring a little dry in under a length change
+    UnblendDry, // and take it back out
+    CountIn,    // one of the count-in's counts has passed, kept by the scheduler
+}
+// restarts the loop this many times within each loop, for beat repeat rolls
+// the grid stays as it is, so the rolls line up with the loop they're cut from
+#[derive(Enum, Debug, Clone, Copy, PartialEq)]
+pub enum Stutter {
+    #[id = "off"]
+    #[name = "Off"]
+    Off,
+    #[id = "half"]
+    #[name = "1/2"]
+    Half,
+    #[id = "quarter"]
+    #[name = "1/4"]
+    Quarter,
+    #[id = "eighth"]
+    #[name = "1/8"]
+    Eighth,
+}
+
+// where the loop starts when it is turned on, by default on the loop's own grid
+// like launching a clip, it can start straight away or on the next beat or bar whatever its length
+#[derive(Enum, Debug, Clone, Copy, PartialEq)]
+pub enum LaunchQuantization {
+    #[id = "loop"]
+    #[name = "Loop"]
+    Loop,
+    #[id = "off"]
+    #[name = "Off"]
+    Off,
+    #[id = "beat"]
+    #[name = "Beat"]
+    Beat,
+    #[id = "bar"]
+    #[name = "Bar"]
+    Bar,
+}
+
+impl LaunchQuantization {
+    // what start_looping waits for, none for the loop's grid and zero for nothing
+    pub fn interval(&self, bar_length: f32) -> Option<f32> {
+        match self {
+            LaunchQuantization::Loop => None,
+            LaunchQuantization::Off => Some(0.0),
+            LaunchQuantization::Beat => Some(1.0),
+            LaunchQuantization::Bar => Some(bar_length),
+        }
+    }
+}
+
+// what the count-in before the loop starts counts
+#[derive(Enum, Debug, Clone, Copy, PartialEq)]
+pub enum CountInUnit {
+    #[id = "beats"]
+    #[name = "Beats"]
+    Beats,
+    #[id = "bars"]
+    #[name = "Bars"]
+    Bars,
+}
+
+impl CountInUnit {
+    pub fn interval(&self, bar_length: f32) -> f32 {
+        match self {
+            CountInUnit::Beats => 1.0,
+            CountInUnit::Bars => bar_length,
+        }
+    }
+}
+
+impl Stutter {
+    pub fn divisions(&self) -> u32 {
+        match self {
+            Stutter::Off => 1,
+            Stutter::Half => 2,
+            Stutter::Quarter => 4,
+            Stutter::Eighth => 8,
+        }
+    }
 }
+
 pub struct LoopScheduler {
     scheduler: Scheduler<LoopEvent>,
-    fade_in_time: f32,
-    grid_interval: f32,
-    current_song_time: f32,
-    time_looping_initiated: f32,
+    fade_in_time: BeatTime,
+    // how far the grid is moved on from the host's bar lines
+    grid_offset: BeatTime,
+    // 0.5 is straight, above that every other grid line is pushed later, see set_swing
+    swing: f32,
+    // how late the next loop is for the swing
+    next_loop_swing: BeatTime,
+    // how long the dry blends in for when the length changes, none to leave the dry alone
+    transition_blend_time: Option<BeatTime>,
+    grid_interval: BeatTime,
+    // what the loop waits for to start, none for the grid interval
+    launch_quantization: Option<BeatTime>,
+    // how many counts of count_in_interval the loop waits for once started, zero for none
+    count_in_length: u32,
+    count_in_interval: BeatTime,
+    // the counts left before the loop starts
+    count_in: CountdownTrigger,
+    // how many times each loop restarts from its beginning, from the next loop on
+    stutter_divisions: u32,
+    // each loop takes the length and offset of the next step, none for the grid interval
+    length_pattern: Option<LengthPattern>,
+    // the offset of the step the loop playing took
+    pattern_offset: f32,
+    // how long the loop playing lasts, with its swing and pattern step
+    loop_length: BeatTime,
+    current_song_time: BeatTime,
+    time_looping_initiated: BeatTime,
     is_looping: bool,
+    // the loop was stopped with the dry held back until a later grid line
+    dry_return_pending: bool,
+    // how many loops have started since looping was started
+    loop_count: u32,
+    // the loop plays this many times and then the dry comes back, none repeats until stopped
+    pass_limit: Option<u32>,
+    // the last pass has been played, the loop is still on but stays quiet until stopped
+    spent: bool,
+    // the chance of each repeat playing, and whether the last one was left out
+    repeat_probability: f32,
+    repeat_skipped: bool,
+    random: Random,
+    // set when an invariant is violated, instead of panicking on the audio thread
+    has_fault: bool,
 }
 
-type BeatTime = f32; // might wanna have f64
+// so the repeats that play come out the same each time the scheduler is reset
+const REPEAT_SEED: u32 = 0xd1ce;
+
+// the host's position is an f64, as an f32 it would be a sample out within minutes at high tempos,
+// so times and the intervals added to them are kept as f64, and only the durations handed on
+// are f32
+type BeatTime = f64;
+
+// each event the scheduler hands out turns into at most two, so these never fill up
+pub const MAX_LOOP_EVENTS: usize = 2 * MAX_DUE_EVENTS;
+pub type LoopEvents = DueEvents<LoopEvent, MAX_LOOP_EVENTS>;
+
+// a time this close to a grid line, in grid intervals, is on it
+// a clock summed a sample at a time drifts off the lines by far less, and a sample is far more
+const GRID_TOLERANCE: BeatTime = 1e-6;
 
 fn next_grid_in_beats(
     song_time: BeatTime,
     grid_interval: BeatTime,
     grid_offset: BeatTime,
 ) -> BeatTime {
-    ((song_time + grid_offset) / grid_interval).ceil() * grid_interval - grid_offset
+    ((song_time + grid_offset) / grid_interval - GRID_TOLERANCE).ceil() * grid_interval
+        - grid_offset
 }
 
 fn previous_grid_in_beats(
@@ -40,7 +184,8 @@ fn previous_grid_in_beats(
     grid_interval: BeatTime,
     grid_offset: BeatTime,
 ) -> BeatTime {
-    ((song_time + grid_offset) / grid_interval).floor() * grid_interval - grid_offset
+    ((song_time + grid_offset) / grid_interval + GRID_TOLERANCE).floor() * grid_interval
+        - grid_offset
 }
 
 impl LoopScheduler {
@@ -48,26 +193,228 @@ impl LoopScheduler {
         LoopScheduler {
             scheduler: Scheduler::new(),
             fade_in_time: 0.0,
+            grid_offset: 0.0,
+            swing: 0.5,
+            next_loop_swing: 0.0,
+            transition_blend_time: None,
             grid_interval: 1.0,
+            launch_quantization: None,
+            count_in_length: 0,
+            count_in_interval: 1.0,
+            count_in: CountdownTrigger::new(0),
+            stutter_divisions: 1,
+            length_pattern: None,
+            pattern_offset: 0.0,
+            loop_length: 0.0,
             current_song_time: -1.0,
             time_looping_initiated: 0.0,
             is_looping: false,
+            dry_return_pending: false,
+            loop_count: 0,
+            pass_limit: None,
+            spent: false,
+            repeat_probability: 1.0,
+            repeat_skipped: false,
+            random: Random::new(REPEAT_SEED),
+            has_fault: false,
         }
     }
 
     pub fn reset(&mut self) {
         self.scheduler.clear();
         self.is_looping = false;
+        self.dry_return_pending = false;
+        self.spent = false;
+        self.count_in.reset(0);
+        self.repeat_skipped = false;
+        self.random = Random::new(REPEAT_SEED);
+        self.has_fault = false;
+    }
+
+    // true once something has gone wrong, until the next reset
+    pub fn has_fault(&self) -> bool {
+        self.has_fault
+    }
+
+    pub fn is_looping(&self) -> bool {
+        self.is_looping
+    }
+
+    // when the next loop starts, if looping
+    pub fn next_loop_time(&self) -> Option<BeatTime> {
+        self.scheduler.next_time_of(LoopEvent::NextLoop)
+    }
+
+    // whether ticking to beat_time, within window, would do anything more than move the time on
+    pub fn is_quiet_until(&self, beat_time: BeatTime, window: BeatTime) -> bool {
+        beat_time >= self.current_song_time
+            && !self
+                .scheduler
+                .next_time()
+                .is_some_and(|time| time <= beat_time + window)
+    }
+
+    pub fn time_to_next_loop(&self) -> Option<f32> {
+        self.next_loop_time()
+            .map(|time| (time - self.current_song_time).max(0.0) as f32)
+    }
+
+    // how many counts are left before the loop starts, zero once it has or when not counting in
+    pub fn count_in_remaining(&self) -> u32 {
+        self.count_in.count() as u32
+    }
+
+    pub fn loop_count(&self) -> u32 {
+        self.loop_count
+    }
+
+    pub fn stutter_divisions(&self) -> u32 {
+        self.stutter_divisions
+    }
+
+    pub fn loop_length(&self) -> f32 {
+        self.loop_length as f32
+    }
+
+    pub fn grid_interval(&self) -> f32 {
+        self.grid_interval as f32
+    }
+
+    fn schedule(&mut self, time: BeatTime, event: LoopEvent) {
+        if !self.scheduler.try_schedule_event(time, event) {
+            self.has_fault = true;
+        }
     }
 
     // set fade lead time in beats
     pub fn set_fade_lead_in(&mut self, fade_in: f32) {
         // Do nothing
-        self.fade_in_time = fade_in;
+        self.fade_in_time = fade_in as BeatTime;
+    }
+
+    // the grid lines are offset later by this, e.g. half a beat to loop on the off-beat
+    // a loop that is playing moves over with the grid
+    pub fn set_grid_offset(&mut self, grid_offset: f32) {
+        let grid_offset = grid_offset as BeatTime;
+        if grid_offset == self.grid_offset {
+            return;
+        }
+        if self.is_looping {
+            self.scheduler.shift(grid_offset - self.grid_offset);
+        }
+        self.grid_offset = grid_offset;
+    }
+
+    // where each pair of loops splits, as a share of the pair's length, from the next loop on
+    // 0.5 is straight, and 2/3 a triplet swing, the first loop of each pair is the longer
+    pub fn set_swing(&mut self, swing: f32) {
+        self.swing = swing;
+    }
+
+    // how late the grid line at time is pushed, only the odd ones are
+    fn swing_delay(&self, time: BeatTime) -> BeatTime {
+        let index = ((time + self.grid_phase()) / self.grid_interval).round() as i64;
+        if index % 2 == 0 {
+            return 0.0;
+        }
+        (self.swing as BeatTime - 0.5) * 2.0 * self.grid_interval
+    }
+
+    // schedules the next loop at the straight grid line time, or later if it's swung
+    // returns when it will start
+    fn schedule_next_loop(&mut self, time: BeatTime) -> BeatTime {
+        self.next_loop_swing = self.swing_delay(time);
+        let time = time + self.next_loop_swing;
+        self.schedule(time, LoopEvent::NextLoop);
+        time
+    }
+
+    // what next_grid_in_beats takes off the time, so the fade starts before each grid line
+    fn grid_phase(&self) -> BeatTime {
+        self.fade_in_time - self.grid_offset
+    }
+
+    // in beats
+    pub fn set_transition_blend(&mut self, blend_time: Option<f32>) {
+        self.transition_blend_time = blend_time.map(|time| time as BeatTime);
+    }
+
+    // each repeat after the first loop plays with this chance, otherwise the dry comes back
+    // until the next one that does
+    pub fn set_repeat_probability(&mut self, probability: f32) {
+        self.repeat_probability = probability;
+    }
+
+    // from the next start_looping, see LaunchQuantization
+    pub fn set_launch_quantization(&mut self, quantization: Option<f32>) {
+        self.launch_quantization = quantization.map(|quantization| quantization as BeatTime);
+    }
+
+    // one-shot, the loop plays this many passes then the dry comes back, counted from the start
+    pub fn set_pass_limit(&mut self, pass_limit: Option<u32>) {
+        self.pass_limit = pass_limit;
+    }
+
+    // from the next start_looping, the loop waits for length counts of interval and then
+    // starts on the first line it would at the end of the count
+    pub fn set_count_in(&mut self, length: u32, interval: f32) {
+        self.count_in_length = length;
+        self.count_in_interval = interval as BeatTime;
+    }
+
+    // schedules the counts from the next count line, and returns when the count ends
+    // the times are left without the grid phase, so the start lines up exactly with the count
+    fn schedule_count_in(&mut self) -> BeatTime {
+        let interval = self.count_in_interval;
+        let first = next_grid_in_beats(self.current_song_time, interval, self.grid_phase())
+            + self.grid_phase();
+        for count in 1..=self.count_in_length {
+            self.schedule(
+                first + count as BeatTime * interval - self.grid_phase(),
+                LoopEvent::CountIn,
+            );
+        }
+        self.count_in.reset(self.count_in_length as i32);
+        first + self.count_in_length as BeatTime * interval
+    }
+
+    // the first line of interval at or after the count-in's end, or the current time without one
+    fn next_start_line(&self, count_in_end: Option<BeatTime>, interval: BeatTime) -> BeatTime {
+        match count_in_end {
+            Some(end) => next_grid_in_beats(end, interval, 0.0) - self.grid_phase(),
+            None => next_grid_in_beats(self.current_song_time, interval, self.grid_phase()),
+        }
+    }
+
+    // from the next loop on, the pattern starts from its first step each time looping starts
+    // the swing still goes by the grid interval
+    pub fn set_length_pattern(&mut self, pattern: Option<LengthPattern>) {
+        self.length_pattern = pattern;
+    }
+
+    // how much further back the loop playing starts for its pattern step, in beats
+    pub fn pattern_offset(&self) -> f32 {
+        self.pattern_offset
+    }
+
+    pub fn set_stutter(&mut self, stutter: Stutter) {
+        self.stutter_divisions = stutter.divisions();
+    }
+
+    // changes the interval from the next loop on, without rescheduling anything
+    // for committing a change right before a loop starts
+    pub fn set_grid_interval_from_next_loop(&mut self, new_interval_beats: f32) {
+        self.grid_interval = new_interval_beats as BeatTime;
     }
 
     pub fn set_grid_interval(&mut self, new_interval_beats: f32) {
-        if new_interval_beats == self.grid_interval || !self.is_looping {
+        let new_interval_beats = new_interval_beats as BeatTime;
+        // counting in, the start is already set, and the new interval follows on from it
+        if new_interval_beats == self.grid_interval
+            || !self.is_looping
+            || self.spent
+            || self.count_in_remaining() > 0
+        {
             self.grid_interval = new_interval_beats;
             return;
         }
@@ -75,98 +422,282 @@ impl LoopScheduler {
         let next_old_grid_interval = next_grid_in_beats(
             self.current_song_time,
             self.grid_interval,
-            self.fade_in_time,
+            self.grid_phase(),
         );
         let next_new_grid_interval = next_grid_in_beats(
             self.current_song_time,
             new_interval_beats,
-            self.fade_in_time,
-        );
-
-        if new_interval_beats < self.grid_interval {
-            // if a shorter interval, need to stop the current grain
-            self.scheduler
-                .schedule_event(next_new_grid_interval, LoopEvent::StopGrain);
-        } else if new_interval_beats > self.grid_interval {
-            if next_new_grid_interval > next_old_grid_interval {
-                // need a grain that will take us to the longer grid interval from the end of the shorter
-                let reduced_grid_interval = next_new_grid_interval - next_old_grid_interval;
-                let how_far_thru = new_interval_beats - reduced_grid_interval;
-                self.scheduler.schedule_event(
-                    next_old_grid_interval,
-                    LoopEvent::StartLegatoGrain {
-                        duration: reduced_grid_interval,
-                        offset_reduction: how_far_thru,
-                    },
-                );
-            }
+            self.grid_phase(),
+        );
+
+        // going between straight and triplet grids, the lines don't all meet, so it is where the
+        // next lines fall that matters rather than which interval is longer
+        if next_new_grid_interval > next_old_grid_interval {
+            // need a grain that will take us to the new grid line from the end of the current loop
+            let reduced_grid_interval = next_new_grid_interval - next_old_grid_interval;
+            let how_far_thru = new_interval_beats - reduced_grid_interval;
+            self.schedule(
+                next_old_grid_interval,
+                LoopEvent::StartLegatoGrain {
+                    duration: reduced_grid_interval as f32,
+                    offset_reduction: how_far_thru as f32,
+                },
+            );
+        } else if new_interval_beats < self.grid_interval
+            || next_new_grid_interval < next_old_grid_interval
+        {
+            // the new grid line comes first, need to stop the current grain
+            self.schedule(next_new_grid_interval, LoopEvent::StopGrain);
+        }
+        // straight, so the blend lines up with it
+        self.next_loop_swing = 0.0;
+        self.schedule(next_new_grid_interval, LoopEvent::NextLoop);
+        if let Some(blend_time) = self.transition_blend_time {
+            // over before the loop after next is scheduled, so the events stay in order
+            let blend_time = blend_time.min(new_interval_beats);
+            self.schedule(next_new_grid_interval, LoopEvent::BlendDry);
+            self.schedule(next_new_grid_interval + blend_time, LoopEvent::UnblendDry);
         }
-        self.scheduler
-            .schedule_event(next_new_grid_interval, LoopEvent::NextLoop);
         self.grid_interval = new_interval_beats;
     }
 
     pub fn start_looping(&mut self) {
-        assert!(!self.is_looping);
+        if self.is_looping {
+            self.has_fault = true;
+            return;
+        }
+        // the dry is still held back, it stays that way for the new loop
+        if std::mem::take(&mut self.dry_return_pending) {
+            self.scheduler.clear();
+        }
         self.is_looping = true;
+        self.spent = false;
+        self.loop_count = 0;
+        self.repeat_skipped = false;
         self.time_looping_initiated = self.current_song_time;
+        let count_in_end = (self.count_in_length > 0).then(|| self.schedule_count_in());
         // schedule a fade out
         // schedule a grain to start at the next grid interval
+        let start = match self.launch_quantization {
+            // off the grid, the loops carry on a grid interval apart from the start, unswung
+            Some(quantization) => {
+                let start = if quantization > 0.0 {
+                    self.next_start_line(count_in_end, quantization)
+                } else {
+                    count_in_end.map_or(self.current_song_time, |end| end - self.grid_phase())
+                };
+                self.next_loop_swing = 0.0;
+                self.schedule(start, LoopEvent::NextLoop);
+                start
+            }
+            None => {
+                let next_grid_interval = self.next_start_line(count_in_end, self.grid_interval);
+                self.schedule_next_loop(next_grid_interval)
+            }
+        };
+        self.schedule(start, LoopEvent::FadeOutDry);
+    }
+
+    pub fn stop_looping(&mut self) {
+        if !self.is_looping {
+            self.has_fault = true;
+            return;
+        }
+        self.is_looping = false;
+        self.count_in.reset(0);
+        // schedule a fade in
+        // schedule a grain to stop at the next grid interval
         let next_grid_interval = next_grid_in_beats(
             self.current_song_time,
             self.grid_interval,
-            self.fade_in_time,
+            self.grid_phase(),
         );
 
-        self.scheduler
-            .schedule_event(next_grid_interval, LoopEvent::NextLoop);
-        self.scheduler
-            .schedule_event(next_grid_interval, LoopEvent::FadeOutDry);
+        self.scheduler.clear();
+
+        self.schedule(next_grid_interval, LoopEvent::StopGrain);
+        self.schedule(next_grid_interval, LoopEvent::FadeInDry);
     }
 
-    pub fn stop_looping(&mut self) {
-        assert!(self.is_looping);
+    // starts the loop again from its beginning at the next multiple of quantization,
+    // stopping the grain that is playing, the loops carry on a grid interval apart from there
+    pub fn retrigger(&mut self, quantization: f32) {
+        if !self.is_looping || self.spent {
+            return;
+        }
+        let next_quantization_point = next_grid_in_beats(
+            self.current_song_time,
+            quantization as BeatTime,
+            self.grid_phase(),
+        );
+
+        self.scheduler.clear();
+
+        self.schedule(next_quantization_point, LoopEvent::StopGrain);
+        self.next_loop_swing = 0.0;
+        self.schedule(next_quantization_point, LoopEvent::NextLoop);
+    }
+
+    // as stop_looping, but a tail lasting tail_length plays from the next grid line,
+    // and the dry only comes back as it ends
+    pub fn stop_looping_with_tail(&mut self, tail_length: f32) {
+        if !self.is_looping {
+            self.has_fault = true;
+            return;
+        }
+        // the dry is already back, or never went, so there is nothing to play a tail over
+        if self.spent || self.count_in_remaining() > 0 {
+            self.stop_looping();
+            return;
+        }
         self.is_looping = false;
-        // schedule a fade in
-        // schedule a grain to stop at the next grid interval
         let next_grid_interval = next_grid_in_beats(
             self.current_song_time,
             self.grid_interval,
-            self.fade_in_time,
+            self.grid_phase(),
         );
 
         self.scheduler.clear();
 
-        self.scheduler
-            .schedule_event(next_grid_interval, LoopEvent::StopGrain);
-        self.scheduler
-            .schedule_event(next_grid_interval, LoopEvent::FadeInDry);
+        self.schedule(next_grid_interval, LoopEvent::StopGrain);
+        self.schedule(
+            next_grid_interval,
+            LoopEvent::ReverseTail {
+                duration: tail_length,
+            },
+        );
+        self.schedule(
+            next_grid_interval + tail_length as BeatTime,
+            LoopEvent::EndTail,
+        );
+        // looping again before the tail ends drops the rest of it
+        self.dry_return_pending = true;
+    }
+
+    // once the loop has been stopped, bring the dry back at the next multiple of interval
+    pub fn fade_in_dry_on_grid(&mut self, interval: f32) {
+        if self.is_looping {
+            self.has_fault = true;
+            return;
+        }
+        let time = next_grid_in_beats(
+            self.current_song_time,
+            interval as BeatTime,
+            self.grid_phase(),
+        );
+        self.schedule(time, LoopEvent::FadeInDry);
+        self.dry_return_pending = true;
+    }
+
+    // the host jumped, e.g. cycling a region, moving the playhead or restarting the transport
+    // pending events move so the next grid line becomes the next grid line from the new time,
+    // which keeps the loop going seamlessly when the jump is a whole number of loops
+    // jumps back are picked up by tick, jumps forward can't be told apart from time passing
+    pub fn realign(&mut self, beat_time: BeatTime) {
+        let delta = next_grid_in_beats(beat_time, self.grid_interval, self.grid_phase())
+            - next_grid_in_beats(
+                self.current_song_time,
+                self.grid_interval,
+                self.grid_phase(),
+            );
+        self.scheduler.shift(delta);
+        self.time_looping_initiated += delta;
+        self.current_song_time = beat_time;
+    }
+
+    #[cfg(test)]
+    pub fn tick(&mut self, beat_time: BeatTime) -> LoopEvents {
+        self.tick_within(beat_time, 0.0)
     }
 
-    pub fn tick(&mut self, beat_time: f32) -> Vec<LoopEvent> {
+    // as tick, but the events due up to window after beat_time go now as well
+    pub fn tick_within(&mut self, beat_time: BeatTime, window: BeatTime) -> LoopEvents {
         if beat_time < self.current_song_time {
-            // we've looped back, now what?
-            self.current_song_time = beat_time;
-            self.time_looping_initiated = beat_time;
+            self.realign(beat_time);
         }
 
+        let last_time = self.current_song_time;
         self.current_song_time = beat_time;
 
-        let events = self.scheduler.tick(beat_time);
-        let mut returned_events = vec![];
-        for event in events {
+        let events = self.scheduler.tick_timed(beat_time + window);
+        let mut returned_events = LoopEvents::new();
+        for (due, event) in events {
+            // the loops follow on from when each was due rather than when it was ticked, so one
+            // that lands between samples doesn't move every loop after it
+            // one that was already due when it was scheduled is due now
+            let due = if due > last_time { due } else { beat_time };
+            // so grains last from now until they were due to end
+            let early = due - beat_time;
             match event {
                 LoopEvent::NextLoop => {
-                    // TODO don't push to the vec, as it allocates
+                    self.loop_count += 1;
+                    // the first loop always plays, only the repeats are left to chance
+                    let plays =
+                        self.loop_count == 1 || self.random.next_f32() < self.repeat_probability;
+                    let step = self
+                        .length_pattern
+                        .map(|pattern| pattern.step(self.loop_count as usize - 1));
+                    self.pattern_offset = step.map_or(0.0, |step| step.offset);
+                    let interval =
+                        self.grid_interval * step.map_or(1.0, |step| step.length as BeatTime);
+                    // swung, this loop and the next are pushed by different amounts
+                    let straight = due - self.next_loop_swing;
+                    let loop_length =
+                        interval - self.next_loop_swing + self.swing_delay(straight + interval);
+                    let stutter_length = loop_length / self.stutter_divisions as BeatTime;
+                    self.loop_length = loop_length;
                     // record when we started the thing
+                    if plays {
+                        returned_events.push(LoopEvent::StartGrain {
+                            duration: (stutter_length + early) as f32,
+                        });
+                        if std::mem::take(&mut self.repeat_skipped) {
+                            returned_events.push(LoopEvent::FadeOutDry);
+                        }
+                        // the rest of the stutters go in before the next loop, to keep the order
+                        for division in 1..self.stutter_divisions {
+                            self.schedule(
+                                due + division as BeatTime * stutter_length,
+                                LoopEvent::StartGrain {
+                                    duration: stutter_length as f32,
+                                },
+                            );
+                        }
+                    } else if !self.repeat_skipped {
+                        // the grain playing fades out as the dry comes back
+                        self.repeat_skipped = true;
+                        returned_events.push(LoopEvent::FadeInDry);
+                    }
+                    if self
+                        .pass_limit
+                        .is_some_and(|limit| self.loop_count >= limit)
+                    {
+                        // the last pass, so the dry comes back where the next loop would start
+                        let end = straight + interval + self.swing_delay(straight + interval);
+                        self.schedule(end, LoopEvent::StopGrain);
+                        self.schedule(end, LoopEvent::FadeInDry);
+                        self.spent = true;
+                    } else {
+                        // schedule the next loop
+                        self.schedule_next_loop(straight + interval);
+                    }
+                }
+                LoopEvent::CountIn => {
+                    self.count_in.tick();
+                }
+                LoopEvent::StartGrain { duration } => {
                     returned_events.push(LoopEvent::StartGrain {
-                        duration: self.grid_interval,
+                        duration: duration + early as f32,
+                    });
+                }
+                LoopEvent::StartLegatoGrain {
+                    duration,
+                    offset_reduction,
+                } => {
+                    returned_events.push(LoopEvent::StartLegatoGrain {
+                        duration: duration + early as f32,
+                        offset_reduction,
                     });
-                    // schedule the next loop
-                    self.scheduler.schedule_event(
-                        self.current_song_time + self.grid_interval,
-                        LoopEvent::NextLoop,
-                    );
                 }
                 _ => {
                     returned_events.push(event);
@@ -181,6 +712,7 @@ impl LoopScheduler {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::length_pattern::PatternStep;
 
     #[test]
     fn test_next_grid_in_beats() {
@@ -220,6 +752,393 @@ mod tests {
         assert_eq!(out9, vec![]);
     }
 
+    #[test]
+    fn test_loop_scheduler_stutter() {
+        let mut scheduler = LoopScheduler::new();
+        scheduler.tick(0.0);
+        scheduler.start_looping();
+        scheduler.tick(1.0);
+        scheduler.set_stutter(Stutter::Quarter);
+
+        // the loop still starts on the grid, but is cut into quarters that each start it again
+        let quarter = LoopEvent::StartGrain { duration: 0.25 };
+        assert_eq!(scheduler.tick(2.0), vec![quarter]);
+        assert_eq!(scheduler.tick(2.2), vec![]);
+        for time in [2.25, 2.5, 2.75, 3.0] {
+            assert_eq!(scheduler.tick(time), vec![quarter]);
+        }
+
+        scheduler.set_stutter(Stutter::Off);
+        for time in [3.25, 3.5, 3.75] {
+            assert_eq!(scheduler.tick(time), vec![quarter]);
+        }
+        assert_eq!(
+            scheduler.tick(4.0),
+            vec![LoopEvent::StartGrain { duration: 1.0 }]
+        );
+        assert_eq!(scheduler.tick(4.5), vec![]);
+        assert!(!scheduler.has_fault());
+    }
+
+    #[test]
+    fn test_loop_scheduler_repeat_probability() {
+        let mut scheduler = LoopScheduler::new();
+        scheduler.tick(0.0);
+        scheduler.set_repeat_probability(0.0);
+        scheduler.start_looping();
+        // the first loop plays whatever the chance
+        let start = LoopEvent::StartGrain { duration: 1.0 };
+        assert_eq!(scheduler.tick(1.0), vec![start, LoopEvent::FadeOutDry]);
+        assert_eq!(scheduler.tick(2.0), vec![LoopEvent::FadeInDry]);
+        assert_eq!(scheduler.tick(3.0), vec![]);
+
+        scheduler.set_repeat_probability(1.0);
+        assert_eq!(scheduler.tick(4.0), vec![start, LoopEvent::FadeOutDry]);
+        assert_eq!(scheduler.tick(5.0), vec![start]);
+
+        // in between, some repeats play and some don't
+        scheduler.set_repeat_probability(0.5);
+        let played = (6..106)
+            .filter(|&time| scheduler.tick(time as BeatTime).contains(&start))
+            .count();
+        assert!(played > 30 && played < 70, "{}", played);
+        assert!(!scheduler.has_fault());
+    }
+
+    #[test]
+    fn test_loop_scheduler_long_session() {
+        // a few hours in at a high tempo, where an f32 beat time is a sample or so out
+        let start_time = 100_000.0;
+        let sample = 1.0 / 4800.0;
+        let mut scheduler = LoopScheduler::new();
+        // just past a grid line, which an f32 would round back onto
+        scheduler.tick(start_time + sample);
+        scheduler.set_grid_interval(0.25);
+        scheduler.start_looping();
+        assert_eq!(scheduler.next_loop_time(), Some(start_time + 0.25));
+        assert_eq!(scheduler.tick(start_time + 0.25 - sample), vec![]);
+        let start = LoopEvent::StartGrain { duration: 0.25 };
+        assert_eq!(
+            scheduler.tick(start_time + 0.25),
+            vec![start, LoopEvent::FadeOutDry]
+        );
+        assert_eq!(scheduler.tick(start_time + 0.5 - sample), vec![]);
+        assert_eq!(scheduler.tick(start_time + 0.5), vec![start]);
+        assert!(!scheduler.has_fault());
+    }
+
+    #[test]
+    fn test_loop_scheduler_grid_offset() {
+        let mut scheduler = LoopScheduler::new();
+        scheduler.tick(0.0);
+        scheduler.set_grid_offset(0.5);
+        scheduler.start_looping();
+        let start = LoopEvent::StartGrain { duration: 1.0 };
+        assert_eq!(scheduler.tick(0.4), vec![]);
+        assert_eq!(scheduler.tick(0.5), vec![start, LoopEvent::FadeOutDry]);
+        assert_eq!(scheduler.tick(1.0), vec![]);
+        assert_eq!(scheduler.tick(1.5), vec![start]);
+
+        // moving the offset moves the loop with it
+        scheduler.set_grid_offset(0.25);
+        assert_eq!(scheduler.tick(2.25), vec![start]);
+        scheduler.stop_looping();
+        assert_eq!(
+            scheduler.tick(3.25),
+            vec![LoopEvent::StopGrain, LoopEvent::FadeInDry]
+        );
+        assert!(!scheduler.has_fault());
+    }
+
+    #[test]
+    fn test_loop_scheduler_swing() {
+        let mut scheduler = LoopScheduler::new();
+        scheduler.tick(0.0);
+        scheduler.set_grid_interval(0.5);
+        scheduler.set_swing(0.75);
+        scheduler.start_looping();
+
+        // every other loop is pushed a quarter beat late, so the pairs go long, short
+        let long = LoopEvent::StartGrain { duration: 0.75 };
+        let short = LoopEvent::StartGrain { duration: 0.25 };
+        assert_eq!(scheduler.tick(0.0), vec![long, LoopEvent::FadeOutDry]);
+        assert_eq!(scheduler.tick(0.5), vec![]);
+        assert_eq!(scheduler.tick(0.75), vec![short]);
+        assert_eq!(scheduler.tick(1.0), vec![long]);
+        assert_eq!(scheduler.tick(1.75), vec![short]);
+
+        // starting on an odd grid line starts late
+        scheduler.stop_looping();
+        scheduler.tick(2.2);
+        scheduler.start_looping();
+        assert_eq!(scheduler.tick(2.5), vec![]);
+        assert_eq!(scheduler.tick(2.75), vec![short, LoopEvent::FadeOutDry]);
+        assert_eq!(scheduler.tick(3.0), vec![long]);
+        assert!(!scheduler.has_fault());
+    }
+
+    #[test]
+    fn test_loop_scheduler_stop_with_tail() {
+        let mut scheduler = LoopScheduler::new();
+        scheduler.tick(0.0);
+        scheduler.start_looping();
+        scheduler.tick(1.5);
+
+        scheduler.stop_looping_with_tail(1.0);
+        assert!(!scheduler.is_looping());
+        assert_eq!(
+            scheduler.tick(2.0),
+            vec![
+                LoopEvent::StopGrain,
+                LoopEvent::ReverseTail { duration: 1.0 }
+            ]
+        );
+        assert_eq!(scheduler.tick(2.5), vec![]);
+        assert_eq!(scheduler.tick(3.0), vec![LoopEvent::EndTail]);
+        assert_eq!(scheduler.tick(9.0), vec![]);
+
+        // starting again during the tail drops the end of it
+        scheduler.start_looping();
+        scheduler.tick(10.5);
+        scheduler.stop_looping_with_tail(1.0);
+        scheduler.tick(11.5);
+        scheduler.start_looping();
+        assert_eq!(
+            scheduler.tick(12.0),
+            vec![
+                LoopEvent::StartGrain { duration: 1.0 },
+                LoopEvent::FadeOutDry
+            ]
+        );
+    }
+
+    #[test]
+    fn test_loop_scheduler_retrigger() {
+        let mut scheduler = LoopScheduler::new();
+        scheduler.tick(0.0);
+        // nothing to restart yet
+        scheduler.retrigger(0.25);
+        assert_eq!(scheduler.tick(0.25), vec![]);
+
+        scheduler.start_looping();
+        scheduler.tick(1.0);
+        scheduler.tick(1.3);
+        scheduler.retrigger(0.25);
+        assert_eq!(scheduler.tick(1.45), vec![]);
+        assert_eq!(
+            scheduler.tick(1.5),
+            vec![
+                LoopEvent::StopGrain,
+                LoopEvent::StartGrain { duration: 1.0 }
+            ]
+        );
+        // the grid is counted from the restart
+        assert_eq!(scheduler.tick(2.0), vec![]);
+        assert_eq!(
+            scheduler.tick(2.5),
+            vec![LoopEvent::StartGrain { duration: 1.0 }]
+        );
+    }
+
+    #[test]
+    fn test_loop_scheduler_pass_limit() {
+        let mut scheduler = LoopScheduler::new();
+        scheduler.set_pass_limit(Some(2));
+        scheduler.tick(0.0);
+        scheduler.start_looping();
+        assert_eq!(
+            scheduler.tick(1.0),
+            vec![
+                LoopEvent::StartGrain { duration: 1.0 },
+                LoopEvent::FadeOutDry
+            ]
+        );
+        assert_eq!(
+            scheduler.tick(2.0),
+            vec![LoopEvent::StartGrain { duration: 1.0 }]
+        );
+        // the second pass was the last
+        assert_eq!(
+            scheduler.tick(3.0),
+            vec![LoopEvent::StopGrain, LoopEvent::FadeInDry]
+        );
+        // still on, but nothing more plays, even if the length changes
+        scheduler.set_grid_interval(0.5);
+        assert_eq!(scheduler.tick(4.0), vec![]);
+        assert!(scheduler.is_looping());
+
+        // stopping and starting again plays it afresh
+        scheduler.stop_looping();
+        scheduler.tick(4.5);
+        scheduler.start_looping();
+        assert_eq!(
+            scheduler.tick(5.0),
+            vec![
+                LoopEvent::StartGrain { duration: 0.5 },
+                LoopEvent::FadeOutDry
+            ]
+        );
+        assert!(!scheduler.has_fault());
+    }
+
+    #[test]
+    fn test_loop_scheduler_launch_quantization() {
+        let mut scheduler = LoopScheduler::new();
+        let started = vec![
+            LoopEvent::StartGrain { duration: 3.0 },
+            LoopEvent::FadeOutDry,
+        ];
+        scheduler.set_grid_interval(3.0);
+        scheduler.tick(0.5);
+
+        // on the next beat rather than the loop's grid line at 3
+        scheduler.set_launch_quantization(LaunchQuantization::Beat.interval(4.0));
+        scheduler.start_looping();
+        assert_eq!(scheduler.tick(0.9), vec![]);
+        assert_eq!(scheduler.tick(1.0), started);
+        assert_eq!(scheduler.tick(3.5), vec![]);
+        assert_eq!(
+            scheduler.tick(4.0),
+            vec![LoopEvent::StartGrain { duration: 3.0 }]
+        );
+        scheduler.stop_looping();
+        scheduler.tick(6.0);
+
+        // on the next bar
+        scheduler.set_launch_quantization(LaunchQuantization::Bar.interval(4.0));
+        scheduler.tick(6.5);
+        scheduler.start_looping();
+        assert_eq!(scheduler.tick(7.9), vec![]);
+        assert_eq!(scheduler.tick(8.0), started);
+        scheduler.stop_looping();
+        scheduler.tick(11.0);
+
+        // straight away, so on the next tick
+        scheduler.set_launch_quantization(LaunchQuantization::Off.interval(4.0));
+        scheduler.tick(11.25);
+        scheduler.start_looping();
+        assert_eq!(scheduler.tick(11.375), started);
+        assert_eq!(scheduler.tick(14.25), vec![]);
+        assert_eq!(
+            scheduler.tick(14.375),
+            vec![LoopEvent::StartGrain { duration: 3.0 }]
+        );
+        assert!(!scheduler.has_fault());
+    }
+
+    #[test]
+    fn test_loop_scheduler_count_in() {
+        let mut scheduler = LoopScheduler::new();
+        scheduler.set_grid_interval(2.0);
+        scheduler.tick(0.5);
+
+        // counted from the next beat, then on the loop's grid
+        scheduler.set_count_in(2, 1.0);
+        scheduler.start_looping();
+        assert_eq!(scheduler.count_in_remaining(), 2);
+        assert_eq!(scheduler.tick(1.5), vec![]);
+        assert_eq!(scheduler.count_in_remaining(), 2);
+        assert_eq!(scheduler.tick(2.0), vec![]);
+        assert_eq!(scheduler.count_in_remaining(), 1);
+        // the length changing doesn't move the start
+        scheduler.set_grid_interval(1.0);
+        assert_eq!(scheduler.tick(3.0), vec![]);
+        assert_eq!(scheduler.count_in_remaining(), 0);
+        assert_eq!(
+            scheduler.tick(4.0),
+            vec![
+                LoopEvent::StartGrain { duration: 1.0 },
+                LoopEvent::FadeOutDry
+            ]
+        );
+        scheduler.stop_looping();
+        scheduler.tick(6.0);
+
+        // a bar, straight into the loop as the count ends
+        scheduler.set_count_in(1, 4.0);
+        scheduler.set_launch_quantization(LaunchQuantization::Off.interval(4.0));
+        scheduler.start_looping();
+        assert_eq!(scheduler.count_in_remaining(), 1);
+        assert_eq!(scheduler.tick(11.5), vec![]);
+        assert_eq!(
+            scheduler.tick(12.0),
+            vec![
+                LoopEvent::StartGrain { duration: 1.0 },
+                LoopEvent::FadeOutDry
+            ]
+        );
+        scheduler.stop_looping();
+        scheduler.tick(14.0);
+
+        // stopped during the count, the loop never starts
+        scheduler.start_looping();
+        scheduler.tick(16.0);
+        scheduler.stop_looping();
+        assert_eq!(scheduler.count_in_remaining(), 0);
+        assert_eq!(
+            scheduler.tick(17.0),
+            vec![LoopEvent::StopGrain, LoopEvent::FadeInDry]
+        );
+        assert_eq!(scheduler.tick(20.0), vec![]);
+        assert!(!scheduler.has_fault());
+    }
+
+    #[test]
+    fn test_loop_scheduler_length_pattern() {
+        let mut scheduler = LoopScheduler::new();
+        let half = PatternStep {
+            length: 0.5,
+            offset: 0.0,
+        };
+        let back = PatternStep {
+            length: 1.0,
+            offset: 1.0,
+        };
+        scheduler.set_length_pattern(LengthPattern::from_steps(&[half, half, back]));
+        scheduler.tick(0.5);
+        scheduler.start_looping();
+        assert_eq!(
+            scheduler.tick(1.0),
+            vec![
+                LoopEvent::StartGrain { duration: 0.5 },
+                LoopEvent::FadeOutDry
+            ]
+        );
+        assert_eq!(
+            scheduler.tick(1.5),
+            vec![LoopEvent::StartGrain { duration: 0.5 }]
+        );
+        assert_eq!(scheduler.pattern_offset(), 0.0);
+        assert_eq!(
+            scheduler.tick(2.0),
+            vec![LoopEvent::StartGrain { duration: 1.0 }]
+        );
+        assert_eq!(scheduler.pattern_offset(), 1.0);
+        // and round again
+        assert_eq!(scheduler.tick(2.5), vec![]);
+        assert_eq!(
+            scheduler.tick(3.0),
+            vec![LoopEvent::StartGrain { duration: 0.5 }]
+        );
+        assert_eq!(scheduler.pattern_offset(), 0.0);
+
+        // starting again starts the pattern again
+        scheduler.stop_looping();
+        scheduler.tick(3.5);
+        scheduler.tick(4.0);
+        scheduler.start_looping();
+        scheduler.tick(5.0);
+        assert_eq!(
+            scheduler.tick(5.5),
+            vec![LoopEvent::StartGrain { duration: 0.5 }]
+        );
+        assert_eq!(
+            scheduler.tick(6.0),
+            vec![LoopEvent::StartGrain { duration: 1.0 }]
+        );
+        assert!(!scheduler.has_fault());
+    }
+
     #[test]
     fn test_loop_scheduler_shorten_loop() {
         let mut scheduler = LoopScheduler::new();
@@ -262,6 +1181,30 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_loop_scheduler_transition_blend() {
+        let mut scheduler = LoopScheduler::new();
+        scheduler.set_transition_blend(Some(0.125));
+        scheduler.tick(0.0);
+        scheduler.start_looping();
+        scheduler.tick(1.25);
+
+        scheduler.set_grid_interval(0.5);
+        assert_eq!(
+            scheduler.tick(1.5),
+            vec![
+                LoopEvent::StopGrain,
+                LoopEvent::StartGrain { duration: 0.5 },
+                LoopEvent::BlendDry
+            ]
+        );
+        assert_eq!(scheduler.tick(1.625), vec![LoopEvent::UnblendDry]);
+        assert_eq!(
+            scheduler.tick(2.0),
+            vec![LoopEvent::StartGrain { duration: 0.5 }]
+        );
+    }
+
     #[test]
     fn test_loop_scheduler_lengthen_loop_early() {
         // this tests the "back to dry" when the loop is lengthened very
@@ -358,4 +1301,163 @@ mod tests {
         let out8 = scheduler.tick(8.0);
         assert_eq!(out8, vec![LoopEvent::StartGrain { duration: grid2 }]);
     }
+
+    #[test]
+    fn test_loop_scheduler_dotted_to_straight() {
+        // dotted and straight grid lines don't all meet, so a shorter loop can need a legato
+        // grain and a longer one can cut the current loop short
+        let mut scheduler = LoopScheduler::new();
+        let dotted = 1.5;
+        let straight = 1.0;
+
+        scheduler.tick(0.0);
+        scheduler.set_grid_interval(dotted);
+        scheduler.start_looping();
+        scheduler.tick(1.5);
+        let out3 = scheduler.tick(3.0);
+        assert_eq!(out3, vec![LoopEvent::StartGrain { duration: dotted }]);
+
+        // the loop ends at 4.5, half a beat short of the next straight line
+        scheduler.tick(4.2);
+        scheduler.set_grid_interval(straight);
+        let out45 = scheduler.tick(4.5);
+        assert_eq!(
+            out45,
+            vec![LoopEvent::StartLegatoGrain {
+                duration: 0.5,
+                offset_reduction: 0.5
+            }]
+        );
+        let out5 = scheduler.tick(5.0);
+        assert_eq!(out5, vec![LoopEvent::StartGrain { duration: straight }]);
+        scheduler.tick(6.0);
+
+        // the next dotted line at 7.5 comes before this loop ends at 8
+        let out7 = scheduler.tick(7.0);
+        assert_eq!(out7, vec![LoopEvent::StartGrain { duration: straight }]);
+        scheduler.tick(7.2);
+        scheduler.set_grid_interval(dotted);
+        let out75 = scheduler.tick(7.5);
+        assert_eq!(
+            out75,
+            vec![
+                LoopEvent::StopGrain,
+                LoopEvent::StartGrain { duration: dotted }
+            ]
+        );
+        let out9 = scheduler.tick(9.0);
+        assert_eq!(out9, vec![LoopEvent::StartGrain { duration: dotted }]);
+        assert!(!scheduler.has_fault());
+    }
+
+    #[test]
+    fn test_loop_scheduler_loop_count() {
+        let mut scheduler = LoopScheduler::new();
+        scheduler.set_grid_interval(1.0);
+        scheduler.tick(0.5);
+        scheduler.start_looping();
+        assert_eq!(scheduler.time_to_next_loop(), Some(0.5));
+        assert_eq!(scheduler.loop_count(), 0);
+
+        scheduler.tick(1.0);
+        assert_eq!(scheduler.loop_count(), 1);
+        scheduler.tick(2.0);
+        scheduler.tick(2.25);
+        assert_eq!(scheduler.loop_count(), 2);
+        assert_eq!(scheduler.time_to_next_loop(), Some(0.75));
+
+        scheduler.stop_looping();
+        assert_eq!(scheduler.time_to_next_loop(), None);
+    }
+
+    #[test]
+    fn test_loop_scheduler_jump_back() {
+        let mut scheduler = LoopScheduler::new();
+        scheduler.set_grid_interval(1.0);
+        scheduler.tick(4.5);
+        scheduler.start_looping();
+        for beat in [5.0, 6.0, 7.0, 7.5] {
+            scheduler.tick(beat);
+        }
+
+        // the host cycles back to the start of the region, the loop carries on
+        assert_eq!(scheduler.tick(0.5), vec![]);
+        assert_eq!(scheduler.time_to_next_loop(), Some(0.5));
+        assert_eq!(
+            scheduler.tick(1.0),
+            vec![LoopEvent::StartGrain { duration: 1.0 }]
+        );
+        assert_eq!(scheduler.loop_count(), 4);
+
+        // the playhead moves off the grid, the next loop starts on the next grid line
+        scheduler.tick(1.9);
+        assert_eq!(scheduler.tick(0.2), vec![]);
+        assert_eq!(scheduler.next_loop_time(), Some(1.0));
+        assert!(!scheduler.has_fault());
+    }
+
+    #[test]
+    fn test_loop_scheduler_realign_forward() {
+        let mut scheduler = LoopScheduler::new();
+        scheduler.set_grid_interval(1.0);
+        scheduler.tick(0.5);
+        scheduler.start_looping();
+        scheduler.tick(1.0);
+        scheduler.tick(1.5);
+
+        // the transport restarts further on, the next loop is on the grid from there
+        scheduler.realign(10.25);
+        assert_eq!(scheduler.next_loop_time(), Some(11.0));
+        assert_eq!(scheduler.tick(10.25), vec![]);
+        assert_eq!(
+            scheduler.tick(11.0),
+            vec![LoopEvent::StartGrain { duration: 1.0 }]
+        );
+    }
+
+    #[test]
+    fn test_loop_scheduler_dry_on_grid() {
+        let mut scheduler = LoopScheduler::new();
+        scheduler.set_grid_interval(1.0);
+        scheduler.tick(0.5);
+        scheduler.start_looping();
+        scheduler.tick(1.0);
+        scheduler.tick(1.5);
+
+        scheduler.reset();
+        scheduler.fade_in_dry_on_grid(4.0);
+        assert_eq!(scheduler.tick(3.0), vec![]);
+        assert_eq!(scheduler.tick(4.0), vec![LoopEvent::FadeInDry]);
+
+        // looping again before the dry is back keeps it out
+        scheduler.fade_in_dry_on_grid(4.0);
+        scheduler.start_looping();
+        assert_eq!(
+            scheduler.tick(5.0),
+            vec![
+                LoopEvent::StartGrain { duration: 1.0 },
+                LoopEvent::FadeOutDry
+            ]
+        );
+        assert!(!scheduler.tick(8.0).contains(&LoopEvent::FadeInDry));
+        assert!(!scheduler.has_fault());
+    }
+
+    #[test]
+    fn test_loop_scheduler_fault() {
+        let mut scheduler = LoopScheduler::new();
+        scheduler.tick(0.0);
+        scheduler.start_looping();
+        assert!(!scheduler.has_fault());
+
+        // starting twice is a fault, not a panic
+        scheduler.start_looping();
+        assert!(scheduler.has_fault());
+
+        scheduler.reset();
+        assert!(!scheduler.has_fault());
+
+        scheduler.stop_looping();
+        assert!(scheduler.has_fault());
+    }
 }
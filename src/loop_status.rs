@@ -0,0 +1,80 @@
+use crate::region_fit::RegionFit;
+use atomic_float::AtomicF32;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering};
+
+// what the looper is doing, as one consistent set of values
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct LoopStatusSnapshot {
+    pub is_looping: bool,
+    pub repeat_count: u32,
+    pub loop_length_seconds: f32,
+    // in beats, or seconds when free running, negative when not looping
+    pub time_to_next_loop: f32,
+    // the counts left before the loop starts, zero when not counting in
+    pub count_in: u32,
+    // whether the last grain had to be changed to fit in the buffers
+    pub region_fit: RegionFit,
+}
+
+// the looper state published once per block, for reading off the audio thread
+// nih_plug has no output parameters, so an editor or a host bridge reads these instead
+// each value is atomic, but a reader can see values from two neighbouring blocks
+#[derive(Default)]
+pub struct LoopStatus {
+    is_looping: AtomicBool,
+    repeat_count: AtomicU32,
+    loop_length_seconds: AtomicF32,
+    time_to_next_loop: AtomicF32,
+    count_in: AtomicU32,
+    region_fit: AtomicU8,
+}
+
+#[allow(dead_code)]
+impl LoopStatus {
+    pub fn publish(&self, snapshot: LoopStatusSnapshot) {
+        self.is_looping
+            .store(snapshot.is_looping, Ordering::Relaxed);
+        self.repeat_count
+            .store(snapshot.repeat_count, Ordering::Relaxed);
+        self.loop_length_seconds
+            .store(snapshot.loop_length_seconds, Ordering::Relaxed);
+        self.time_to_next_loop
+            .store(snapshot.time_to_next_loop, Ordering::Relaxed);
+        self.count_in.store(snapshot.count_in, Ordering::Relaxed);
+        self.region_fit
+            .store(snapshot.region_fit.to_u8(), Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> LoopStatusSnapshot {
+        LoopStatusSnapshot {
+            is_looping: self.is_looping.load(Ordering::Relaxed),
+            repeat_count: self.repeat_count.load(Ordering::Relaxed),
+            loop_length_seconds: self.loop_length_seconds.load(Ordering::Relaxed),
+            time_to_next_loop: self.time_to_next_loop.load(Ordering::Relaxed),
+            count_in: self.count_in.load(Ordering::Relaxed),
+            region_fit: RegionFit::from_u8(self.region_fit.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_loop_status_round_trip() {
+        let status = LoopStatus::default();
+        assert_eq!(status.snapshot(), LoopStatusSnapshot::default());
+
+        let snapshot = LoopStatusSnapshot {
+            is_looping: true,
+            repeat_count: 3,
+            loop_length_seconds: 0.5,
+            time_to_next_loop: 0.25,
+            count_in: 2,
+            region_fit: RegionFit::Moved,
+        };
+        status.publish(snapshot);
+        assert_eq!(status.snapshot(), snapshot);
+    }
+}
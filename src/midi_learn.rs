@@ -0,0 +1,224 @@
+use crate::soft_takeover::SoftTakeover;
+use nih_plug::prelude::{Enum, Param};
+
+// maps midi ccs onto a few params, each cc learned by arming and moving a controller
+// as with the scrub cc, a cc takes over its param once it moves, until the param itself is moved
+// a cc on a continuous param only takes over once it has come up to the value playing, see
+// SoftTakeover, so a controller left somewhere else doesn't jump the loop
+// the values are normalized, so a cc sweeps the param's whole range
+
+pub const NUM_TARGETS: usize = 4;
+// what a target's cc is while nothing is mapped to it, midi ccs only go up to 127
+pub const UNMAPPED: u8 = u8::MAX;
+
+#[derive(Enum, Debug, Clone, Copy, PartialEq)]
+pub enum LearnTarget {
+    #[id = "loop"]
+    #[name = "Loop"]
+    Loop,
+    #[id = "length"]
+    #[name = "Length"]
+    Length,
+    #[id = "reverse"]
+    #[name = "Reverse"]
+    Reverse,
+    #[id = "speed"]
+    #[name = "Speed"]
+    Speed,
+}
+
+pub const LEARN_TARGETS: [LearnTarget; NUM_TARGETS] = [
+    LearnTarget::Loop,
+    LearnTarget::Length,
+    LearnTarget::Reverse,
+    LearnTarget::Speed,
+];
+
+impl LearnTarget {
+    pub fn index(&self) -> usize {
+        match self {
+            LearnTarget::Loop => 0,
+            LearnTarget::Length => 1,
+            LearnTarget::Reverse => 2,
+            LearnTarget::Speed => 3,
+        }
+    }
+
+    // switches are flipped straight over by a button, only sweeps need picking up
+    pub fn is_continuous(&self) -> bool {
+        matches!(self, LearnTarget::Length | LearnTarget::Speed)
+    }
+}
+
+pub struct MidiLearn {
+    learn_armed: bool,
+    learn_enabled: bool,
+    // None while the param is in control
+    values: [Option<f32>; NUM_TARGETS],
+    last_param_values: [Option<f32>; NUM_TARGETS],
+    takeovers: [SoftTakeover; NUM_TARGETS],
+}
+
+#[allow(dead_code)]
+impl MidiLearn {
+    pub fn new() -> MidiLearn {
+        MidiLearn {
+            learn_armed: false,
+            learn_enabled: false,
+            values: [None; NUM_TARGETS],
+            last_param_values: [None; NUM_TARGETS],
+            takeovers: [(); NUM_TARGETS].map(|_| SoftTakeover::new()),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.values = [None; NUM_TARGETS];
+        self.last_param_values = [None; NUM_TARGETS];
+        for takeover in self.takeovers.iter_mut() {
+            takeover.reset();
+        }
+    }
+
+    // turning learning on arms it, the next cc received is mapped to the target
+    pub fn set_learning(&mut self, enabled: bool) {
+        if enabled && !self.learn_enabled {
+            self.learn_armed = true;
+        }
+        if !enabled {
+            self.learn_armed = false;
+        }
+        self.learn_enabled = enabled;
+    }
+
+    pub fn is_learning(&self) -> bool {
+        self.learn_armed
+    }
+
+    // mapping holds the cc of each target
+    // returns the cc to store for the target if this message was learned
+    pub fn handle_cc(
+        &mut self,
+        mapping: &[u8; NUM_TARGETS],
+        target: LearnTarget,
+        cc: u8,
+        value: f32,
+    ) -> Option<u8> {
+        if self.learn_armed {
+            self.learn_armed = false;
+            // a new controller has to pick the param up afresh
+            self.takeovers[target.index()].reset();
+            self.take_over(target, value);
+            return Some(cc);
+        }
+        for target in LEARN_TARGETS {
+            if mapping[target.index()] == cc {
+                self.take_over(target, value);
+            }
+        }
+        None
+    }
+
+    // the cc only moves a continuous target once it has picked up the value playing
+    fn take_over(&mut self, target: LearnTarget, value: f32) {
+        let index = target.index();
+        // with nothing playing yet, there is nothing to jump from
+        let current = self.values[index].or(self.last_param_values[index]);
+        self.values[index] = match current {
+            Some(current) if target.is_continuous() => self.takeovers[index]
+                .process(value, current)
+                .or(self.values[index]),
+            _ => Some(value),
+        };
+    }
+
+    // call once per block with the target param, normalized
+    // returns the normalized value to use
+    pub fn process(&mut self, target: LearnTarget, param_value: f32) -> f32 {
+        let index = target.index();
+        if let Some(last) = self.last_param_values[index] {
+            if last != param_value {
+                self.values[index] = None;
+                self.takeovers[index].reset();
+            }
+        }
+        self.last_param_values[index] = Some(param_value);
+        self.values[index].unwrap_or(param_value)
+    }
+
+    // as process, but the param's own value, whatever its type
+    pub fn value<P: Param>(&mut self, target: LearnTarget, param: &P) -> P::Plain {
+        param.preview_plain(self.process(target, param.modulated_normalized_value()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_midi_learn() {
+        let mut learn = MidiLearn::new();
+        let mut mapping = [UNMAPPED; NUM_TARGETS];
+        assert_eq!(learn.process(LearnTarget::Speed, 0.5), 0.5);
+        // nothing is mapped yet
+        assert_eq!(learn.handle_cc(&mapping, LearnTarget::Speed, 20, 1.0), None);
+        assert_eq!(learn.process(LearnTarget::Speed, 0.5), 0.5);
+
+        learn.set_learning(true);
+        assert!(learn.is_learning());
+        let learned = learn.handle_cc(&mapping, LearnTarget::Speed, 20, 0.75);
+        assert_eq!(learned, Some(20));
+        mapping[LearnTarget::Speed.index()] = 20;
+        assert!(!learn.is_learning());
+        // the controller is still to pick the param up
+        assert_eq!(learn.process(LearnTarget::Speed, 0.5), 0.5);
+
+        // only mapped ccs move their target
+        learn.handle_cc(&mapping, LearnTarget::Loop, 20, 0.25);
+        learn.handle_cc(&mapping, LearnTarget::Loop, 21, 1.0);
+        assert_eq!(learn.process(LearnTarget::Speed, 0.5), 0.25);
+        assert_eq!(learn.process(LearnTarget::Loop, 0.0), 0.0);
+    }
+
+    #[test]
+    fn test_midi_learn_param_takes_back() {
+        let mut learn = MidiLearn::new();
+        let mapping = [7; NUM_TARGETS];
+        learn.process(LearnTarget::Length, 0.5);
+        learn.handle_cc(&mapping, LearnTarget::Loop, 7, 0.4);
+        learn.handle_cc(&mapping, LearnTarget::Loop, 7, 1.0);
+        assert_eq!(learn.process(LearnTarget::Length, 0.5), 1.0);
+        assert_eq!(learn.process(LearnTarget::Length, 0.4), 0.4);
+        assert_eq!(learn.process(LearnTarget::Length, 0.4), 0.4);
+    }
+
+    #[test]
+    fn test_midi_learn_soft_takeover() {
+        let mut learn = MidiLearn::new();
+        let mut mapping = [UNMAPPED; NUM_TARGETS];
+        mapping[LearnTarget::Length.index()] = 7;
+        mapping[LearnTarget::Reverse.index()] = 8;
+        learn.process(LearnTarget::Length, 0.5);
+        learn.process(LearnTarget::Reverse, 0.0);
+
+        // a controller left far from the length doesn't move it
+        learn.handle_cc(&mapping, LearnTarget::Loop, 7, 0.9);
+        assert_eq!(learn.process(LearnTarget::Length, 0.5), 0.5);
+        learn.handle_cc(&mapping, LearnTarget::Loop, 7, 0.7);
+        assert_eq!(learn.process(LearnTarget::Length, 0.5), 0.5);
+        // until it comes past it
+        learn.handle_cc(&mapping, LearnTarget::Loop, 7, 0.3);
+        assert_eq!(learn.process(LearnTarget::Length, 0.5), 0.3);
+        learn.handle_cc(&mapping, LearnTarget::Loop, 7, 0.9);
+        assert_eq!(learn.process(LearnTarget::Length, 0.5), 0.9);
+
+        // once the param takes back control, it has to be picked up again
+        assert_eq!(learn.process(LearnTarget::Length, 0.2), 0.2);
+        learn.handle_cc(&mapping, LearnTarget::Loop, 7, 0.8);
+        assert_eq!(learn.process(LearnTarget::Length, 0.2), 0.2);
+
+        // a switch is flipped straight over
+        learn.handle_cc(&mapping, LearnTarget::Loop, 8, 1.0);
+        assert_eq!(learn.process(LearnTarget::Reverse, 0.0), 1.0);
+    }
+}
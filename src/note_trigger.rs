@@ -0,0 +1,159 @@
+use nih_plug::prelude::Enum;
+
+#[derive(Enum, Debug, Clone, Copy, PartialEq)]
+pub enum NoteTriggerMode {
+    // each note on toggles the loop
+    #[id = "latch"]
+    #[name = "Latch"]
+    Latch,
+    // loops while the note is held
+    #[id = "momentary"]
+    #[name = "Momentary"]
+    Momentary,
+}
+
+// how the dry comes back when a momentary trigger is let go
+#[derive(Enum, Debug, Clone, Copy, PartialEq)]
+pub enum ReleaseDry {
+    // the loop plays to the end, then the dry fades in as usual
+    #[id = "with-loop"]
+    #[name = "With Loop"]
+    WithLoop,
+    #[id = "instant"]
+    #[name = "Instant"]
+    Instant,
+    // over the fade time
+    #[id = "fade"]
+    #[name = "Fade"]
+    Fade,
+    // stays ducked until the next bar line
+    #[id = "next-bar"]
+    #[name = "Next Bar"]
+    NextBar,
+}
+
+// turns midi notes on a single key into a loop on/off state
+pub struct NoteTrigger {
+    note: u8,
+    mode: NoteTriggerMode,
+    held: bool,
+    latched: bool,
+    // a momentary key was let go since we last asked
+    released: bool,
+}
+
+#[allow(dead_code)]
+impl NoteTrigger {
+    pub fn new(note: u8, mode: NoteTriggerMode) -> NoteTrigger {
+        NoteTrigger {
+            note,
+            mode,
+            held: false,
+            latched: false,
+            released: false,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.held = false;
+        self.latched = false;
+        self.released = false;
+    }
+
+    // changing the key lets go of it, as we won't see its note off
+    pub fn set_note(&mut self, note: u8) {
+        if note != self.note {
+            self.note = note;
+            self.held = false;
+        }
+    }
+
+    pub fn set_mode(&mut self, mode: NoteTriggerMode) {
+        if mode != self.mode {
+            self.mode = mode;
+            self.reset();
+        }
+    }
+
+    pub fn note_on(&mut self, note: u8) {
+        if note != self.note {
+            return;
+        }
+        self.held = true;
+        if self.mode == NoteTriggerMode::Latch {
+            self.latched = !self.latched;
+        }
+    }
+
+    pub fn note_off(&mut self, note: u8) {
+        if note == self.note {
+            if self.held && self.mode == NoteTriggerMode::Momentary {
+                self.released = true;
+            }
+            self.held = false;
+        }
+    }
+
+    // true once after a momentary key is let go
+    pub fn take_release(&mut self) -> bool {
+        std::mem::take(&mut self.released)
+    }
+
+    // whether the notes are asking for the loop to be on
+    pub fn is_active(&self) -> bool {
+        match self.mode {
+            NoteTriggerMode::Latch => self.latched,
+            NoteTriggerMode::Momentary => self.held,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_note_trigger_latch() {
+        let mut trigger = NoteTrigger::new(36, NoteTriggerMode::Latch);
+        trigger.note_on(37);
+        assert!(!trigger.is_active());
+        trigger.note_on(36);
+        trigger.note_off(36);
+        assert!(trigger.is_active());
+        trigger.note_on(36);
+        assert!(!trigger.is_active());
+    }
+
+    #[test]
+    fn test_note_trigger_momentary() {
+        let mut trigger = NoteTrigger::new(36, NoteTriggerMode::Momentary);
+        trigger.note_on(36);
+        assert!(trigger.is_active());
+        trigger.note_off(37);
+        assert!(trigger.is_active());
+        trigger.note_off(36);
+        assert!(!trigger.is_active());
+
+        // moving the key while it is held lets go
+        trigger.note_on(36);
+        trigger.set_note(38);
+        assert!(!trigger.is_active());
+    }
+
+    #[test]
+    fn test_note_trigger_release() {
+        let mut trigger = NoteTrigger::new(36, NoteTriggerMode::Momentary);
+        trigger.note_off(36);
+        assert!(!trigger.take_release());
+        trigger.note_on(36);
+        trigger.note_off(36);
+        assert!(trigger.take_release());
+        assert!(!trigger.take_release());
+
+        // latched notes are never released
+        trigger.set_mode(NoteTriggerMode::Latch);
+        trigger.note_on(36);
+        trigger.note_off(36);
+        assert!(!trigger.take_release());
+    }
+}
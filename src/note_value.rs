@@ -0,0 +1,111 @@
+use nih_plug::prelude::Enum;
+
+// musical lengths that the loop grid can be set to
+#[derive(Enum, Debug, Clone, Copy, PartialEq)]
+pub enum NoteValue {
+    #[id = "1/32"]
+    #[name = "1/32"]
+    ThirtySecond,
+    #[id = "1/16t"]
+    #[name = "1/16T"]
+    SixteenthTriplet,
+    #[id = "1/16"]
+    #[name = "1/16"]
+    Sixteenth,
+    #[id = "1/8"]
+    #[name = "1/8"]
+    Eighth,
+    #[id = "1/4"]
+    #[name = "1/4"]
+    Quarter,
+    #[id = "1/2"]
+    #[name = "1/2"]
+    Half,
+    #[id = "bar"]
+    #[name = "1 bar"]
+    Bar,
+}
+
+// stretches a note value into a triplet or dotted one, e.g. a triplet quarter is a third of a half
+#[derive(Enum, Debug, Clone, Copy, PartialEq)]
+pub enum NoteFeel {
+    #[id = "straight"]
+    #[name = "Straight"]
+    Straight,
+    #[id = "triplet"]
+    #[name = "Triplet"]
+    Triplet,
+    #[id = "dotted"]
+    #[name = "Dotted"]
+    Dotted,
+}
+
+impl NoteFeel {
+    pub fn ratio(&self) -> f32 {
+        match self {
+            NoteFeel::Straight => 1.0,
+            NoteFeel::Triplet => 2.0 / 3.0,
+            NoteFeel::Dotted => 3.0 / 2.0,
+        }
+    }
+}
+
+impl NoteValue {
+    // length in beats (quarter notes), a bar is assumed to be 4/4
+    pub fn beats(&self) -> f32 {
+        match self {
+            NoteValue::ThirtySecond => 1.0 / 8.0,
+            NoteValue::SixteenthTriplet => 1.0 / 6.0,
+            NoteValue::Sixteenth => 1.0 / 4.0,
+            NoteValue::Eighth => 1.0 / 2.0,
+            NoteValue::Quarter => 1.0,
+            NoteValue::Half => 2.0,
+            NoteValue::Bar => 4.0,
+        }
+    }
+
+    pub fn beats_with_feel(&self, feel: NoteFeel) -> f32 {
+        self.beats() * feel.ratio()
+    }
+
+    // rounds a time in beats to the nearest multiple of this
+    pub fn snap(&self, beats: f32) -> f32 {
+        (beats / self.beats()).round() * self.beats()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_note_value_beats() {
+        assert_eq!(NoteValue::Quarter.beats(), 1.0);
+        assert_eq!(NoteValue::Bar.beats(), 4.0);
+        assert_eq!(NoteValue::ThirtySecond.beats(), 0.125);
+        // three sixteenth triplets fit in an eighth
+        assert_eq!(
+            NoteValue::SixteenthTriplet.beats() * 3.0,
+            NoteValue::Eighth.beats()
+        );
+    }
+
+    #[test]
+    fn test_note_value_snap() {
+        assert_eq!(NoteValue::Sixteenth.snap(0.3), 0.25);
+        assert_eq!(NoteValue::Sixteenth.snap(0.4), 0.5);
+        assert_eq!(NoteValue::Quarter.snap(1.49), 1.0);
+        assert_eq!(NoteValue::Eighth.snap(0.0), 0.0);
+    }
+
+    #[test]
+    fn test_note_value_feel() {
+        assert_eq!(NoteValue::Quarter.beats_with_feel(NoteFeel::Straight), 1.0);
+        assert_eq!(NoteValue::Quarter.beats_with_feel(NoteFeel::Dotted), 1.5);
+        // three triplet quarters fit in a half
+        assert_eq!(
+            NoteValue::Quarter.beats_with_feel(NoteFeel::Triplet) * 3.0,
+            NoteValue::Half.beats()
+        );
+    }
+}
@@ -0,0 +1,70 @@
+use crate::audio_frame::{AudioFrame, MAX_CHANNELS};
+use nih_plug::prelude::Enum;
+
+// what ends up on the main outputs
+#[derive(Enum, Debug, Clone, Copy, PartialEq)]
+pub enum OutputMode {
+    #[id = "mix"]
+    #[name = "Mix"]
+    Mix,
+    #[id = "wet"]
+    #[name = "Wet Only"]
+    WetOnly,
+    #[id = "dry"]
+    #[name = "Dry Only"]
+    DryOnly,
+    // for checking the loop against the input, e.g. for phase issues
+    // with more than two channels, the wet is on the first and the dry on the rest
+    #[id = "wet-l-dry-r"]
+    #[name = "Wet L + Dry R"]
+    WetLeftDryRight,
+}
+
+impl OutputMode {
+    pub fn apply<T: AudioFrame>(&self, wet: T, dry: T) -> T {
+        match self {
+            OutputMode::Mix => wet + dry,
+            OutputMode::WetOnly => wet,
+            OutputMode::DryOnly => dry,
+            OutputMode::WetLeftDryRight => {
+                let num_channels = T::NUM_CHANNELS;
+                let mut wet_channels = [0.0; MAX_CHANNELS];
+                let mut channels = [0.0; MAX_CHANNELS];
+                wet.write_channels(&mut wet_channels[..num_channels]);
+                dry.write_channels(&mut channels[..num_channels]);
+                channels[0] = wet_channels[0];
+                T::from_channels(&channels[..num_channels])
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame::Frame;
+    use crate::stereo_pair::StereoPair;
+
+    #[test]
+    fn test_output_mode() {
+        let wet = StereoPair::new(1.0, 2.0);
+        let dry = StereoPair::new(10.0, 20.0);
+        assert_eq!(OutputMode::Mix.apply(wet, dry), StereoPair::new(11.0, 22.0));
+        assert_eq!(OutputMode::WetOnly.apply(wet, dry), wet);
+        assert_eq!(OutputMode::DryOnly.apply(wet, dry), dry);
+        assert_eq!(
+            OutputMode::WetLeftDryRight.apply(wet, dry),
+            StereoPair::new(1.0, 20.0)
+        );
+    }
+
+    #[test]
+    fn test_output_mode_frame() {
+        let wet = Frame([1.0, 2.0, 3.0, 4.0]);
+        let dry = Frame([10.0, 20.0, 30.0, 40.0]);
+        assert_eq!(
+            OutputMode::WetLeftDryRight.apply(wet, dry),
+            Frame([1.0, 20.0, 30.0, 40.0])
+        );
+    }
+}
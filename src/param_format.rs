@@ -0,0 +1,113 @@
+use std::sync::Arc;
+
+type ValueToString = Arc<dyn Fn(f32) -> String + Send + Sync>;
+type StringToValue = Arc<dyn Fn(&str) -> Option<f32> + Send + Sync>;
+
+// value to string and string to value conversions for params that read better than their raw values
+// the loop length needs none, its names already read as "1/8" and can be typed in as they are
+
+// seconds shown as whole milliseconds, e.g. "20 ms"
+pub fn v2s_seconds_to_ms() -> ValueToString {
+    Arc::new(|value| format!("{:.0} ms", value * 1000.0))
+}
+
+// "20", "20 ms" and "0.02 s" are all 20ms
+pub fn s2v_ms_to_seconds() -> StringToValue {
+    Arc::new(|string| {
+        let string = string.trim().to_lowercase();
+        if let Some(ms) = string.strip_suffix("ms") {
+            return ms.trim().parse::<f32>().ok().map(|ms| ms / 1000.0);
+        }
+        if let Some(seconds) = string.strip_suffix('s') {
+            return seconds.trim().parse().ok();
+        }
+        string.parse::<f32>().ok().map(|ms| ms / 1000.0)
+    })
+}
+
+// sixteenth notes, four to the beat
+const SIXTEENTHS_PER_BEAT: f32 = 4.0;
+
+// beats shown as whole beats and sixteenth notes, e.g. "1 + 1/16", or "2/16" under a beat
+// a part of a sixteenth shows to two places, e.g. "0.5/16"
+pub fn v2s_beats_to_sixteenths() -> ValueToString {
+    Arc::new(|value| {
+        let sign = if value < 0.0 { "-" } else { "" };
+        let beats = value.abs().trunc();
+        let sixteenths = format!("{:.2}", (value.abs() - beats) * SIXTEENTHS_PER_BEAT);
+        let sixteenths = sixteenths.trim_end_matches('0').trim_end_matches('.');
+        if beats == 0.0 {
+            format!("{}{}/16", sign, sixteenths)
+        } else {
+            format!("{}{} + {}/16", sign, beats, sixteenths)
+        }
+    })
+}
+
+// accepts what's shown, a note value like "1/8", or plain beats like "1.5"
+pub fn s2v_sixteenths_to_beats() -> StringToValue {
+    Arc::new(|string| {
+        let string = string.trim().trim_end_matches("beats").trim();
+        let (sign, string) = match string.strip_prefix('-') {
+            Some(rest) => (-1.0, rest.trim()),
+            None => (1.0, string),
+        };
+        let mut beats = 0.0;
+        for term in string.split('+') {
+            beats += parse_beats(term.trim())?;
+        }
+        Some(sign * beats)
+    })
+}
+
+// "1/4" is a quarter note, a beat, so "2/16" is two sixteenth notes, half a beat
+fn parse_beats(term: &str) -> Option<f32> {
+    match term.split_once('/') {
+        Some((numerator, denominator)) => {
+            let numerator: f32 = numerator.trim().parse().ok()?;
+            let denominator: f32 = denominator.trim().parse().ok()?;
+            // a whole note is sixteen sixteenths
+            (denominator != 0.0).then(|| numerator / denominator * 16.0 / SIXTEENTHS_PER_BEAT)
+        }
+        None => term.parse().ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_ms_format() {
+        assert_eq!(v2s_seconds_to_ms()(0.02), "20 ms");
+        assert_eq!(v2s_seconds_to_ms()(0.0055), "6 ms");
+        let parse = s2v_ms_to_seconds();
+        assert_eq!(parse("20"), Some(0.02));
+        assert_eq!(parse(" 20 ms"), Some(0.02));
+        assert_eq!(parse("0.05 s"), Some(0.05));
+        assert_eq!(parse("fast"), None);
+    }
+
+    #[test]
+    fn test_sixteenths_format() {
+        let format = v2s_beats_to_sixteenths();
+        // an eighth note is two sixteenths
+        assert_eq!(format(0.5), "2/16");
+        assert_eq!(format(1.25), "1 + 1/16");
+        assert_eq!(format(-0.125), "-0.5/16");
+        assert_eq!(format(2.0), "2 + 0/16");
+
+        let parse = s2v_sixteenths_to_beats();
+        assert_eq!(parse("1/4"), Some(1.0));
+        assert_eq!(parse("1/8"), Some(0.5));
+        assert_eq!(parse("1 + 1/16"), Some(1.25));
+        assert_eq!(parse("0.5 beats"), Some(0.5));
+        assert_eq!(parse("-2/16"), Some(-0.5));
+        assert_eq!(parse(&format(-0.125)), Some(-0.125));
+        assert_eq!(parse("1/0"), None);
+        assert_eq!(parse("a/16"), None);
+        // what's shown reads back as what it was
+        assert_abs_diff_eq!(parse(&format(0.3)).unwrap(), 0.3, epsilon = 1e-3);
+    }
+}
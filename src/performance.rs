@@ -0,0 +1,189 @@
+// keeps the last few bars of what the loop was told to do, so it can be played again
+// every time the loop goes on or off, or its length or offset moves, the new state is logged
+// with its beat time, and a replay plays back the bars before the press from the next bar line
+// the log is a ring, so the oldest changes go once it is full
+
+// how many bars are played back
+pub const PERFORMANCE_BARS: f32 = 4.0;
+// a few bars of busy scrubbing, a change at most every block
+const LOG_CAPACITY: usize = 1024;
+
+// the loop commands that are logged
+// length and offset are in beats when synced, and seconds when free running
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoopState {
+    pub on: bool,
+    pub length: f32,
+    pub offset: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Replay {
+    // when the replay starts, and how far the log is moved forward to get there
+    start: f64,
+    shift: f64,
+    // the end of the logged bars being replayed
+    end: f64,
+}
+
+pub struct Performance {
+    events: Vec<(f64, LoopState)>,
+    // where the oldest event is in the ring
+    first: usize,
+    replay: Option<Replay>,
+}
+
+#[allow(dead_code)]
+impl Performance {
+    pub fn new() -> Performance {
+        Performance {
+            events: Vec::with_capacity(LOG_CAPACITY),
+            first: 0,
+            replay: None,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.events.clear();
+        self.first = 0;
+        self.replay = None;
+    }
+
+    fn event(&self, index: usize) -> (f64, LoopState) {
+        self.events[(self.first + index) % self.events.len()]
+    }
+
+    fn last(&self) -> Option<(f64, LoopState)> {
+        (!self.events.is_empty()).then(|| self.event(self.events.len() - 1))
+    }
+
+    // logs the state if it has changed, nothing is logged while replaying
+    pub fn record(&mut self, time: f64, state: LoopState) {
+        if self.replay.is_some() {
+            return;
+        }
+        match self.last() {
+            Some((_, last)) if last == state => return,
+            // the host jumped back, so the log no longer makes sense
+            Some((last_time, _)) if time < last_time => self.reset(),
+            _ => (),
+        }
+        if self.events.len() < LOG_CAPACITY {
+            self.events.push((time, state));
+        } else {
+            self.events[self.first] = (time, state);
+            self.first = (self.first + 1) % LOG_CAPACITY;
+        }
+    }
+
+    // replays the bars before the one that time is in, from the next bar line
+    // pressing again while replaying stops it
+    pub fn toggle_replay(&mut self, time: f64, bar_length: f32) {
+        if self.replay.take().is_some() {
+            return;
+        }
+        let bar_length = bar_length as f64;
+        let end = (time / bar_length).floor() * bar_length;
+        let start = (time / bar_length).ceil() * bar_length;
+        let length = PERFORMANCE_BARS as f64 * bar_length;
+        self.replay = Some(Replay {
+            start,
+            shift: start - (end - length),
+            end,
+        });
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        self.replay.is_some()
+    }
+
+    // what the replay says the loop should be doing at time, if anything
+    // before the replay starts and after it ends this is none, and the params are in charge
+    pub fn replayed_state(&mut self, time: f64) -> Option<LoopState> {
+        let replay = self.replay?;
+        if time < replay.start {
+            return None;
+        }
+        let logged_time = time - replay.shift;
+        if logged_time >= replay.end {
+            self.replay = None;
+            return None;
+        }
+        (0..self.events.len())
+            .map(|index| self.event(index))
+            .take_while(|(event_time, _)| *event_time <= logged_time)
+            .last()
+            .map(|(_, state)| state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(on: bool, offset: f32) -> LoopState {
+        LoopState {
+            on,
+            length: 1.0,
+            offset,
+        }
+    }
+
+    #[test]
+    fn test_performance_replay() {
+        let mut performance = Performance::new();
+        performance.record(0.0, state(false, 0.0));
+        performance.record(5.0, state(true, 0.0));
+        performance.record(6.0, state(true, 0.0));
+        performance.record(9.5, state(true, 0.5));
+        performance.record(17.0, state(false, 0.5));
+
+        // pressed in the fifth bar, so bars one to four replay from the sixth
+        performance.toggle_replay(18.0, 4.0);
+        assert!(performance.is_replaying());
+        assert_eq!(performance.replayed_state(19.0), None);
+        assert_eq!(performance.replayed_state(20.0), Some(state(false, 0.0)));
+        // nothing is logged while replaying
+        performance.record(20.0, state(true, 0.25));
+        assert_eq!(performance.replayed_state(25.0), Some(state(true, 0.0)));
+        assert_eq!(performance.replayed_state(29.6), Some(state(true, 0.5)));
+        assert_eq!(performance.replayed_state(35.9), Some(state(true, 0.5)));
+        // the fifth bar isn't part of the replay
+        assert_eq!(performance.replayed_state(36.0), None);
+        assert!(!performance.is_replaying());
+    }
+
+    #[test]
+    fn test_performance_replay_stops() {
+        let mut performance = Performance::new();
+        performance.record(0.0, state(true, 0.0));
+        performance.toggle_replay(16.0, 4.0);
+        assert_eq!(performance.replayed_state(16.0), Some(state(true, 0.0)));
+        performance.toggle_replay(17.0, 4.0);
+        assert_eq!(performance.replayed_state(17.0), None);
+
+        // jumping back clears the log
+        performance.record(12.0, state(false, 0.0));
+        performance.record(4.0, state(false, 0.5));
+        performance.toggle_replay(16.0, 4.0);
+        assert_eq!(performance.replayed_state(16.0), None);
+        assert_eq!(performance.replayed_state(20.0), Some(state(false, 0.5)));
+    }
+
+    #[test]
+    fn test_performance_log_is_a_ring() {
+        let mut performance = Performance::new();
+        for i in 0..LOG_CAPACITY + 10 {
+            performance.record(i as f64 * 0.01, state(true, i as f32));
+        }
+        assert_eq!(performance.events.len(), LOG_CAPACITY);
+        assert_eq!(performance.event(0).1, state(true, 10.0));
+        assert_eq!(
+            performance.last(),
+            Some((
+                (LOG_CAPACITY + 9) as f64 * 0.01,
+                state(true, (LOG_CAPACITY + 9) as f32)
+            ))
+        );
+    }
+}
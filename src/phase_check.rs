@@ -0,0 +1,150 @@
+use crate::host_sim::{channels, HostSim};
+use std::f32::consts::TAU;
+
+// a diagnostic for the loop's signal path, run offline on a plugin of its own the way a host runs
+// it, so the loop goes through the same interleaving and latency as it would live
+// at the default settings, with the offset a whole loop back, the loop of a tone that repeats
+// exactly within it lines up with the tone, give or take the fade it leads by, so taking one
+// from the other should leave next to nothing
+// shallow cancellation means the path is off, e.g. channels mixed up or a stray sample of delay,
+// and a loop that correlates negatively with the tone has been flipped
+
+// each channel gets its own harmonic of this, so swapped channels don't cancel
+const TONE_PERIOD_SAMPLES: usize = 64;
+// the loop is a beat, at whatever tempo makes it this many periods long
+// a power of two, so every sample's beat time is exact and the grid can't round either way
+const BEAT_PERIODS: usize = 256;
+// how many beats go by before the loop is triggered, and how many are measured after the first
+const LEAD_IN_BEATS: usize = 2;
+const MEASURED_BEATS: usize = 4;
+// as a host might hand them over, not a whole number of periods so blocks can't hide a slip
+const BLOCK_SIZE: usize = 500;
+// a path that works cancels far below this, a broken one nowhere near it
+pub const PASS_DEPTH_DB: f32 = -60.0;
+
+// how well a signal cancels against a reference, across all channels
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Cancellation {
+    reference_power: f32,
+    residual_power: f32,
+    correlation: f32,
+}
+
+#[allow(dead_code)]
+impl Cancellation {
+    pub fn add(&mut self, reference: f32, signal: f32) {
+        self.reference_power += reference * reference;
+        self.residual_power += (signal - reference) * (signal - reference);
+        self.correlation += reference * signal;
+    }
+
+    // how far the residual is below the reference, the more negative the better
+    pub fn depth_db(&self) -> f32 {
+        if self.reference_power == 0.0 {
+            return 0.0;
+        }
+        10.0 * (self.residual_power.max(1e-30) / self.reference_power).log10()
+    }
+
+    pub fn is_inverted(&self) -> bool {
+        self.correlation < 0.0
+    }
+
+    pub fn passes(&self) -> bool {
+        !self.is_inverted() && self.depth_db() <= PASS_DEPTH_DB
+    }
+}
+
+fn tone(channel: usize, n: usize) -> f32 {
+    let phase = (n % TONE_PERIOD_SAMPLES) as f32 / TONE_PERIOD_SAMPLES as f32;
+    0.5 * (TAU * (channel + 1) as f32 * phase).sin()
+}
+
+// triggers a loop of the tone and measures the loop output against the tone it replaced
+pub fn check(sample_rate: f32, num_channels: usize) -> Cancellation {
+    let beat = TONE_PERIOD_SAMPLES * BEAT_PERIODS;
+    let mut sim = HostSim::new(sample_rate, num_channels, BLOCK_SIZE);
+    sim.transport.tempo = Some(sample_rate as f64 * 60.0 / beat as f64);
+    // the offset's whole range is a beat, so the loop plays the beat before it
+    sim.scrub_offset(1.0);
+    let num_samples = (LEAD_IN_BEATS + 1 + MEASURED_BEATS) * beat;
+    let input = channels(num_channels, num_samples, tone);
+
+    let lead_in = LEAD_IN_BEATS * beat;
+    let head: Vec<Vec<f32>> = input
+        .iter()
+        .map(|samples| samples[..lead_in].to_vec())
+        .collect();
+    let tail: Vec<Vec<f32>> = input
+        .iter()
+        .map(|samples| samples[lead_in..].to_vec())
+        .collect();
+    sim.process(&head);
+    sim.press_trigger();
+    let (_, loop_output) = sim.process(&tail);
+
+    // the first beat is left out, as the loop fades in over it
+    let lead = sim.fade_samples();
+    let mut cancellation = Cancellation::default();
+    for (channel, signal) in loop_output.iter().enumerate() {
+        for (n, signal) in signal.iter().enumerate().skip(beat) {
+            cancellation.add(tone(channel, lead_in + n + lead), *signal);
+        }
+    }
+    cancellation
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancellation() {
+        let mut cancellation = Cancellation::default();
+        cancellation.add(1.0, 1.0);
+        assert!(cancellation.passes());
+
+        let mut cancellation = Cancellation::default();
+        cancellation.add(1.0, -1.0);
+        assert!(cancellation.is_inverted());
+        assert!(!cancellation.passes());
+
+        let mut cancellation = Cancellation::default();
+        cancellation.add(1.0, 0.5);
+        cancellation.add(0.5, 1.0);
+        assert!(!cancellation.is_inverted());
+        assert!(!cancellation.passes());
+    }
+
+    #[test]
+    fn test_tone_shows_path_errors() {
+        // one sample late
+        let mut cancellation = Cancellation::default();
+        for n in 1..1000 {
+            cancellation.add(tone(0, n), tone(0, n - 1));
+        }
+        assert!(cancellation.depth_db() > -40.0);
+
+        // channels swapped
+        let mut cancellation = Cancellation::default();
+        for n in 0..1000 {
+            cancellation.add(tone(0, n), tone(1, n));
+            cancellation.add(tone(1, n), tone(0, n));
+        }
+        assert!(!cancellation.passes());
+    }
+
+    #[test]
+    fn test_loop_cancels_against_dry() {
+        // mono and stereo go through the stereo looper, more channels through the surround one
+        for num_channels in [1, 2, 4] {
+            let cancellation = check(48000.0, num_channels);
+            assert!(
+                cancellation.passes(),
+                "{} channels: {:?} dB",
+                num_channels,
+                cancellation.depth_db()
+            );
+        }
+    }
+}
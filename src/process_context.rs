@@ -0,0 +1,85 @@
+use nih_plug::prelude::Transport;
+
+// the host state for one block of audio, handed to the engine in one go
+// so that everything it needs from the host changes at the same time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProcessContextLite {
+    pub tempo: f32,
+    // beat time at the start of the block
+    pub beat_time: f64,
+    pub sample_rate: f32,
+    pub is_playing: bool,
+    // numerator, denominator
+    pub time_sig: (i32, i32),
+}
+
+impl ProcessContextLite {
+    // the beat time of a sample within the block
+    pub fn beat_time_at(&self, sample_index: usize) -> f64 {
+        self.beat_time + sample_index as f64 * self.tempo as f64 / 60.0 / self.sample_rate as f64
+    }
+
+    // the length of a bar in beats, which are quarter notes
+    pub fn bar_beats(&self) -> f32 {
+        let (numerator, denominator) = self.time_sig;
+        numerator as f32 * 4.0 / denominator.max(1) as f32
+    }
+}
+
+// the parts of the host's transport that the plugin reads, each none when the host doesn't say
+// a copy of its own, so tests can make one up
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HostTransport {
+    pub playing: bool,
+    pub sample_rate: f32,
+    pub tempo: Option<f64>,
+    pub pos_beats: Option<f64>,
+    pub time_sig_numerator: Option<i32>,
+    pub time_sig_denominator: Option<i32>,
+}
+
+impl HostTransport {
+    pub fn from_transport(transport: &Transport) -> HostTransport {
+        HostTransport {
+            playing: transport.playing,
+            sample_rate: transport.sample_rate,
+            tempo: transport.tempo,
+            pos_beats: transport.pos_beats(),
+            time_sig_numerator: transport.time_sig_numerator,
+            time_sig_denominator: transport.time_sig_denominator,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_beat_time_at() {
+        let context = ProcessContextLite {
+            tempo: 120.0,
+            beat_time: 4.0,
+            sample_rate: 10.0,
+            is_playing: true,
+            time_sig: (4, 4),
+        };
+        assert_eq!(context.beat_time_at(0), 4.0);
+        // two beats per second, so each sample is a fifth of a beat
+        assert_eq!(context.beat_time_at(5), 5.0);
+    }
+
+    #[test]
+    fn test_bar_beats() {
+        let mut context = ProcessContextLite {
+            tempo: 120.0,
+            beat_time: 0.0,
+            sample_rate: 10.0,
+            is_playing: true,
+            time_sig: (4, 4),
+        };
+        assert_eq!(context.bar_beats(), 4.0);
+        context.time_sig = (6, 8);
+        assert_eq!(context.bar_beats(), 3.0);
+    }
+}
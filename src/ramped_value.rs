@@ -1,3 +1,35 @@
+use nih_plug::prelude::Enum;
+use std::f64::consts::FRAC_PI_2;
+
+// the law a fade follows, from the linear position through it
+// linear keeps the level on correlated material, like the loop crossing over itself,
+// equal power keeps it on uncorrelated material, like the loop crossing over the dry,
+// and raised cosine is linear with its corners rounded off
+#[derive(Enum, Debug, Clone, Copy, PartialEq)]
+pub enum FadeShape {
+    #[id = "linear"]
+    #[name = "Linear"]
+    Linear,
+    #[id = "equal-power"]
+    #[name = "Equal Power"]
+    EqualPower,
+    #[id = "raised-cosine"]
+    #[name = "Raised Cosine"]
+    RaisedCosine,
+}
+
+impl FadeShape {
+    // position runs from 0 to 1, fading in, and back down to fade out
+    pub fn apply(&self, position: f64) -> f64 {
+        match self {
+            FadeShape::Linear => position,
+            FadeShape::EqualPower => (position * FRAC_PI_2).sin(),
+            FadeShape::RaisedCosine => 0.5 - 0.5 * (position * 2.0 * FRAC_PI_2).cos(),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
 pub struct RampedValue {
     value: f64,
     target_value: f64,
@@ -40,6 +72,33 @@ impl RampedValue {
         self.value += self.increment;
         self.value
     }
+
+    // what tick last returned, without moving on
+    pub fn value(&self) -> f64 {
+        if self.ramp_time_counter == 0 {
+            self.target_value
+        } else {
+            self.value
+        }
+    }
+
+    // where the ramp is heading, the value itself once it's done
+    pub fn target(&self) -> f64 {
+        self.target_value
+    }
+
+    // as calling tick num_ticks times, returning the last value
+    pub fn tick_many(&mut self, num_ticks: usize) -> f64 {
+        let steps = num_ticks.min(self.ramp_time_counter);
+        if steps == self.ramp_time_counter {
+            self.ramp_time_counter = 0;
+            self.value = self.target_value;
+            return self.target_value;
+        }
+        self.ramp_time_counter -= steps;
+        self.value += self.increment * steps as f64;
+        self.value
+    }
 }
 
 #[cfg(test)]
@@ -66,6 +125,17 @@ mod tests {
         assert_abs_diff_eq!(ramped_value.tick(), 1.0, epsilon = EPS);
     }
 
+    #[test]
+    fn test_ramped_value_tick_many() {
+        let mut ramped_value = RampedValue::new(0.0);
+
+        ramped_value.ramp(1.0, 4);
+        assert_abs_diff_eq!(ramped_value.tick_many(2), 0.4, epsilon = EPS);
+        assert_abs_diff_eq!(ramped_value.tick(), 0.6, epsilon = EPS);
+        assert_abs_diff_eq!(ramped_value.tick_many(10), 1.0, epsilon = EPS);
+        assert_abs_diff_eq!(ramped_value.tick(), 1.0, epsilon = EPS);
+    }
+
     #[test]
     fn test_ramped_value_zero_length() {
         let mut ramped_value = RampedValue::new(0.0);
@@ -74,6 +144,28 @@ mod tests {
         assert_eq!(ramped_value.tick(), 1.0);
     }
 
+    #[test]
+    fn test_fade_shapes() {
+        for shape in [
+            FadeShape::Linear,
+            FadeShape::EqualPower,
+            FadeShape::RaisedCosine,
+        ] {
+            assert_abs_diff_eq!(shape.apply(0.0), 0.0, epsilon = EPS);
+            assert_abs_diff_eq!(shape.apply(1.0), 1.0, epsilon = EPS);
+        }
+        // crossing over, the amplitudes or the powers of the fade in and out add up to one
+        for position in [0.1, 0.25, 0.5, 0.8] {
+            let fade_out = 1.0 - position;
+            let raised_cosine =
+                FadeShape::RaisedCosine.apply(position) + FadeShape::RaisedCosine.apply(fade_out);
+            assert_abs_diff_eq!(raised_cosine, 1.0, epsilon = EPS);
+            let equal_power = FadeShape::EqualPower.apply(position).powi(2)
+                + FadeShape::EqualPower.apply(fade_out).powi(2);
+            assert_abs_diff_eq!(equal_power, 1.0, epsilon = EPS);
+        }
+    }
+
     #[test]
     fn test_ramped_value_down() {
         let mut ramped_value = RampedValue::new(1.0);
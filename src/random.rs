@@ -0,0 +1,52 @@
+// random numbers cheap enough for the audio thread, no allocation or locking
+// xorshift, seeded so a run can be repeated, e.g. in tests
+
+pub struct Random {
+    state: u32,
+}
+
+#[allow(dead_code)]
+impl Random {
+    pub fn new(seed: u32) -> Random {
+        // xorshift never leaves zero
+        Random { state: seed.max(1) }
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+        x
+    }
+
+    // uniform in [0, 1)
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1 << 24) as f32
+    }
+
+    // uniform in [-1, 1)
+    pub fn bipolar(&mut self) -> f32 {
+        self.next_f32() * 2.0 - 1.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_random() {
+        let mut random = Random::new(0);
+        let values: Vec<f32> = (0..1000).map(|_| random.next_f32()).collect();
+        assert!(values.iter().all(|&value| (0.0..1.0).contains(&value)));
+        let mean = values.iter().sum::<f32>() / values.len() as f32;
+        assert!((mean - 0.5).abs() < 0.05, "{}", mean);
+
+        // the same seed gives the same numbers
+        let mut again = Random::new(0);
+        assert_eq!(again.next_f32(), values[0]);
+        assert!((0..1000).all(|_| (-1.0..1.0).contains(&random.bipolar())));
+    }
+}
@@ -0,0 +1,200 @@
+use nih_plug::prelude::Enum;
+
+// what to do with a grain that would read audio the buffers don't hold,
+// either further back than the loopable region or further on from when looping started
+#[derive(Enum, Debug, Clone, Copy, PartialEq)]
+pub enum RegionPolicy {
+    // play only the part of the grain that is there
+    #[id = "shorten"]
+    #[name = "Shorten"]
+    Shorten,
+    // keep the length, and move the offset until it fits
+    #[id = "move-offset"]
+    #[name = "Move Offset"]
+    MoveOffset,
+    // don't play the grain at all
+    #[id = "skip"]
+    #[name = "Skip"]
+    Skip,
+}
+
+// what happened to the last grain, for showing in the ui
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum RegionFit {
+    #[default]
+    Fits,
+    Shortened,
+    Moved,
+    Skipped,
+}
+
+impl RegionFit {
+    pub fn to_u8(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_u8(value: u8) -> RegionFit {
+        match value {
+            1 => RegionFit::Shortened,
+            2 => RegionFit::Moved,
+            3 => RegionFit::Skipped,
+            _ => RegionFit::Fits,
+        }
+    }
+}
+
+// the delays a grain can read from, relative to where looping started
+// newest is negative, as the buffers keep recording for a while after looping starts
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReadableRange {
+    pub newest: f32,
+    pub oldest: f32,
+}
+
+// fits a grain with the given offset and length (both in samples) into the readable range
+// returns the new offset and length, or None when the grain should not be played
+pub fn fit_grain(
+    offset: f32,
+    length: usize,
+    speed: f32,
+    reverse: bool,
+    range: ReadableRange,
+    policy: RegionPolicy,
+) -> (Option<(f32, usize)>, RegionFit) {
+    let span = length as f32 * speed;
+    // forwards the grain reads from the offset towards newer audio,
+    // backwards it ends at the offset
+    let (newest, oldest) = if reverse {
+        let newest = offset - length as f32;
+        (newest, newest + span)
+    } else {
+        (offset - span, offset)
+    };
+
+    if newest >= range.newest && oldest <= range.oldest {
+        return (Some((offset, length)), RegionFit::Fits);
+    }
+
+    let to_offset = |newest: f32, length: usize| {
+        if reverse {
+            newest + length as f32
+        } else {
+            newest + length as f32 * speed
+        }
+    };
+
+    match policy {
+        RegionPolicy::Skip => (None, RegionFit::Skipped),
+        RegionPolicy::Shorten => {
+            let newest = newest.max(range.newest);
+            let oldest = oldest.min(range.oldest);
+            let length = ((oldest - newest) / speed).max(0.0) as usize;
+            if length == 0 {
+                return (None, RegionFit::Skipped);
+            }
+            (
+                Some((to_offset(newest, length), length)),
+                RegionFit::Shortened,
+            )
+        }
+        RegionPolicy::MoveOffset => {
+            // a grain longer than the whole range can only be shortened
+            let length = length.min(((range.oldest - range.newest) / speed) as usize);
+            let span = length as f32 * speed;
+            let newest = newest.min(range.oldest - span).max(range.newest);
+            (Some((to_offset(newest, length), length)), RegionFit::Moved)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RANGE: ReadableRange = ReadableRange {
+        newest: -10.0,
+        oldest: 100.0,
+    };
+
+    #[test]
+    fn test_fit_grain_fits() {
+        for policy in [
+            RegionPolicy::Shorten,
+            RegionPolicy::MoveOffset,
+            RegionPolicy::Skip,
+        ] {
+            assert_eq!(
+                fit_grain(50.0, 40, 1.0, false, RANGE, policy),
+                (Some((50.0, 40)), RegionFit::Fits)
+            );
+            assert_eq!(
+                fit_grain(50.0, 40, 1.0, true, RANGE, policy),
+                (Some((50.0, 40)), RegionFit::Fits)
+            );
+        }
+    }
+
+    #[test]
+    fn test_fit_grain_too_new() {
+        // reads from 20 back to -20, but only 10 samples were recorded after looping started
+        assert_eq!(
+            fit_grain(20.0, 40, 1.0, false, RANGE, RegionPolicy::Shorten),
+            (Some((20.0, 30)), RegionFit::Shortened)
+        );
+        assert_eq!(
+            fit_grain(20.0, 40, 1.0, false, RANGE, RegionPolicy::MoveOffset),
+            (Some((30.0, 40)), RegionFit::Moved)
+        );
+        assert_eq!(
+            fit_grain(20.0, 40, 1.0, false, RANGE, RegionPolicy::Skip),
+            (None, RegionFit::Skipped)
+        );
+        // at half speed it only reads back to 0
+        assert_eq!(
+            fit_grain(20.0, 40, 0.5, false, RANGE, RegionPolicy::Skip),
+            (Some((20.0, 40)), RegionFit::Fits)
+        );
+    }
+
+    #[test]
+    fn test_fit_grain_too_old() {
+        assert_eq!(
+            fit_grain(120.0, 40, 1.0, false, RANGE, RegionPolicy::Shorten),
+            (Some((100.0, 20)), RegionFit::Shortened)
+        );
+        assert_eq!(
+            fit_grain(120.0, 40, 1.0, false, RANGE, RegionPolicy::MoveOffset),
+            (Some((100.0, 40)), RegionFit::Moved)
+        );
+        // backwards the grain ends at the offset, so the end is cut off
+        assert_eq!(
+            fit_grain(120.0, 40, 1.0, true, RANGE, RegionPolicy::Shorten),
+            (Some((100.0, 20)), RegionFit::Shortened)
+        );
+        // entirely out of range
+        assert_eq!(
+            fit_grain(200.0, 40, 1.0, false, RANGE, RegionPolicy::Shorten),
+            (None, RegionFit::Skipped)
+        );
+    }
+
+    #[test]
+    fn test_fit_grain_longer_than_range() {
+        assert_eq!(
+            fit_grain(50.0, 200, 1.0, false, RANGE, RegionPolicy::MoveOffset),
+            (Some((100.0, 110)), RegionFit::Moved)
+        );
+    }
+
+    #[test]
+    fn test_region_fit_u8() {
+        for fit in [
+            RegionFit::Fits,
+            RegionFit::Shortened,
+            RegionFit::Moved,
+            RegionFit::Skipped,
+        ] {
+            assert_eq!(RegionFit::from_u8(fit.to_u8()), fit);
+        }
+    }
+}
@@ -0,0 +1,121 @@
+#![allow(dead_code)]
+use crate::stereo_pair::AudioSampleOps;
+use std::f32::consts::PI;
+
+// offline windowed sinc resampling, for audio coming in or going out at another sample rate
+// not for the audio thread, it allocates and costs HALF_WIDTH * 2 taps per output sample
+
+// zero crossings of the sinc either side of each output sample
+const HALF_WIDTH: usize = 16;
+
+pub fn sinc(x: f32) -> f32 {
+    if x == 0.0 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+// blackman window over -1..1
+pub fn window(x: f32) -> f32 {
+    if x.abs() >= 1.0 {
+        return 0.0;
+    }
+    let phase = PI * (x + 1.0);
+    0.42 - 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos()
+}
+
+// the input, one sample per output sample at the new rate
+// when going down in rate the sinc is widened to filter out what the new rate can't hold
+pub fn resample<T: AudioSampleOps>(input: &[T], from_rate: f32, to_rate: f32) -> Vec<T> {
+    if input.is_empty() || from_rate == to_rate {
+        return input.to_vec();
+    }
+
+    let ratio = to_rate as f64 / from_rate as f64;
+    let cutoff = (ratio as f32).min(1.0);
+    // in input samples
+    let half_width = HALF_WIDTH as f32 / cutoff;
+    let output_len = (input.len() as f64 * ratio).round() as usize;
+
+    let mut output = Vec::with_capacity(output_len);
+    for n in 0..output_len {
+        let position = (n as f64 / ratio) as f32;
+        let first = (position - half_width).ceil().max(0.0) as usize;
+        let last = ((position + half_width).floor() as usize).min(input.len() - 1);
+
+        let mut sum = T::default();
+        for (k, sample) in input.iter().enumerate().take(last + 1).skip(first) {
+            let distance = position - k as f32;
+            let gain = cutoff * sinc(cutoff * distance) * window(distance / half_width);
+            sum += *sample * gain;
+        }
+        output.push(sum);
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stereo_pair::StereoPair;
+
+    fn sine(len: usize, freq: f32, sample_rate: f32) -> Vec<f32> {
+        (0..len)
+            .map(|i| (2.0 * PI * freq * i as f32 / sample_rate).sin())
+            .collect()
+    }
+
+    // ignores the edges, where the filter runs off the ends of the input
+    fn max_error(a: &[f32], b: &[f32]) -> f32 {
+        let edge = 2 * HALF_WIDTH;
+        a[edge..a.len() - edge]
+            .iter()
+            .zip(&b[edge..b.len() - edge])
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn test_resample_same_rate() {
+        let input = vec![1.0, 2.0, 3.0];
+        assert_eq!(resample(&input, 48000.0, 48000.0), input);
+    }
+
+    #[test]
+    fn test_resample_up() {
+        let input = sine(441, 1000.0, 44100.0);
+        let output = resample(&input, 44100.0, 48000.0);
+        assert_eq!(output.len(), 480);
+        assert!(max_error(&output, &sine(480, 1000.0, 48000.0)) < 0.01);
+    }
+
+    #[test]
+    fn test_resample_down() {
+        let input = sine(960, 1000.0, 48000.0);
+        let output = resample(&input, 48000.0, 44100.0);
+        assert_eq!(output.len(), 882);
+        assert!(max_error(&output, &sine(882, 1000.0, 44100.0)) < 0.01);
+    }
+
+    #[test]
+    fn test_resample_down_filters() {
+        // 20k can't be held at 22050, it should mostly disappear
+        let input = sine(960, 20000.0, 48000.0);
+        let output = resample(&input, 48000.0, 22050.0);
+        let silence = vec![0.0; output.len()];
+        assert!(max_error(&output, &silence) < 0.05);
+    }
+
+    #[test]
+    fn test_resample_stereo() {
+        let input: Vec<StereoPair<f32>> = sine(441, 1000.0, 44100.0)
+            .iter()
+            .map(|&x| StereoPair::new(x, -x))
+            .collect();
+        let output = resample(&input, 44100.0, 48000.0);
+        let left: Vec<f32> = output.iter().map(|x| x.left).collect();
+        let right: Vec<f32> = output.iter().map(|x| -x.right).collect();
+        assert_eq!(left, right);
+    }
+}
@@ -0,0 +1,99 @@
+use crate::stereo_pair::StereoPair;
+use crate::wet_effect::WetEffect;
+
+// multiplies the input by a sine carrier
+// when synced the carrier is a whole number of cycles per beat, and its phase follows
+// the song position, so every repeat of a loop is modulated the same way
+pub struct RingMod {
+    sample_rate: f32,
+    tempo: f32,
+    frequency_hz: f32,
+    synced: bool,
+    phase: f32,
+}
+
+#[allow(dead_code)]
+impl RingMod {
+    pub fn new(sample_rate: f32) -> RingMod {
+        RingMod {
+            sample_rate,
+            tempo: 120.0,
+            frequency_hz: 100.0,
+            synced: false,
+            phase: 0.0,
+        }
+    }
+
+    fn cycles_per_beat(&self) -> f64 {
+        (self.frequency_hz * 60.0 / self.tempo).round().max(1.0) as f64
+    }
+
+    fn carrier_phase(&mut self, beat_time: f64) -> f32 {
+        if self.synced {
+            return (beat_time * self.cycles_per_beat()).rem_euclid(1.0) as f32;
+        }
+        let phase = self.phase;
+        self.phase = (self.phase + self.frequency_hz / self.sample_rate).fract();
+        phase
+    }
+}
+
+impl WetEffect for RingMod {
+    fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+    }
+
+    fn set_tempo(&mut self, tempo: f32) {
+        self.tempo = tempo;
+    }
+
+    fn set_frequency(&mut self, frequency_hz: f32) {
+        self.frequency_hz = frequency_hz;
+    }
+
+    fn set_synced(&mut self, synced: bool) {
+        self.synced = synced;
+    }
+
+    fn reset(&mut self) {
+        self.phase = 0.0;
+    }
+
+    fn process(&mut self, input: StereoPair<f32>, beat_time: f64) -> StereoPair<f32> {
+        let carrier = (self.carrier_phase(beat_time) * std::f32::consts::TAU).sin();
+        input * carrier
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ring_mod_free() {
+        // a quarter of a cycle per sample
+        let mut ring_mod = RingMod::new(100.0);
+        ring_mod.set_frequency(25.0);
+        let input = StereoPair::new(1.0, 0.5);
+        let out: Vec<f32> = (0..4).map(|_| ring_mod.process(input, 0.0).right).collect();
+        let expected = [0.0, 0.5, 0.0, -0.5];
+        for (o, e) in out.iter().zip(expected.iter()) {
+            assert!((o - e).abs() < 0.0001);
+        }
+    }
+
+    #[test]
+    fn test_ring_mod_synced() {
+        // at 60 bpm, 3.2 hz rounds to 3 cycles per beat
+        let mut ring_mod = RingMod::new(100.0);
+        ring_mod.set_tempo(60.0);
+        ring_mod.set_frequency(3.2);
+        ring_mod.set_synced(true);
+        let input = StereoPair::new(1.0, 1.0);
+        let a = ring_mod.process(input, 0.25 / 3.0);
+        assert!((a.left - 1.0).abs() < 0.0001);
+        // the same position in the next beat is the same
+        let b = ring_mod.process(input, 1.0 + 0.25 / 3.0);
+        assert!((b.left - 1.0).abs() < 0.0001);
+    }
+}
@@ -1,37 +1,181 @@
+// the most events a tick hands out, any more stay due for the next tick
+pub const MAX_DUE_EVENTS: usize = 16;
+// the most events that can be waiting, any more are dropped
+pub const MAX_SCHEDULED_EVENTS: usize = 100;
+
+// the events due on a tick, held in place rather than in a vec so ticking never allocates
+// iterating takes them out in the order they were due
+#[derive(Debug, Clone, Copy)]
+pub struct DueEvents<E: Copy, const N: usize = MAX_DUE_EVENTS> {
+    events: [Option<E>; N],
+    len: usize,
+    next: usize,
+}
+
+#[allow(dead_code)]
+impl<E: Copy, const N: usize> DueEvents<E, N> {
+    pub fn new() -> DueEvents<E, N> {
+        DueEvents {
+            events: [None; N],
+            len: 0,
+            next: 0,
+        }
+    }
+
+    // returns false and drops the event if there's no room left
+    pub fn push(&mut self, event: E) -> bool {
+        if self.is_full() {
+            return false;
+        }
+        self.events[self.len] = Some(event);
+        self.len += 1;
+        true
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    // how many are left to take out
+    pub fn len(&self) -> usize {
+        self.len - self.next
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn contains(&self, event: &E) -> bool
+    where
+        E: PartialEq,
+    {
+        self.events[self.next..self.len].contains(&Some(*event))
+    }
+}
+
+impl<E: Copy, const N: usize> Iterator for DueEvents<E, N> {
+    type Item = E;
+
+    fn next(&mut self) -> Option<E> {
+        if self.next == self.len {
+            return None;
+        }
+        self.next += 1;
+        self.events[self.next - 1]
+    }
+}
+
+#[cfg(test)]
+impl<E: Copy + PartialEq, const N: usize> PartialEq<Vec<E>> for DueEvents<E, N> {
+    fn eq(&self, other: &Vec<E>) -> bool {
+        Iterator::eq(*self, other.iter().copied())
+    }
+}
+
 // E is the event type
-pub struct Scheduler<E: Clone + Copy + PartialEq> {
-    events: Vec<(f32, E)>,
+// the events wait in a ring held in place, in the order they are due, so scheduling and
+// ticking never allocate
+pub struct Scheduler<E: Clone + Copy + PartialEq, const N: usize = MAX_SCHEDULED_EVENTS> {
+    events: [Option<(f64, E)>; N],
+    // where the next due event is in the ring
+    first: usize,
+    len: usize,
 }
 
 #[allow(dead_code)]
-impl<E: Clone + Copy + PartialEq> Scheduler<E> {
-    pub fn new() -> Scheduler<E> {
-        let empty_reserved = Vec::with_capacity(100);
+impl<E: Clone + Copy + PartialEq, const N: usize> Scheduler<E, N> {
+    pub fn new() -> Scheduler<E, N> {
         Scheduler {
-            events: empty_reserved,
+            events: [None; N],
+            first: 0,
+            len: 0,
+        }
+    }
+
+    #[cfg(test)]
+    pub fn schedule_event(&mut self, time: f64, event: E) {
+        assert!(self.try_schedule_event(time, event));
+    }
+
+    // returns false and drops the event if it would be scheduled before the last event,
+    // or there's no room left
+    pub fn try_schedule_event(&mut self, time: f64, event: E) -> bool {
+        if self.len == N || self.last().is_some_and(|(t, _)| time < t) {
+            return false;
+        }
+        self.events[(self.first + self.len) % N] = Some((time, event));
+        self.len += 1;
+        true
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    // the waiting events in the order they are due
+    fn iter(&self) -> impl Iterator<Item = (f64, E)> + '_ {
+        (0..self.len).filter_map(move |index| self.events[(self.first + index) % N])
+    }
+
+    fn last(&self) -> Option<(f64, E)> {
+        self.len
+            .checked_sub(1)
+            .and_then(|index| self.events[(self.first + index) % N])
+    }
+
+    fn pop_first(&mut self) -> Option<(f64, E)> {
+        if self.len == 0 {
+            return None;
         }
+        let event = self.events[self.first].take();
+        self.first = (self.first + 1) % N;
+        self.len -= 1;
+        event
     }
 
-    pub fn schedule_event(&mut self, time: f32, event: E) {
-        assert!(time >= self.events.last().map(|&(t, _)| t).unwrap_or(0.0));
-        self.events.push((time, event));
+    pub fn tick(&mut self, time: f64) -> DueEvents<E> {
+        let mut events = DueEvents::new();
+        for (_, event) in self.tick_timed(time) {
+            events.push(event);
+        }
+        events
     }
 
-    pub fn tick(&mut self, time: f32) -> Vec<E> {
-        let mut events = Vec::new();
-        while let Some(&(event_time, ref event)) = self.events.first() {
-            if event_time <= time {
-                events.push(event.clone());
-                self.events.remove(0);
-            } else {
+    // as tick, with the time each event was due
+    pub fn tick_timed(&mut self, time: f64) -> DueEvents<(f64, E)> {
+        let mut events = DueEvents::new();
+        while let Some(event_time) = self.next_time() {
+            if event_time > time || events.is_full() {
                 break;
             }
+            if let Some(event) = self.pop_first() {
+                events.push(event);
+            }
         }
         events
     }
 
     pub fn clear(&mut self) {
-        self.events.clear();
+        self.events = [None; N];
+        self.first = 0;
+        self.len = 0;
+    }
+
+    // moves every event by the same amount, keeping their order
+    pub fn shift(&mut self, delta: f64) {
+        for (time, _) in self.events.iter_mut().flatten() {
+            *time += delta;
+        }
+    }
+
+    // when the first event is due, if there is one
+    pub fn next_time(&self) -> Option<f64> {
+        self.iter().next().map(|(time, _)| time)
+    }
+
+    // the time of the next occurrence of an event, if it is scheduled
+    pub fn next_time_of(&self, event: E) -> Option<f64> {
+        self.iter().find(|&(_, e)| e == event).map(|(time, _)| time)
     }
 }
 
@@ -62,4 +206,96 @@ mod tests {
         scheduler.clear();
         assert_eq!(scheduler.tick(5.0), vec![]);
     }
+
+    #[test]
+    fn test_scheduler_due_events_overflow() {
+        let mut scheduler = Scheduler::<TestEvent>::new();
+        for index in 0..MAX_DUE_EVENTS + 2 {
+            scheduler.schedule_event(index as f64 * 0.1, TestEvent::A);
+        }
+        scheduler.schedule_event(10.0, TestEvent::B);
+
+        // too many due at once, so the latest wait for the next tick
+        let events = scheduler.tick_timed(5.0);
+        assert!(events.is_full());
+        assert_eq!(events.len(), MAX_DUE_EVENTS);
+        assert_eq!(
+            events.last(),
+            Some((0.1 * (MAX_DUE_EVENTS - 1) as f64, TestEvent::A))
+        );
+        assert_eq!(scheduler.tick(5.0), vec![TestEvent::A, TestEvent::A]);
+        assert!(scheduler.tick(5.0).is_empty());
+        assert!(scheduler.tick(10.0).contains(&TestEvent::B));
+    }
+
+    #[test]
+    fn test_scheduler_next_time_of() {
+        let mut scheduler = Scheduler::<TestEvent>::new();
+        scheduler.schedule_event(1.0, TestEvent::A);
+        scheduler.schedule_event(2.0, TestEvent::B);
+        scheduler.schedule_event(3.0, TestEvent::B);
+        assert_eq!(scheduler.next_time_of(TestEvent::B), Some(2.0));
+        scheduler.tick(2.0);
+        assert_eq!(scheduler.next_time_of(TestEvent::B), Some(3.0));
+        assert_eq!(scheduler.next_time_of(TestEvent::A), None);
+    }
+
+    #[test]
+    fn test_scheduler_tick_timed() {
+        let mut scheduler = Scheduler::<TestEvent>::new();
+        scheduler.schedule_event(1.0, TestEvent::A);
+        scheduler.schedule_event(1.5, TestEvent::B);
+        assert_eq!(
+            scheduler.tick_timed(2.0),
+            vec![(1.0, TestEvent::A), (1.5, TestEvent::B)]
+        );
+    }
+
+    #[test]
+    fn test_scheduler_shift() {
+        let mut scheduler = Scheduler::<TestEvent>::new();
+        scheduler.schedule_event(8.0, TestEvent::A);
+        scheduler.schedule_event(9.0, TestEvent::B);
+        scheduler.shift(-7.0);
+        assert_eq!(scheduler.tick(1.0), vec![TestEvent::A]);
+        assert_eq!(scheduler.next_time_of(TestEvent::B), Some(2.0));
+    }
+
+    #[test]
+    fn test_scheduler_full() {
+        let mut scheduler = Scheduler::<TestEvent, 3>::new();
+        assert!(scheduler.try_schedule_event(1.0, TestEvent::A));
+        assert!(scheduler.try_schedule_event(2.0, TestEvent::B));
+        assert!(scheduler.try_schedule_event(3.0, TestEvent::A));
+        assert!(scheduler.is_full());
+        assert!(!scheduler.try_schedule_event(4.0, TestEvent::B));
+        assert_eq!(scheduler.tick(1.0), vec![TestEvent::A]);
+
+        // room again, and the ring wraps round keeping the order
+        assert!(scheduler.try_schedule_event(4.0, TestEvent::B));
+        assert!(!scheduler.try_schedule_event(5.0, TestEvent::A));
+        scheduler.shift(1.0);
+        assert_eq!(scheduler.next_time_of(TestEvent::B), Some(3.0));
+        assert_eq!(
+            scheduler.tick_timed(5.0),
+            vec![
+                (3.0, TestEvent::B),
+                (4.0, TestEvent::A),
+                (5.0, TestEvent::B)
+            ]
+        );
+        assert_eq!(scheduler.next_time(), None);
+    }
+
+    #[test]
+    fn test_scheduler_out_of_order() {
+        let mut scheduler = Scheduler::<TestEvent>::new();
+        assert!(scheduler.try_schedule_event(2.0, TestEvent::A));
+        assert!(!scheduler.try_schedule_event(1.0, TestEvent::B));
+        assert_eq!(scheduler.tick(3.0), vec![TestEvent::A]);
+
+        // before the clock has started, times can be negative
+        assert!(scheduler.try_schedule_event(-1.0, TestEvent::B));
+        assert_eq!(scheduler.tick(0.0), vec![TestEvent::B]);
+    }
 }
@@ -0,0 +1,157 @@
+use crate::grain_looper::ReverseMode;
+use nih_plug::prelude::Enum;
+
+pub const NUM_SECTIONS: usize = 3;
+
+#[derive(Enum, Debug, Clone, Copy, PartialEq)]
+pub enum SectionSlot {
+    #[id = "a"]
+    #[name = "A"]
+    A,
+    #[id = "b"]
+    #[name = "B"]
+    B,
+    #[id = "c"]
+    #[name = "C"]
+    C,
+}
+
+impl SectionSlot {
+    pub fn index(&self) -> usize {
+        match self {
+            SectionSlot::A => 0,
+            SectionSlot::B => 1,
+            SectionSlot::C => 2,
+        }
+    }
+}
+
+// the timing of a loop, as the params set it or as a section stored it
+// length and offset are in beats when synced, and seconds when free running
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SectionSettings {
+    pub sync: bool,
+    pub length: f32,
+    pub offset: f32,
+    pub reverse: ReverseMode,
+    pub speed: f32,
+}
+
+// the stored timing of each section, and which one is playing
+// the audio of each section is kept by the looper
+// an empty section plays whatever the params say, like having no sections at all
+pub struct Sections {
+    settings: [Option<SectionSettings>; NUM_SECTIONS],
+    active: SectionSlot,
+    // switched to at the next bar line
+    pending: Option<SectionSlot>,
+}
+
+#[allow(dead_code)]
+impl Sections {
+    pub fn new() -> Sections {
+        Sections {
+            settings: [None; NUM_SECTIONS],
+            active: SectionSlot::A,
+            pending: None,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.settings = [None; NUM_SECTIONS];
+        self.pending = None;
+    }
+
+    pub fn select(&mut self, slot: SectionSlot) {
+        self.pending = if slot == self.active {
+            None
+        } else {
+            Some(slot)
+        };
+    }
+
+    // switches to the selected section if a bar line falls in the block from start to end
+    pub fn advance(&mut self, start: f64, end: f64, bar_length: f32) {
+        let pending = match self.pending {
+            Some(pending) => pending,
+            None => return,
+        };
+        let bar_length = bar_length as f64;
+        let next_bar = (start / bar_length).ceil() * bar_length;
+        if next_bar < end {
+            self.active = pending;
+            self.pending = None;
+        }
+    }
+
+    pub fn active(&self) -> SectionSlot {
+        self.active
+    }
+
+    // the active section, if anything has been stored in it
+    pub fn stored(&self) -> Option<SectionSlot> {
+        self.settings[self.active.index()].map(|_| self.active)
+    }
+
+    pub fn active_settings(&self) -> Option<SectionSettings> {
+        self.settings[self.active.index()]
+    }
+
+    pub fn store(&mut self, settings: SectionSettings) {
+        self.settings[self.active.index()] = Some(settings);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(length: f32) -> SectionSettings {
+        SectionSettings {
+            sync: true,
+            length,
+            offset: 1.0,
+            reverse: ReverseMode::Forward,
+            speed: 1.0,
+        }
+    }
+
+    #[test]
+    fn test_sections_switch_on_bar() {
+        let mut sections = Sections::new();
+        sections.select(SectionSlot::B);
+        sections.advance(1.0, 2.0, 4.0);
+        assert_eq!(sections.active(), SectionSlot::A);
+        sections.advance(3.5, 4.5, 4.0);
+        assert_eq!(sections.active(), SectionSlot::B);
+
+        // a block starting on the bar line switches straight away
+        sections.select(SectionSlot::C);
+        sections.advance(8.0, 8.5, 4.0);
+        assert_eq!(sections.active(), SectionSlot::C);
+
+        // changing your mind before the bar
+        sections.select(SectionSlot::A);
+        sections.select(SectionSlot::C);
+        sections.advance(11.5, 12.5, 4.0);
+        assert_eq!(sections.active(), SectionSlot::C);
+    }
+
+    #[test]
+    fn test_sections_store() {
+        let mut sections = Sections::new();
+        assert_eq!(sections.stored(), None);
+        sections.store(settings(0.5));
+        assert_eq!(sections.stored(), Some(SectionSlot::A));
+        assert_eq!(sections.active_settings(), Some(settings(0.5)));
+
+        sections.select(SectionSlot::B);
+        sections.advance(0.0, 1.0, 4.0);
+        assert_eq!(sections.stored(), None);
+
+        sections.reset();
+        sections.select(SectionSlot::A);
+        sections.advance(0.0, 1.0, 4.0);
+        assert_eq!(sections.stored(), None);
+    }
+}
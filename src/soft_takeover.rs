@@ -0,0 +1,108 @@
+// soft takeover ("pickup") for an external control mapped onto a parameter
+// the control only starts moving the parameter once it has crossed, or come close to,
+// the parameter's current value, so a controller that is out of sync with the
+// parameter doesn't make it jump
+// all values are normalized 0..1
+pub struct SoftTakeover {
+    picked_up: bool,
+    last_control: Option<f32>,
+    last_output: Option<f32>,
+    threshold: f32,
+}
+
+const DEFAULT_PICKUP_THRESHOLD: f32 = 0.02;
+
+#[allow(dead_code)]
+impl SoftTakeover {
+    pub fn new() -> SoftTakeover {
+        SoftTakeover::new_with_threshold(DEFAULT_PICKUP_THRESHOLD)
+    }
+
+    // threshold: how close the control must be to the parameter to pick it up
+    pub fn new_with_threshold(threshold: f32) -> SoftTakeover {
+        SoftTakeover {
+            picked_up: false,
+            last_control: None,
+            last_output: None,
+            threshold,
+        }
+    }
+
+    // forget the pickup, e.g. when the mapping changes
+    pub fn reset(&mut self) {
+        self.picked_up = false;
+        self.last_control = None;
+        self.last_output = None;
+    }
+
+    // feed a new control value, with the current value of the parameter it controls
+    // returns the value the parameter should take, or None if the control hasn't picked it up
+    pub fn process(&mut self, control: f32, current: f32) -> Option<f32> {
+        // if something else moved the parameter since we last set it, we need to pick it up again
+        if let Some(last_output) = self.last_output {
+            if (last_output - current).abs() > self.threshold {
+                self.picked_up = false;
+            }
+        }
+
+        if !self.picked_up {
+            let is_close = (control - current).abs() <= self.threshold;
+            let has_crossed = match self.last_control {
+                Some(last_control) => (last_control - current) * (control - current) <= 0.0,
+                None => false,
+            };
+            self.picked_up = is_close || has_crossed;
+        }
+
+        self.last_control = Some(control);
+
+        if self.picked_up {
+            self.last_output = Some(control);
+            Some(control)
+        } else {
+            self.last_output = None;
+            None
+        }
+    }
+
+    pub fn is_picked_up(&self) -> bool {
+        self.picked_up
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_soft_takeover_close_picks_up() {
+        let mut takeover = SoftTakeover::new();
+        assert_eq!(takeover.process(0.5, 0.51), Some(0.5));
+        assert!(takeover.is_picked_up());
+        assert_eq!(takeover.process(0.9, 0.5), Some(0.9));
+    }
+
+    #[test]
+    fn test_soft_takeover_waits_for_crossing() {
+        let mut takeover = SoftTakeover::new();
+        // controller is far below the parameter
+        assert_eq!(takeover.process(0.1, 0.6), None);
+        assert_eq!(takeover.process(0.3, 0.6), None);
+        assert!(!takeover.is_picked_up());
+        // jumps past the parameter value between two messages
+        assert_eq!(takeover.process(0.7, 0.6), Some(0.7));
+        assert_eq!(takeover.process(0.75, 0.7), Some(0.75));
+    }
+
+    #[test]
+    fn test_soft_takeover_lost_when_moved_elsewhere() {
+        let mut takeover = SoftTakeover::new();
+        assert_eq!(takeover.process(0.5, 0.5), Some(0.5));
+        // automation moved the parameter away
+        assert_eq!(takeover.process(0.52, 0.9), None);
+        assert!(!takeover.is_picked_up());
+
+        takeover.reset();
+        assert_eq!(takeover.process(0.2, 0.9), None);
+    }
+}
@@ -0,0 +1,164 @@
+use crate::audio_frame::{AudioFrame, MAX_CHANNELS};
+
+// how much audio either side of a splice is looked at
+const ANALYSIS_WINDOW: usize = 1024;
+// the window is split into blocks to follow the envelope
+const ENVELOPE_BLOCK: usize = 64;
+// the level has to jump by this much from one block to the next to count as a hit,
+// so the ripple of low notes in the envelope doesn't
+const ONSET_RATIO: f32 = 2.0;
+// a zero crossing rate this high counts as fully noisy, about 5k at 48k
+const NOISY_CROSSING_RATE: f32 = 0.2;
+// the envelope matters more than the brightness
+const FLUX_WEIGHT: f32 = 0.75;
+
+// keeps a mono copy of the input, to look at the audio around where a loop will be spliced
+// percussive material wants a tight fade so the hits stay sharp, pads want a long one to hide the join
+pub struct SpliceAnalyzer {
+    history: Vec<f32>,
+    write_index: usize,
+}
+
+#[allow(dead_code)]
+impl SpliceAnalyzer {
+    pub fn new(length: usize) -> SpliceAnalyzer {
+        SpliceAnalyzer {
+            history: vec![0.0; length.max(ANALYSIS_WINDOW)],
+            write_index: 0,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.history.fill(0.0);
+        self.write_index = 0;
+    }
+
+    pub fn push_frames<T: AudioFrame>(&mut self, frames: &[T]) {
+        let mut channels = [0.0; MAX_CHANNELS];
+        for frame in frames {
+            frame.write_channels(&mut channels[..T::NUM_CHANNELS]);
+            let mono = channels[..T::NUM_CHANNELS].iter().sum::<f32>() / T::NUM_CHANNELS as f32;
+            self.history[self.write_index] = mono;
+            self.write_index = (self.write_index + 1) % self.history.len();
+        }
+    }
+
+    // the sample this many samples ago
+    fn read(&self, delay: usize) -> f32 {
+        let len = self.history.len();
+        self.history[(self.write_index + len - 1 - delay % len) % len]
+    }
+
+    // 0 for steady material, towards 1 for percussive or noisy material
+    // looks at the window centred on the sample delay samples ago, or as close as we have
+    pub fn percussiveness(&self, delay: usize) -> f32 {
+        let newest = delay
+            .saturating_sub(ANALYSIS_WINDOW / 2)
+            .min(self.history.len() - ANALYSIS_WINDOW);
+        // oldest first
+        let window = (0..ANALYSIS_WINDOW).map(|i| self.read(newest + ANALYSIS_WINDOW - 1 - i));
+
+        let mut crossings = 0;
+        let mut previous = 0.0;
+        let mut block_energy = 0.0;
+        let mut envelope = [0.0; ANALYSIS_WINDOW / ENVELOPE_BLOCK];
+        for (i, sample) in window.enumerate() {
+            if (sample >= 0.0) != (previous >= 0.0) {
+                crossings += 1;
+            }
+            previous = sample;
+            block_energy += sample * sample;
+            if (i + 1) % ENVELOPE_BLOCK == 0 {
+                envelope[i / ENVELOPE_BLOCK] = (block_energy / ENVELOPE_BLOCK as f32).sqrt();
+                block_energy = 0.0;
+            }
+        }
+
+        let peak = envelope.iter().cloned().fold(0.0, f32::max);
+        if peak <= f32::EPSILON {
+            return 0.0;
+        }
+        // how much the level jumps up, compared to how loud it gets
+        let rises: f32 = envelope
+            .windows(2)
+            .map(|pair| (pair[1] - pair[0] * ONSET_RATIO).max(0.0))
+            .sum();
+        let flux = (rises / peak).min(1.0);
+        let crossing_rate = crossings as f32 / ANALYSIS_WINDOW as f32;
+        let noisiness = (crossing_rate / NOISY_CROSSING_RATE).min(1.0);
+
+        FLUX_WEIGHT * flux + (1.0 - FLUX_WEIGHT) * noisiness
+    }
+
+    // a fade between shortest and longest for splicing at these delays,
+    // as short as the most percussive one needs
+    pub fn fade_for_splices(&self, splices: &[usize], shortest: f32, longest: f32) -> f32 {
+        let percussiveness = splices
+            .iter()
+            .map(|&delay| self.percussiveness(delay))
+            .fold(0.0, f32::max);
+        longest - (longest - shortest) * percussiveness
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stereo_pair::StereoPair;
+
+    fn analyzer_with(samples: &[f32]) -> SpliceAnalyzer {
+        let mut analyzer = SpliceAnalyzer::new(4096);
+        analyzer.push_frames(samples);
+        analyzer
+    }
+
+    fn sine(len: usize, period: f32) -> Vec<f32> {
+        (0..len)
+            .map(|i| (2.0 * std::f32::consts::PI * i as f32 / period).sin())
+            .collect()
+    }
+
+    #[test]
+    fn test_splice_analyzer_steady() {
+        let analyzer = analyzer_with(&sine(4096, 100.0));
+        assert!(analyzer.percussiveness(2048) < 0.1);
+        assert_eq!(analyzer_with(&[0.0; 4096]).percussiveness(2048), 0.0);
+    }
+
+    #[test]
+    fn test_splice_analyzer_hit() {
+        // silence, then a decaying hit half way through the window
+        let mut samples = vec![0.0; 2048];
+        samples.extend(
+            sine(2048, 20.0)
+                .iter()
+                .enumerate()
+                .map(|(i, x)| x * (-(i as f32) / 200.0).exp()),
+        );
+        let analyzer = analyzer_with(&samples);
+        assert!(analyzer.percussiveness(2048) > 0.7);
+        // well after the hit has gone
+        assert!(analyzer.percussiveness(0) < analyzer.percussiveness(2048));
+    }
+
+    #[test]
+    fn test_splice_analyzer_fade() {
+        let analyzer = analyzer_with(&sine(4096, 100.0));
+        let fade = analyzer.fade_for_splices(&[100, 2048], 0.01, 0.1);
+        assert!(fade > 0.09 && fade <= 0.1);
+
+        let mut samples = vec![0.0; 2048];
+        samples.extend(vec![1.0; 2048]);
+        let analyzer = analyzer_with(&samples);
+        let fade = analyzer.fade_for_splices(&[100, 2048], 0.01, 0.1);
+        assert!(fade < 0.04);
+    }
+
+    #[test]
+    fn test_splice_analyzer_downmix() {
+        let mut analyzer = SpliceAnalyzer::new(4);
+        analyzer.push_frames(&[StereoPair::new(1.0, 0.0), StereoPair::new(1.0, -1.0)]);
+        assert_eq!(analyzer.read(0), 0.0);
+        assert_eq!(analyzer.read(1), 0.5);
+    }
+}
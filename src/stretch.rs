@@ -0,0 +1,187 @@
+use crate::grain::Grain;
+
+// time-stretching, so the loop can be slowed down or sped up without changing its pitch
+// rather than playing the loop as one long grain, the audio that grain would have played is
+// covered by short overlapping grains, each played at the pitch, while the point they start
+// from moves through the audio at the loop's speed
+// the loop still lasts as long as its grain would have, so it stays on the grid
+
+// how many grains overlap, at the fewest each fades out as the next fades in
+pub const MIN_DENSITY: f32 = 2.0;
+// the grain player has room for the overlapping grains of two loops crossing over
+pub const MAX_DENSITY: f32 = 4.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StretchSettings {
+    // how long each short grain lasts, in samples
+    pub grain_size: usize,
+    // how many grains overlap at once
+    pub density: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stretch {
+    wait: usize,
+    // where the loop's grain would have started reading, and which way it would have gone
+    start_delay: f32,
+    reverse: bool,
+    // how far the grains' start moves through the audio each sample
+    speed: f32,
+    pitch: f32,
+    // how long the loop lasts, and how much of it has gone
+    duration: usize,
+    elapsed: usize,
+    grain_size: usize,
+    // how often a grain starts
+    hop: usize,
+    // keeps the overlapping fades at unity
+    gain: f32,
+    // the furthest into the audio a grain can start and still end within it
+    last_position: f32,
+}
+
+#[allow(dead_code)]
+impl Stretch {
+    // covers the audio loop_grain would have played, from when it would have played it
+    // pitch is how fast each short grain plays, 1 keeps the pitch of the audio
+    pub fn new(loop_grain: &Grain, settings: StretchSettings, pitch: f32) -> Stretch {
+        let speed = loop_grain.speed().abs();
+        let duration = loop_grain.duration();
+        let region_length = duration as f32 * speed;
+        // a grain can't read more than the loop's grain would have
+        let grain_size = (settings.grain_size as f32).min(region_length / pitch) as usize;
+        let density = settings.density.clamp(MIN_DENSITY, MAX_DENSITY);
+        Stretch {
+            wait: loop_grain.scheduled_wait(),
+            start_delay: loop_grain.delay_pos(),
+            reverse: loop_grain.speed() < 0.0,
+            speed,
+            pitch,
+            duration,
+            elapsed: 0,
+            grain_size,
+            hop: ((grain_size as f32 / density) as usize).max(1),
+            gain: MIN_DENSITY / density,
+            last_position: (region_length - grain_size as f32 * pitch).max(0.0),
+        }
+    }
+
+    // the grain to start this sample, if it's time for one
+    pub fn tick(&mut self) -> Option<Grain> {
+        if self.wait > 0 {
+            self.wait -= 1;
+            return None;
+        }
+        if self.is_finished() {
+            return None;
+        }
+        let elapsed = self.elapsed;
+        self.elapsed += 1;
+        if !elapsed.is_multiple_of(self.hop) {
+            return None;
+        }
+        let position = (elapsed as f32 * self.speed).min(self.last_position);
+        // the offset is where grain::new works out the first delay from
+        let offset = if self.reverse {
+            self.start_delay + position + self.grain_size as f32
+        } else {
+            self.start_delay - position + 1.0
+        };
+        let mut grain = Grain::new(
+            0,
+            offset,
+            self.grain_size,
+            self.grain_size / 2,
+            self.reverse,
+            self.pitch,
+        );
+        grain.set_gain(self.gain);
+        Some(grain)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(grain_size: usize, density: f32) -> StretchSettings {
+        StretchSettings {
+            grain_size,
+            density,
+        }
+    }
+
+    // the first delay each grain reads, and when it starts
+    fn spawned(stretch: &mut Stretch, num_ticks: usize) -> Vec<(usize, f32)> {
+        (0..num_ticks)
+            .filter_map(|n| stretch.tick().map(|grain| (n, grain.delay_pos())))
+            .collect()
+    }
+
+    #[test]
+    fn test_stretch_half_speed() {
+        // a loop of 16 samples reading 8 samples of audio, from a delay of 20 down to 12
+        let loop_grain = Grain::new(2, 21.0, 16, 0, false, 0.5);
+        let mut stretch = Stretch::new(&loop_grain, settings(4, 2.0), 1.0);
+        // grains start every 2 samples after the wait, moving through the audio at half speed
+        // and stopping where a grain would run past the end of it
+        assert_eq!(
+            spawned(&mut stretch, 20),
+            vec![
+                (2, 20.0),
+                (4, 19.0),
+                (6, 18.0),
+                (8, 17.0),
+                (10, 16.0),
+                (12, 16.0),
+                (14, 16.0),
+                (16, 16.0)
+            ]
+        );
+        assert!(stretch.is_finished());
+    }
+
+    #[test]
+    fn test_stretch_reverse_and_pitch() {
+        // reversed, the loop's grain reads from a delay of 5 up to 21
+        let loop_grain = Grain::new(0, 21.0, 16, 0, true, 1.0);
+        let mut stretch = Stretch::new(&loop_grain, settings(4, 4.0), 2.0);
+        let mut grains = vec![];
+        for _ in 0..16 {
+            if let Some(grain) = stretch.tick() {
+                grains.push(grain);
+            }
+        }
+        assert_eq!(grains.len(), 16);
+        // each grain is an octave up and reads backwards from where the loop has got to
+        let first: Vec<f32> = (0..4).map(|_| grains[0].tick().0).collect();
+        assert_eq!(first, vec![5.0, 7.0, 9.0, 11.0]);
+        assert_eq!(grains[3].delay_pos(), 8.0);
+        // the last grain still ends inside the audio
+        assert_eq!(grains[15].delay_pos(), 13.0);
+    }
+
+    #[test]
+    fn test_stretch_overlap_is_flat() {
+        let loop_grain = Grain::new(0, 100.0, 64, 0, false, 1.0);
+        for density in [2.0, 4.0] {
+            let mut stretch = Stretch::new(&loop_grain, settings(16, density), 1.0);
+            let mut grains: Vec<Grain> = vec![];
+            let mut gains = vec![];
+            for _ in 0..64 {
+                if let Some(grain) = stretch.tick() {
+                    grains.push(grain);
+                }
+                gains.push(grains.iter_mut().map(|grain| grain.tick().1).sum::<f32>());
+            }
+            // once the grains have faded in, they add up to unity
+            for gain in gains[16..].iter() {
+                assert!((gain - 1.0).abs() < 0.15, "{} {:?}", density, gains);
+            }
+        }
+    }
+}
@@ -1,15 +1,31 @@
-pub fn all_near(a: &Vec<f32>, b: &Vec<f32>, epsilon: f32) {
+use crate::stereo_pair::StereoPair;
+use std::fmt::Debug;
+
+// samples that can be compared with a tolerance
+pub trait ApproxEq {
+    fn near(&self, other: &Self, epsilon: f32) -> bool;
+}
+
+impl ApproxEq for f32 {
+    fn near(&self, other: &f32, epsilon: f32) -> bool {
+        (self - other).abs() < epsilon
+    }
+}
+
+impl ApproxEq for StereoPair<f32> {
+    fn near(&self, other: &StereoPair<f32>, epsilon: f32) -> bool {
+        self.left.near(&other.left, epsilon) && self.right.near(&other.right, epsilon)
+    }
+}
+
+pub fn all_near<T: ApproxEq + Debug>(a: &Vec<T>, b: &Vec<T>, epsilon: f32) {
     if a.len() != b.len() {
         println!("");
         println!("left = {:?}\nright = {:?}", a, b);
         println!("");
         panic!("lengths differ: {} != {}", a.len(), b.len());
     }
-    let near = a
-        .iter()
-        .zip(b.iter())
-        .map(|(a, b)| (a - b).abs())
-        .all(|x| x < epsilon);
+    let near = a.iter().zip(b.iter()).all(|(a, b)| a.near(b, epsilon));
     println!("");
     assert!(near, "left = {:?}\nright = {:?}", a, b);
     println!("");
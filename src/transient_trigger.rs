@@ -0,0 +1,94 @@
+// starts the loop hands-free when the input jumps above a threshold, e.g. as a drummer comes in
+// arming it waits for the next hit, which latches the loop on until it is disarmed
+// the level is looked at a block at a time, the loop then starts on the grid as usual
+
+pub struct TransientTrigger {
+    armed: bool,
+    triggered: bool,
+    threshold: f32,
+    // the last block's peak, so only a jump past the threshold counts
+    last_peak: f32,
+}
+
+#[allow(dead_code)]
+impl TransientTrigger {
+    pub fn new() -> TransientTrigger {
+        TransientTrigger {
+            armed: false,
+            triggered: false,
+            threshold: 1.0,
+            last_peak: 0.0,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.triggered = false;
+        self.last_peak = 0.0;
+    }
+
+    // disarming lets go of the loop, and arming again waits for a new hit
+    pub fn set_armed(&mut self, armed: bool) {
+        if !armed {
+            self.triggered = false;
+        }
+        self.armed = armed;
+    }
+
+    // as a linear gain
+    pub fn set_threshold(&mut self, threshold: f32) {
+        self.threshold = threshold;
+    }
+
+    // call with the input's peak for each block
+    pub fn process(&mut self, peak: f32) {
+        if self.armed && self.last_peak < self.threshold && peak >= self.threshold {
+            self.triggered = true;
+        }
+        self.last_peak = peak;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.triggered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transient_trigger() {
+        let mut trigger = TransientTrigger::new();
+        trigger.set_threshold(0.5);
+        // not armed yet
+        trigger.process(0.9);
+        trigger.process(0.1);
+        assert!(!trigger.is_active());
+
+        trigger.set_armed(true);
+        trigger.process(0.4);
+        assert!(!trigger.is_active());
+        trigger.process(0.6);
+        assert!(trigger.is_active());
+        // latched once hit
+        trigger.process(0.0);
+        assert!(trigger.is_active());
+
+        trigger.set_armed(false);
+        assert!(!trigger.is_active());
+    }
+
+    #[test]
+    fn test_transient_trigger_needs_a_jump() {
+        let mut trigger = TransientTrigger::new();
+        trigger.set_threshold(0.5);
+        trigger.process(0.8);
+        // already loud when armed, so it waits for the level to drop and come back
+        trigger.set_armed(true);
+        trigger.process(0.8);
+        assert!(!trigger.is_active());
+        trigger.process(0.2);
+        trigger.process(0.7);
+        assert!(trigger.is_active());
+    }
+}
@@ -0,0 +1,14 @@
+use nih_plug::prelude::Enum;
+
+// what the loop does while the host transport is stopped
+#[derive(Enum, Debug, Clone, Copy, PartialEq)]
+pub enum TransportStopMode {
+    // carry on looping on our own clock at the host tempo
+    #[id = "keep-looping"]
+    #[name = "Keep Looping"]
+    KeepLooping,
+    // fade the loop out, it starts again when the transport does
+    #[id = "stop"]
+    #[name = "Stop"]
+    Stop,
+}
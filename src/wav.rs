@@ -0,0 +1,70 @@
+use std::io::{self, Write};
+
+// just enough of the wav format to keep a loop, 32 bit float so nothing is lost to dither
+
+const FORMAT_FLOAT: u16 = 3;
+const BYTES_PER_SAMPLE: u16 = 4;
+
+// samples are interleaved, num_channels to a frame
+pub fn write_wav<W: Write>(
+    writer: &mut W,
+    samples: &[f32],
+    num_channels: u16,
+    sample_rate: u32,
+) -> io::Result<()> {
+    let data_size = u32::try_from(samples.len() * BYTES_PER_SAMPLE as usize)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "too long for a wav file"))?;
+    let block_align = num_channels * BYTES_PER_SAMPLE;
+
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&(36 + data_size).to_le_bytes())?;
+    writer.write_all(b"WAVE")?;
+
+    writer.write_all(b"fmt ")?;
+    writer.write_all(&16u32.to_le_bytes())?;
+    writer.write_all(&FORMAT_FLOAT.to_le_bytes())?;
+    writer.write_all(&num_channels.to_le_bytes())?;
+    writer.write_all(&sample_rate.to_le_bytes())?;
+    writer.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+    writer.write_all(&block_align.to_le_bytes())?;
+    writer.write_all(&(BYTES_PER_SAMPLE * 8).to_le_bytes())?;
+
+    writer.write_all(b"data")?;
+    writer.write_all(&data_size.to_le_bytes())?;
+    for sample in samples {
+        writer.write_all(&sample.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u16_at(bytes: &[u8], at: usize) -> u16 {
+        u16::from_le_bytes([bytes[at], bytes[at + 1]])
+    }
+
+    fn u32_at(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_write_wav() {
+        let mut bytes = Vec::new();
+        write_wav(&mut bytes, &[0.5, -0.5, 1.0, -1.0], 2, 48000).unwrap();
+        assert_eq!(bytes.len(), 44 + 16);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(u32_at(&bytes, 4), 36 + 16);
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!(u16_at(&bytes, 20), FORMAT_FLOAT);
+        assert_eq!(u16_at(&bytes, 22), 2);
+        assert_eq!(u32_at(&bytes, 24), 48000);
+        assert_eq!(u32_at(&bytes, 28), 48000 * 8);
+        assert_eq!(u16_at(&bytes, 32), 8);
+        assert_eq!(u16_at(&bytes, 34), 32);
+        assert_eq!(&bytes[36..40], b"data");
+        assert_eq!(u32_at(&bytes, 40), 16);
+        assert_eq!(f32::from_le_bytes(bytes[52..56].try_into().unwrap()), 1.0);
+    }
+}
@@ -0,0 +1,156 @@
+use crate::audio_frame::{AudioFrame, MAX_CHANNELS};
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+// a few hundred points of what the loop can play, for drawing it off the audio thread
+// the audio thread writes one of three slots while the editor reads another, so neither waits,
+// the audio thread only ever tries the lock, and skips a snapshot rather than wait for it
+
+pub const WAVEFORM_POINTS: usize = 256;
+// how many samples each point looks at, spread across its span, rather than all of them
+// so a snapshot costs the same however long the region is, though a short peak can be missed
+pub const READS_PER_POINT: usize = 16;
+
+// the lowest and highest sample across the channels in the span of one point
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct WaveformPoint {
+    pub min: f32,
+    pub max: f32,
+}
+
+impl WaveformPoint {
+    // before any samples are added
+    pub const EMPTY: WaveformPoint = WaveformPoint {
+        min: f32::INFINITY,
+        max: f32::NEG_INFINITY,
+    };
+
+    pub fn add<T: AudioFrame>(&mut self, frame: &T) {
+        let mut channels = [0.0; MAX_CHANNELS];
+        frame.write_channels(&mut channels[..T::NUM_CHANNELS]);
+        for sample in &channels[..T::NUM_CHANNELS] {
+            self.min = self.min.min(*sample);
+            self.max = self.max.max(*sample);
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Waveform {
+    // counts up with each snapshot, so the editor can tell when there's a new one
+    pub version: u32,
+    // oldest first
+    pub points: [WaveformPoint; WAVEFORM_POINTS],
+}
+
+impl Default for Waveform {
+    fn default() -> Waveform {
+        Waveform {
+            version: 0,
+            points: [WaveformPoint::default(); WAVEFORM_POINTS],
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct WaveformSnapshot {
+    slots: [Mutex<Waveform>; 3],
+    // the slot with the newest snapshot, and the one the editor last read from
+    latest: AtomicUsize,
+    reading: AtomicUsize,
+    version: AtomicU32,
+}
+
+#[allow(dead_code)]
+impl WaveformSnapshot {
+    // from the audio thread, fill fills the points in a slot the editor isn't reading
+    // returns false if the slot was busy after all, and nothing was published
+    pub fn publish(&self, fill: impl FnOnce(&mut [WaveformPoint; WAVEFORM_POINTS])) -> bool {
+        let latest = self.latest.load(Ordering::Acquire);
+        let reading = self.reading.load(Ordering::Acquire);
+        let slot = (0..3)
+            .find(|&slot| slot != latest && slot != reading)
+            .unwrap_or((latest + 1) % 3);
+        let Ok(mut waveform) = self.slots[slot].try_lock() else {
+            return false;
+        };
+        fill(&mut waveform.points);
+        waveform.version = self.version.fetch_add(1, Ordering::Relaxed) + 1;
+        drop(waveform);
+        self.latest.store(slot, Ordering::Release);
+        true
+    }
+
+    // from the editor, a copy of the newest snapshot, version 0 before there is one
+    pub fn latest(&self) -> Waveform {
+        let slot = self.latest.load(Ordering::Acquire);
+        self.reading.store(slot, Ordering::Release);
+        // only waits if the audio thread took the slot just before it was marked as being read
+        match self.slots[slot].lock() {
+            Ok(waveform) => *waveform,
+            Err(poisoned) => *poisoned.into_inner(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stereo_pair::StereoPair;
+
+    #[test]
+    fn test_waveform_point() {
+        let mut point = WaveformPoint::EMPTY;
+        point.add(&StereoPair::new(0.5, 0.25));
+        assert_eq!(
+            point,
+            WaveformPoint {
+                min: 0.25,
+                max: 0.5
+            }
+        );
+        point.add(&StereoPair::new(0.5, -0.25));
+        point.add(&StereoPair::new(-0.5, 0.75));
+        assert_eq!(
+            point,
+            WaveformPoint {
+                min: -0.5,
+                max: 0.75
+            }
+        );
+    }
+
+    #[test]
+    fn test_waveform_snapshot() {
+        let snapshot = WaveformSnapshot::default();
+        assert_eq!(snapshot.latest().version, 0);
+
+        let fill = |value: f32| {
+            move |points: &mut [WaveformPoint; WAVEFORM_POINTS]| {
+                points.fill(WaveformPoint {
+                    min: -value,
+                    max: value,
+                })
+            }
+        };
+        assert!(snapshot.publish(fill(0.5)));
+        let first = snapshot.latest();
+        assert_eq!(first.version, 1);
+        assert_eq!(first.points[0].max, 0.5);
+
+        // publishing while the editor holds on to what it read doesn't touch it
+        assert!(snapshot.publish(fill(1.0)));
+        assert!(snapshot.publish(fill(0.25)));
+        assert_eq!(first.points[0].max, 0.5);
+        let latest = snapshot.latest();
+        assert_eq!(latest.version, 3);
+        assert_eq!(latest.points[WAVEFORM_POINTS - 1].max, 0.25);
+
+        // a slot that's locked is skipped rather than waited on
+        let held: Vec<_> = snapshot.slots.iter().map(|slot| slot.lock()).collect();
+        assert!(!snapshot.publish(fill(1.0)));
+        drop(held);
+        assert!(snapshot.publish(fill(1.0)));
+        assert_eq!(snapshot.latest().version, 4);
+    }
+}
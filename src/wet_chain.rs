@@ -0,0 +1,126 @@
+use crate::process_context::ProcessContextLite;
+use crate::stereo_pair::StereoPair;
+use crate::MetaloopParams;
+
+// one module in the chain of effects on the loop
+// each picks up its own parameters, so adding an effect only means adding it to the chain
+pub trait WetProcess: Send {
+    // called once per block before processing
+    fn update_params(&mut self, params: &MetaloopParams);
+    fn process_block(&mut self, context: &ProcessContextLite, block: &mut [StereoPair<f32>]);
+    fn set_sample_rate(&mut self, _sample_rate: f32) {}
+    fn reset(&mut self) {}
+}
+
+struct WetSlot {
+    processor: Box<dyn WetProcess>,
+    enabled: bool,
+}
+
+// the effects on the loop, run in the order they were added
+pub struct WetChain {
+    slots: Vec<WetSlot>,
+}
+
+#[allow(dead_code)]
+impl WetChain {
+    pub fn new() -> WetChain {
+        WetChain { slots: vec![] }
+    }
+
+    // returns the index of the slot, for enabling it
+    pub fn push(&mut self, processor: Box<dyn WetProcess>) -> usize {
+        self.slots.push(WetSlot {
+            processor,
+            enabled: true,
+        });
+        self.slots.len() - 1
+    }
+
+    // a disabled slot is skipped entirely, and reset when it is enabled again
+    pub fn set_enabled(&mut self, index: usize, enabled: bool) {
+        let slot = &mut self.slots[index];
+        if enabled && !slot.enabled {
+            slot.processor.reset();
+        }
+        slot.enabled = enabled;
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        for slot in self.slots.iter_mut() {
+            slot.processor.set_sample_rate(sample_rate);
+        }
+    }
+
+    pub fn reset(&mut self) {
+        for slot in self.slots.iter_mut() {
+            slot.processor.reset();
+        }
+    }
+
+    pub fn update_params(&mut self, params: &MetaloopParams) {
+        for slot in self.slots.iter_mut() {
+            slot.processor.update_params(params);
+        }
+    }
+
+    pub fn process_block(&mut self, context: &ProcessContextLite, block: &mut [StereoPair<f32>]) {
+        for slot in self.slots.iter_mut().filter(|slot| slot.enabled) {
+            slot.processor.process_block(context, block);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Gain(f32);
+
+    impl WetProcess for Gain {
+        fn update_params(&mut self, _params: &MetaloopParams) {}
+        fn process_block(&mut self, _context: &ProcessContextLite, block: &mut [StereoPair<f32>]) {
+            for frame in block.iter_mut() {
+                *frame = *frame * self.0;
+            }
+        }
+    }
+
+    struct Offset(f32);
+
+    impl WetProcess for Offset {
+        fn update_params(&mut self, _params: &MetaloopParams) {}
+        fn process_block(&mut self, _context: &ProcessContextLite, block: &mut [StereoPair<f32>]) {
+            for frame in block.iter_mut() {
+                *frame += StereoPair::new(self.0, self.0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_wet_chain_order_and_enable() {
+        let context = ProcessContextLite {
+            tempo: 120.0,
+            beat_time: 0.0,
+            sample_rate: 10.0,
+            is_playing: true,
+            time_sig: (4, 4),
+        };
+        let mut chain = WetChain::new();
+        let gain = chain.push(Box::new(Gain(2.0)));
+        chain.push(Box::new(Offset(1.0)));
+
+        let mut block = [StereoPair::new(1.0, 2.0)];
+        chain.process_block(&context, &mut block);
+        assert_eq!(block[0], StereoPair::new(3.0, 5.0));
+
+        chain.set_enabled(gain, false);
+        let mut block = [StereoPair::new(1.0, 2.0)];
+        chain.process_block(&context, &mut block);
+        assert_eq!(block[0], StereoPair::new(2.0, 3.0));
+    }
+}
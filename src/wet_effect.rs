@@ -0,0 +1,145 @@
+use crate::freq_shifter::FreqShifter;
+use crate::process_context::ProcessContextLite;
+use crate::ring_mod::RingMod;
+use crate::stereo_pair::StereoPair;
+use crate::wet_chain::WetProcess;
+use crate::MetaloopParams;
+use nih_plug::prelude::Enum;
+
+// an effect that colors the loop, without touching the dry signal
+pub trait WetEffect: Send {
+    fn set_sample_rate(&mut self, sample_rate: f32);
+    fn set_tempo(&mut self, _tempo: f32) {}
+    // the main control of the effect, e.g. a carrier frequency
+    fn set_frequency(&mut self, frequency_hz: f32);
+    // lock the effect to the song position where it makes sense
+    fn set_synced(&mut self, _synced: bool) {}
+    fn reset(&mut self);
+    fn process(&mut self, input: StereoPair<f32>, beat_time: f64) -> StereoPair<f32>;
+}
+
+#[derive(Enum, Debug, Clone, Copy, PartialEq)]
+pub enum ColorEffect {
+    #[id = "off"]
+    #[name = "Off"]
+    Off,
+    #[id = "ring-mod"]
+    #[name = "Ring Mod"]
+    RingMod,
+    #[id = "freq-shift"]
+    #[name = "Freq Shift"]
+    FreqShift,
+}
+
+// a single effect slot on the wet path, holding one of each effect so switching doesn't allocate
+pub struct ColorSlot {
+    selected: ColorEffect,
+    ring_mod: Box<dyn WetEffect>,
+    freq_shifter: Box<dyn WetEffect>,
+    mix: f32,
+}
+
+#[allow(dead_code)]
+impl ColorSlot {
+    pub fn new(sample_rate: f32) -> ColorSlot {
+        ColorSlot {
+            selected: ColorEffect::Off,
+            ring_mod: Box::new(RingMod::new(sample_rate)),
+            freq_shifter: Box::new(FreqShifter::new(sample_rate)),
+            mix: 1.0,
+        }
+    }
+
+    fn effects(&mut self) -> [&mut Box<dyn WetEffect>; 2] {
+        [&mut self.ring_mod, &mut self.freq_shifter]
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.effects()
+            .into_iter()
+            .for_each(|e| e.set_sample_rate(sample_rate));
+    }
+
+    pub fn set_tempo(&mut self, tempo: f32) {
+        self.effects().into_iter().for_each(|e| e.set_tempo(tempo));
+    }
+
+    pub fn set_frequency(&mut self, frequency_hz: f32) {
+        self.effects()
+            .into_iter()
+            .for_each(|e| e.set_frequency(frequency_hz));
+    }
+
+    pub fn set_synced(&mut self, synced: bool) {
+        self.effects()
+            .into_iter()
+            .for_each(|e| e.set_synced(synced));
+    }
+
+    // the newly selected effect starts from silence rather than stale state
+    pub fn select(&mut self, effect: ColorEffect) {
+        if effect != self.selected {
+            self.selected = effect;
+            self.effects().into_iter().for_each(|e| e.reset());
+        }
+    }
+
+    pub fn set_mix(&mut self, mix: f32) {
+        self.mix = mix;
+    }
+
+    pub fn reset(&mut self) {
+        self.effects().into_iter().for_each(|e| e.reset());
+    }
+
+    pub fn process(&mut self, input: StereoPair<f32>, beat_time: f64) -> StereoPair<f32> {
+        let effect = match self.selected {
+            ColorEffect::Off => return input,
+            ColorEffect::RingMod => &mut self.ring_mod,
+            ColorEffect::FreqShift => &mut self.freq_shifter,
+        };
+        let colored = effect.process(input, beat_time);
+        input + (colored - input) * self.mix
+    }
+}
+
+impl WetProcess for ColorSlot {
+    fn update_params(&mut self, params: &MetaloopParams) {
+        self.select(params.color.value());
+        self.set_frequency(params.color_freq.value());
+        self.set_synced(params.color_sync.value());
+        self.set_mix(params.color_mix.value());
+    }
+
+    fn process_block(&mut self, context: &ProcessContextLite, block: &mut [StereoPair<f32>]) {
+        self.set_tempo(context.tempo);
+        for (i, frame) in block.iter_mut().enumerate() {
+            *frame = self.process(*frame, context.beat_time_at(i));
+        }
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f32) {
+        ColorSlot::set_sample_rate(self, sample_rate);
+    }
+
+    fn reset(&mut self) {
+        ColorSlot::reset(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_color_slot_off_passes_through() {
+        let mut slot = ColorSlot::new(100.0);
+        let input = StereoPair::new(0.5, -0.25);
+        assert_eq!(slot.process(input, 0.0), input);
+
+        // no mix is no change either
+        slot.select(ColorEffect::RingMod);
+        slot.set_mix(0.0);
+        assert_eq!(slot.process(input, 0.1), input);
+    }
+}